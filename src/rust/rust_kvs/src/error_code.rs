@@ -14,6 +14,7 @@ extern crate alloc;
 use alloc::string::FromUtf8Error;
 use core::array::TryFromSliceError;
 
+use crate::kvs_log::log_error;
 use crate::kvs_value::KvsValue;
 use std::collections::HashMap;
 use std::sync::{MutexGuard, PoisonError};
@@ -63,6 +64,9 @@ pub enum ErrorCode {
     /// Authentication failed
     AuthenticationFailed,
 
+    /// Access to the key isn't permitted
+    PermissionDenied,
+
     /// Key not found
     KeyNotFound,
 
@@ -72,6 +76,9 @@ pub enum ErrorCode {
     /// Invalid snapshot ID
     InvalidSnapshotId,
 
+    /// Key was changed since its version was read
+    VersionConflict,
+
     /// Conversion failed
     ConversionFailed,
 
@@ -85,7 +92,7 @@ impl From<std::io::Error> for ErrorCode {
         match kind {
             std::io::ErrorKind::NotFound => ErrorCode::FileNotFound,
             _ => {
-                eprintln!("error: unmapped error: {kind}");
+                log_error!("unmapped error: {kind}");
                 ErrorCode::UnmappedError
             }
         }
@@ -94,28 +101,28 @@ impl From<std::io::Error> for ErrorCode {
 
 impl From<FromUtf8Error> for ErrorCode {
     fn from(cause: FromUtf8Error) -> Self {
-        eprintln!("error: UTF-8 conversion failed: {cause:#?}");
+        log_error!("UTF-8 conversion failed: {cause:#?}");
         ErrorCode::ConversionFailed
     }
 }
 
 impl From<TryFromSliceError> for ErrorCode {
     fn from(cause: TryFromSliceError) -> Self {
-        eprintln!("error: try_into from slice failed: {cause:#?}");
+        log_error!("try_into from slice failed: {cause:#?}");
         ErrorCode::ConversionFailed
     }
 }
 
 impl From<Vec<u8>> for ErrorCode {
     fn from(cause: Vec<u8>) -> Self {
-        eprintln!("error: try_into from u8 vector failed: {cause:#?}");
+        log_error!("try_into from u8 vector failed: {cause:#?}");
         ErrorCode::ConversionFailed
     }
 }
 
 impl From<PoisonError<MutexGuard<'_, HashMap<std::string::String, KvsValue>>>> for ErrorCode {
     fn from(cause: PoisonError<MutexGuard<'_, HashMap<std::string::String, KvsValue>>>) -> Self {
-        eprintln!("error: Mutex locking failed: {cause:#?}");
+        log_error!("Mutex locking failed: {cause:#?}");
         ErrorCode::MutexLockFailed
     }
 }
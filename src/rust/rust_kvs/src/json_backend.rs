@@ -1,11 +1,73 @@
 use crate::error_code::ErrorCode;
+use crate::kvs_api::{Compression, Durability, StorageFormat};
 use crate::kvs_backend::KvsBackend;
+use crate::kvs_base64;
+use crate::kvs_cbor;
+use crate::kvs_cipher::KvsCipher;
+use crate::kvs_compress;
+use crate::kvs_log::log_error;
+use crate::kvs_platform::{atomic_replace, path_with_suffix};
+use crate::kvs_platform::{commit_replace, stage_replace, staged_content};
 use crate::kvs_value::{KvsMap, KvsValue};
 use std::fs;
 use std::path::PathBuf;
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use tinyjson::{JsonGenerateError, JsonParseError, JsonValue};
 
+/// Member name of a JSON object holding an `I64` value as decimal string
+const JSON_TAG_I64: &str = "$i64";
+
+/// Member name of a JSON object holding an `U64` value as decimal string
+const JSON_TAG_U64: &str = "$u64";
+
+/// Member name of a JSON object holding a `Bytes` value as base64 string
+const JSON_TAG_BYTES: &str = "$bytes";
+
+/// First character of the tag members
+///
+/// Object members of values starting with it are written with the character doubled, so a
+/// value object can never look like a tagged value. Members with a single `$` that aren't a
+/// tagged value are read unchanged, as written before members were escaped.
+const JSON_TAG_PREFIX: char = '$';
+
+/// Escape the member name of a value object
+fn escape_key(key: &str) -> Cow<'_, str> {
+    if key.starts_with(JSON_TAG_PREFIX) {
+        Cow::Owned(format!("{JSON_TAG_PREFIX}{key}"))
+    } else {
+        Cow::Borrowed(key)
+    }
+}
+
+/// Reverse [`escape_key`]
+fn unescape_key(key: String) -> String {
+    match key.strip_prefix(JSON_TAG_PREFIX) {
+        Some(rest) if rest.starts_with(JSON_TAG_PREFIX) => rest.to_string(),
+        _ => key,
+    }
+}
+
+/// Return the value of a tagged JSON object, e.g. `{"$u64": "18446744073709551615"}`
+///
+/// JSON numbers are parsed as `f64`, so 64-bit integers are stored as strings to keep all bits.
+/// Byte arrays are stored as base64 strings.
+fn tagged_value(obj: &HashMap<String, JsonValue>) -> Option<KvsValue> {
+    if obj.len() != 1 {
+        return None;
+    }
+    let (tag, JsonValue::String(text)) = obj.iter().next()? else {
+        return None;
+    };
+    match tag.as_str() {
+        JSON_TAG_I64 => text.parse().ok().map(KvsValue::I64),
+        JSON_TAG_U64 => text.parse().ok().map(KvsValue::U64),
+        JSON_TAG_BYTES => kvs_base64::decode(text).map(KvsValue::Bytes),
+        _ => None,
+    }
+}
+
 /// Backend-specific JsonValue -> KvsValue conversion.
 impl From<JsonValue> for KvsValue {
     fn from(val: JsonValue) -> KvsValue {
@@ -15,11 +77,14 @@ impl From<JsonValue> for KvsValue {
             JsonValue::String(s) => KvsValue::String(s),
             JsonValue::Null => KvsValue::Null,
             JsonValue::Array(arr) => KvsValue::Array(arr.into_iter().map(KvsValue::from).collect()),
-            JsonValue::Object(obj) => KvsValue::Object(
-                obj.into_iter()
-                    .map(|(k, v)| (k.clone(), KvsValue::from(v)))
-                    .collect(),
-            ),
+            JsonValue::Object(obj) => match tagged_value(&obj) {
+                Some(value) => value,
+                None => KvsValue::Object(
+                    obj.into_iter()
+                        .map(|(k, v)| (unescape_key(k), KvsValue::from(v)))
+                        .collect(),
+                ),
+            },
         }
     }
 }
@@ -29,6 +94,18 @@ impl From<KvsValue> for JsonValue {
     fn from(val: KvsValue) -> JsonValue {
         match val {
             KvsValue::Number(n) => JsonValue::Number(n),
+            KvsValue::I64(n) => JsonValue::Object(HashMap::from([(
+                JSON_TAG_I64.to_string(),
+                JsonValue::String(n.to_string()),
+            )])),
+            KvsValue::U64(n) => JsonValue::Object(HashMap::from([(
+                JSON_TAG_U64.to_string(),
+                JsonValue::String(n.to_string()),
+            )])),
+            KvsValue::Bytes(b) => JsonValue::Object(HashMap::from([(
+                JSON_TAG_BYTES.to_string(),
+                JsonValue::String(kvs_base64::encode(&b)),
+            )])),
             KvsValue::Boolean(b) => JsonValue::Boolean(b),
             KvsValue::String(s) => JsonValue::String(s),
             KvsValue::Null => JsonValue::Null,
@@ -37,18 +114,72 @@ impl From<KvsValue> for JsonValue {
             }
             KvsValue::Object(map) => JsonValue::Object(
                 map.into_iter()
-                    .map(|(k, v)| (k.clone(), JsonValue::from(v)))
+                    .map(|(k, v)| (escape_key(&k).into_owned(), JsonValue::from(v)))
                     .collect(),
             ),
         }
     }
 }
 
+/// Serialize a value as JSON with sorted object keys
+///
+/// tinyjson writes object members in the arbitrary order of its `HashMap`, so the same data
+/// would be written differently by every process.
+fn write_json(out: &mut String, value: &KvsValue) -> Result<(), ErrorCode> {
+    match value {
+        KvsValue::Array(arr) => {
+            out.push('[');
+            for (idx, item) in arr.iter().enumerate() {
+                if idx > 0 {
+                    out.push(',');
+                }
+                write_json(out, item)?;
+            }
+            out.push(']');
+        }
+        KvsValue::Object(map) => write_json_map(out, map, true)?,
+        scalar => out.push_str(&JsonValue::from(scalar.clone()).stringify()?),
+    }
+    Ok(())
+}
+
+/// Serialize a map as JSON object with sorted keys, `escape` the keys of value objects
+fn write_json_map(out: &mut String, map: &KvsMap, escape: bool) -> Result<(), ErrorCode> {
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+
+    out.push('{');
+    for (idx, key) in keys.into_iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        let name = if escape {
+            escape_key(key)
+        } else {
+            Cow::Borrowed(key.as_str())
+        };
+        out.push_str(&JsonValue::String(name.into_owned()).stringify()?);
+        out.push(':');
+        write_json(out, &map[key])?;
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// Serialize a map as canonical JSON document
+///
+/// The members of the document are KVS keys, they are never tagged values and not escaped.
+pub(crate) fn canonical_json(map: &KvsMap) -> Result<Vec<u8>, ErrorCode> {
+    let mut out = String::new();
+    write_json_map(&mut out, map, false)?;
+    Ok(out.into_bytes())
+}
+
 /// tinyjson::JsonParseError -> ErrorCode::JsonParseError
 impl From<JsonParseError> for ErrorCode {
     fn from(cause: JsonParseError) -> Self {
-        eprintln!(
-            "error: JSON parser error: line = {}, column = {}",
+        log_error!(
+            "JSON parser error: line = {}, column = {}",
             cause.line(),
             cause.column()
         );
@@ -59,12 +190,27 @@ impl From<JsonParseError> for ErrorCode {
 /// tinyjson::JsonGenerateError -> ErrorCode::JsonGenerateError
 impl From<JsonGenerateError> for ErrorCode {
     fn from(cause: JsonGenerateError) -> Self {
-        eprintln!("error: JSON generator error: msg = {}", cause.message());
+        log_error!("JSON generator error: msg = {}", cause.message());
         ErrorCode::JsonGeneratorError
     }
 }
 
 /// KVS backend implementation based on TinyJSON.
+///
+/// Files can also be written as CBOR (see [`StorageFormat`]), the format is detected on load.
+/// The filenames are the same for both formats. In JSON `I64` and `U64` values are written as
+/// objects with a single member `$i64` or `$u64` and the decimal value as string, `Bytes`
+/// values as object with the member `$bytes` and the base64 encoded value. Members of object
+/// values starting with `$` are written with an additional `$`, e.g. `{"$u64": "1"}` as
+/// `{"$$u64": "1"}`, so they're never mistaken for a tagged value.
+///
+/// # Compatibility
+///
+/// Readers that don't know the tags, like the C++ implementation, read tagged values as objects
+/// with a single string member and escaped members with the additional `$`. Documents without
+/// `I64`, `U64` and `Bytes` values and without object members starting with `$` are written
+/// exactly as before, the top-level keys are never changed. Files shared with such readers
+/// should only hold these values.
 pub struct JsonBackend;
 
 impl JsonBackend {
@@ -73,9 +219,40 @@ impl JsonBackend {
             .map_err(|_e: JsonParseError| crate::error_code::ErrorCode::JsonParserError)
     }
 
-    fn stringify(val: &JsonValue) -> Result<String, ErrorCode> {
-        val.stringify()
-            .map_err(|_e: JsonGenerateError| crate::error_code::ErrorCode::JsonParserError)
+    /// Parse a KVS document, JSON or CBOR is detected from the content
+    pub(crate) fn parse_kvs(data: &[u8]) -> Result<KvsMap, ErrorCode> {
+        let decompressed;
+        let data = if kvs_compress::is_compressed(data) {
+            decompressed = kvs_compress::decompress(data)?;
+            &decompressed
+        } else {
+            data
+        };
+        if kvs_cbor::is_cbor(data) {
+            return kvs_cbor::decode(data);
+        }
+
+        let data = std::str::from_utf8(data).map_err(|_| ErrorCode::JsonParserError)?;
+        let json_value = Self::parse(data).map_err(|_| ErrorCode::JsonParserError)?;
+        if let JsonValue::Object(obj) = json_value {
+            Ok(obj
+                .into_iter()
+                .map(|(key, value)| (key, KvsValue::from(value)))
+                .collect())
+        } else {
+            Err(ErrorCode::JsonParserError)
+        }
+    }
+
+    /// Serialize a KVS document in the given format
+    ///
+    /// Object keys are written sorted in both formats, so equal data always gives byte-identical
+    /// documents and hashes.
+    pub(crate) fn serialize_kvs(kvs: &KvsMap, format: StorageFormat) -> Result<Vec<u8>, ErrorCode> {
+        match format {
+            StorageFormat::Json => canonical_json(kvs),
+            StorageFormat::Cbor => Ok(kvs_cbor::encode(kvs)),
+        }
     }
 }
 
@@ -85,77 +262,112 @@ impl KvsBackend for JsonBackend {
         verify_hash: bool,
         hash_source: Option<PathBuf>,
     ) -> Result<KvsMap, ErrorCode> {
-        let filename = source_path.with_extension("json");
-        let data = fs::read_to_string(&filename).map_err(|_| ErrorCode::KvsFileReadError)?;
-        let json_value = Self::parse(&data).map_err(|_| ErrorCode::JsonParserError)?;
+        Self::load_kvs_with_cipher(source_path, verify_hash, hash_source, None)
+    }
+
+    fn save_kvs(kvs: &KvsMap, destination_path: PathBuf, add_hash: bool) -> Result<(), ErrorCode> {
+        Self::save_kvs_as(kvs, destination_path, add_hash, StorageFormat::Json)
+    }
+
+    fn save_kvs_as(
+        kvs: &KvsMap,
+        destination_path: PathBuf,
+        add_hash: bool,
+        format: StorageFormat,
+    ) -> Result<(), ErrorCode> {
+        Self::save_kvs_with_cipher(
+            kvs,
+            destination_path,
+            add_hash,
+            format,
+            Compression::Off,
+            Durability::default(),
+            None,
+        )
+    }
+
+    fn load_kvs_with_cipher(
+        source_path: PathBuf,
+        verify_hash: bool,
+        hash_source: Option<PathBuf>,
+        cipher: Option<&dyn KvsCipher>,
+    ) -> Result<KvsMap, ErrorCode> {
+        // The path is a filename without extension, which may contain dots, e.g. of a snapshot tag
+        let filename = if source_path.extension().is_some_and(|ext| ext == "json") {
+            source_path
+        } else {
+            path_with_suffix(&source_path, ".json")
+        };
+        let data = fs::read(&filename).map_err(|_| ErrorCode::KvsFileReadError)?;
 
         // Hash check logic (use parsed data)
         if verify_hash {
             if let Some(hash_filename) = hash_source {
                 if !hash_filename.as_os_str().is_empty() {
-                    match fs::read(&hash_filename) {
-                        Ok(hash_bytes) => {
-                            let hash_kvs =
-                                adler32::RollingAdler32::from_buffer(data.as_bytes()).hash();
-                            if hash_bytes.len() == 4 {
-                                let file_hash = u32::from_be_bytes([
-                                    hash_bytes[0],
-                                    hash_bytes[1],
-                                    hash_bytes[2],
-                                    hash_bytes[3],
-                                ]);
-                                if hash_kvs != file_hash {
-                                    return Err(ErrorCode::ValidationFailed);
-                                }
-                            } else {
-                                return Err(ErrorCode::ValidationFailed);
-                            }
-                        }
-                        Err(_) => {
-                            return Err(ErrorCode::KvsHashFileReadError);
-                        }
+                    let hash_kvs = adler32::RollingAdler32::from_buffer(&data)
+                        .hash()
+                        .to_be_bytes();
+                    let hash_file = fs::read(&hash_filename);
+                    // A flush interrupted between replacing the KVS file and its hash file left
+                    // the matching hash staged
+                    let matches = |hash: &[u8]| hash == hash_kvs;
+                    if !hash_file.as_deref().is_ok_and(matches)
+                        && !staged_content(&hash_filename).is_some_and(|hash| matches(&hash))
+                    {
+                        return Err(match hash_file {
+                            Ok(_) => ErrorCode::ValidationFailed,
+                            Err(_) => ErrorCode::KvsHashFileReadError,
+                        });
                     }
                 }
             }
         }
 
-        let kvs_value = KvsValue::from(json_value);
-        if let KvsValue::Object(kvs_map) = kvs_value {
-            Ok(kvs_map)
-        } else {
-            Err(ErrorCode::JsonParserError)
+        match cipher {
+            Some(cipher) => Self::parse_kvs(&cipher.decrypt(&data)?),
+            None => Self::parse_kvs(&data),
         }
     }
 
-    fn save_kvs(kvs: &KvsMap, destination_path: PathBuf, add_hash: bool) -> Result<(), ErrorCode> {
-        let filename = destination_path
-            .with_extension("json")
-            .with_file_name(format!("{}_0.json", destination_path.display()));
-
-        let kvs_value = KvsValue::Object(kvs.clone());
-        let json_value = JsonValue::from(kvs_value);
-
-        let json_str = Self::stringify(&json_value).map_err(|_| ErrorCode::JsonParserError)?;
-        fs::write(&filename, &json_str).map_err(|_| ErrorCode::KvsFileReadError)?;
-
-        if add_hash {
-            // Compute hash and write to hash file
-            let hash = adler32::RollingAdler32::from_buffer(json_str.as_bytes()).hash();
-            // If filename ends with .json, replace with .hash
-            let filename_hash = if let Some(stem) = filename.file_stem() {
-                let mut hash_path = filename.clone();
-                hash_path.set_file_name(format!("{}.hash", stem.to_string_lossy()));
-                hash_path
-            } else {
-                let mut hash_path = filename.clone();
-                hash_path.set_extension("hash");
-                hash_path
-            };
-            fs::write(&filename_hash, hash.to_be_bytes())
-                .map_err(|_| ErrorCode::KvsFileReadError)?;
+    fn save_kvs_with_cipher(
+        kvs: &KvsMap,
+        destination_path: PathBuf,
+        add_hash: bool,
+        format: StorageFormat,
+        compression: Compression,
+        durability: Durability,
+        cipher: Option<&dyn KvsCipher>,
+    ) -> Result<(), ErrorCode> {
+        let filename = path_with_suffix(&destination_path, "_0.json");
+
+        let data = kvs_compress::compress(Self::serialize_kvs(kvs, format)?, compression);
+        let data = match cipher {
+            Some(cipher) => cipher.encrypt(&data)?,
+            None => data,
+        };
+        if !add_hash {
+            return atomic_replace(&filename, &data, durability)
+                .map_err(|_| ErrorCode::KvsFileReadError);
         }
 
-        Ok(())
+        // Compute hash and write to hash file
+        let hash = adler32::RollingAdler32::from_buffer(&data).hash();
+        // If filename ends with .json, replace with .hash
+        let filename_hash = if let Some(stem) = filename.file_stem() {
+            let mut hash_path = filename.clone();
+            hash_path.set_file_name(format!("{}.hash", stem.to_string_lossy()));
+            hash_path
+        } else {
+            let mut hash_path = filename.clone();
+            hash_path.set_extension("hash");
+            hash_path
+        };
+        // The new hash is staged first, so it's available on load if the flush is interrupted
+        // after the KVS file was replaced
+        stage_replace(&filename_hash, &hash.to_be_bytes(), durability)
+            .and_then(|_| atomic_replace(&filename, &data, durability))
+            .and_then(|_| commit_replace(&filename_hash, durability))
+            .map_err(|_| ErrorCode::KvsFileReadError)
     }
 }
 
@@ -171,6 +383,113 @@ mod tests {
         assert_eq!(ErrorCode::from(error), ErrorCode::JsonParserError);
     }
 
+    #[test]
+    fn test_tagged_value_roundtrip() {
+        for value in [
+            KvsValue::Bytes(vec![]),
+            KvsValue::Bytes(vec![0x00, 0xff, 0x10]),
+            KvsValue::I64(i64::MIN),
+            KvsValue::I64(-1),
+            KvsValue::U64(u64::MAX),
+            KvsValue::U64(9007199254740993),
+        ] {
+            let json = JsonValue::from(value.clone()).stringify().unwrap();
+            let parsed: JsonValue = json.parse().unwrap();
+            assert_eq!(KvsValue::from(parsed), value);
+        }
+
+        // Objects only look like tagged integers if the value is a valid decimal string
+        let json: JsonValue = r#"{"$u64": "-1"}"#.parse().unwrap();
+        assert!(matches!(KvsValue::from(json), KvsValue::Object(_)));
+    }
+
+    #[test]
+    fn test_tag_like_objects_roundtrip() {
+        let object = |key: &str, value: KvsValue| {
+            KvsValue::Object(HashMap::from([(key.to_string(), value)]))
+        };
+        let string = |text: &str| KvsValue::String(text.to_string());
+        let values = [
+            object("$u64", string("42")),
+            object("$i64", string("-1")),
+            object("$bytes", string("AAAA")),
+            object("$$u64", string("42")),
+            object("$", KvsValue::Null),
+            object("$u64", KvsValue::U64(42)),
+            object("a", object("$bytes", string("AAAA"))),
+            KvsValue::Array(vec![object("$i64", string("7"))]),
+        ];
+        let kvs: KvsMap = values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| (format!("key{idx}"), value.clone()))
+            .chain([("$u64".to_string(), string("42"))])
+            .collect();
+
+        let json = JsonBackend::serialize_kvs(&kvs, StorageFormat::Json).unwrap();
+        assert_eq!(JsonBackend::parse_kvs(&json).unwrap(), kvs);
+        let text = String::from_utf8(json).unwrap();
+        assert!(text.contains(r#""key0":{"$$u64":"42"}"#));
+        assert!(text.contains(r#""$u64":"42""#));
+
+        for value in values {
+            let json = JsonValue::from(value.clone()).stringify().unwrap();
+            assert_eq!(KvsValue::from(json.parse::<JsonValue>().unwrap()), value);
+        }
+    }
+
+    #[test]
+    fn test_unescaped_dollar_members_are_read_unchanged() {
+        let kvs = JsonBackend::parse_kvs(br#"{"a": {"$schema": 1, "$$b": 2}}"#).unwrap();
+        assert_eq!(
+            kvs["a"],
+            KvsValue::Object(HashMap::from([
+                ("$schema".to_string(), KvsValue::from(1.0)),
+                ("$b".to_string(), KvsValue::from(2.0)),
+            ]))
+        );
+        assert_eq!(
+            JsonBackend::parse_kvs(br#"{"$u64": "42"}"#).unwrap()["$u64"],
+            KvsValue::String("42".to_string())
+        );
+    }
+
+    #[test]
+    fn test_serialize_sorted() {
+        let nested = |order: &[&str]| {
+            KvsValue::Object(
+                order
+                    .iter()
+                    .map(|key| (key.to_string(), KvsValue::from(1.0)))
+                    .collect(),
+            )
+        };
+        let mut first = KvsMap::new();
+        let mut second = KvsMap::new();
+        for (idx, key) in ["b", "a", "d", "c"].iter().enumerate() {
+            first.insert(key.to_string(), nested(&["y", "x", "z"]));
+            second.insert(
+                ["c", "d", "a", "b"][idx].to_string(),
+                nested(&["z", "x", "y"]),
+            );
+        }
+        let json = JsonBackend::serialize_kvs(&first, StorageFormat::Json).unwrap();
+        assert_eq!(
+            json,
+            JsonBackend::serialize_kvs(&second, StorageFormat::Json).unwrap()
+        );
+        assert!(String::from_utf8(json)
+            .unwrap()
+            .starts_with(r#"{"a":{"x":1,"y":1,"z":1},"b":"#));
+        assert_eq!(
+            JsonBackend::serialize_kvs(
+                &KvsMap::from([("nan".to_string(), KvsValue::from(f64::NAN))]),
+                StorageFormat::Json
+            ),
+            Err(ErrorCode::JsonGeneratorError)
+        );
+    }
+
     #[test]
     fn test_unknown_error_code_from_json_generate_error() {
         let data: JsonValue = JsonValue::Number(f64::INFINITY);
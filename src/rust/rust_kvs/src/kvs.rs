@@ -10,22 +10,63 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //std dependencies
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::atomic::{self, AtomicBool};
-use std::sync::Mutex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{self, AtomicBool, AtomicU64};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::error_code::ErrorCode;
-use crate::kvs_api::{InstanceId, KvsApi, SnapshotId};
-use crate::kvs_api::{OpenNeedDefaults, OpenNeedKvs};
+use crate::json_backend::JsonBackend;
+use crate::kvs_access::AccessPolicy;
+use crate::kvs_api::KVS_MAX_SNAPSHOTS;
+use crate::kvs_api::{CompactionReport, DefaultsPrecedence, InstanceId, KeyStatsMode, KvsApi};
+use crate::kvs_api::{Compression, Durability, StorageFormat};
+use crate::kvs_api::{FlushInfo, KvsFlushCallback};
+use crate::kvs_api::{GrowthTrend, JournalRole, KvsOptions, KvsStats};
+use crate::kvs_api::{ImportMode, IntegrityIssue, IntegrityReport};
+use crate::kvs_api::{OpenMode, OpenNeedDefaults, OpenNeedKvs};
+use crate::kvs_api::{SnapshotGeneration, SnapshotId, SnapshotInfo, SnapshotRetention};
+use crate::kvs_auto_flush::{AutoFlush, Schedule};
 use crate::kvs_backend::KvsBackend;
+use crate::kvs_cipher::KvsCipher;
+use crate::kvs_compress;
+use crate::kvs_csv::{self, CsvRow};
+use crate::kvs_cursor::KvsCursor;
+use crate::kvs_default_provider::{DefaultProviders, KvsDefaultProvider};
+use crate::kvs_defaults;
+use crate::kvs_defaults_hash::{self, DefaultsHash};
+use crate::kvs_fairness::{AccessGate, GateGuard};
+use crate::kvs_flags;
+use crate::kvs_glob::glob_matches;
+use crate::kvs_journal::{JournalLock, JournalRead, MergeJournal};
+use crate::kvs_lock::ProcessLock;
+use crate::kvs_log::{log_debug, log_error, log_info, OpTimer};
+use crate::kvs_metadata::{KeyMetadata, KeyMetadataStore};
+use crate::kvs_migration::{self, KvsMigration, Migrations};
+use crate::kvs_namespace::KvsNamespace;
+use crate::kvs_observer::{operation_events, replace_events, KvsEvent, KvsObserver};
+use crate::kvs_observer::{Observers, OverflowPolicy, SubscriptionId};
+use crate::kvs_override::{KvsOverrideSession, Overrides};
+use crate::kvs_platform::{atomic_replace, link_or_copy, path_with_suffix, staged_content};
+use crate::kvs_pointer;
+use crate::kvs_rules::{self, KvsRestoreHook, KvsRule, RuleContext, RuleViolation, Rules};
+use crate::kvs_schema::KeySchemas;
+use crate::kvs_snapshot_view::{KeyDiff, KvsReadOnlyView};
+use crate::kvs_stats::{KeyCounters, KeyStats};
+use crate::kvs_transaction::{KvsOperation, KvsTransaction};
+use crate::kvs_trend::SizeHistory;
+use crate::kvs_validator::{KvsValidator, Validators};
 use crate::kvs_value::{KvsMap, KvsValue};
+use crate::kvs_value_ref::KvsValueRef;
+use crate::kvs_version::{self, KeyVersions};
+use crate::kvs_wal::{WalRecord, WriteAheadLog};
+use crate::kvs_worker::WorkerConfig;
+use crate::kvs_write_stats::{record_size, value_size, WriteCounters, WriteReport};
 
-/// Maximum number of snapshots
-///
-/// Feature: `FEAT_REQ__KVS__snapshots`
-const KVS_MAX_SNAPSHOTS: usize = 3;
+/// Tag of the snapshot created by [`GenericKvs::compact`]
+const COMPACTION_SNAPSHOT_TAG: &str = "pre-compaction";
 
 /// Key-value-storage data
 pub struct GenericKvs<J: KvsBackend> {
@@ -34,6 +75,9 @@ pub struct GenericKvs<J: KvsBackend> {
     /// Feature: `FEAT_REQ__KVS__thread_safety` (Mutex)
     kvs: Mutex<KvsMap>,
 
+    /// Admission of readers and writers to the storage data
+    gate: AccessGate,
+
     /// Optional default values
     ///
     /// Feature: `FEAT_REQ__KVS__default_values`
@@ -42,9 +86,123 @@ pub struct GenericKvs<J: KvsBackend> {
     /// Filename prefix
     filename_prefix: PathBuf,
 
+    /// Format used when the KVS file is written
+    storage_format: StorageFormat,
+
+    /// Compression the KVS file is written with
+    compression: Compression,
+
+    /// Synchronization of written files
+    durability: Durability,
+
+    /// Data is kept in memory only
+    ephemeral: bool,
+
+    /// Data can't be changed
+    read_only: bool,
+
+    /// Lock against other processes, held while the instance is open
+    _process_lock: Option<ProcessLock>,
+
+    /// Only keys with a static default can be set, except for the exempted prefixes
+    strict_keys: Option<Vec<String>>,
+
     /// Flush on exit flag
     flush_on_exit: AtomicBool,
 
+    /// Optional cipher the KVS file and snapshots are encrypted with
+    cipher: Option<Arc<dyn KvsCipher>>,
+
+    /// Optional write-ahead log
+    ///
+    /// Feature: `FEAT_REQ__KVS__persistency`
+    wal: Option<WriteAheadLog>,
+
+    /// Retention policy of the snapshots
+    ///
+    /// Feature: `FEAT_REQ__KVS__snapshots`
+    retention: SnapshotRetention,
+
+    /// Schema version of the data
+    schema_version: AtomicU64,
+
+    /// Registered schema migrations
+    migrations: Mutex<Migrations>,
+
+    /// Client identity of the instance
+    identity: Option<String>,
+
+    /// Protected key namespaces
+    access: AccessPolicy,
+
+    /// Delta journal of incremental flushes, `None` if ephemeral or read-only
+    delta: Option<WriteAheadLog>,
+
+    /// Optional merge journal shared with cooperating processes
+    journal: Option<MergeJournal>,
+
+    /// Key-change observers
+    observers: Observers,
+
+    /// Cross-key consistency rules
+    rules: Rules,
+
+    /// Per-key value validators
+    validators: Validators,
+
+    /// JSON Schemas of the defaults file
+    schemas: KeySchemas,
+
+    /// Maximum logical size of a single value
+    max_value_size: Option<u64>,
+
+    /// Temporary in-memory overrides of diagnostics sessions
+    overrides: Overrides,
+
+    /// Hook to transform or veto restored snapshot data
+    restore_hook: Mutex<Option<KvsRestoreHook>>,
+
+    /// Callback notified of written KVS files
+    flush_callback: Mutex<Option<KvsFlushCallback>>,
+
+    /// Provider of dynamic default values
+    ///
+    /// Feature: `FEAT_REQ__KVS__default_values`
+    default_provider: DefaultProviders,
+
+    /// Optional per-key access counters
+    key_stats: Option<KeyCounters>,
+
+    /// Logically changed and written bytes
+    write_stats: WriteCounters,
+
+    /// Settings of the worker threads
+    worker: WorkerConfig,
+
+    /// Keys set or removed since the last flush
+    dirty: Mutex<HashSet<String>>,
+
+    /// Per-key version counters, `None` if disabled
+    versions: Option<KeyVersions>,
+
+    /// Per-key change time and writer, `None` if disabled
+    metadata: Option<KeyMetadataStore>,
+
+    /// Hash of the defaults, written to the KVS file
+    defaults_hash: DefaultsHash,
+
+    /// Keys whose default changed since the previous open
+    changed_defaults: Vec<String>,
+
+    /// Sampled store sizes, `None` if disabled
+    size_history: Option<SizeHistory>,
+
+    /// Time of the last flush that wrote the KVS file
+    last_flush: Mutex<Option<SystemTime>>,
+
+    /// Periodic flush of changed data
+    auto_flush: AutoFlush,
+
     _backend: std::marker::PhantomData<J>,
 }
 
@@ -86,6 +244,16 @@ enum OpenKvsVerifyHash {
     Yes,
 }
 
+/// Return the keys that are removed, added or changed by replacing the data, each once
+fn changed_keys<'a>(old: &'a KvsMap, new: &'a KvsMap) -> impl Iterator<Item = &'a String> {
+    let removed = old.keys().filter(|key| !new.contains_key(*key));
+    let set = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, _)| key);
+    removed.chain(set)
+}
+
 impl<J: KvsBackend> GenericKvs<J> {
     /// Open and parse a JSON file
     ///
@@ -110,6 +278,7 @@ impl<J: KvsBackend> GenericKvs<J> {
         need_file: T,
         verify_hash: OpenKvsVerifyHash,
         hash_filename: Option<&PathBuf>,
+        cipher: Option<&dyn KvsCipher>,
     ) -> Result<KvsMap, ErrorCode>
     where
         T: Into<OpenKvsNeedFile>,
@@ -117,28 +286,100 @@ impl<J: KvsBackend> GenericKvs<J> {
         let do_hash = matches!(verify_hash, OpenKvsVerifyHash::Yes);
         let filename_path = filename.clone();
         let hash_filename_path = hash_filename.cloned();
-        match J::load_kvs(filename_path.clone(), do_hash, hash_filename_path.clone()) {
+        match J::load_kvs_with_cipher(
+            filename_path.clone(),
+            do_hash,
+            hash_filename_path.clone(),
+            cipher,
+        ) {
             Ok(_) => {
-                let map = J::load_kvs(filename_path, do_hash, hash_filename_path).map_err(|e| {
-                    eprintln!("error: {e:?}");
-                    e
-                })?;
+                let map =
+                    J::load_kvs_with_cipher(filename_path, do_hash, hash_filename_path, cipher)
+                        .map_err(|e| {
+                            log_error!("{e:?}");
+                            e
+                        })?;
                 Ok(map)
             }
+            // A file that exists but can't be decrypted must not be replaced with empty data
+            Err(ErrorCode::EncryptionFailed) => {
+                log_error!("file {filename:?} could not be decrypted");
+                Err(ErrorCode::EncryptionFailed)
+            }
             Err(e) => {
                 if need_file.into() == OpenKvsNeedFile::Required {
-                    eprintln!("error: file {filename:?} could not be read: {e:?}");
+                    log_error!("file {filename:?} could not be read: {e:?}");
                     Err(e)
                 } else {
-                    println!("file {filename:?} not found, using empty data");
+                    log_info!("file {filename:?} not found, using empty data");
                     Ok(KvsMap::new())
                 }
             }
         }
     }
 
+    /// Combine embedded defaults with the on-disk defaults file
+    ///
+    /// With embedded defaults a missing defaults file is never an error.
+    ///
+    /// # Parameters
+    ///   * `filename`: On-disk defaults file
+    ///   * `data`: Embedded defaults document (JSON or CBOR)
+    ///   * `precedence`: Precedence of the embedded defaults
+    ///
+    /// # Return Values
+    ///   * Ok: Default values
+    ///   * `ErrorCode::JsonParserError`: Embedded JSON document is invalid
+    ///   * `ErrorCode::SerializationFailed`: Embedded CBOR document is invalid
+    ///   * `ErrorCode::ValidationFailed`: Typed defaults don't match their annotations
+    fn open_embedded_defaults(
+        filename: &PathBuf,
+        data: &[u8],
+        precedence: DefaultsPrecedence,
+    ) -> Result<KvsMap, ErrorCode> {
+        let embedded = JsonBackend::parse_kvs(data).inspect_err(|e| {
+            log_error!("embedded defaults could not be parsed: {e:?}");
+        })?;
+        let mut embedded = kvs_defaults::resolve(embedded)?;
+        if precedence == DefaultsPrecedence::Embedded {
+            return Ok(embedded);
+        }
+
+        let on_disk = match J::load_kvs(filename.clone(), false, None) {
+            Ok(on_disk) => Some(kvs_defaults::resolve(on_disk)?),
+            Err(_) => None,
+        };
+        match (precedence, on_disk) {
+            (_, None) => {
+                log_info!("file {filename:?} not found, using embedded defaults");
+                Ok(embedded)
+            }
+            (DefaultsPrecedence::MergeOnDisk, Some(on_disk)) => {
+                embedded.extend(on_disk);
+                Ok(embedded)
+            }
+            (_, Some(on_disk)) => Ok(on_disk),
+        }
+    }
+
+    /// Return the path of a snapshot file
+    ///
+    /// # Parameters
+    ///   * `idx`: Snapshot index, 0 is the current KVS
+    ///   * `ext`: File extension (`json`, `hash` or `gen`)
+    ///
+    /// # Return Values
+    ///   * Path in the form `<dir>/kvs_<instance_id>_<idx>.<ext>`
+    fn snapshot_path(&self, idx: usize, ext: &str) -> PathBuf {
+        path_with_suffix(&self.filename_prefix, &format!("_{idx}.{ext}"))
+    }
+
     /// Rotate snapshots
     ///
+    /// The current KVS file is linked, not moved, to snapshot 1. It stays in place until the
+    /// flush atomically replaces it, so an interrupted flush never leaves the KVS without its
+    /// file.
+    ///
     /// # Features
     ///   * `FEAT_REQ__KVS__snapshots`
     ///
@@ -146,793 +387,6285 @@ impl<J: KvsBackend> GenericKvs<J> {
     ///   * Ok: Rotation successful, also if no rotation was needed
     ///   * `ErrorCode::UnmappedError`: Unmapped error
     fn snapshot_rotate(&self) -> Result<(), ErrorCode> {
-        for idx in (1..=KVS_MAX_SNAPSHOTS).rev() {
-            let hash_old = format!("{}_{}.hash", self.filename_prefix.display(), idx - 1);
-            let hash_new = format!("{}_{}.hash", self.filename_prefix.display(), idx);
-            let snap_old = format!("{}_{}.json", self.filename_prefix.display(), idx - 1);
-            let snap_new = format!("{}_{}.json", self.filename_prefix.display(), idx);
-
-            println!("rotating: {snap_old} -> {snap_new}");
-
-            let res = fs::rename(hash_old, hash_new);
-            if let Err(err) = res {
-                if err.kind() != std::io::ErrorKind::NotFound {
-                    return Err(err.into());
+        for idx in (1..=self.retention.max_count).rev() {
+            let hash_old = self.snapshot_path(idx - 1, "hash");
+            let hash_new = self.snapshot_path(idx, "hash");
+            let snap_old = self.snapshot_path(idx - 1, "json");
+            let snap_new = self.snapshot_path(idx, "json");
+            let rotate = |from: &Path, to: &Path| -> Result<(), ErrorCode> {
+                if idx == 1 {
+                    link_or_copy(from, to, self.durability)
                 } else {
-                    continue;
+                    fs::rename(from, to).map_err(ErrorCode::from)
                 }
+            };
+
+            log_info!("rotating: {} -> {}", snap_old.display(), snap_new.display());
+
+            if !hash_old.exists() {
+                continue;
             }
+            rotate(&hash_old, &hash_new)?;
+            rotate(&snap_old, &snap_new)?;
 
-            let res = fs::rename(snap_old, snap_new);
-            if let Err(err) = res {
-                return Err(err.into());
+            // Snapshots written without a generation have no generation file, a stale one of the
+            // overwritten snapshot must not stay behind
+            let gen_old = self.snapshot_path(idx - 1, "gen");
+            let gen_new = self.snapshot_path(idx, "gen");
+            if gen_old.exists() {
+                rotate(&gen_old, &gen_new)?;
+            } else {
+                let _ = fs::remove_file(gen_new);
             }
         }
 
         Ok(())
     }
-}
 
-impl<J: KvsBackend> KvsApi for GenericKvs<J> {
-    /// Open the key-value-storage
+    /// Remove the snapshots the retention policy doesn't keep
     ///
-    /// Checks and opens a key-value-storage. Flush on exit is enabled by default and can be
-    /// controlled with [`flush_on_exit`](Self::flush_on_exit).
+    /// Snapshots are removed oldest first, so the kept ones stay a chain without gaps. Snapshots
+    /// behind the maximum count are left over from a previously larger count.
     ///
     /// # Features
-    ///   * `FEAT_REQ__KVS__default_values`
-    ///   * `FEAT_REQ__KVS__multiple_kvs`
-    ///   * `FEAT_REQ__KVS__integrity_check`
-    ///
-    /// # Parameters
-    ///   * `instance_id`: Instance ID
-    ///   * `need_defaults`: Fail when no default file was found
-    ///   * `need_kvs`: Fail when no KVS file was found
+    ///   * `FEAT_REQ__KVS__snapshots`
     ///
     /// # Return Values
-    ///   * Ok: KVS instance
-    ///   * `ErrorCode::ValidationFailed`: KVS hash validation failed
-    ///   * `ErrorCode::JsonParserError`: JSON parser error (invalid JSON or type error)
-    ///   * `ErrorCode::KvsFileReadError`: KVS file read error (I/O error)
-    ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error (hash file missing or unreadable)
-    ///   * `ErrorCode::UnmappedError`: Generic error
-    fn open(
-        instance_id: InstanceId,
-        need_defaults: OpenNeedDefaults,
-        need_kvs: OpenNeedKvs,
-        dir: Option<String>,
-    ) -> Result<GenericKvs<J>, ErrorCode> {
-        let dir = if let Some(dir) = dir {
-            format!("{dir}/")
-        } else {
-            "".to_string()
-        };
-        let filename_default = PathBuf::from(format!("{dir}kvs_{instance_id}_default"));
-        let filename_prefix = PathBuf::from(format!("{dir}kvs_{instance_id}"));
-        let filename_kvs =
-            filename_prefix.with_file_name(format!("{}_0", filename_prefix.display()));
+    ///   * Ok: Pruning successful, also if nothing was pruned
+    ///   * `ErrorCode::UnmappedError`: Snapshot file couldn't be removed
+    fn snapshot_prune(&self) -> Result<(), ErrorCode> {
+        let mut idx = self.retention.max_count + 1;
+        while self.snapshot_path(idx, "json").exists() {
+            self.snapshot_remove(idx)?;
+            idx += 1;
+        }
 
-        let default = GenericKvs::<J>::open_kvs(
-            &filename_default,
-            need_defaults,
-            OpenKvsVerifyHash::No,
-            None,
-        )?;
-        // Use hash checking for the main KVS file
-        let hash_path =
-            filename_prefix.with_file_name(format!("{}_0.hash", filename_prefix.display()));
-        let kvs = GenericKvs::<J>::open_kvs(
-            &filename_kvs,
-            need_kvs,
-            OpenKvsVerifyHash::Yes,
-            Some(&hash_path),
-        )?;
+        let mut snapshots: Vec<(usize, SystemTime, u64)> = (1..=self.retention.max_count)
+            .map_while(|idx| {
+                let meta = fs::metadata(self.snapshot_path(idx, "json")).ok()?;
+                let hash_size =
+                    fs::metadata(self.snapshot_path(idx, "hash")).map_or(0, |m| m.len());
+                Some((idx, meta.modified().ok()?, meta.len() + hash_size))
+            })
+            .collect();
+        let mut total_size: u64 = snapshots.iter().map(|(_, _, size)| size).sum();
+        let now = SystemTime::now();
+        while let Some(&(idx, modified, size)) = snapshots.last() {
+            let too_old = self
+                .retention
+                .max_age
+                .is_some_and(|age| now.duration_since(modified).unwrap_or_default() > age);
+            let over_budget = self
+                .retention
+                .max_total_size
+                .is_some_and(|budget| total_size > budget);
+            if !too_old && !over_budget {
+                break;
+            }
+            log_info!("pruning snapshot {idx}");
+            self.snapshot_remove(idx)?;
+            total_size -= size;
+            snapshots.pop();
+        }
+        Ok(())
+    }
 
-        println!("opened KVS: instance '{instance_id}'");
-        println!("max snapshot count: {KVS_MAX_SNAPSHOTS}");
+    /// Remove the files of a snapshot index
+    fn snapshot_remove(&self, idx: usize) -> Result<(), ErrorCode> {
+        for ext in ["json", "hash", "gen"] {
+            match fs::remove_file(self.snapshot_path(idx, ext)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 
-        Ok(GenericKvs {
-            kvs: Mutex::new(kvs),
-            default,
-            filename_prefix,
-            flush_on_exit: AtomicBool::new(true),
-            _backend: std::marker::PhantomData,
-        })
+    /// Read the generation of a snapshot index
+    ///
+    /// # Return Values
+    ///   * Generation, `None` if the generation file is missing or invalid
+    fn read_generation(&self, idx: usize) -> Option<SnapshotGeneration> {
+        fs::read_to_string(self.snapshot_path(idx, "gen"))
+            .ok()
+            .and_then(|text| text.trim().parse().ok())
+            .map(SnapshotGeneration)
     }
 
-    /// Control the flush on exit behaviour
+    /// Return the generation for the next KVS file
     ///
-    /// # Parameters
-    ///   * `flush_on_exit`: Flag to control flush-on-exit behaviour
-    fn flush_on_exit(&self, flush_on_exit: bool) {
-        self.flush_on_exit
-            .store(flush_on_exit, atomic::Ordering::Relaxed);
+    /// Must be called before the snapshots are rotated, so the oldest snapshot still counts.
+    fn next_generation(&self) -> SnapshotGeneration {
+        let newest = (0..=self.retention.max_count)
+            .filter_map(|idx| self.read_generation(idx))
+            .max()
+            .map_or(0, |generation| generation.0);
+        SnapshotGeneration(newest + 1)
     }
 
-    /// Resets a key-value-storage to its initial state
+    /// Journal a mutation in the write-ahead log
+    ///
+    /// Must be called with the KVS data locked, before the mutation is applied.
     ///
     /// # Return Values
-    ///   * Ok: Reset of the KVS was successful
-    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    fn reset(&self) -> Result<(), ErrorCode> {
-        *self.kvs.lock()? = HashMap::new();
-        Ok(())
+    ///   * Ok(`true`): Mutation journaled, the log must be compacted after applying it
+    ///   * Ok(`false`): Mutation journaled or no write-ahead log configured
+    ///   * `ErrorCode::PhysicalStorageFailure`: Mutation couldn't be journaled
+    fn wal_append(&self, record: WalRecord) -> Result<bool, ErrorCode> {
+        self.wal_append_with_lock(record, None)
     }
 
-    /// Get list of all keys
+    /// Journal a mutation like [`wal_append`](Self::wal_append), with the exclusive lock of the
+    /// merge journal held by the caller
+    fn wal_append_with_lock(
+        &self,
+        record: WalRecord,
+        journal_lock: Option<&mut JournalLock>,
+    ) -> Result<bool, ErrorCode> {
+        let size = record_size(&record);
+        match (&self.journal, journal_lock) {
+            (Some(journal), Some(lock)) => journal.append_locked(lock, &record)?,
+            (Some(journal), None) => journal.append(&record)?,
+            (None, _) => (),
+        }
+        let compact = match &self.wal {
+            Some(wal) => wal.append(record)?,
+            None => false,
+        };
+        self.write_stats.changed(size);
+        Ok(compact)
+    }
+
+    /// Return the size of a KVS file and its hash file in bytes
     ///
-    /// # Return Values
-    ///   * Ok: List of all keys
-    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
-        Ok(self.kvs.lock()?.keys().map(|x| x.to_string()).collect())
+    /// # Parameters
+    ///   * `prefix`: Filename prefix the files were saved with
+    fn saved_size(prefix: &Path) -> u64 {
+        [
+            path_with_suffix(prefix, "_0.json"),
+            path_with_suffix(prefix, "_0.hash"),
+        ]
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|meta| meta.len())
+        .sum()
     }
 
-    /// Check if a key exists
+    /// Count the bytes appended to the write-ahead log as written
+    fn collect_wal_written(&self) {
+        for log in [&self.wal, &self.delta].into_iter().flatten() {
+            self.write_stats.written(log.take_written());
+        }
+    }
+
+    /// Append the current state of the dirty keys to the delta journal
     ///
     /// # Parameters
-    ///   * `key`: Key to check for existence
+    ///   * `kvs`: Locked KVS data
     ///
     /// # Return Values
-    ///   * Ok(`true`): Key exists
-    ///   * Ok(`false`): Key doesn't exist
+    ///   * Ok: Keys journaled, no-op without delta journal or dirty keys
     ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
-        Ok(self.kvs.lock()?.contains_key(key))
+    ///   * `ErrorCode::PhysicalStorageFailure`: Delta journal couldn't be written
+    fn append_delta(&self, kvs: &KvsMap) -> Result<(), ErrorCode> {
+        let Some(delta) = &self.delta else {
+            return Ok(());
+        };
+        let ops: Vec<KvsOperation> = self
+            .dirty
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .iter()
+            .map(|key| match kvs.get(key) {
+                Some(value) => KvsOperation::Set(key.clone(), value.clone()),
+                None => KvsOperation::Remove(key.clone()),
+            })
+            .collect();
+        if ops.is_empty() {
+            return Ok(());
+        }
+        match &self.versions {
+            Some(versions) => {
+                let versions = ops
+                    .iter()
+                    .map(|op| {
+                        let key = match op {
+                            KvsOperation::Set(key, _) | KvsOperation::Remove(key) => key,
+                        };
+                        Ok((key.clone(), versions.get(key)?))
+                    })
+                    .collect::<Result<_, ErrorCode>>()?;
+                delta.append_versioned(WalRecord::Batch(&ops), versions)?;
+            }
+            None => {
+                delta.append(WalRecord::Batch(&ops))?;
+            }
+        }
+        Ok(())
     }
 
-    /// Get the assigned value for a given key
+    /// Write the KVS file and fold the delta journal into it
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__default_values`
+    /// A non-empty delta journal gets the dirty keys appended first, so replaying it after a
+    /// crash before its removal leads to the written data.
     ///
     /// # Parameters
-    ///   * `key`: Key to retrieve the value from
+    ///   * `kvs`: Locked KVS data
+    fn save_base(&self, kvs: &KvsMap) -> Result<(), ErrorCode> {
+        if self.delta.as_ref().is_some_and(|delta| !delta.is_empty()) {
+            self.append_delta(kvs)?;
+        }
+        self.save_kvs(kvs, &self.filename_prefix)?;
+        if let Some(delta) = &self.delta {
+            delta.truncate()?;
+        }
+        Ok(())
+    }
+
+    /// Write KVS data with the configured format and cipher
     ///
-    /// # Return Value
-    ///   * Ok: Type specific value if key was found
-    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
-    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
-        let kvs = self.kvs.lock()?;
+    /// # Parameters
+    ///   * `kvs`: KVS data
+    ///   * `prefix`: Filename prefix, `_0.json` and `_0.hash` are appended
+    fn save_kvs(&self, kvs: &KvsMap, prefix: &Path) -> Result<(), ErrorCode> {
+        let version = self.schema_version.load(atomic::Ordering::Relaxed);
+        let mut data = kvs_migration::with_version(kvs, version);
+        if let Some(versions) = &self.versions {
+            versions.store(data.to_mut())?;
+        }
+        self.defaults_hash.store(data.to_mut());
+        J::save_kvs_with_cipher(
+            &data,
+            prefix.to_path_buf(),
+            true,
+            self.storage_format,
+            self.compression,
+            self.durability,
+            self.cipher.as_deref(),
+        )
+    }
 
-        if let Some(value) = kvs.get(key) {
-            Ok(value.clone())
-        } else if let Some(value) = self.default.get(key) {
-            Ok(value.clone())
+    /// Fail operations that write the filesystem in ephemeral or read-only mode
+    fn check_persistent(&self) -> Result<(), ErrorCode> {
+        if self.ephemeral {
+            log_error!("operation isn't available for an ephemeral KVS");
+            Err(ErrorCode::PhysicalStorageFailure)
         } else {
-            eprintln!("error: get_value could not find key: {key}");
-            Err(ErrorCode::KeyNotFound)
+            self.check_writable()
         }
     }
 
-    /// Get the assigned value for a given key
-    ///
-    /// See [Variants](https://docs.rs/tinyjson/latest/tinyjson/enum.JsonValue.html#variants) for
-    /// supported value types.
+    /// Fail changes of the data in read-only mode
+    fn check_writable(&self) -> Result<(), ErrorCode> {
+        if self.read_only {
+            log_error!("operation isn't available for a read-only KVS");
+            Err(ErrorCode::PhysicalStorageFailure)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that the data can be changed and announce a writer at the gate
+    fn write_gate(&self) -> Result<Option<GateGuard<'_>>, ErrorCode> {
+        self.check_writable()?;
+        self.gate.write()
+    }
+
+    /// Serialize the KVS data into a buffer
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__default_values`
+    /// The buffer receives the content [`flush`](KvsApi::flush) would write to the KVS file, in
+    /// the configured storage format and compression and encrypted if a cipher is configured. Ephemeral
+    /// instances use this to hand their data to the caller.
     ///
     /// # Parameters
-    ///   * `key`: Key to retrieve the value from
+    ///   * `buffer`: Buffer to replace the content of
     ///
-    /// # Return Value
-    ///   * Ok: Type specific value if key was found
+    /// # Return Values
+    ///   * Ok: Data serialized
     ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    ///   * `ErrorCode::ConversionFailed`: Type conversion failed
-    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
-    fn get_value_as<T>(&self, key: &str) -> Result<T, ErrorCode>
-    where
-        for<'a> T: TryFrom<&'a KvsValue> + std::clone::Clone,
-        for<'a> <T as TryFrom<&'a KvsValue>>::Error: std::fmt::Debug,
-    {
+    ///   * `ErrorCode::JsonGeneratorError`: Data couldn't be serialized
+    ///   * `ErrorCode::EncryptionFailed`: Data couldn't be encrypted
+    pub fn flush_to(&self, buffer: &mut Vec<u8>) -> Result<(), ErrorCode> {
+        self.check_access_all(false)?;
         let kvs = self.kvs.lock()?;
-
-        if let Some(value) = kvs.get(key) {
-            match T::try_from(value) {
-                Ok(value) => Ok(value),
-                Err(err) => {
-                    eprintln!(
-                        "error: get_value could not convert KvsValue from KVS store: {err:#?}"
-                    );
-                    Err(ErrorCode::ConversionFailed)
-                }
-            }
-        } else if let Some(value) = self.default.get(key) {
-            // check if key has a default value
-            match T::try_from(value) {
-                Ok(value) => Ok(value),
-                Err(err) => {
-                    eprintln!(
-                        "error: get_value could not convert KvsValue from default store: {err:#?}"
-                    );
-                    Err(ErrorCode::ConversionFailed)
-                }
-            }
-        } else {
-            eprintln!("error: get_value could not find key: {key}");
-
-            Err(ErrorCode::KeyNotFound)
-        }
+        let version = self.schema_version.load(atomic::Ordering::Relaxed);
+        let data = kvs_compress::compress(
+            JsonBackend::serialize_kvs(
+                &kvs_migration::with_version(&kvs, version),
+                self.storage_format,
+            )?,
+            self.compression,
+        );
+        drop(kvs);
+        *buffer = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&data)?,
+            None => data,
+        };
+        Ok(())
     }
 
-    /// Get default value for a given key
+    /// Compact the write-ahead log into the KVS file
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__default_values`
-    ///   * `FEAT_REQ__KVS__default_value_retrieval`
+    /// Writes the current KVS file without snapshot rotation and truncates the log. A failed
+    /// compaction isn't an error as all mutations are still journaled, it's retried with the
+    /// next mutation.
     ///
     /// # Parameters
-    ///   * `key`: Key to get the default for
-    ///
-    /// # Return Values
-    ///   * Ok: `KvsValue` for the key
-    ///   * `ErrorCode::KeyNotFound`: Key not found in defaults
-    fn get_default_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
-        if let Some(value) = self.default.get(key) {
-            Ok(value.clone())
-        } else {
-            Err(ErrorCode::KeyNotFound)
+    ///   * `kvs`: Locked KVS data
+    fn wal_compact(&self, kvs: &KvsMap) {
+        if let Some(wal) = &self.wal {
+            let res = self
+                .save_base(kvs)
+                .inspect(|_| {
+                    self.write_stats
+                        .written(Self::saved_size(&self.filename_prefix))
+                })
+                .and_then(|_| wal.truncate());
+            if let Err(e) = res {
+                log_error!("write-ahead log compaction failed: {e:?}");
+            }
         }
     }
 
-    /// Return if the value wasn't set yet and uses its default value
+    /// Open the key-value-storage in a specific directory
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__default_values`
+    /// All files of the instance (defaults, KVS, hash, snapshot and write-ahead log files) are
+    /// placed in the directory, so instances can be put on different partitions, e.g. a
+    /// persistent partition and a tmpfs. Unlike [`open`](KvsApi::open) the directory must exist.
     ///
     /// # Parameters
-    ///   * `key`: Key to check if a default exists
+    ///   * `instance_id`: Instance ID
+    ///   * `dir`: Storage directory of the instance
+    ///   * `need_defaults`: Fail when no default file was found
+    ///   * `need_kvs`: Fail when no KVS file was found
     ///
     /// # Return Values
-    ///   * Ok(true): Key currently returns the default value
-    ///   * Ok(false): Key returns the set value
-    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    ///   * `ErrorCode::KeyNotFound`: Key wasn't found
-    fn is_value_default(&self, key: &str) -> Result<bool, ErrorCode> {
-        if self.kvs.lock()?.contains_key(key) {
-            Ok(false)
-        } else if self.default.contains_key(key) {
-            Ok(true)
-        } else {
-            Err(ErrorCode::KeyNotFound)
+    ///   * Ok: KVS instance
+    ///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+    ///   * `ErrorCode::ConversionFailed`: Directory path isn't valid UTF-8
+    ///   * Errors of [`open`](KvsApi::open)
+    pub fn open_with_dir<P: AsRef<Path>>(
+        instance_id: InstanceId,
+        dir: P,
+        need_defaults: OpenNeedDefaults,
+        need_kvs: OpenNeedKvs,
+    ) -> Result<Self, ErrorCode> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            log_error!("storage directory {dir:?} not found");
+            return Err(ErrorCode::FileNotFound);
         }
+        let dir = dir.to_str().ok_or(ErrorCode::ConversionFailed)?;
+        Self::open(instance_id, need_defaults, need_kvs, Some(dir.to_string()))
     }
 
-    /// Assign a value to a given key
+    /// Open a named key-value-storage instance
+    ///
+    /// Same as [`open`](KvsApi::open) with [`InstanceId::named`], which describes the naming of
+    /// the instance files.
     ///
     /// # Parameters
-    ///   * `key`: Key to set value
+    ///   * `name`: Instance name, e.g. the name of the owning component
+    ///   * `need_defaults`: Fail when no default file was found
+    ///   * `need_kvs`: Fail when no KVS file was found
+    ///   * `dir`: Directory of the instance files, the working directory if `None`
+    ///
+    /// # Return Values
+    ///   * Ok: KVS instance
+    ///   * Errors of [`open`](KvsApi::open)
+    pub fn open_named(
+        name: &str,
+        need_defaults: OpenNeedDefaults,
+        need_kvs: OpenNeedKvs,
+        dir: Option<String>,
+    ) -> Result<Self, ErrorCode> {
+        Self::open(InstanceId::named(name), need_defaults, need_kvs, dir)
+    }
+
+    /// Begin a transaction
+    ///
+    /// Operations staged on the returned transaction are applied atomically on
+    /// [`commit`](KvsTransaction::commit).
+    ///
+    /// # Return Values
+    ///   * Empty transaction bound to this KVS
+    pub fn begin_transaction(&self) -> KvsTransaction<'_, J> {
+        KvsTransaction::new(self)
+    }
+
+    /// Register a cross-key consistency rule
+    ///
+    /// The rule is evaluated on every transaction commit against the state after the commit and
+    /// on flush, see [`kvs_rules`](crate::kvs_rules). A rule with the same name is replaced.
+    ///
+    /// # Parameters
+    ///   * `name`: Rule name used in violation reports
+    ///   * `rule`: Check returning a violation message for inconsistent states
+    ///
+    /// # Return Values
+    ///   * Ok: Rule registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn add_rule<S, F>(&self, name: S, rule: F) -> Result<(), ErrorCode>
+    where
+        S: Into<String>,
+        F: Fn(&RuleContext) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.rules
+            .add(name.into(), std::sync::Arc::new(rule) as KvsRule)
+    }
+
+    /// Assign a value to a key and annotate the change with a reason
+    ///
+    /// Behaves like [`set_value`](KvsApi::set_value). The reason is passed to observers in
+    /// [`KvsEvent::Set`], so an audit observer can record why a value changed.
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set value
     ///   * `value`: Value to be set
+    ///   * `reason`: Change reason, e.g. `"calibration update 2025-07"`
     ///
     /// # Return Values
     ///   * Ok: Value was assigned to key
     ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    fn set_value<S: Into<String>, V: Into<KvsValue>>(
+    ///   * `ErrorCode::ValidationFailed`: Key has no default in strict mode or value rejected by a validator
+    pub fn set_value_with_reason<S, V, R>(
         &self,
         key: S,
         value: V,
+        reason: R,
+    ) -> Result<(), ErrorCode>
+    where
+        S: Into<String>,
+        V: Into<KvsValue>,
+        R: Into<String>,
+    {
+        self.set_value_annotated(key.into(), value.into(), Some(reason.into()))
+    }
+
+    /// Assign a value to a key, notifying observers with an optional change reason
+    fn set_value_annotated(
+        &self,
+        key: String,
+        value: KvsValue,
+        reason: Option<String>,
     ) -> Result<(), ErrorCode> {
-        self.kvs.lock()?.insert(key.into(), value.into());
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        self.check_value(&key, &value)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let events = self.commit_set(&mut kvs, key, value, reason)?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
         Ok(())
     }
 
-    /// Remove a key
+    /// Write a checked value of a key to the write-ahead log and the KVS data
+    ///
+    /// Called with the write gate and the lock of `kvs` held. Returns the events to notify the
+    /// observers of after both are released.
+    fn commit_set(
+        &self,
+        kvs: &mut KvsMap,
+        key: String,
+        value: KvsValue,
+        reason: Option<String>,
+    ) -> Result<Vec<KvsEvent>, ErrorCode> {
+        self.commit_set_with_lock(kvs, key, value, reason, None)
+    }
+
+    /// Write a checked value like [`commit_set`](Self::commit_set), with the exclusive lock of
+    /// the merge journal held by the caller
+    fn commit_set_with_lock(
+        &self,
+        kvs: &mut KvsMap,
+        key: String,
+        value: KvsValue,
+        reason: Option<String>,
+        journal_lock: Option<&mut JournalLock>,
+    ) -> Result<Vec<KvsEvent>, ErrorCode> {
+        let compact = self.wal_append_with_lock(WalRecord::Set(&key, &value), journal_lock)?;
+        let events = if self.observers.is_empty() {
+            Vec::new()
+        } else {
+            vec![KvsEvent::Set {
+                key: key.clone(),
+                value: value.clone(),
+                reason,
+            }]
+        };
+        self.record_write(&key);
+        kvs.insert(key, value);
+        if compact {
+            self.wal_compact(kvs);
+        }
+        Ok(events)
+    }
+
+    /// Get the value of a key together with its version
+    ///
+    /// The version is the input for [`set_value_if_version`](Self::set_value_if_version). A key
+    /// that was never changed has version 0 and returns its default value. Overrides of
+    /// diagnostics sessions aren't considered. Only available with
+    /// [`KvsOptions::key_versions`].
     ///
     /// # Parameters
-    ///   * `key`: Key to remove
+    ///   * `key`: Key to retrieve the value from
     ///
     /// # Return Values
-    ///   * Ok: Key removed successfully
+    ///   * Ok: Value and version of the key
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
+    ///   * `ErrorCode::ValidationFailed`: Key versions aren't enabled
     ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    ///   * `ErrorCode::KeyNotFound`: Key not found
-    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
-        if self.kvs.lock()?.remove(key).is_some() {
-            Ok(())
+    pub fn get_value_with_version(&self, key: &str) -> Result<(KvsValue, u64), ErrorCode> {
+        self.check_read(key)?;
+        let versions = self.key_versions()?;
+        let (stored, version) = {
+            let _gate = self.gate.read()?;
+            let kvs = self.kvs.lock()?;
+            (kvs.get(key).cloned(), versions.get(key)?)
+        };
+        self.count_read(key);
+        let value = match stored {
+            Some(value) => value,
+            None => self.lookup_default(key)?.ok_or(ErrorCode::KeyNotFound)?,
+        };
+        Ok((value, version))
+    }
+
+    /// Assign a value to a key if the key wasn't changed since its version was read
+    ///
+    /// With a merge journal the records of the other processes are merged first, the version is
+    /// compared and the value appended while the journal is locked, so concurrent conditional
+    /// writes of cooperating processes can't both succeed. Only available with
+    /// [`KvsOptions::key_versions`].
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set value
+    ///   * `value`: Value to be set
+    ///   * `expected_version`: Version returned by
+    ///     [`get_value_with_version`](Self::get_value_with_version)
+    ///
+    /// # Return Values
+    ///   * Ok: New version of the key
+    ///   * `ErrorCode::VersionConflict`: Key was changed since, also by another process sharing
+    ///     the merge journal
+    ///   * `ErrorCode::ValidationFailed`: Key versions aren't enabled, key not declared in the
+    ///     defaults in strict mode or value rejected by a validator
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log or merge journal couldn't be
+    ///     written
+    pub fn set_value_if_version<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        value: V,
+        expected_version: u64,
+    ) -> Result<u64, ErrorCode> {
+        let key = key.into();
+        let value = value.into();
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        self.check_value(&key, &value)?;
+        let versions = self.key_versions()?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        // Changes of other processes are merged and the record appended under one journal lock
+        let mut events = Vec::new();
+        let mut journal_lock = match &self.journal {
+            Some(journal) => {
+                let mut lock = journal.lock(true)?;
+                let read = journal.read(&mut lock)?;
+                events = self.apply_journal(&mut kvs, read)?.1;
+                Some(lock)
+            }
+            None => None,
+        };
+        let result = if versions.get(&key)? == expected_version {
+            events.extend(self.commit_set_with_lock(
+                &mut kvs,
+                key.clone(),
+                value,
+                None,
+                journal_lock.as_mut(),
+            )?);
+            versions.get(&key)
         } else {
-            Err(ErrorCode::KeyNotFound)
+            Err(ErrorCode::VersionConflict)
+        };
+        drop(journal_lock);
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        result
+    }
+
+    /// Return the version counters
+    ///
+    /// # Return Values
+    ///   * Ok: Version counters
+    ///   * `ErrorCode::ValidationFailed`: Key versions aren't enabled
+    fn key_versions(&self) -> Result<&KeyVersions, ErrorCode> {
+        self.versions.as_ref().ok_or_else(|| {
+            log_error!("key versions aren't enabled");
+            ErrorCode::ValidationFailed
+        })
+    }
+
+    /// Assign a value to a key if its current value equals an expected value
+    ///
+    /// Comparison and assignment happen atomically under the lock of the KVS data, so threads
+    /// sharing an instance can coordinate without a lock of their own. The current value is the
+    /// stored value or the static default, overrides and provider defaults aren't considered.
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set value
+    ///   * `expected`: Expected current value, `None` if the key is expected to have no value
+    ///   * `value`: Value to be set
+    ///
+    /// # Return Values
+    ///   * Ok: `true` if the value was set, `false` if the current value differs
+    ///   * `ErrorCode::ValidationFailed`: Key not declared in the defaults in strict mode or value
+    ///     rejected by a validator
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn compare_and_set<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        expected: Option<&KvsValue>,
+        value: V,
+    ) -> Result<bool, ErrorCode> {
+        let key = key.into();
+        let value = value.into();
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        self.check_value(&key, &value)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        if kvs.get(&key).or_else(|| self.default.get(&key)) != expected {
+            return Ok(false);
         }
+        let events = self.commit_set(&mut kvs, key, value, None)?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(true)
     }
 
-    /// Flush the in-memory key-value-storage to the persistent storage
+    /// Add a delta to the numeric value of a key
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__snapshots`
-    ///   * `FEAT_REQ__KVS__persistency`
-    ///   * `FEAT_REQ__KVS__integrity_check`
+    /// Reading, adding and assigning happen atomically under the lock of the KVS data, so
+    /// concurrent increments aren't lost. The current value is the stored value or the static
+    /// default and keeps its type: `I64` and `U64` values are incremented without loss and fail
+    /// on overflow, a `Number` delta must be integral for them. A key without a value is created
+    /// with the delta.
+    ///
+    /// # Parameters
+    ///   * `key`: Key of the counter
+    ///   * `delta`: Numeric delta, negative to decrement
     ///
     /// # Return Values
-    ///   * Ok: Flush successful
+    ///   * Ok: New value
+    ///   * `ErrorCode::ConversionFailed`: Value or delta isn't numeric or the result overflows
+    ///   * `ErrorCode::ValidationFailed`: Key not declared in the defaults in strict mode or value
+    ///     rejected by a validator
     ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
-    ///   * `ErrorCode::JsonGeneratorError`: Failed to serialize to JSON
-    ///   * `ErrorCode::ConversionFailed`: JSON could not serialize into String
-    ///   * `ErrorCode::UnmappedError`: Unmapped error
-    fn flush(&self) -> Result<(), ErrorCode> {
-        self.snapshot_rotate().map_err(|e| {
-            eprintln!("error: snapshot_rotate failed: {e:?}");
-            e
-        })?;
-        let kvs = self.kvs.lock().map_err(|e| {
-            eprintln!("error: Mutex lock failed: {e:?}");
-            ErrorCode::MutexLockFailed
-        })?;
-        J::save_kvs(&kvs, self.filename_prefix.clone(), true).map_err(|e| {
-            eprintln!("error: save_kvs failed: {e:?}");
-            e
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn increment<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        delta: V,
+    ) -> Result<KvsValue, ErrorCode> {
+        let key = key.into();
+        let delta = delta.into();
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let value = match kvs.get(&key).or_else(|| self.default.get(&key)) {
+            Some(current) => current.checked_add(&delta),
+            None => delta.checked_add(&KvsValue::I64(0)),
+        }
+        .ok_or_else(|| {
+            log_error!("value of {key} can't be incremented by {delta:?}");
+            ErrorCode::ConversionFailed
         })?;
-        Ok(())
+        self.check_value(&key, &value)?;
+        let events = self.commit_set(&mut kvs, key, value.clone(), None)?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(value)
     }
 
-    /// Get the count of snapshots
+    /// Append a value to the array of a key
+    ///
+    /// Appending happens atomically under the lock of the KVS data without copying the array to
+    /// the caller. The current array is the stored value or the static default, a key without a
+    /// value is created with an array of the value. With a maximum length the array is a ring
+    /// buffer that drops its oldest elements.
+    ///
+    /// # Parameters
+    ///   * `key`: Key of the array
+    ///   * `value`: Value to append
+    ///   * `max_len`: Maximum length of the array, `None` for no limit
     ///
     /// # Return Values
-    ///   * usize: Count of found snapshots
-    fn snapshot_count(&self) -> usize {
-        let mut count = 0;
-
-        for idx in 0..KVS_MAX_SNAPSHOTS {
-            let snapshot_path = self.filename_prefix.with_file_name(format!(
-                "{}_{}.json",
-                self.filename_prefix.display(),
-                idx
-            ));
-            if !snapshot_path.exists() {
-                break;
+    ///   * Ok: Length of the array after appending
+    ///   * `ErrorCode::ConversionFailed`: Value of the key isn't an array
+    ///   * `ErrorCode::ValidationFailed`: Key not declared in the defaults in strict mode, array
+    ///     rejected by a validator or maximum length of 0
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn array_append<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        value: V,
+        max_len: Option<usize>,
+    ) -> Result<usize, ErrorCode> {
+        let key = key.into();
+        if max_len == Some(0) {
+            log_error!("array {key} can't have a maximum length of 0");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let mut array = match kvs.get(&key).or_else(|| self.default.get(&key)) {
+            Some(KvsValue::Array(array)) => array.clone(),
+            Some(_) => {
+                log_error!("value of {key} isn't an array");
+                return Err(ErrorCode::ConversionFailed);
             }
-
-            count += 1;
+            None => Vec::new(),
+        };
+        array.push(value.into());
+        if let Some(max_len) = max_len {
+            array.drain(..array.len().saturating_sub(max_len));
         }
+        let len = array.len();
+        let array = KvsValue::Array(array);
+        self.check_value(&key, &array)?;
+        let events = self.commit_set(&mut kvs, key, array, None)?;
+        drop(kvs);
+        drop(gate);
 
-        count
+        self.observers.notify(&events);
+        Ok(len)
     }
 
-    /// Return maximum snapshot count
+    /// Update an object value with a JSON merge patch (RFC 7386)
+    ///
+    /// Members of an object patch replace the members of the current value recursively, `Null`
+    /// members remove them, so single fields of a large object are changed without passing the
+    /// whole object. Any other patch replaces the value. The current value is the stored value or
+    /// the static default, the update happens atomically under the lock of the KVS data.
+    ///
+    /// # Parameters
+    ///   * `key`: Key of the object
+    ///   * `patch`: Merge patch
     ///
     /// # Return Values
-    ///   * usize: Maximum count of snapshots
-    fn snapshot_max_count() -> usize {
-        KVS_MAX_SNAPSHOTS
+    ///   * Ok: Value after the update
+    ///   * `ErrorCode::ValidationFailed`: Key not declared in the defaults in strict mode or value
+    ///     rejected by a validator
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn merge_value<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        patch: V,
+    ) -> Result<KvsValue, ErrorCode> {
+        let key = key.into();
+        let patch = patch.into();
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let current = kvs.get(&key).or_else(|| self.default.get(&key)).cloned();
+        let value = current.unwrap_or(KvsValue::Null).merge_patch(&patch);
+        self.check_value(&key, &value)?;
+        let events = self.commit_set(&mut kvs, key, value.clone(), None)?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(value)
     }
 
-    /// Recover key-value-storage from snapshot
-    ///
-    /// Restore a previously created KVS snapshot.
+    /// Get a nested value by a JSON Pointer (RFC 6901)
     ///
-    /// # Features
-    ///   * `FEAT_REQ__KVS__snapshots`
+    /// Resolves the value of the key like [`get_value`](KvsApi::get_value) without copying it and
+    /// returns only the referenced part, e.g. `/network/eth0/mtu`. In tokens `~1` stands for `/`
+    /// and `~0` for `~`, array elements are referenced by their index.
     ///
     /// # Parameters
-    ///   * `id`: Snapshot ID
+    ///   * `key`: Key to retrieve the value from
+    ///   * `pointer`: JSON Pointer into the value, empty for the whole value
     ///
     /// # Return Values
-    ///   * `Ok`: Snapshot restored
-    ///   * `ErrorCode::InvalidSnapshotId`: Invalid snapshot ID
-    ///   * `ErrorCode::ValidationFailed`: KVS hash validation failed
-    ///   * `ErrorCode::JsonParserError`: JSON parser error
-    ///   * `ErrorCode::KvsFileReadError`: KVS file not found
-    ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error
-    ///   * `ErrorCode::UnmappedError`: Generic error
-    fn snapshot_restore(&self, id: SnapshotId) -> Result<(), ErrorCode> {
-        // fail if the snapshot ID is the current KVS
-        if id.0 == 0 {
-            eprintln!("error: tried to restore current KVS as snapshot");
-            return Err(ErrorCode::InvalidSnapshotId);
-        }
+    ///   * Ok: Referenced value
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults or the referenced
+    ///     member or element doesn't exist
+    ///   * `ErrorCode::ConversionFailed`: Pointer references into a value that isn't a container
+    ///   * `ErrorCode::ValidationFailed`: Invalid pointer
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_value_at_path(&self, key: &str, pointer: &str) -> Result<KvsValue, ErrorCode> {
+        let value = self.get_value_ref(key)?;
+        kvs_pointer::get(&value, pointer).cloned()
+    }
 
-        if self.snapshot_count() < id.0 {
-            eprintln!("error: tried to restore a non-existing snapshot");
-            return Err(ErrorCode::InvalidSnapshotId);
+    /// Set a nested value by a JSON Pointer (RFC 6901)
+    ///
+    /// The container of the referenced value must exist: members are added to objects, array
+    /// elements are replaced or appended with the index of the length or `-`. The current value
+    /// is the stored value or the static default, the update happens atomically under the lock
+    /// of the KVS data.
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set the value of
+    ///   * `pointer`: JSON Pointer into the value, empty for the whole value
+    ///   * `value`: Value to be set
+    ///
+    /// # Return Values
+    ///   * Ok: Value set
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults or the container
+    ///     doesn't exist
+    ///   * `ErrorCode::ConversionFailed`: Pointer references into a value that isn't a container
+    ///   * `ErrorCode::ValidationFailed`: Invalid pointer, key not declared in the defaults in
+    ///     strict mode or value rejected by a validator
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn set_value_at_path<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        pointer: &str,
+        value: V,
+    ) -> Result<(), ErrorCode> {
+        let key = key.into();
+        if pointer.is_empty() {
+            return self.set_value(key, value);
         }
+        self.check_write(&key)?;
+        self.check_key_declared(&key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let mut updated = kvs
+            .get(&key)
+            .or_else(|| self.default.get(&key))
+            .cloned()
+            .ok_or(ErrorCode::KeyNotFound)?;
+        kvs_pointer::set(&mut updated, pointer, value.into())?;
+        self.check_value(&key, &updated)?;
+        let events = self.commit_set(&mut kvs, key, updated, None)?;
+        drop(kvs);
+        drop(gate);
 
-        let snap_path = PathBuf::from(format!("{}_{}", self.filename_prefix.display(), id.0));
-        let kvs = Self::open_kvs(
-            &snap_path,
-            OpenKvsNeedFile::Required,
-            OpenKvsVerifyHash::Yes,
-            None,
-        )?;
-        *self.kvs.lock()? = kvs;
-
+        self.observers.notify(&events);
         Ok(())
     }
 
-    /// Return the KVS-filename for a given snapshot ID
+    /// Return the length of the array of a key without copying it
     ///
     /// # Parameters
-    ///   * `id`: Snapshot ID to get the filename for
+    ///   * `key`: Key of the array
     ///
     /// # Return Values
-    ///   * `Ok`: Filename for ID
-    ///   * `ErrorCode::FileNotFound`: KVS file for snapshot ID not found
-    fn get_kvs_filename(&self, id: SnapshotId) -> Result<PathBuf, ErrorCode> {
-        let path = self.filename_prefix.with_file_name(format!(
-            "{}_{}.json",
-            self.filename_prefix.display(),
-            id
-        ));
-        if !path.exists() {
-            Err(ErrorCode::FileNotFound)
-        } else {
-            Ok(path)
+    ///   * Ok: Length of the stored array or of the static default
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
+    ///   * `ErrorCode::ConversionFailed`: Value of the key isn't an array
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn array_len(&self, key: &str) -> Result<usize, ErrorCode> {
+        self.check_read(key)?;
+        let _gate = self.gate.read()?;
+        let kvs = self.kvs.lock()?;
+        match kvs.get(key).or_else(|| self.default.get(key)) {
+            Some(KvsValue::Array(array)) => Ok(array.len()),
+            Some(_) => Err(ErrorCode::ConversionFailed),
+            None => Err(ErrorCode::KeyNotFound),
         }
     }
 
-    /// Return the hash-filename for a given snapshot ID
+    /// Register a validator for the values of matching keys
+    ///
+    /// The validator is called with every new value of a key matching the glob pattern, see
+    /// [`kvs_validator`](crate::kvs_validator). A validator with the same pattern is replaced.
+    /// Already stored values aren't checked.
     ///
     /// # Parameters
-    ///   * `id`: Snapshot ID to get the hash filename for
+    ///   * `pattern`: Glob key pattern, e.g. `audio/*`
+    ///   * `validator`: Check returning a message for rejected values
     ///
     /// # Return Values
-    ///   * `Ok`: Hash filename for ID
-    ///   * `ErrorCode::FileNotFound`: Hash file for snapshot ID not found
-    fn get_hash_filename(&self, id: SnapshotId) -> Result<PathBuf, ErrorCode> {
-        let path = self.filename_prefix.with_file_name(format!(
-            "{}_{}.hash",
-            self.filename_prefix.display(),
-            id
-        ));
-        if !path.exists() {
-            Err(ErrorCode::FileNotFound)
-        } else {
-            Ok(path)
-        }
+    ///   * Ok: Validator registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn register_validator<S, F>(&self, pattern: S, validator: F) -> Result<(), ErrorCode>
+    where
+        S: Into<String>,
+        F: Fn(&KvsValue) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.validators.add(
+            pattern.into(),
+            std::sync::Arc::new(validator) as KvsValidator,
+        )
     }
-}
 
-impl<J: KvsBackend> Drop for GenericKvs<J> {
-    fn drop(&mut self) {
-        if self.flush_on_exit.load(atomic::Ordering::Relaxed) {
-            if let Err(e) = self.flush() {
-                eprintln!("GenericKvs::flush() failed in Drop: {e:?}");
-            }
-        }
+    /// Remove the validator of a key pattern
+    ///
+    /// # Return Values
+    ///   * Ok(`true`): Validator removed
+    ///   * Ok(`false`): No validator registered for this pattern
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn unregister_validator(&self, pattern: &str) -> Result<bool, ErrorCode> {
+        self.validators.remove(pattern)
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Remove a consistency rule
+    ///
+    /// # Return Values
+    ///   * Ok(`true`): Rule removed
+    ///   * Ok(`false`): No rule with this name registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn remove_rule(&self, name: &str) -> Result<bool, ErrorCode> {
+        self.rules.remove(name)
+    }
 
-    use super::*;
-    use crate::Kvs;
-    use tempfile::tempdir;
+    /// Evaluate all consistency rules against the current state
+    ///
+    /// # Return Values
+    ///   * Ok: Violations in registration order, empty if the state is consistent
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn check_rules(&self) -> Result<Vec<RuleViolation>, ErrorCode> {
+        self.rule_violations(&[])
+    }
 
-    mod mock_backend {
-        use super::*;
-        use crate::kvs_backend::KvsBackend;
+    /// Evaluate all consistency rules against the state after applying operations
+    pub(crate) fn rule_violations(
+        &self,
+        ops: &[KvsOperation],
+    ) -> Result<Vec<RuleViolation>, ErrorCode> {
+        let kvs = self.kvs.lock()?;
+        self.rule_violations_after(&kvs, ops)
+    }
 
-        #[derive(Default, Clone)]
-        pub struct KvsMockBackend {}
+    /// Evaluate all consistency rules against the locked data after applying operations
+    ///
+    /// The data is only copied if rules are registered.
+    ///
+    /// # Parameters
+    ///   * `kvs`: Locked KVS data
+    ///   * `ops`: Operations to apply to a copy of the data before the evaluation
+    fn rule_violations_after(
+        &self,
+        kvs: &KvsMap,
+        ops: &[KvsOperation],
+    ) -> Result<Vec<RuleViolation>, ErrorCode> {
+        if self.rules.is_empty()? {
+            return Ok(Vec::new());
+        }
+        if ops.is_empty() {
+            return self.rules.evaluate(&RuleContext::new(kvs, &self.default));
+        }
 
-        impl KvsBackend for KvsMockBackend {
-            fn load_kvs(
-                source_path: PathBuf,
-                _verify_hash: bool,
-                _hash_source: Option<PathBuf>,
-            ) -> Result<KvsMap, ErrorCode> {
-                let mut map = KvsMap::new();
-                let fname = source_path.display().to_string();
-                if fname.ends_with("default") {
-                    map.insert("mock_default_key".to_string(), KvsValue::from(111.0));
-                } else {
-                    map.insert("mock_key".to_string(), KvsValue::from(123.0));
+        let mut data = kvs.clone();
+        for op in ops {
+            match op {
+                KvsOperation::Set(key, value) => {
+                    data.insert(key.clone(), value.clone());
                 }
-                Ok(map)
+                KvsOperation::Remove(key) => {
+                    data.remove(key);
+                }
+            }
+        }
+        self.rules.evaluate(&RuleContext::new(&data, &self.default))
+    }
+
+    /// Evaluate a feature flag for a device
+    ///
+    /// The flag value is read like with [`get_value`](KvsApi::get_value), so defaults apply. See
+    /// [`kvs_flags`](crate::kvs_flags) for the supported flag values.
+    ///
+    /// # Parameters
+    ///   * `key`: Flag key
+    ///   * `device_id`: Stable ID of the device, e.g. the VIN
+    ///
+    /// # Return Values
+    ///   * Ok: Flag is enabled (`true`) or disabled (`false`) for the device
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Flag wasn't found in KVS nor in defaults
+    ///   * `ErrorCode::ConversionFailed`: Value isn't a valid flag
+    pub fn evaluate_flag(&self, key: &str, device_id: &str) -> Result<bool, ErrorCode> {
+        kvs_flags::evaluate(key, &self.get_value(key)?, device_id)
+    }
+
+    /// Return a view of the KVS scoped to a namespace
+    ///
+    /// All keys accessed through the view are prefixed with `<name>/`, see
+    /// [`kvs_namespace`](crate::kvs_namespace).
+    ///
+    /// # Parameters
+    ///   * `name`: Namespace name, e.g. the component name
+    pub fn namespace(&self, name: &str) -> KvsNamespace<'_, J> {
+        KvsNamespace::new(self, "", name)
+    }
+
+    /// Return the count of stored keys without copying them
+    ///
+    /// Like [`get_all_keys`](KvsApi::get_all_keys) only stored keys are counted, not defaults,
+    /// and keys the identity can't read aren't counted.
+    ///
+    /// # Return Values
+    ///   * Ok: Count of stored keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn key_count(&self) -> Result<usize, ErrorCode> {
+        Ok(self
+            .kvs
+            .lock()?
+            .keys()
+            .filter(|key| self.readable(key))
+            .count())
+    }
+
+    /// Return the count of stored keys starting with a prefix without copying them
+    ///
+    /// The data is unordered, so all keys are compared. Keys the identity can't read aren't
+    /// counted.
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix, e.g. `diagnostics/`
+    ///
+    /// # Return Values
+    ///   * Ok: Count of matching keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn key_count_prefix(&self, prefix: &str) -> Result<usize, ErrorCode> {
+        Ok(self
+            .kvs
+            .lock()?
+            .keys()
+            .filter(|key| key.starts_with(prefix) && self.readable(key))
+            .count())
+    }
+
+    /// Return the size of the stored data serialized in the storage format in bytes
+    ///
+    /// Same as [`KvsStats::serialized_size`], but no snapshot files are read, so it's cheap
+    /// enough to poll the growth of the store. The size is before compression and encryption.
+    ///
+    /// # Return Values
+    ///   * Ok: Serialized size
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonGeneratorError`: Data couldn't be serialized
+    ///   * `ErrorCode::PermissionDenied`: Data is partly protected against the identity
+    pub fn serialized_size(&self) -> Result<u64, ErrorCode> {
+        self.check_access_all(false)?;
+        let kvs = self.kvs.lock()?;
+        Ok(JsonBackend::serialize_kvs(&kvs, self.storage_format)?.len() as u64)
+    }
+
+    /// Return if any stored key starts with a prefix
+    ///
+    /// Stops at the first matching key. Keys the identity can't read aren't considered.
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix, e.g. `diagnostics/`
+    ///
+    /// # Return Values
+    ///   * Ok: `true` if a matching key is stored
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn any_key_with_prefix(&self, prefix: &str) -> Result<bool, ErrorCode> {
+        Ok(self
+            .kvs
+            .lock()?
+            .keys()
+            .any(|key| key.starts_with(prefix) && self.readable(key)))
+    }
+
+    /// Get the stored keys starting with a prefix
+    ///
+    /// Like [`get_all_keys`](KvsApi::get_all_keys) only stored keys are returned, not defaults.
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix, e.g. `diagnostics/`
+    ///
+    /// # Return Values
+    ///   * Ok: Matching keys sorted by key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_keys_with_prefix(&self, prefix: &str) -> Result<Vec<String>, ErrorCode> {
+        let mut keys: Vec<String> = self
+            .kvs
+            .lock()?
+            .keys()
+            .filter(|key| key.starts_with(prefix) && self.readable(key))
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Get the stored keys matching a glob pattern
+    ///
+    /// `*` matches any sequence of characters, `?` a single character. Like
+    /// [`get_all_keys`](KvsApi::get_all_keys) only stored keys are returned, not defaults.
+    ///
+    /// # Parameters
+    ///   * `pattern`: Glob pattern, e.g. `diagnostics/*/count`
+    ///
+    /// # Return Values
+    ///   * Ok: Matching keys sorted by key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_keys_matching(&self, pattern: &str) -> Result<Vec<String>, ErrorCode> {
+        let mut keys: Vec<String> = self
+            .kvs
+            .lock()?
+            .keys()
+            .filter(|key| glob_matches(pattern, key) && self.readable(key))
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Remove all stored keys starting with a prefix
+    ///
+    /// The keys are removed under a single lock, so a concurrent flush persists either all or none
+    /// of the removals. Observers get a single [`KvsEvent::PrefixRemoved`] event. Defaults aren't
+    /// affected.
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix, e.g. `diagnostics/`
+    ///
+    /// # Return Values
+    ///   * Ok: Count of removed keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    pub fn remove_prefix(&self, prefix: &str) -> Result<usize, ErrorCode> {
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let keys: Vec<String> = kvs
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        for key in keys.iter() {
+            if let Err(e) = self.access.check(self.identity.as_deref(), key, true) {
+                drop(kvs);
+                drop(gate);
+                self.report_denied_write(key);
+                return Err(e);
+            }
+        }
+
+        let ops: Vec<KvsOperation> = keys.iter().cloned().map(KvsOperation::Remove).collect();
+        let compact = self.wal_append(WalRecord::Batch(&ops))?;
+        for key in keys.iter() {
+            self.record_write(key);
+            kvs.remove(key);
+        }
+        if compact {
+            self.wal_compact(&kvs);
+        }
+        drop(kvs);
+        drop(gate);
+
+        let count = keys.len();
+        self.observers.notify(&[KvsEvent::PrefixRemoved {
+            prefix: prefix.to_string(),
+            keys,
+        }]);
+        Ok(count)
+    }
+
+    /// Open a cursor over the stored keys and values
+    ///
+    /// The KVS data stays locked until the cursor is dropped, see
+    /// [`kvs_cursor`](crate::kvs_cursor). Calling into the KVS from the same thread while the
+    /// cursor exists deadlocks.
+    ///
+    /// # Return Values
+    ///   * Ok: Cursor over the stored data
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn cursor(&self) -> Result<KvsCursor<'_>, ErrorCode> {
+        self.check_access_all(false)?;
+        Ok(KvsCursor::new(self.kvs.lock()?))
+    }
+
+    /// Get all stored keys with their values in one call
+    ///
+    /// Like [`get_all_keys`](KvsApi::get_all_keys) defaults aren't included. Unlike a
+    /// [`cursor`](Self::cursor) the data is copied, so the lock is only held while copying.
+    ///
+    /// # Return Values
+    ///   * Ok: Key-value pairs sorted by key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_all_entries(&self) -> Result<Vec<(String, KvsValue)>, ErrorCode> {
+        let _gate = self.gate.read()?;
+        let mut entries: Vec<(String, KvsValue)> = self
+            .kvs
+            .lock()?
+            .iter()
+            .filter(|(key, _)| self.readable(key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
+    }
+
+    /// Assign values to several keys under a single lock
+    ///
+    /// The values are journaled as one record and applied atomically, like a transaction with
+    /// only sets. Restoring many parameters this way avoids a lock acquisition per key.
+    ///
+    /// # Parameters
+    ///   * `entries`: Key-value pairs, later entries win for duplicate keys
+    ///
+    /// # Return Values
+    ///   * Ok: All values set
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: A key has no default value in strict mode or a value is
+    ///     rejected by a validator
+    pub fn set_values<I, S, V>(&self, entries: I) -> Result<(), ErrorCode>
+    where
+        I: IntoIterator<Item = (S, V)>,
+        S: Into<String>,
+        V: Into<KvsValue>,
+    {
+        self.apply_operations(
+            entries
+                .into_iter()
+                .map(|(key, value)| KvsOperation::Set(key.into(), value.into()))
+                .collect(),
+        )
+    }
+
+    /// Return the values of several keys read under a single lock
+    ///
+    /// Keys without a value fall back to their default like [`get_value`](KvsApi::get_value).
+    ///
+    /// # Parameters
+    ///   * `keys`: Keys to read
+    ///
+    /// # Return Values
+    ///   * Ok: Values in the order of `keys`, `None` for keys neither in the KVS nor in defaults
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_values(&self, keys: &[&str]) -> Result<Vec<Option<KvsValue>>, ErrorCode> {
+        let values = keys
+            .iter()
+            .map(|key| self.current_value(key))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        keys.iter()
+            .zip(values)
+            .map(|(key, value)| {
+                self.count_read(key);
+                match value {
+                    Some(value) => Ok(Some(value)),
+                    None => self.lookup_default(key),
+                }
+            })
+            .collect()
+    }
+
+    /// Apply a list of operations under a single lock
+    ///
+    /// All operations are validated before the first one is applied.
+    ///
+    /// # Return Values
+    ///   * Ok: All operations applied
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: A remove refers to a key that doesn't exist
+    pub(crate) fn apply_operations(&self, ops: Vec<KvsOperation>) -> Result<(), ErrorCode> {
+        self.apply_built_operations(|_| Ok(ops)).map(|_| ())
+    }
+
+    /// Build a list of operations from the locked data and apply it under the same lock
+    ///
+    /// Nothing is written when no operations are built.
+    ///
+    /// # Parameters
+    ///   * `build`: Creates the operations from the current data
+    ///
+    /// # Return Values
+    ///   * Ok: Count of applied operations
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: A remove refers to a key that doesn't exist
+    fn apply_built_operations<F>(&self, build: F) -> Result<usize, ErrorCode>
+    where
+        F: FnOnce(&KvsMap) -> Result<Vec<KvsOperation>, ErrorCode>,
+    {
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let ops = build(&kvs)?;
+        if ops.is_empty() {
+            return Ok(0);
+        }
+
+        let denied = ops.iter().find_map(|op| match op {
+            KvsOperation::Set(key, _) | KvsOperation::Remove(key) => self
+                .access
+                .check(self.identity.as_deref(), key, true)
+                .err()
+                .map(|e| (key, e)),
+        });
+        if let Some((key, e)) = denied {
+            // Observers are notified with the data unlocked
+            drop(kvs);
+            drop(gate);
+            self.report_denied_write(key);
+            return Err(e);
+        }
+
+        let mut exists: HashMap<&str, bool> = HashMap::new();
+        for op in ops.iter() {
+            match op {
+                KvsOperation::Set(key, value) => {
+                    self.check_key_declared(key)?;
+                    self.check_value(key, value)?;
+                    exists.insert(key, true);
+                }
+                KvsOperation::Remove(key) => {
+                    let found = exists
+                        .get(key.as_str())
+                        .copied()
+                        .unwrap_or_else(|| kvs.contains_key(key));
+                    if !found {
+                        log_error!("transaction could not find key: {key}");
+                        return Err(ErrorCode::KeyNotFound);
+                    }
+                    exists.insert(key, false);
+                }
+            }
+        }
+
+        kvs_rules::reject(&self.rule_violations_after(&kvs, &ops)?)?;
+
+        let count = ops.len();
+        let compact = self.wal_append(WalRecord::Batch(&ops))?;
+        let events = if self.observers.is_empty() {
+            Vec::new()
+        } else {
+            operation_events(&ops)
+        };
+        for op in ops {
+            match op {
+                KvsOperation::Set(key, value) => {
+                    self.record_write(&key);
+                    kvs.insert(key, value);
+                }
+                KvsOperation::Remove(key) => {
+                    self.record_write(&key);
+                    kvs.remove(&key);
+                }
+            }
+        }
+        if compact {
+            self.wal_compact(&kvs);
+        }
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(count)
+    }
+
+    /// Replace the locked KVS data
+    ///
+    /// # Parameters
+    ///   * `data`: Locked KVS data
+    ///   * `new`: New KVS data
+    ///
+    /// # Return Values
+    ///   * Ok: Events to notify after unlocking
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    fn replace_data(&self, data: &mut KvsMap, new: KvsMap) -> Result<Vec<KvsEvent>, ErrorCode> {
+        let compact = self.wal_append(WalRecord::Replace(&new))?;
+        let events = self.swap_data(data, new);
+        if compact {
+            self.wal_compact(data);
+        }
+        Ok(events)
+    }
+
+    /// Replace the locked KVS data without journaling
+    ///
+    /// # Return Values
+    ///   * Events to notify after unlocking
+    fn swap_data(&self, data: &mut KvsMap, new: KvsMap) -> Vec<KvsEvent> {
+        let events = if self.observers.is_empty() {
+            Vec::new()
+        } else {
+            replace_events(data, &new)
+        };
+        for key in changed_keys(data, &new) {
+            self.mark_changed(key);
+        }
+        *data = new;
+        events
+    }
+
+    /// Apply the records other processes appended to the merge journal
+    ///
+    /// Also called by [`flush`](KvsApi::flush). Observers are notified of the changed keys.
+    ///
+    /// # Return Values
+    ///   * Ok: Count of applied records, 0 without a merge journal
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Journal couldn't be read
+    ///   * `ErrorCode::KvsFileReadError`: KVS file written by the compactor couldn't be read
+    pub fn merge_journal(&self) -> Result<usize, ErrorCode> {
+        let Some(journal) = &self.journal else {
+            return Ok(0);
+        };
+        // The data is locked before the journal, like in mutations that append to the journal
+        let gate = self.gate.write()?;
+        let mut data = self.kvs.lock()?;
+        let read = journal.read(&mut journal.lock(false)?)?;
+        let (count, events) = self.apply_journal(&mut data, read)?;
+        drop(data);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(count)
+    }
+
+    /// Apply records read from the merge journal to the locked KVS data
+    ///
+    /// # Return Values
+    ///   * Ok: Count of applied records and events to notify after unlocking
+    ///   * `ErrorCode::KvsFileReadError`: KVS file written by the compactor couldn't be read
+    fn apply_journal(
+        &self,
+        data: &mut KvsMap,
+        read: JournalRead,
+    ) -> Result<(usize, Vec<KvsEvent>), ErrorCode> {
+        if read.is_empty() {
+            return Ok((0, Vec::new()));
+        }
+        let mut merged = if read.compacted {
+            let mut merged = Self::open_kvs(
+                &path_with_suffix(&self.filename_prefix, "_0"),
+                OpenKvsNeedFile::Optional,
+                OpenKvsVerifyHash::Yes,
+                Some(&path_with_suffix(&self.filename_prefix, "_0.hash")),
+                None,
+            )?;
+            kvs_migration::take_version(&mut merged)?;
+            kvs_version::take_versions(&mut merged);
+            kvs_defaults_hash::take_defaults_hash(&mut merged);
+            merged
+        } else {
+            data.clone()
+        };
+        let count = read.apply(&mut merged);
+        Ok((count, self.swap_data(data, merged)))
+    }
+
+    /// Validate restored snapshot data and replace the current data with it
+    ///
+    /// The restore hook runs first and may transform the data or veto the restore. The result
+    /// must pass strict mode and the consistency rules, else the current data is kept.
+    ///
+    /// # Parameters
+    ///   * `restored`: Data loaded from the snapshot
+    ///
+    /// # Return Values
+    ///   * Ok: Data restored
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: Restore vetoed by the hook, undeclared key in strict
+    ///     mode, value rejected by a validator or consistency rule violated
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log couldn't be written
+    fn restore_data(&self, mut restored: KvsMap) -> Result<(), ErrorCode> {
+        let _timer = OpTimer::start("restore", &self.filename_prefix);
+        self.check_writable()?;
+        self.check_access_all(true)?;
+        let hook = self
+            .restore_hook
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clone();
+        let version = kvs_migration::take_version(&mut restored)?;
+        kvs_version::take_versions(&mut restored);
+        kvs_defaults_hash::take_defaults_hash(&mut restored);
+        let version = match self
+            .migrations
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .apply(&restored, version)?
+        {
+            Some((migrated, version)) => {
+                restored = migrated;
+                version
             }
+            None => version,
+        };
+        if let Some(hook) = hook {
+            hook(&mut restored).map_err(|reason| {
+                log_error!("snapshot restore vetoed: {reason}");
+                ErrorCode::ValidationFailed
+            })?;
+        }
+        for (key, value) in restored.iter() {
+            self.check_key_declared(key)?;
+            self.check_value(key, value)?;
+        }
+
+        let mut data = self.kvs.lock()?;
+        kvs_rules::reject(
+            &self
+                .rules
+                .evaluate(&RuleContext::new(&restored, &self.default))?,
+        )?;
+        self.save_version(&restored, version)?;
+        let events = self.replace_data(&mut data, restored)?;
+        drop(data);
+
+        self.observers.notify(&events);
+        Ok(())
+    }
+
+    /// Apply the registered migrations starting at the schema version of the data
+    ///
+    /// # Return Values
+    ///   * Ok: Data migrated or no migration starts at its version
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: A migration failed
+    fn migrate(&self) -> Result<(), ErrorCode> {
+        let gate = self.gate.write()?;
+        let mut data = self.kvs.lock()?;
+        let version = self.schema_version.load(atomic::Ordering::Relaxed);
+        let Some((migrated, migrated_version)) = self
+            .migrations
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .apply(&data, version)?
+        else {
+            return Ok(());
+        };
+        self.save_version(&migrated, migrated_version)?;
+        let events = self.swap_data(&mut data, migrated);
+        drop(data);
+        drop(gate);
+        log_info!("migrated data from schema version {version} to {migrated_version}");
+
+        self.observers.notify(&events);
+        Ok(())
+    }
+
+    /// Set the schema version of the data and write the KVS file if it changed
+    ///
+    /// Logged records describe data of the previous version, so the KVS file is rewritten with
+    /// the new data and the write-ahead log is truncated. Ephemeral and read-only instances keep
+    /// the data in memory only.
+    ///
+    /// # Parameters
+    ///   * `kvs`: Data of the new version
+    ///   * `version`: New schema version
+    fn save_version(&self, kvs: &KvsMap, version: u64) -> Result<(), ErrorCode> {
+        let previous = self.schema_version.swap(version, atomic::Ordering::Relaxed);
+        if previous == version || self.ephemeral || self.read_only {
+            return Ok(());
+        }
+        self.save_base(kvs)
+            .and_then(|_| {
+                self.write_stats
+                    .written(Self::saved_size(&self.filename_prefix));
+                self.collect_wal_written();
+                self.wal.as_ref().map_or(Ok(()), |wal| wal.truncate())
+            })
+            .inspect_err(|_| {
+                self.schema_version
+                    .store(previous, atomic::Ordering::Relaxed);
+            })
+    }
+
+    /// Register a schema migration and apply it if the data has its source version
+    ///
+    /// Migrations are usually registered with
+    /// [`KvsBuilder::migration`](crate::kvs_builder::KvsBuilder::migration), so they're applied
+    /// on open, see [`kvs_migration`](crate::kvs_migration). A migration with the same source
+    /// version is replaced.
+    ///
+    /// # Parameters
+    ///   * `from`: Schema version the migration starts at
+    ///   * `to`: Schema version after the migration, higher than `from`
+    ///   * `migration`: Transformation of the data
+    ///
+    /// # Return Values
+    ///   * Ok: Migration registered, the data is migrated if the chain starting at its version
+    ///     reaches the migration
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: `to` isn't higher than `from` or a migration failed
+    ///   * `ErrorCode::KvsFileReadError`: Migrated data couldn't be written
+    pub fn register_migration<F>(&self, from: u64, to: u64, migration: F) -> Result<(), ErrorCode>
+    where
+        F: Fn(&mut KvsMap) -> Result<(), String> + Send + Sync + 'static,
+    {
+        if to <= from {
+            log_error!("migration from version {from} to {to} doesn't raise the version");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        self.check_access_all(true)?;
+        let migration: KvsMigration = Arc::new(migration);
+        self.migrations
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .register(from, to, migration);
+        self.migrate()
+    }
+
+    /// Return the schema version of the data, 0 if it was never migrated
+    pub fn schema_version(&self) -> u64 {
+        self.schema_version.load(atomic::Ordering::Relaxed)
+    }
+
+    /// Register the hook called with snapshot data before it's restored
+    ///
+    /// The hook may transform the data or veto the restore, see
+    /// [`KvsRestoreHook`](crate::kvs_rules::KvsRestoreHook). A previous hook is replaced.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Return Values
+    ///   * Ok: Hook registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn set_restore_hook<F>(&self, hook: F) -> Result<(), ErrorCode>
+    where
+        F: Fn(&mut KvsMap) -> Result<(), String> + Send + Sync + 'static,
+    {
+        let hook: KvsRestoreHook = std::sync::Arc::new(hook);
+        *self
+            .restore_hook
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = Some(hook);
+        Ok(())
+    }
+
+    /// Remove the restore hook
+    ///
+    /// # Return Values
+    ///   * Ok: Hook removed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn clear_restore_hook(&self) -> Result<(), ErrorCode> {
+        *self
+            .restore_hook
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = None;
+        Ok(())
+    }
+
+    /// Register the callback called after every successful flush
+    ///
+    /// The callback gets the generation and size of the written KVS file, so persistence can be
+    /// monitored without polling the files. Flushes that don't write the KVS file, of ephemeral
+    /// instances or of merge journal writers, don't call it. A previous callback is replaced.
+    ///
+    /// # Return Values
+    ///   * Ok: Callback registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn set_flush_callback<F>(&self, callback: F) -> Result<(), ErrorCode>
+    where
+        F: Fn(&FlushInfo) + Send + Sync + 'static,
+    {
+        let callback: KvsFlushCallback = std::sync::Arc::new(callback);
+        *self
+            .flush_callback
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = Some(callback);
+        Ok(())
+    }
+
+    /// Remove the flush callback
+    ///
+    /// # Return Values
+    ///   * Ok: Callback removed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn clear_flush_callback(&self) -> Result<(), ErrorCode> {
+        *self
+            .flush_callback
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = None;
+        Ok(())
+    }
+
+    /// Start a session of temporary overrides
+    ///
+    /// Values set through the returned guard shadow the stored values for readers until the
+    /// guard is dropped, see [`kvs_override`](crate::kvs_override).
+    ///
+    /// # Return Values
+    ///   * Ok: Session guard
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn begin_override_session(&self) -> Result<KvsOverrideSession<'_, J>, ErrorCode> {
+        KvsOverrideSession::new(self)
+    }
+
+    /// Disable flush on exit and return if it was enabled
+    pub(crate) fn take_flush_on_exit(&self) -> bool {
+        self.flush_on_exit.swap(false, atomic::Ordering::Relaxed)
+    }
+
+    /// Return the settings of the worker threads of the instance
+    ///
+    /// Background work of the instance is spawned with [`WorkerConfig::spawn`].
+    pub fn worker_config(&self) -> &WorkerConfig {
+        &self.worker
+    }
+
+    /// Enable the periodic flush of changed data
+    ///
+    /// Changed data is flushed by [`tick`](Self::tick) once the interval elapsed since the last
+    /// flush, see [`kvs_auto_flush`](crate::kvs_auto_flush). The shared handle
+    /// [`GenericSharedKvs::enable_auto_flush`](crate::kvs_shared::GenericSharedKvs::enable_auto_flush)
+    /// also starts a worker thread calling it. A previous interval is replaced.
+    ///
+    /// # Parameters
+    ///   * `interval`: Maximum time between a flush and the next flush of changed data
+    ///
+    /// # Return Values
+    ///   * Ok: Auto-flush enabled
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is read-only
+    ///   * `ErrorCode::ValidationFailed`: Interval is 0
+    pub fn enable_auto_flush(&self, interval: Duration) -> Result<(), ErrorCode> {
+        self.schedule_auto_flush(interval).map(|_| ())
+    }
+
+    /// Enable auto-flush and return the new schedule
+    pub(crate) fn schedule_auto_flush(&self, interval: Duration) -> Result<Schedule, ErrorCode> {
+        self.check_writable()?;
+        if interval.is_zero() {
+            log_error!("auto-flush interval must not be 0");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        self.auto_flush.enable(interval)
+    }
+
+    /// Disable the periodic flush, a running worker thread ends
+    ///
+    /// # Return Values
+    ///   * Ok: Auto-flush disabled
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn disable_auto_flush(&self) -> Result<(), ErrorCode> {
+        self.auto_flush.disable()
+    }
+
+    /// Flush changed data if the auto-flush interval elapsed
+    ///
+    /// # Return Values
+    ///   * Ok(`true`): Data flushed
+    ///   * Ok(`false`): Auto-flush disabled, interval not elapsed, nothing changed or KVS
+    ///     ephemeral
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * Errors of [`flush`](KvsApi::flush)
+    pub fn tick(&self) -> Result<bool, ErrorCode> {
+        let Some(schedule) = self.auto_flush.schedule()? else {
+            return Ok(false);
+        };
+        if self.ephemeral
+            || !self
+                .auto_flush_remaining(schedule)?
+                .is_some_and(|r| r.is_zero())
+        {
+            return Ok(false);
+        }
+        if self
+            .dirty
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .is_empty()
+        {
+            return Ok(false);
+        }
+        self.flush()?;
+        Ok(true)
+    }
+
+    /// Return the time until the next auto-flush is due
+    ///
+    /// # Return Values
+    ///   * Ok: Remaining time, `None` if the schedule was replaced or auto-flush disabled
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn auto_flush_remaining(
+        &self,
+        schedule: Schedule,
+    ) -> Result<Option<Duration>, ErrorCode> {
+        if self.auto_flush.schedule()? != Some(schedule) {
+            return Ok(None);
+        }
+        let last_flush = *self
+            .last_flush
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        Ok(Some(schedule.remaining(last_flush, SystemTime::now())))
+    }
+
+    /// Return the overrides of all sessions
+    pub(crate) fn overrides(&self) -> &Overrides {
+        &self.overrides
+    }
+
+    /// Get a reference to the value of a key without copying it
+    ///
+    /// Resolves the value like [`get_value`](KvsApi::get_value). A stored value keeps the KVS
+    /// data locked until the reference is dropped, see [`kvs_value_ref`](crate::kvs_value_ref).
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to retrieve the value from
+    ///
+    /// # Return Values
+    ///   * Ok: Reference to the value
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
+    pub fn get_value_ref(&self, key: &str) -> Result<KvsValueRef<'_>, ErrorCode> {
+        self.check_read(key)?;
+        self.count_read(key);
+        if let Some(value) = self.overrides.get(key)? {
+            return Ok(KvsValueRef::owned(value));
+        }
+        let gate = self.gate.read()?;
+        let kvs = self.kvs.lock()?;
+        if kvs.contains_key(key) {
+            return Ok(KvsValueRef::stored(kvs, gate, key));
+        }
+        drop(kvs);
+        drop(gate);
+
+        if let Some(value) = self.default.get(key) {
+            Ok(KvsValueRef::borrowed(value))
+        } else if let Some(value) = self.default_provider.get(key)? {
+            Ok(KvsValueRef::owned(value))
+        } else {
+            log_error!("get_value_ref could not find key: {key}");
+            Err(ErrorCode::KeyNotFound)
+        }
+    }
+
+    /// Return the value readers see for a key: an override or the stored value
+    ///
+    /// Defaults aren't considered.
+    fn current_value(&self, key: &str) -> Result<Option<KvsValue>, ErrorCode> {
+        self.check_read(key)?;
+        match self.overrides.get(key)? {
+            Some(value) => Ok(Some(value)),
+            None => {
+                let _gate = self.gate.read()?;
+                Ok(self.kvs.lock()?.get(key).cloned())
+            }
+        }
+    }
+
+    /// Check that the identity of the instance may read a key
+    ///
+    /// # Return Values
+    ///   * Ok: Key isn't protected or the identity is permitted
+    ///   * `ErrorCode::AuthenticationFailed`: Key is protected and the instance has no identity
+    ///   * `ErrorCode::PermissionDenied`: Key is protected against the identity
+    fn check_read(&self, key: &str) -> Result<(), ErrorCode> {
+        self.access.check(self.identity.as_deref(), key, false)
+    }
+
+    /// Check that the identity of the instance may set or remove a key
+    ///
+    /// A denied write is reported to the observers, so the KVS data must not be locked.
+    fn check_write(&self, key: &str) -> Result<(), ErrorCode> {
+        self.access
+            .check(self.identity.as_deref(), key, true)
+            .inspect_err(|_| self.report_denied_write(key))
+    }
+
+    /// Report a write denied by the access policy to the observers
+    fn report_denied_write(&self, key: &str) {
+        self.observers.notify(&[KvsEvent::WriteDenied {
+            key: key.to_string(),
+            identity: self.identity.clone(),
+        }]);
+    }
+
+    /// Check that the identity of the instance may access all protected namespaces, required by
+    /// operations on the whole data
+    fn check_access_all(&self, write: bool) -> Result<(), ErrorCode> {
+        self.access.check_all(self.identity.as_deref(), write)
+    }
+
+    /// Return if the identity of the instance may read a key, used to filter key listings
+    fn readable(&self, key: &str) -> bool {
+        self.access.readable(self.identity.as_deref(), key)
+    }
+
+    /// Check that a key may be set in strict mode
+    ///
+    /// The schema version, key versions and defaults hash members of the KVS file are reserved in
+    /// every mode.
+    ///
+    /// # Return Values
+    ///   * Ok: Strict mode is off, the key has a static default or an exempted prefix
+    ///   * `ErrorCode::ValidationFailed`: Key isn't declared in the defaults or is reserved
+    fn check_key_declared(&self, key: &str) -> Result<(), ErrorCode> {
+        if key == kvs_migration::VERSION_KEY {
+            log_error!("key is reserved for the schema version: {key}");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        if key == kvs_version::VERSIONS_KEY {
+            log_error!("key is reserved for the key versions: {key}");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        if key == kvs_defaults_hash::DEFAULTS_HASH_KEY {
+            log_error!("key is reserved for the defaults hash: {key}");
+            return Err(ErrorCode::ValidationFailed);
+        }
+        let Some(exempt) = &self.strict_keys else {
+            return Ok(());
+        };
+        if self.default.contains_key(key) || exempt.iter().any(|prefix| key.starts_with(prefix)) {
+            Ok(())
+        } else {
+            log_error!("strict mode rejects key not declared in defaults: {key}");
+            Err(ErrorCode::ValidationFailed)
+        }
+    }
+
+    /// Check a new value against the size limit, the schemas and the validators of its key
+    ///
+    /// # Return Values
+    ///   * Ok: Value may be stored
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: Value too large, not conforming to a schema or rejected
+    ///     by a validator
+    fn check_value(&self, key: &str, value: &KvsValue) -> Result<(), ErrorCode> {
+        if let Some(max) = self.max_value_size {
+            let size = value_size(value);
+            if size > max {
+                log_error!("value of {key} exceeds the size limit: {size} > {max} bytes");
+                return Err(ErrorCode::ValidationFailed);
+            }
+        }
+        self.schemas.check(key, value)?;
+        self.validators.check(key, value)
+    }
+
+    /// Count a read of a key if statistics are enabled
+    fn count_read(&self, key: &str) {
+        if let Some(stats) = &self.key_stats {
+            stats.read(key);
+        }
+    }
+
+    /// Record a write of a key: mark it changed and count it if statistics are enabled
+    fn record_write(&self, key: &str) {
+        self.mark_changed(key);
+        if let Some(stats) = &self.key_stats {
+            stats.write(key);
+        }
+    }
+
+    /// Mark a key as changed: dirty until the next flush, its version incremented and the change
+    /// recorded in its metadata if these are enabled
+    fn mark_changed(&self, key: &str) {
+        if let Ok(mut dirty) = self.dirty.lock() {
+            if !dirty.contains(key) {
+                dirty.insert(key.to_string());
+            }
+        }
+        if let Some(versions) = &self.versions {
+            versions.increment(key);
+        }
+        if let Some(metadata) = &self.metadata {
+            metadata.touch(key);
+        }
+    }
+
+    /// Get the time and writer of the last change of a key
+    ///
+    /// Requires [`KvsOptions::key_metadata`]. Removals are changes too, the metadata of a removed
+    /// key tells when and by whom it was removed. Changes flushed by other processes are seen
+    /// after the next flush.
+    ///
+    /// # Parameters
+    ///   * `key`: Key to get the metadata of
+    ///
+    /// # Return Values
+    ///   * Ok: Metadata, `None` if no change of the key was recorded
+    ///   * `ErrorCode::ValidationFailed`: Key metadata isn't enabled
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_key_metadata(&self, key: &str) -> Result<Option<KeyMetadata>, ErrorCode> {
+        self.check_read(key)?;
+        let Some(metadata) = &self.metadata else {
+            log_error!("key metadata isn't enabled");
+            return Err(ErrorCode::ValidationFailed);
+        };
+        metadata.get(key)
+    }
+
+    /// Return usage statistics for health monitoring
+    ///
+    /// The serialized size is computed by serializing the data, the snapshot disk usage by
+    /// reading the file metadata of all snapshots.
+    ///
+    /// # Return Values
+    ///   * Ok: Usage statistics
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonGeneratorError`: Data couldn't be serialized
+    ///   * `ErrorCode::UnmappedError`: Snapshot file metadata couldn't be read
+    ///   * `ErrorCode::PermissionDenied`: Data is partly protected against the identity
+    pub fn stats(&self) -> Result<KvsStats, ErrorCode> {
+        self.check_access_all(false)?;
+        let (key_count, serialized_size) = {
+            let kvs = self.kvs.lock()?;
+            let data = JsonBackend::serialize_kvs(&kvs, self.storage_format)?;
+            (kvs.len(), data.len() as u64)
+        };
+
+        let mut snapshot_disk_usage = 0;
+        for info in self.snapshot_list()? {
+            let sidecar_size: u64 = ["hash", "gen"]
+                .iter()
+                .filter_map(|ext| fs::metadata(self.snapshot_path(info.id.0, ext)).ok())
+                .map(|meta| meta.len())
+                .sum();
+            snapshot_disk_usage += info.size + sidecar_size;
+        }
+        for tag in self.snapshot_tags()? {
+            snapshot_disk_usage += Self::saved_size(&self.tagged_snapshot_prefix(&tag));
+        }
+
+        let queue_stats = self.observers.queue_stats()?;
+        Ok(KvsStats {
+            key_count,
+            serialized_size,
+            last_flush: *self
+                .last_flush
+                .lock()
+                .map_err(|_| ErrorCode::MutexLockFailed)?,
+            snapshot_disk_usage,
+            dirty_key_count: self
+                .dirty
+                .lock()
+                .map_err(|_| ErrorCode::MutexLockFailed)?
+                .len(),
+            notification_queue_depth: queue_stats.depth,
+            notifications_dropped: queue_stats.dropped,
+        })
+    }
+
+    /// Return the growth trend of the store
+    ///
+    /// Requires [`KvsOptions::size_history`]. The size of the KVS file and hash file is sampled
+    /// after a flush, at most once per hour, and the last 64 samples are kept across restarts.
+    /// The growth rate is fitted to the samples, [`GrowthTrend::time_to_size`] projects when a
+    /// quota is reached.
+    ///
+    /// # Return Values
+    ///   * Ok: Growth trend
+    ///   * `ErrorCode::ValidationFailed`: Size history isn't enabled
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn growth_trend(&self) -> Result<GrowthTrend, ErrorCode> {
+        let Some(size_history) = &self.size_history else {
+            log_error!("size history isn't enabled");
+            return Err(ErrorCode::ValidationFailed);
+        };
+        size_history.trend()
+    }
+
+    /// Return the per-key read and write counters
+    ///
+    /// Only available if enabled with [`KvsOptions::key_stats`], else the list is empty.
+    ///
+    /// # Return Values
+    ///   * Ok: Accessed keys and their counters, most active first
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn key_stats(&self) -> Result<Vec<(String, KeyStats)>, ErrorCode> {
+        match &self.key_stats {
+            Some(stats) => stats.sorted(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Return the write amplification statistics
+    ///
+    /// Compares the bytes logically changed by mutations with the bytes written to storage, per
+    /// flush and since the KVS was opened. See [`kvs_write_stats`](crate::kvs_write_stats) for how
+    /// the bytes are counted.
+    ///
+    /// # Return Values
+    ///   * Ok: Write statistics
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn write_stats(&self) -> Result<WriteReport, ErrorCode> {
+        self.collect_wal_written();
+        self.write_stats.report()
+    }
+
+    /// Subscribe to key changes
+    ///
+    /// The observer is called for every set or removed key matching `pattern`. A pattern ending
+    /// with `*` matches all keys with the preceding prefix, other patterns match a single key.
+    /// Observers are called after the KVS data is unlocked from the thread that changed the key.
+    ///
+    /// # Parameters
+    ///   * `pattern`: Key pattern
+    ///   * `observer`: Callback receiving the change event
+    ///
+    /// # Return Values
+    ///   * Ok: Subscription ID
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn subscribe<S, F>(&self, pattern: S, observer: F) -> Result<SubscriptionId, ErrorCode>
+    where
+        S: Into<String>,
+        F: Fn(&KvsEvent) + Send + Sync + 'static,
+    {
+        let observer: KvsObserver = std::sync::Arc::new(observer);
+        self.observers.subscribe(pattern.into(), observer)
+    }
+
+    /// Subscribe to key changes through a bounded queue
+    ///
+    /// Behaves like [`subscribe`](Self::subscribe), but the observer is called from a delivery
+    /// thread of the subscription, spawned with the instance's [`WorkerConfig`] under the role
+    /// `notify`. Writers and other subscribers never wait for the observer. If more than
+    /// `capacity` events are pending, `policy` decides which are discarded; a subscription
+    /// disconnected on overflow is removed as if unsubscribed.
+    ///
+    /// # Parameters
+    ///   * `pattern`: Key pattern
+    ///   * `capacity`: Maximum count of pending events
+    ///   * `policy`: Behavior on overflow
+    ///   * `observer`: Callback receiving the change event
+    ///
+    /// # Return Values
+    ///   * Ok: Subscription ID
+    ///   * `ErrorCode::ValidationFailed`: Capacity is 0
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::UnmappedError`: Delivery thread couldn't be spawned
+    pub fn subscribe_queued<S, F>(
+        &self,
+        pattern: S,
+        capacity: usize,
+        policy: OverflowPolicy,
+        observer: F,
+    ) -> Result<SubscriptionId, ErrorCode>
+    where
+        S: Into<String>,
+        F: Fn(&KvsEvent) + Send + Sync + 'static,
+    {
+        let observer: KvsObserver = std::sync::Arc::new(observer);
+        self.observers
+            .subscribe_queued(pattern.into(), observer, capacity, policy, &self.worker)
+    }
+
+    /// Unsubscribe from key changes
+    ///
+    /// # Parameters
+    ///   * `id`: Subscription ID returned by [`subscribe`](Self::subscribe)
+    ///
+    /// # Return Values
+    ///   * Ok: Observer removed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Unknown subscription ID
+    pub fn unsubscribe(&self, id: SubscriptionId) -> Result<(), ErrorCode> {
+        self.observers.unsubscribe(id)
+    }
+
+    /// Return the filename prefix of a tagged snapshot
+    ///
+    /// # Return Values
+    ///   * Path in the form `<dir>/kvs_<instance_id>_tag_<tag>`
+    fn tagged_snapshot_prefix(&self, tag: &str) -> PathBuf {
+        path_with_suffix(&self.filename_prefix, &format!("_tag_{tag}"))
+    }
+
+    /// Check that a snapshot tag can be used in a filename
+    ///
+    /// Tags must not be empty and may only contain ASCII letters, digits, `-`, `_` and `.`.
+    fn validate_snapshot_tag(tag: &str) -> Result<(), ErrorCode> {
+        let valid = !tag.is_empty()
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if valid {
+            Ok(())
+        } else {
+            log_error!("invalid snapshot tag: {tag:?}");
+            Err(ErrorCode::InvalidSnapshotId)
+        }
+    }
+
+    /// Create a tagged snapshot of the current data
+    ///
+    /// Tagged snapshots are stored next to the KVS file as `kvs_<instance_id>_tag_<tag>_0.json`
+    /// with a hash file. They are not part of the flush rotation and stay until they are
+    /// overwritten by a snapshot with the same tag.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `tag`: Snapshot name, ASCII letters, digits, `-`, `_` and `.`
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot created
+    ///   * `ErrorCode::InvalidSnapshotId`: Invalid tag
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is ephemeral
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KvsFileReadError`: Snapshot file couldn't be written
+    pub fn snapshot_create(&self, tag: &str) -> Result<(), ErrorCode> {
+        Self::validate_snapshot_tag(tag)?;
+        self.check_persistent()?;
+        let _timer = OpTimer::start("snapshot_create", &self.filename_prefix);
+        let kvs = self.kvs.lock()?;
+        let prefix = self.tagged_snapshot_prefix(tag);
+        self.save_kvs(&kvs, &prefix)?;
+        self.write_stats.written(Self::saved_size(&prefix));
+        Ok(())
+    }
+
+    /// Restore a tagged snapshot
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `tag`: Snapshot name used with [`snapshot_create`](Self::snapshot_create)
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot restored
+    ///   * `ErrorCode::InvalidSnapshotId`: Invalid tag
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is ephemeral
+    ///   * `ErrorCode::ValidationFailed`: Snapshot hash validation failed or restored data
+    ///     rejected by the restore hook, strict mode, a validator or a consistency rule
+    ///   * `ErrorCode::KvsFileReadError`: Snapshot not found
+    ///   * `ErrorCode::KvsHashFileReadError`: Snapshot hash file read error
+    pub fn snapshot_restore_tag(&self, tag: &str) -> Result<(), ErrorCode> {
+        Self::validate_snapshot_tag(tag)?;
+        self.check_persistent()?;
+        let prefix = self.tagged_snapshot_prefix(tag);
+        let kvs = Self::open_kvs(
+            &path_with_suffix(&prefix, "_0"),
+            OpenKvsNeedFile::Required,
+            OpenKvsVerifyHash::Yes,
+            Some(&path_with_suffix(&prefix, "_0.hash")),
+            self.cipher.as_deref(),
+        )?;
+        self.restore_data(kvs)
+    }
+
+    /// Return the tags of all tagged snapshots, sorted by name
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot tags
+    ///   * `ErrorCode::FileNotFound`: KVS directory not found
+    ///   * `ErrorCode::UnmappedError`: Generic error
+    pub fn snapshot_tags(&self) -> Result<Vec<String>, ErrorCode> {
+        if self.ephemeral {
+            return Ok(Vec::new());
+        }
+        let name_prefix = match self.filename_prefix.file_name() {
+            Some(name) => format!("{}_tag_", name.to_string_lossy()),
+            None => return Ok(Vec::new()),
+        };
+        let dir = match self.filename_prefix.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let mut tags = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let tag = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&name_prefix))
+                .and_then(|name| name.strip_suffix("_0.json"));
+            if let Some(tag) = tag {
+                tags.push(tag.to_string());
+            }
+        }
+        tags.sort();
+        Ok(tags)
+    }
+
+    /// Return the retention policy of the snapshots
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    pub fn snapshot_retention(&self) -> SnapshotRetention {
+        self.retention
+    }
+
+    /// Return the descriptors of all snapshots that can be restored
+    ///
+    /// The current KVS file (ID 0) isn't included.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot descriptors, ordered by ID (newest first)
+    ///   * `ErrorCode::UnmappedError`: Snapshot file metadata couldn't be read
+    pub fn snapshot_list(&self) -> Result<Vec<SnapshotInfo>, ErrorCode> {
+        let mut list = Vec::new();
+        if self.ephemeral {
+            return Ok(list);
+        }
+        for idx in 1..=self.retention.max_count {
+            let meta = match fs::metadata(self.snapshot_path(idx, "json")) {
+                Ok(meta) => meta,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let hash = fs::read(self.snapshot_path(idx, "hash"))
+                .ok()
+                .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok())
+                .map(u32::from_be_bytes);
+
+            list.push(SnapshotInfo {
+                id: SnapshotId::new(idx),
+                generation: self.read_generation(idx),
+                created: meta.modified()?,
+                size: meta.len(),
+                hash,
+            });
+        }
+        Ok(list)
+    }
+
+    /// Verify a stored file against its hash file and parse it
+    fn verify_file(&self, json: PathBuf, hash: PathBuf, report: &mut IntegrityReport) {
+        report.checked_files += 1;
+        let data = match fs::read(&json) {
+            Ok(data) => data,
+            Err(_) => {
+                report.issues.push(IntegrityIssue::Unreadable(json));
+                return;
+            }
+        };
+        match fs::read(&hash).map(<[u8; 4]>::try_from) {
+            Ok(Ok(bytes)) => {
+                let expected = adler32::RollingAdler32::from_buffer(&data)
+                    .hash()
+                    .to_be_bytes();
+                // A hash left staged by an interrupted flush is accepted like on open
+                if bytes != expected
+                    && staged_content(&hash).is_none_or(|staged| staged != expected)
+                {
+                    report
+                        .issues
+                        .push(IntegrityIssue::HashMismatch(json.clone()));
+                }
+            }
+            _ => report.issues.push(IntegrityIssue::HashMissing(hash)),
+        }
+        let parsed = match &self.cipher {
+            Some(cipher) => cipher
+                .decrypt(&data)
+                .and_then(|data| JsonBackend::parse_kvs(&data)),
+            None => JsonBackend::parse_kvs(&data),
+        };
+        if let Err(e) = parsed {
+            report.issues.push(IntegrityIssue::ParseFailed(json, e));
+        }
+    }
+
+    /// Verify the KVS file and all snapshots
+    ///
+    /// Every file is compared with its hash file and parsed, the rotated snapshots must form a
+    /// chain without gaps and with generations getting older. Unlike opening or restoring, all
+    /// problems are collected instead of failing on the first one. Flushes wait until the
+    /// verification is finished.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__integrity_check`
+    ///
+    /// # Return Values
+    ///   * Ok: Verification report, empty for an ephemeral KVS
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::FileNotFound`: KVS directory not found
+    pub fn verify_integrity(&self) -> Result<IntegrityReport, ErrorCode> {
+        let mut report = IntegrityReport::default();
+        if self.ephemeral {
+            return Ok(report);
+        }
+        let _gate = self.gate.read()?;
+
+        let mut newer: Option<(bool, Option<SnapshotGeneration>)> = None;
+        for idx in 0..=self.retention.max_count {
+            let json = self.snapshot_path(idx, "json");
+            let exists = json.exists();
+            let generation = self.read_generation(idx);
+            if exists {
+                match newer {
+                    Some((false, _)) => report
+                        .issues
+                        .push(IntegrityIssue::SnapshotGap(SnapshotId::new(idx))),
+                    Some((true, Some(newer_generation)))
+                        if generation.is_some_and(|generation| generation >= newer_generation) =>
+                    {
+                        report
+                            .issues
+                            .push(IntegrityIssue::GenerationOrder(SnapshotId::new(idx)))
+                    }
+                    _ => {}
+                }
+                self.verify_file(json, self.snapshot_path(idx, "hash"), &mut report);
+            }
+            // The KVS file is missing until the first flush, older snapshots still form a chain
+            if idx > 0 || exists {
+                newer = Some((exists, generation));
+            }
+        }
+        for tag in self.snapshot_tags()? {
+            let prefix = self.tagged_snapshot_prefix(&tag);
+            self.verify_file(
+                path_with_suffix(&prefix, "_0.json"),
+                path_with_suffix(&prefix, "_0.hash"),
+                &mut report,
+            );
+        }
+        Ok(report)
+    }
+
+    /// Open a read-only view of a snapshot without restoring it
+    ///
+    /// The snapshot is loaded and verified against its hash file, the live state isn't touched.
+    /// The view holds a copy of the stored values, defaults aren't applied.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID, 0 opens the flushed state of the current KVS file
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot view
+    ///   * `ErrorCode::InvalidSnapshotId`: Snapshot doesn't exist
+    ///   * `ErrorCode::ValidationFailed`: Snapshot hash validation failed
+    ///   * `ErrorCode::JsonParserError`: JSON parser error
+    ///   * `ErrorCode::KvsHashFileReadError`: Snapshot hash file read error
+    pub fn open_snapshot_view(&self, id: SnapshotId) -> Result<KvsReadOnlyView, ErrorCode> {
+        self.check_access_all(false)?;
+        if self.ephemeral
+            || id.0 > self.retention.max_count
+            || !self.snapshot_path(id.0, "json").exists()
+        {
+            log_error!("tried to view a non-existing snapshot");
+            return Err(ErrorCode::InvalidSnapshotId);
+        }
+        let mut data = Self::open_kvs(
+            &path_with_suffix(&self.filename_prefix, &format!("_{}", id.0)),
+            OpenKvsNeedFile::Required,
+            OpenKvsVerifyHash::Yes,
+            Some(&self.snapshot_path(id.0, "hash")),
+            self.cipher.as_deref(),
+        )?;
+        kvs_migration::take_version(&mut data)?;
+        kvs_version::take_versions(&mut data);
+        kvs_defaults_hash::take_defaults_hash(&mut data);
+        Ok(KvsReadOnlyView::new(id, data))
+    }
+
+    /// Return the key-level differences between a snapshot and the live store
+    ///
+    /// Compares the stored values of the snapshot with the current stored values, including
+    /// unflushed changes. Defaults aren't considered, nothing is restored.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID, 0 compares with the flushed state of the current KVS file
+    ///
+    /// # Return Values
+    ///   * Ok: Changed keys sorted by key
+    ///   * `ErrorCode::InvalidSnapshotId`: Snapshot doesn't exist
+    ///   * `ErrorCode::ValidationFailed`: Snapshot hash validation failed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn snapshot_diff(&self, id: SnapshotId) -> Result<Vec<KeyDiff>, ErrorCode> {
+        let view = self.open_snapshot_view(id)?;
+        let kvs = self.kvs.lock()?;
+        Ok(view.diff(&kvs))
+    }
+
+    /// Return the stable generation of a snapshot
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID, 0 returns the generation of the current KVS file
+    ///
+    /// # Return Values
+    ///   * Ok: Generation of the snapshot
+    ///   * `ErrorCode::InvalidSnapshotId`: Snapshot doesn't exist or was written without a
+    ///     generation
+    pub fn snapshot_generation(&self, id: SnapshotId) -> Result<SnapshotGeneration, ErrorCode> {
+        if self.ephemeral
+            || id.0 > self.retention.max_count
+            || !self.snapshot_path(id.0, "json").exists()
+        {
+            return Err(ErrorCode::InvalidSnapshotId);
+        }
+        self.read_generation(id.0)
+            .ok_or(ErrorCode::InvalidSnapshotId)
+    }
+
+    /// Return the current snapshot ID of a generation
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `generation`: Stable snapshot generation
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot ID, 0 if the generation is the current KVS file
+    ///   * `ErrorCode::InvalidSnapshotId`: Generation unknown or already pruned
+    pub fn snapshot_id(&self, generation: SnapshotGeneration) -> Result<SnapshotId, ErrorCode> {
+        if self.ephemeral {
+            return Err(ErrorCode::InvalidSnapshotId);
+        }
+        (0..=self.retention.max_count)
+            .find(|&idx| {
+                self.read_generation(idx) == Some(generation)
+                    && self.snapshot_path(idx, "json").exists()
+            })
+            .map(SnapshotId::new)
+            .ok_or(ErrorCode::InvalidSnapshotId)
+    }
+
+    /// Restore the snapshot of a generation
+    ///
+    /// See [`snapshot_restore`](KvsApi::snapshot_restore).
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `generation`: Stable snapshot generation
+    ///
+    /// # Return Values
+    ///   * Ok: Snapshot restored
+    ///   * `ErrorCode::InvalidSnapshotId`: Generation unknown, pruned or the current KVS file
+    pub fn snapshot_restore_generation(
+        &self,
+        generation: SnapshotGeneration,
+    ) -> Result<(), ErrorCode> {
+        self.snapshot_restore(self.snapshot_id(generation)?)
+    }
+
+    /// Persist only the keys changed since the last flush
+    ///
+    /// The current state of every key set or removed since the last flush is appended as one
+    /// record to the delta journal `kvs_<instance_id>.delta` instead of rewriting the whole KVS
+    /// file, which saves flash wear on large instances with few changes. On open the journal is
+    /// replayed on top of the KVS file. Every write of the KVS file, by [`flush`](KvsApi::flush),
+    /// [`compact`](Self::compact) or a write-ahead log compaction, folds the journal into it.
+    ///
+    /// Incremental flushes don't rotate snapshots, call the flush callback or notify observers.
+    /// A full flush is done instead once the journal got as large as the KVS file, and always if
+    /// the KVS is encrypted or shares a merge journal.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__persistency`
+    ///
+    /// # Return Values
+    ///   * Ok: Changed keys persisted
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: A consistency rule is violated
+    ///   * `ErrorCode::PhysicalStorageFailure`: Delta journal couldn't be written
+    ///   * Errors of [`flush`](KvsApi::flush)
+    pub fn flush_incremental(&self) -> Result<(), ErrorCode> {
+        let _timer = OpTimer::start("flush_incremental", &self.filename_prefix);
+        if self.ephemeral {
+            return self.flush();
+        }
+        let delta_path = path_with_suffix(&self.filename_prefix, ".delta");
+        let delta_size = fs::metadata(&delta_path).map_or(0, |meta| meta.len());
+        if self.delta.is_none()
+            || self.cipher.is_some()
+            || self.journal.is_some()
+            || delta_size >= Self::saved_size(&self.filename_prefix)
+        {
+            return self.flush();
+        }
+        let gate = self.write_gate()?;
+        kvs_rules::reject(&self.check_rules()?)?;
+        let kvs = self.kvs.lock()?;
+        self.append_delta(&kvs)?;
+        self.collect_wal_written();
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+        self.dirty
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clear();
+        drop(kvs);
+        self.write_stats.flushed()?;
+        if let Some(metadata) = &self.metadata {
+            metadata.save()?;
+        }
+        drop(gate);
+        Ok(())
+    }
+
+    /// Return the size of the KVS file, its hash file, the write-ahead log and the delta journal
+    /// in bytes
+    fn store_size(&self) -> u64 {
+        let log_size: u64 = [".wal", ".delta"]
+            .iter()
+            .filter_map(|suffix| fs::metadata(path_with_suffix(&self.filename_prefix, suffix)).ok())
+            .map(|meta| meta.len())
+            .sum();
+        Self::saved_size(&self.filename_prefix) + log_size
+    }
+
+    /// Rewrite the KVS file and reclaim the space of dead data
+    ///
+    /// Journaled mutations are folded into the KVS file, the write-ahead log and the delta journal
+    /// of [`flush_incremental`](Self::flush_incremental) are removed. The
+    /// data from before the compaction is kept as tagged snapshot `pre-compaction`, which can be
+    /// restored with [`snapshot_restore_tag`](Self::snapshot_restore_tag). Unlike
+    /// [`flush`](KvsApi::flush) the snapshot rotation is left untouched.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__persistency`
+    ///
+    /// # Return Values
+    ///   * Ok: Sizes before and after the compaction
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KvsFileReadError`: Snapshot or KVS file couldn't be written
+    ///   * `ErrorCode::PhysicalStorageFailure`: Instance is ephemeral or read-only or the
+    ///     write-ahead log couldn't be removed
+    pub fn compact(&self) -> Result<CompactionReport, ErrorCode> {
+        self.check_persistent()?;
+        let _gate = self.write_gate()?;
+        let kvs = self.kvs.lock()?;
+        let size_before = self.store_size();
+        let snapshot_prefix = self.tagged_snapshot_prefix(COMPACTION_SNAPSHOT_TAG);
+        self.save_kvs(&kvs, &snapshot_prefix)?;
+        self.write_stats.written(Self::saved_size(&snapshot_prefix));
+        self.save_base(&kvs)?;
+        self.write_stats
+            .written(Self::saved_size(&self.filename_prefix));
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+
+        Ok(CompactionReport {
+            size_before,
+            size_after: self.store_size(),
+            snapshot_tag: COMPACTION_SNAPSHOT_TAG.to_string(),
+        })
+    }
+
+    /// Return the default value of a key
+    ///
+    /// Static defaults take precedence over the default provider.
+    fn lookup_default(&self, key: &str) -> Result<Option<KvsValue>, ErrorCode> {
+        match self.default.get(key) {
+            Some(value) => Ok(Some(value.clone())),
+            None => self.default_provider.get(key),
+        }
+    }
+
+    /// Register a default provider
+    ///
+    /// The provider is called for keys without a static default and returns the default value
+    /// or `None`. It replaces a previously registered provider. With `cache` enabled the first
+    /// returned value of a key is kept until the provider is replaced or removed. The provider is
+    /// called without the KVS data locked.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Parameters
+    ///   * `provider`: Callback returning the default value of a key
+    ///   * `cache`: Cache returned default values
+    ///
+    /// # Return Values
+    ///   * Ok: Provider registered
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn set_default_provider<F>(&self, provider: F, cache: bool) -> Result<(), ErrorCode>
+    where
+        F: Fn(&str) -> Option<KvsValue> + Send + Sync + 'static,
+    {
+        let provider: KvsDefaultProvider = std::sync::Arc::new(provider);
+        self.default_provider.set(Some((provider, cache)))
+    }
+
+    /// Remove the default provider
+    ///
+    /// # Return Values
+    ///   * Ok: Provider removed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn clear_default_provider(&self) -> Result<(), ErrorCode> {
+        self.default_provider.set(None)
+    }
+
+    /// Return the keys whose default changed since the KVS was previously opened
+    ///
+    /// The hashes of the defaults are written to the KVS file with the data, changes are seen
+    /// after the KVS file was written once with the previous defaults. Keys with an added,
+    /// removed or changed default are listed, whether or not a value is set for them.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Return Values
+    ///   * Sorted keys, empty if the defaults are unchanged or weren't recorded before
+    pub fn changed_defaults(&self) -> &[String] {
+        &self.changed_defaults
+    }
+
+    /// Reset a single key to its default value
+    ///
+    /// The stored value is removed, so the default is returned again. A key that already returns
+    /// its default is left unchanged.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_value_reset`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to reset
+    ///
+    /// # Return Values
+    ///   * Ok: Key returns its default value
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key has no default value
+    pub fn reset_key_to_default(&self, key: &str) -> Result<(), ErrorCode> {
+        if self.lookup_default(key)?.is_none() {
+            log_error!("reset_key_to_default found no default for key: {key}");
+            return Err(ErrorCode::KeyNotFound);
+        }
+        self.reset_key(key).map(|_| ())
+    }
+
+    /// Remove the explicitly set value of a key
+    ///
+    /// Subsequent reads fall back to the default value, if there is one. Unlike
+    /// [`remove_key`](Self::remove_key) it's no error if the key was never set.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_value_reset`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to reset
+    ///
+    /// # Return Values
+    ///   * Ok(true): Explicitly set value was removed
+    ///   * Ok(false): Key wasn't set
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is read-only or the write-ahead log couldn't
+    ///     be written
+    pub fn reset_key(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.check_write(key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        if !kvs.contains_key(key) {
+            return Ok(false);
+        }
+        let compact = self.wal_append(WalRecord::Remove(key))?;
+        self.record_write(key);
+        kvs.remove(key);
+        if compact {
+            self.wal_compact(&kvs);
+        }
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&[KvsEvent::Removed {
+            key: key.to_string(),
+        }]);
+        Ok(true)
+    }
+
+    /// Reset the KVS to its factory state
+    ///
+    /// Unlike [`reset`](KvsApi::reset), which leaves an empty store, all stored values are
+    /// replaced by the static default values. The defaults are stored explicitly, so they're
+    /// flushed and later changes of the defaults file don't change them. Dynamic defaults of
+    /// [`KvsDefaultProvider`](crate::kvs_default_provider::KvsDefaultProvider)s aren't stored.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_value_reset`
+    ///
+    /// # Return Values
+    ///   * Ok: KVS reset to the defaults
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is read-only or the write-ahead log couldn't
+    ///     be written
+    pub fn reset_to_defaults(&self) -> Result<(), ErrorCode> {
+        self.check_access_all(true)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let events = self.replace_data(&mut kvs, self.default.clone())?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(())
+    }
+
+    /// Export all scalar keys as CSV
+    ///
+    /// Creates one row per key of the KVS and the defaults with the columns `key`, `type`,
+    /// `value`, `default`, `is_default` and `last_modified`. Arrays and objects are skipped.
+    /// `last_modified` is the time the current KVS file was written, it's empty for values not
+    /// persisted yet.
+    ///
+    /// # Return Values
+    ///   * Ok: CSV document
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn export_csv(&self) -> Result<String, ErrorCode> {
+        self.check_access_all(false)?;
+        let modified = fs::metadata(self.snapshot_path(0, "json"))
+            .and_then(|meta| meta.modified())
+            .ok();
+        let kvs = self.kvs.lock()?;
+
+        let mut keys: Vec<&String> = kvs.keys().chain(self.default.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let rows: Vec<CsvRow> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let default = self.default.get(key);
+                let (value, is_default) = match kvs.get(key) {
+                    Some(value) => (value, false),
+                    None => (default?, true),
+                };
+                Some(CsvRow {
+                    key,
+                    value,
+                    default,
+                    is_default,
+                    modified: if is_default { None } else { modified },
+                })
+            })
+            .collect();
+        Ok(kvs_csv::write(&rows))
+    }
+
+    /// Import scalar keys from CSV
+    ///
+    /// Evaluates the columns `key`, `type`, `value` and `is_default` of a document created by
+    /// [`export_csv`](Self::export_csv). Rows with a changed value are set, rows marked as
+    /// `is_default` remove the stored value. All changes are applied atomically.
+    ///
+    /// # Parameters
+    ///   * `csv`: CSV document
+    ///
+    /// # Return Values
+    ///   * Ok: Count of changed keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ConversionFailed`: Invalid CSV document or value
+    ///   * `ErrorCode::ValidationFailed`: A key has no default in strict mode or a value is rejected
+    ///   * `ErrorCode::PhysicalStorageFailure`: Instance is read-only or the write-ahead log
+    ///     couldn't be written
+    pub fn import_csv(&self, csv: &str) -> Result<usize, ErrorCode> {
+        let entries = kvs_csv::read(csv)?;
+
+        self.apply_built_operations(|kvs| {
+            let mut current: HashMap<&str, Option<&KvsValue>> = HashMap::new();
+            let mut ops = Vec::new();
+            for entry in entries.iter() {
+                let value = current
+                    .get(entry.key.as_str())
+                    .copied()
+                    .unwrap_or_else(|| kvs.get(&entry.key));
+                if value == entry.value.as_ref() {
+                    continue;
+                }
+                current.insert(&entry.key, entry.value.as_ref());
+                ops.push(match &entry.value {
+                    Some(value) => KvsOperation::Set(entry.key.clone(), value.clone()),
+                    None => KvsOperation::Remove(entry.key.clone()),
+                });
+            }
+            Ok(ops)
+        })
+    }
+
+    /// Export the stored keys as JSON text
+    ///
+    /// The document has the format of a JSON KVS file, independent of the configured storage
+    /// format and cipher. Defaults aren't included.
+    ///
+    /// # Return Values
+    ///   * Ok: JSON document
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonGeneratorError`: Data couldn't be serialized
+    pub fn export_json(&self) -> Result<String, ErrorCode> {
+        self.check_access_all(false)?;
+        let kvs = self.kvs.lock()?;
+        let version = self.schema_version.load(atomic::Ordering::Relaxed);
+        let data = JsonBackend::serialize_kvs(
+            &kvs_migration::with_version(&kvs, version),
+            StorageFormat::Json,
+        )?;
+        String::from_utf8(data).map_err(|_| ErrorCode::ConversionFailed)
+    }
+
+    /// Import keys from JSON text
+    ///
+    /// Reads a document created by [`export_json`](Self::export_json) or a JSON KVS file. All
+    /// changes are applied atomically.
+    ///
+    /// # Parameters
+    ///   * `json`: JSON document
+    ///   * `mode`: Handling of the stored keys
+    ///
+    /// # Return Values
+    ///   * Ok: Count of changed keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonParserError`: Invalid JSON document
+    ///   * `ErrorCode::ValidationFailed`: A key has no default in strict mode or a value is rejected
+    ///   * `ErrorCode::PhysicalStorageFailure`: Instance is read-only or the write-ahead log
+    ///     couldn't be written
+    pub fn import_json(&self, json: &str, mode: ImportMode) -> Result<usize, ErrorCode> {
+        let mut document = JsonBackend::parse_kvs(json.as_bytes())?;
+        let version = kvs_migration::take_version(&mut document)?;
+        kvs_version::take_versions(&mut document);
+        kvs_defaults_hash::take_defaults_hash(&mut document);
+        if let Some((migrated, _)) = self
+            .migrations
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .apply(&document, version)?
+        {
+            document = migrated;
+        }
+
+        self.apply_built_operations(|kvs| {
+            let mut ops: Vec<KvsOperation> = Vec::new();
+            if mode == ImportMode::Replace {
+                ops.extend(
+                    kvs.keys()
+                        .filter(|key| !document.contains_key(*key))
+                        .map(|key| KvsOperation::Remove(key.clone())),
+                );
+            }
+            ops.extend(
+                document
+                    .into_iter()
+                    .filter(|(key, value)| match kvs.get(key) {
+                        Some(_) if mode == ImportMode::KeepExisting => false,
+                        current => current != Some(value),
+                    })
+                    .map(|(key, value)| KvsOperation::Set(key, value)),
+            );
+            Ok(ops)
+        })
+    }
+}
+
+impl<J: KvsBackend> KvsApi for GenericKvs<J> {
+    /// Open the key-value-storage
+    ///
+    /// Checks and opens a key-value-storage. Flush on exit is enabled by default and can be
+    /// controlled with [`flush_on_exit`](Self::flush_on_exit).
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///   * `FEAT_REQ__KVS__multiple_kvs`
+    ///   * `FEAT_REQ__KVS__integrity_check`
+    ///
+    /// # Parameters
+    ///   * `instance_id`: Instance ID
+    ///   * `need_defaults`: Fail when no default file was found
+    ///   * `need_kvs`: Fail when no KVS file was found
+    ///
+    /// # Return Values
+    ///   * Ok: KVS instance
+    ///   * `ErrorCode::ValidationFailed`: KVS hash validation failed or typed defaults don't match
+    ///     their annotations
+    ///   * `ErrorCode::JsonParserError`: JSON parser error (invalid JSON or type error)
+    ///   * `ErrorCode::KvsFileReadError`: KVS file read error (I/O error)
+    ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error (hash file missing or unreadable)
+    ///   * `ErrorCode::UnmappedError`: Generic error
+    fn open(
+        instance_id: InstanceId,
+        need_defaults: OpenNeedDefaults,
+        need_kvs: OpenNeedKvs,
+        dir: Option<String>,
+    ) -> Result<GenericKvs<J>, ErrorCode> {
+        Self::open_with_options(
+            instance_id,
+            need_defaults,
+            need_kvs,
+            dir,
+            KvsOptions::default(),
+        )
+    }
+
+    /// Open the key-value-storage with additional settings
+    ///
+    /// Same as [`open`](Self::open). If the write-ahead log is enabled, it's replayed on top of
+    /// the loaded KVS file.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///   * `FEAT_REQ__KVS__multiple_kvs`
+    ///   * `FEAT_REQ__KVS__integrity_check`
+    ///   * `FEAT_REQ__KVS__persistency`
+    ///
+    /// # Parameters
+    ///   * `instance_id`: Instance ID
+    ///   * `need_defaults`: Fail when no default file was found
+    ///   * `need_kvs`: Fail when no KVS file was found
+    ///   * `options`: Additional settings
+    ///
+    /// # Return Values
+    ///   * Ok: KVS instance
+    ///   * `ErrorCode::ValidationFailed`: KVS hash validation failed or typed defaults don't match
+    ///     their annotations
+    ///   * `ErrorCode::JsonParserError`: JSON parser error (invalid JSON or type error)
+    ///   * `ErrorCode::KvsFileReadError`: KVS file read error (I/O error)
+    ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error (hash file missing or unreadable)
+    ///   * `ErrorCode::PhysicalStorageFailure`: Write-ahead log or lock file couldn't be read
+    ///   * `ErrorCode::ResourceBusy`: Instance is locked by another process
+    ///   * `ErrorCode::UnmappedError`: Generic error
+    fn open_with_options(
+        instance_id: InstanceId,
+        need_defaults: OpenNeedDefaults,
+        need_kvs: OpenNeedKvs,
+        dir: Option<String>,
+        options: KvsOptions,
+    ) -> Result<GenericKvs<J>, ErrorCode> {
+        let start = Instant::now();
+        let dir = dir.map(PathBuf::from).unwrap_or_default();
+        let filename_default = dir.join(format!("{}_default", instance_id.filename_prefix()));
+        let filename_prefix = dir.join(instance_id.filename_prefix());
+        let filename_kvs = path_with_suffix(&filename_prefix, "_0");
+
+        let ephemeral = options.open_mode == OpenMode::Ephemeral;
+        let read_only = options.open_mode == OpenMode::ReadOnly;
+        let journaled = options.journal_role != JournalRole::Off && !ephemeral;
+        let process_lock = if options.file_lock && !ephemeral {
+            Some(ProcessLock::acquire(
+                &path_with_suffix(&filename_prefix, ".lock"),
+                read_only || journaled,
+            )?)
+        } else {
+            None
+        };
+        let mut default = match options.embedded_defaults {
+            None if ephemeral => KvsMap::new(),
+            None => kvs_defaults::resolve(GenericKvs::<J>::open_kvs(
+                &filename_default,
+                need_defaults,
+                OpenKvsVerifyHash::No,
+                None,
+                None,
+            )?)?,
+            Some(data) if ephemeral => kvs_defaults::resolve(JsonBackend::parse_kvs(data)?)?,
+            Some(data) => {
+                Self::open_embedded_defaults(&filename_default, data, options.defaults_precedence)?
+            }
+        };
+        let schemas = KeySchemas::take(&mut default)?;
+        let access = if ephemeral {
+            options.access_policy
+        } else {
+            options
+                .access_policy
+                .load_manifest(&path_with_suffix(&filename_prefix, "_owners.json"))?
+        };
+        let mut kvs = if ephemeral {
+            KvsMap::new()
+        } else {
+            // Use hash checking for the main KVS file
+            let hash_path = path_with_suffix(&filename_prefix, "_0.hash");
+            GenericKvs::<J>::open_kvs(
+                &filename_kvs,
+                need_kvs,
+                OpenKvsVerifyHash::Yes,
+                Some(&hash_path),
+                options.cipher.as_deref(),
+            )?
+        };
+        let schema_version = kvs_migration::take_version(&mut kvs)?;
+        let mut stored_versions = kvs_version::take_versions(&mut kvs);
+        let defaults_hash = DefaultsHash::new(&default);
+        let changed_defaults =
+            defaults_hash.changed_keys(kvs_defaults_hash::take_defaults_hash(&mut kvs).as_ref());
+        if options.write_ahead_log && options.cipher.is_some() {
+            log_error!("the write-ahead log can't be combined with encryption");
+            return Err(ErrorCode::EncryptionFailed);
+        }
+        let delta = if ephemeral {
+            None
+        } else {
+            let delta = WriteAheadLog::new(
+                path_with_suffix(&filename_prefix, ".delta"),
+                usize::MAX,
+                options.durability,
+            );
+            let count = delta.replay(&mut kvs, &mut stored_versions)?;
+            log_info!("replayed {count} delta journal records");
+            (!read_only).then_some(delta)
+        };
+        let wal = if options.write_ahead_log && !ephemeral {
+            let wal = WriteAheadLog::new(
+                path_with_suffix(&filename_prefix, ".wal"),
+                options.wal_compact_threshold,
+                options.durability,
+            );
+            let count = wal.replay(&mut kvs, &mut stored_versions)?;
+            log_info!("replayed {count} write-ahead log records");
+            (!read_only).then_some(wal)
+        } else {
+            None
+        };
+
+        if journaled && (options.write_ahead_log || options.cipher.is_some()) {
+            log_error!(
+                "the merge journal can't be combined with the write-ahead log or encryption"
+            );
+            return Err(ErrorCode::ValidationFailed);
+        }
+        // Keys changed by merge journal records get a version above the stored one
+        let replay_base = options.key_versions.then(|| kvs.clone());
+        let journal = if journaled {
+            let journal = MergeJournal::new(
+                path_with_suffix(&filename_prefix, ".journal"),
+                options.journal_role == JournalRole::Compactor,
+            );
+            let count = journal.read(&mut journal.lock(false)?)?.apply(&mut kvs);
+            log_info!("applied {count} merge journal records");
+            Some(journal)
+        } else {
+            None
+        };
+
+        let key_stats = match options.key_stats {
+            KeyStatsMode::Off => None,
+            KeyStatsMode::Memory => Some(KeyCounters::new(None, options.durability)),
+            KeyStatsMode::Persistent if ephemeral => {
+                Some(KeyCounters::new(None, options.durability))
+            }
+            KeyStatsMode::Persistent => Some(KeyCounters::new(
+                Some(path_with_suffix(&filename_prefix, "_stats.json")),
+                options.durability,
+            )),
+        };
+
+        let versions = replay_base.map(|base| {
+            let versions = KeyVersions::new(stored_versions);
+            for key in changed_keys(&base, &kvs) {
+                versions.increment(key);
+            }
+            versions
+        });
+
+        let metadata = options.key_metadata.then(|| {
+            KeyMetadataStore::new(
+                (!ephemeral).then(|| path_with_suffix(&filename_prefix, "_metadata.json")),
+                options.durability,
+                options.writer_id,
+            )
+        });
+
+        let size_history = options.size_history.then(|| {
+            SizeHistory::new(
+                (!ephemeral).then(|| path_with_suffix(&filename_prefix, "_size_history.json")),
+                options.durability,
+            )
+        });
+
+        log_info!("opened KVS: instance '{instance_id}'");
+        log_info!("snapshot retention: {:?}", options.snapshot_retention);
+        log_debug!("open of {filename_prefix:?} took {:?}", start.elapsed());
+
+        let kvs = GenericKvs {
+            kvs: Mutex::new(kvs),
+            gate: AccessGate::new(options.fairness_policy),
+            default,
+            filename_prefix,
+            storage_format: options.storage_format,
+            compression: options.compression,
+            durability: options.durability,
+            ephemeral,
+            read_only,
+            _process_lock: process_lock,
+            strict_keys: options
+                .strict_keys
+                .then_some(options.strict_exempt_prefixes),
+            flush_on_exit: AtomicBool::new(options.flush_on_exit && !read_only),
+            cipher: options.cipher,
+            wal,
+            retention: options.snapshot_retention,
+            schema_version: AtomicU64::new(schema_version),
+            migrations: Mutex::new(options.migrations),
+            identity: options.identity,
+            access,
+            delta,
+            journal,
+            observers: Observers::default(),
+            rules: Rules::default(),
+            validators: Validators::default(),
+            schemas,
+            max_value_size: options.max_value_size,
+            overrides: Overrides::default(),
+            restore_hook: Mutex::new(None),
+            flush_callback: Mutex::new(None),
+            default_provider: DefaultProviders::default(),
+            key_stats,
+            write_stats: WriteCounters::new(options.write_amplification_threshold),
+            worker: options.worker,
+            dirty: Mutex::new(HashSet::new()),
+            versions,
+            metadata,
+            defaults_hash,
+            changed_defaults,
+            size_history,
+            last_flush: Mutex::new(None),
+            auto_flush: AutoFlush::default(),
+            _backend: std::marker::PhantomData,
+        };
+        if let Err(e) = kvs.migrate() {
+            kvs.flush_on_exit(false);
+            return Err(e);
+        }
+        Ok(kvs)
+    }
+
+    /// Control the flush on exit behaviour
+    ///
+    /// # Parameters
+    ///   * `flush_on_exit`: Flag to control flush-on-exit behaviour
+    fn flush_on_exit(&self, flush_on_exit: bool) {
+        self.flush_on_exit
+            .store(flush_on_exit, atomic::Ordering::Relaxed);
+    }
+
+    /// Resets a key-value-storage to its initial state
+    ///
+    /// # Return Values
+    ///   * Ok: Reset of the KVS was successful
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    fn reset(&self) -> Result<(), ErrorCode> {
+        self.check_access_all(true)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        let events = self.replace_data(&mut kvs, HashMap::new())?;
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&events);
+        Ok(())
+    }
+
+    /// Get list of all keys
+    ///
+    /// # Return Values
+    ///   * Ok: List of all keys sorted by key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        let _gate = self.gate.read()?;
+        let mut keys: Vec<String> = self
+            .kvs
+            .lock()?
+            .keys()
+            .filter(|key| self.readable(key))
+            .map(|x| x.to_string())
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Check if a key exists
+    ///
+    /// # Parameters
+    ///   * `key`: Key to check for existence
+    ///
+    /// # Return Values
+    ///   * Ok(`true`): Key exists
+    ///   * Ok(`false`): Key doesn't exist
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.check_read(key)?;
+        let _gate = self.gate.read()?;
+        Ok(self.overrides.get(key)?.is_some() || self.kvs.lock()?.contains_key(key))
+    }
+
+    /// Get the assigned value for a given key
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to retrieve the value from
+    ///
+    /// # Return Value
+    ///   * Ok: Type specific value if key was found
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
+    fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        self.count_read(key);
+        let value = self.current_value(key)?;
+
+        if let Some(value) = value {
+            Ok(value)
+        } else if let Some(value) = self.lookup_default(key)? {
+            Ok(value)
+        } else {
+            log_error!("get_value could not find key: {key}");
+            Err(ErrorCode::KeyNotFound)
+        }
+    }
+
+    /// Get the assigned value for a given key
+    ///
+    /// See [Variants](https://docs.rs/tinyjson/latest/tinyjson/enum.JsonValue.html#variants) for
+    /// supported value types. Numbers are converted between `f64`, `f32` and the integer types
+    /// if they fit without truncation, [`Parsed`](crate::kvs_value::Parsed) additionally parses
+    /// strings.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to retrieve the value from
+    ///
+    /// # Return Value
+    ///   * Ok: Type specific value if key was found
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ConversionFailed`: Type conversion failed or value is out of range
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found in KVS nor in defaults
+    fn get_value_as<T>(&self, key: &str) -> Result<T, ErrorCode>
+    where
+        for<'a> T: TryFrom<&'a KvsValue> + std::clone::Clone,
+        for<'a> <T as TryFrom<&'a KvsValue>>::Error: std::fmt::Debug,
+    {
+        self.count_read(key);
+        let value = self.current_value(key)?;
+
+        if let Some(value) = &value {
+            match T::try_from(value) {
+                Ok(value) => Ok(value),
+                Err(err) => {
+                    log_error!("get_value could not convert KvsValue from KVS store: {err:#?}");
+                    Err(ErrorCode::ConversionFailed)
+                }
+            }
+        } else if let Some(value) = self.lookup_default(key)? {
+            // check if key has a default value
+            match T::try_from(&value) {
+                Ok(value) => Ok(value),
+                Err(err) => {
+                    log_error!("get_value could not convert KvsValue from default store: {err:#?}");
+                    Err(ErrorCode::ConversionFailed)
+                }
+            }
+        } else {
+            log_error!("get_value could not find key: {key}");
+
+            Err(ErrorCode::KeyNotFound)
+        }
+    }
+
+    /// Get default value for a given key
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///   * `FEAT_REQ__KVS__default_value_retrieval`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to get the default for
+    ///
+    /// # Return Values
+    ///   * Ok: `KvsValue` for the key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key not found in defaults
+    fn get_default_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        self.check_read(key)?;
+        if let Some(value) = self.lookup_default(key)? {
+            Ok(value)
+        } else {
+            Err(ErrorCode::KeyNotFound)
+        }
+    }
+
+    /// Return if the value wasn't set yet and uses its default value
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__default_values`
+    ///
+    /// # Parameters
+    ///   * `key`: Key to check if a default exists
+    ///
+    /// # Return Values
+    ///   * Ok(true): Key currently returns the default value
+    ///   * Ok(false): Key returns the set value
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key wasn't found
+    fn is_value_default(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.check_read(key)?;
+        let stored = {
+            let _gate = self.gate.read()?;
+            self.kvs.lock()?.contains_key(key)
+        };
+
+        if stored {
+            Ok(false)
+        } else if self.lookup_default(key)?.is_some() {
+            Ok(true)
+        } else {
+            Err(ErrorCode::KeyNotFound)
+        }
+    }
+
+    /// Assign a value to a given key
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set value
+    ///   * `value`: Value to be set
+    ///
+    /// # Return Values
+    ///   * Ok: Value was assigned to key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: Key has no default in strict mode or value rejected by a validator
+    fn set_value<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        value: V,
+    ) -> Result<(), ErrorCode> {
+        self.set_value_annotated(key.into(), value.into(), None)
+    }
+
+    /// Remove a key
+    ///
+    /// # Parameters
+    ///   * `key`: Key to remove
+    ///
+    /// # Return Values
+    ///   * Ok: Key removed successfully
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Key not found
+    fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        self.check_write(key)?;
+        let gate = self.write_gate()?;
+        let mut kvs = self.kvs.lock()?;
+        if !kvs.contains_key(key) {
+            return Err(ErrorCode::KeyNotFound);
+        }
+        let compact = self.wal_append(WalRecord::Remove(key))?;
+        self.record_write(key);
+        kvs.remove(key);
+        if compact {
+            self.wal_compact(&kvs);
+        }
+        drop(kvs);
+        drop(gate);
+
+        self.observers.notify(&[KvsEvent::Removed {
+            key: key.to_string(),
+        }]);
+        Ok(())
+    }
+
+    /// Flush the in-memory key-value-storage to the persistent storage
+    ///
+    /// Persistent key statistics are written along with the KVS file. The flush is rejected if a
+    /// consistency rule is violated, see [`add_rule`](GenericKvs::add_rule).
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///   * `FEAT_REQ__KVS__persistency`
+    ///   * `FEAT_REQ__KVS__integrity_check`
+    ///
+    /// # Return Values
+    ///   * Ok: Flush successful
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonGeneratorError`: Failed to serialize to JSON
+    ///   * `ErrorCode::ConversionFailed`: JSON could not serialize into String
+    ///   * `ErrorCode::ValidationFailed`: A consistency rule is violated
+    ///   * `ErrorCode::UnmappedError`: Unmapped error
+    fn flush(&self) -> Result<(), ErrorCode> {
+        let _timer = OpTimer::start("flush", &self.filename_prefix);
+        let gate = self.write_gate()?;
+        kvs_rules::reject(&self.check_rules()?)?;
+        if self.ephemeral {
+            return Ok(());
+        }
+        if self
+            .journal
+            .as_ref()
+            .is_some_and(|journal| !journal.is_compactor())
+        {
+            drop(gate);
+            return self.merge_journal().map(|_| ());
+        }
+        let generation = self.next_generation();
+        self.snapshot_rotate().map_err(|e| {
+            log_error!("snapshot_rotate failed: {e:?}");
+            e
+        })?;
+        self.snapshot_prune().map_err(|e| {
+            log_error!("snapshot_prune failed: {e:?}");
+            e
+        })?;
+        let mut kvs = self.kvs.lock().map_err(|e| {
+            log_error!("Mutex lock failed: {e:?}");
+            ErrorCode::MutexLockFailed
+        })?;
+        // The compactor merges the journal and empties it while appends are locked out
+        let mut events = Vec::new();
+        let mut journal_lock = match &self.journal {
+            Some(journal) => {
+                let mut lock = journal.lock(true)?;
+                let read = journal.read(&mut lock)?;
+                events = self.apply_journal(&mut kvs, read)?.1;
+                Some((journal, lock))
+            }
+            None => None,
+        };
+        self.save_base(&kvs).map_err(|e| {
+            log_error!("save_kvs failed: {e:?}");
+            e
+        })?;
+        atomic_replace(
+            &self.snapshot_path(0, "gen"),
+            generation.to_string().as_bytes(),
+            self.durability,
+        )?;
+        let size = Self::saved_size(&self.filename_prefix);
+        self.write_stats.written(size);
+        self.collect_wal_written();
+        if let Some(wal) = &self.wal {
+            wal.truncate()?;
+        }
+        if let Some((journal, lock)) = &mut journal_lock {
+            journal.reset(lock)?;
+        }
+        drop(journal_lock);
+        self.dirty
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clear();
+        *self
+            .last_flush
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = Some(SystemTime::now());
+        drop(kvs);
+        self.write_stats.flushed()?;
+        if let Some(stats) = &self.key_stats {
+            stats.save()?;
+        }
+        if let Some(metadata) = &self.metadata {
+            metadata.save()?;
+        }
+        if let Some(size_history) = &self.size_history {
+            size_history.record(size, SystemTime::now())?;
+        }
+        drop(gate);
+
+        self.observers.notify(&events);
+        let callback = self
+            .flush_callback
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clone();
+        if let Some(callback) = callback {
+            callback(&FlushInfo { generation, size });
+        }
+        Ok(())
+    }
+
+    /// Get the count of snapshots
+    ///
+    /// # Return Values
+    ///   * usize: Count of found snapshots
+    fn snapshot_count(&self) -> usize {
+        let mut count = 0;
+        if self.ephemeral {
+            return count;
+        }
+
+        for idx in 0..self.retention.max_count {
+            let snapshot_path = self.snapshot_path(idx, "json");
+            if !snapshot_path.exists() {
+                break;
+            }
+
+            count += 1;
+        }
+
+        count
+    }
+
+    /// Return the default maximum snapshot count
+    ///
+    /// The count of an instance can be configured with
+    /// [`KvsBuilder::snapshot_retention`](crate::kvs_builder::KvsBuilder::snapshot_retention).
+    ///
+    /// # Return Values
+    ///   * usize: Default maximum count of snapshots
+    fn snapshot_max_count() -> usize {
+        KVS_MAX_SNAPSHOTS
+    }
+
+    /// Recover key-value-storage from snapshot
+    ///
+    /// Restore a previously created KVS snapshot.
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__snapshots`
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID
+    ///
+    /// # Return Values
+    ///   * `Ok`: Snapshot restored
+    ///   * `ErrorCode::InvalidSnapshotId`: Invalid snapshot ID
+    ///   * `ErrorCode::ValidationFailed`: KVS hash validation failed or restored data rejected by
+    ///     the restore hook, strict mode, a validator or a consistency rule
+    ///   * `ErrorCode::JsonParserError`: JSON parser error
+    ///   * `ErrorCode::KvsFileReadError`: KVS file not found
+    ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error
+    ///   * `ErrorCode::UnmappedError`: Generic error
+    fn snapshot_restore(&self, id: SnapshotId) -> Result<(), ErrorCode> {
+        // fail if the snapshot ID is the current KVS
+        if id.0 == 0 {
+            log_error!("tried to restore current KVS as snapshot");
+            return Err(ErrorCode::InvalidSnapshotId);
+        }
+
+        if self.snapshot_count() < id.0 {
+            log_error!("tried to restore a non-existing snapshot");
+            return Err(ErrorCode::InvalidSnapshotId);
+        }
+
+        let snap_path = path_with_suffix(&self.filename_prefix, &format!("_{}", id.0));
+        let kvs = Self::open_kvs(
+            &snap_path,
+            OpenKvsNeedFile::Required,
+            OpenKvsVerifyHash::Yes,
+            None,
+            self.cipher.as_deref(),
+        )?;
+        self.restore_data(kvs)
+    }
+
+    /// Return the KVS-filename for a given snapshot ID
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID to get the filename for
+    ///
+    /// # Return Values
+    ///   * `Ok`: Filename for ID
+    ///   * `ErrorCode::FileNotFound`: KVS file for snapshot ID not found
+    fn get_kvs_filename(&self, id: SnapshotId) -> Result<PathBuf, ErrorCode> {
+        let path = self.snapshot_path(id.0, "json");
+        if self.ephemeral || !path.exists() {
+            Err(ErrorCode::FileNotFound)
+        } else {
+            Ok(path)
+        }
+    }
+
+    /// Return the hash-filename for a given snapshot ID
+    ///
+    /// # Parameters
+    ///   * `id`: Snapshot ID to get the hash filename for
+    ///
+    /// # Return Values
+    ///   * `Ok`: Hash filename for ID
+    ///   * `ErrorCode::FileNotFound`: Hash file for snapshot ID not found
+    fn get_hash_filename(&self, id: SnapshotId) -> Result<PathBuf, ErrorCode> {
+        let path = self.snapshot_path(id.0, "hash");
+        if self.ephemeral || !path.exists() {
+            Err(ErrorCode::FileNotFound)
+        } else {
+            Ok(path)
+        }
+    }
+}
+
+impl<J: KvsBackend> Drop for GenericKvs<J> {
+    fn drop(&mut self) {
+        if self.flush_on_exit.load(atomic::Ordering::Relaxed) {
+            if let Err(e) = self.flush() {
+                log_error!("GenericKvs::flush() failed in Drop: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::kvs_builder::KvsBuilder;
+    use crate::kvs_value::Parsed;
+    use crate::kvs_write_stats::WriteStats;
+    use crate::Kvs;
+    use tempfile::tempdir;
+
+    mod mock_backend {
+        use super::*;
+        use crate::kvs_backend::KvsBackend;
+
+        #[derive(Default, Clone)]
+        pub struct KvsMockBackend {}
+
+        impl KvsBackend for KvsMockBackend {
+            fn load_kvs(
+                source_path: PathBuf,
+                _verify_hash: bool,
+                _hash_source: Option<PathBuf>,
+            ) -> Result<KvsMap, ErrorCode> {
+                let mut map = KvsMap::new();
+                let fname = source_path.display().to_string();
+                if fname.ends_with("default") {
+                    map.insert("mock_default_key".to_string(), KvsValue::from(111.0));
+                } else {
+                    map.insert("mock_key".to_string(), KvsValue::from(123.0));
+                }
+                Ok(map)
+            }
+
+            fn save_kvs(
+                _kvs: &KvsMap,
+                _destination_path: PathBuf,
+                _add_hash: bool,
+            ) -> Result<(), ErrorCode> {
+                Ok(())
+            }
+        }
+
+        #[derive(Default, Clone)]
+        pub struct KvsMockBackendFail;
+
+        impl KvsBackend for KvsMockBackendFail {
+            fn load_kvs(
+                _source_path: PathBuf,
+                _verify_hash: bool,
+                _hash_source: Option<PathBuf>,
+            ) -> Result<KvsMap, ErrorCode> {
+                Err(ErrorCode::UnmappedError)
+            }
+
+            fn save_kvs(
+                _kvs: &KvsMap,
+                _destination_path: PathBuf,
+                _add_hash: bool,
+            ) -> Result<(), ErrorCode> {
+                Err(ErrorCode::UnmappedError)
+            }
+        }
+    }
+
+    use mock_backend::KvsMockBackend;
+    use mock_backend::KvsMockBackendFail;
+
+    /// KVS with mock backend, owning the directory for the files not written by the backend
+    struct MockKvs {
+        kvs: GenericKvs<KvsMockBackend>,
+        _dir: tempfile::TempDir,
+    }
+
+    impl std::ops::Deref for MockKvs {
+        type Target = GenericKvs<KvsMockBackend>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.kvs
+        }
+    }
+
+    /// Return a temporary directory and its path for tests opening the KVS directly
+    fn mock_dir() -> (tempfile::TempDir, Option<String>) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        (dir, Some(path))
+    }
+
+    fn new_kvs_with_mock() -> MockKvs {
+        let instance_id = InstanceId::new(100);
+        let (dir, path) = mock_dir();
+        let kvs = GenericKvs::<KvsMockBackend>::open(
+            instance_id,
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            path,
+        )
+        .unwrap();
+        MockKvs { kvs, _dir: dir }
+    }
+
+    fn new_kvs_with_mock_required() -> MockKvs {
+        let instance_id = InstanceId::new(101);
+        let (dir, path) = mock_dir();
+        let kvs = GenericKvs::<KvsMockBackend>::open(
+            instance_id,
+            OpenNeedDefaults::Required,
+            OpenNeedKvs::Required,
+            path,
+        )
+        .unwrap();
+        MockKvs { kvs, _dir: dir }
+    }
+
+    fn new_kvs_with_mock_required_fail() -> Result<GenericKvs<KvsMockBackendFail>, ErrorCode> {
+        let instance_id = InstanceId::new(102);
+        GenericKvs::<KvsMockBackendFail>::open(
+            instance_id,
+            OpenNeedDefaults::Required,
+            OpenNeedKvs::Required,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_open_kvs_with_mock_required_success() {
+        let kvs = new_kvs_with_mock_required();
+        // Should contain the mock_key from mock backend
+        assert!(kvs.key_exists("mock_key").unwrap());
+        let value = kvs.get_value("mock_key").unwrap();
+        assert_eq!(*value.get::<f64>().unwrap(), 123.0);
+    }
+
+    #[test]
+    fn test_open_kvs_with_mock_required_fail() {
+        let res = new_kvs_with_mock_required_fail();
+        assert!(res.is_err());
+        // Should be ErrorCode::UnmappedError
+        assert!(matches!(res, Err(ErrorCode::UnmappedError)));
+    }
+
+    #[test]
+    fn test_open_kvs_with_mock_backend() {
+        let kvs = new_kvs_with_mock();
+        // Should contain the mock_key from mock backend
+        assert!(kvs.key_exists("mock_key").unwrap());
+        let value = kvs.get_value("mock_key").unwrap();
+        assert_eq!(*value.get::<f64>().unwrap(), 123.0);
+    }
+
+    #[test]
+    fn test_set_and_get_value() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("foo", 42.0).unwrap();
+        let value = kvs.get_value("foo").unwrap();
+        assert_eq!(*value.get::<f64>().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_set_and_get_values() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_values([("a", KvsValue::from(1.0)), ("b", KvsValue::from(true))])
+            .unwrap();
+        kvs.set_values(vec![("a".to_string(), 2.0)]).unwrap();
+
+        assert_eq!(
+            kvs.get_values(&["a", "missing", "b"]).unwrap(),
+            vec![Some(KvsValue::from(2.0)), None, Some(KvsValue::from(true))]
+        );
+        assert!(kvs.get_values(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_value_as() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("bar", 99.0).unwrap();
+        let v: f64 = kvs.get_value_as("bar").unwrap();
+        assert_eq!(v, 99.0);
+    }
+
+    #[test]
+    fn test_evaluate_flag() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("feature.on", true).unwrap();
+        kvs.set_value(
+            "feature.pilot",
+            KvsMap::from([(
+                "allowlist".to_string(),
+                KvsValue::from(vec![KvsValue::from("vin-1".to_string())]),
+            )]),
+        )
+        .unwrap();
+
+        assert_eq!(kvs.evaluate_flag("feature.on", "vin-2"), Ok(true));
+        assert_eq!(kvs.evaluate_flag("feature.pilot", "vin-1"), Ok(true));
+        assert_eq!(kvs.evaluate_flag("feature.pilot", "vin-2"), Ok(false));
+        assert_eq!(
+            kvs.evaluate_flag("feature.missing", "vin-1"),
+            Err(ErrorCode::KeyNotFound)
+        );
+    }
+
+    #[test]
+    fn test_get_default_value_and_is_value_default() {
+        let kvs = new_kvs_with_mock_required();
+        println!("{:?}", kvs.default);
+        // mock_key is always present as default in mock backend
+        assert!(kvs.is_value_default("mock_default_key").unwrap());
+        let def = kvs.get_default_value("mock_default_key").unwrap();
+        assert_eq!(*def.get::<f64>().unwrap(), 111.0);
+
+        // mock_key is always present as default in mock backend
+        assert!(!kvs.is_value_default("mock_key").unwrap());
+    }
+
+    #[test]
+    fn test_default_provider() {
+        let kvs = std::sync::Arc::new(new_kvs_with_mock_required());
+        kvs.set_value("vin", "WVW000".to_string()).unwrap();
+
+        // Provider may read other keys of the KVS
+        let weak = std::sync::Arc::downgrade(&kvs);
+        kvs.set_default_provider(
+            move |key| match key {
+                "region" => {
+                    let vin: String = weak.upgrade()?.get_value_as("vin").ok()?;
+                    Some(KvsValue::from(vin[..3].to_string()))
+                }
+                "mock_default_key" => Some(KvsValue::from(0.0)),
+                _ => None,
+            },
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            kvs.get_value_as::<String>("region").unwrap(),
+            "WVW".to_string()
+        );
+        assert!(kvs.is_value_default("region").unwrap());
+        assert_eq!(
+            kvs.get_default_value("region").unwrap(),
+            KvsValue::from("WVW".to_string())
+        );
+        // Static defaults take precedence
+        assert_eq!(kvs.get_value_as::<f64>("mock_default_key").unwrap(), 111.0);
+        assert_eq!(kvs.get_value("unknown"), Err(ErrorCode::KeyNotFound));
+
+        kvs.set_value("region", "XYZ".to_string()).unwrap();
+        kvs.reset_key_to_default("region").unwrap();
+        assert!(kvs.is_value_default("region").unwrap());
+
+        kvs.clear_default_provider().unwrap();
+        assert_eq!(kvs.get_value("region"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_key_exists_and_get_all_keys() {
+        let kvs = new_kvs_with_mock();
+        assert!(kvs.key_exists("mock_key").unwrap());
+        kvs.set_value("foo", true).unwrap();
+        assert!(kvs.key_exists("foo").unwrap());
+        let keys = kvs.get_all_keys().unwrap();
+        assert!(keys.contains(&"foo".to_string()));
+        assert!(keys.contains(&"mock_key".to_string()));
+    }
+
+    #[test]
+    fn test_get_all_entries() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("foo", true).unwrap();
+        // Defaults aren't included, entries are sorted
+        assert_eq!(
+            kvs.get_all_entries().unwrap(),
+            vec![
+                ("foo".to_string(), KvsValue::from(true)),
+                ("mock_key".to_string(), KvsValue::from(123.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_keys_with_prefix_and_matching() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("diagnostics/dtc/count", 1.0).unwrap();
+        kvs.set_value("diagnostics/uptime", 2.0).unwrap();
+        kvs.set_value("diag", 3.0).unwrap();
+
+        let mut keys = kvs.get_keys_with_prefix("diagnostics/").unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["diagnostics/dtc/count", "diagnostics/uptime"]);
+        assert!(kvs.get_keys_with_prefix("none").unwrap().is_empty());
+
+        assert_eq!(
+            kvs.get_keys_matching("diagnostics/*/count").unwrap(),
+            vec!["diagnostics/dtc/count"]
+        );
+        assert_eq!(kvs.get_keys_matching("dia?").unwrap(), vec!["diag"]);
+    }
+
+    #[test]
+    fn test_key_count() {
+        let kvs = new_kvs_with_mock();
+        assert_eq!(kvs.key_count().unwrap(), 1);
+        kvs.set_value("diagnostics/dtc/count", 1.0).unwrap();
+        kvs.set_value("diagnostics/uptime", 2.0).unwrap();
+        kvs.set_value("diag", 3.0).unwrap();
+
+        assert_eq!(kvs.key_count().unwrap(), 4);
+        assert_eq!(kvs.key_count_prefix("diagnostics/").unwrap(), 2);
+        assert_eq!(kvs.key_count_prefix("none").unwrap(), 0);
+        assert!(kvs.any_key_with_prefix("diag").unwrap());
+        assert!(!kvs.any_key_with_prefix("none").unwrap());
+
+        let size = kvs.serialized_size().unwrap();
+        assert_eq!(size, kvs.stats().unwrap().serialized_size);
+        kvs.set_value("name", "front-left".to_string()).unwrap();
+        assert!(kvs.serialized_size().unwrap() > size);
+    }
+
+    #[test]
+    fn test_open_with_dir() {
+        let dir = tempdir().unwrap();
+        let partition = dir.path().join("persistent");
+        assert_eq!(
+            Kvs::open_with_dir(
+                InstanceId::new(0),
+                &partition,
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Optional,
+            )
+            .err(),
+            Some(ErrorCode::FileNotFound)
+        );
+
+        fs::create_dir(&partition).unwrap();
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            &partition,
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.flush().unwrap();
+        assert!(partition.join("kvs_0_0.json").exists());
+    }
+
+    #[test]
+    fn test_fairness_policy() {
+        use crate::kvs_fairness::FairnessPolicy;
+
+        for policy in [
+            FairnessPolicy::WriterPreferring,
+            FairnessPolicy::BoundedReaders(1),
+        ] {
+            let kvs = std::sync::Arc::new(
+                KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                    .open_mode(OpenMode::Ephemeral)
+                    .fairness_policy(policy)
+                    .build()
+                    .unwrap(),
+            );
+            // Observers run after the writer left the gate and may read
+            let observer_kvs = std::sync::Arc::downgrade(&kvs);
+            kvs.subscribe("a", move |_: &KvsEvent| {
+                if let Some(kvs) = observer_kvs.upgrade() {
+                    kvs.get_value("a").unwrap();
+                }
+            })
+            .unwrap();
+
+            let handles: Vec<_> = (0..4)
+                .map(|idx| {
+                    let kvs = kvs.clone();
+                    std::thread::spawn(move || {
+                        for n in 0..25 {
+                            kvs.set_value("a", (idx * n) as f64).unwrap();
+                            kvs.get_value("a").unwrap();
+                            assert!(kvs.key_exists("a").unwrap());
+                        }
+                        kvs.flush().unwrap();
+                    })
+                })
+                .collect();
+            for handle in handles {
+                handle.join().unwrap();
+            }
+            assert_eq!(kvs.get_all_keys().unwrap(), vec!["a"]);
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.set_value("b", 2.0).unwrap();
+        kvs.set_value("a", 3.0).unwrap();
+
+        let stats = kvs.stats().unwrap();
+        assert_eq!(stats.key_count, 2);
+        assert_eq!(stats.dirty_key_count, 2);
+        assert_eq!(stats.last_flush, None);
+        assert_eq!(stats.snapshot_disk_usage, 0);
+
+        kvs.flush().unwrap();
+        let stats = kvs.stats().unwrap();
+        assert_eq!(stats.dirty_key_count, 0);
+        assert!(stats.last_flush.is_some());
+        assert_eq!(
+            stats.serialized_size,
+            fs::metadata(dir.path().join("kvs_0_0.json")).unwrap().len()
+        );
+
+        kvs.remove_key("b").unwrap();
+        kvs.flush().unwrap();
+        let rotated = kvs.stats().unwrap().snapshot_disk_usage;
+        assert!(rotated > 0);
+        kvs.snapshot_create("t").unwrap();
+        assert!(kvs.stats().unwrap().snapshot_disk_usage > rotated);
+
+        kvs.reset().unwrap();
+        assert_eq!(kvs.stats().unwrap().dirty_key_count, 1);
+    }
+
+    #[test]
+    fn test_ephemeral() {
+        let dir = tempdir().unwrap();
+        let kvs = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .open_mode(OpenMode::Ephemeral)
+            .embedded_defaults(br#"{"mode": "eco"}"#)
+            .write_ahead_log(true)
+            .build()
+            .unwrap();
+        assert!(kvs.is_value_default("mode").unwrap());
+
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.flush().unwrap();
+        kvs.flush().unwrap();
+        assert_eq!(kvs.snapshot_count(), 0);
+        assert_eq!(
+            kvs.snapshot_restore(SnapshotId::new(1)),
+            Err(ErrorCode::InvalidSnapshotId)
+        );
+        assert_eq!(
+            kvs.snapshot_create("tag"),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert!(kvs.snapshot_list().unwrap().is_empty());
+
+        let mut buffer = b"old".to_vec();
+        kvs.flush_to(&mut buffer).unwrap();
+        assert_eq!(
+            JsonBackend::parse_kvs(&buffer).unwrap(),
+            KvsMap::from([("a".to_string(), KvsValue::from(1.0))])
+        );
+        drop(kvs);
+
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_remove_prefix() {
+        let kvs = new_kvs_with_mock();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let events_cb = events.clone();
+        kvs.subscribe("diagnostics/dtc/*", move |event: &KvsEvent| {
+            events_cb.lock().unwrap().push(event.clone());
+        })
+        .unwrap();
+        kvs.set_value("diagnostics/dtc/count", 1.0).unwrap();
+        kvs.set_value("diagnostics/uptime", 2.0).unwrap();
+        kvs.set_value("diag", 3.0).unwrap();
+        events.lock().unwrap().clear();
+
+        assert_eq!(kvs.remove_prefix("diagnostics/").unwrap(), 2);
+        assert!(kvs.get_keys_with_prefix("diagnostics/").unwrap().is_empty());
+        assert!(kvs.key_exists("diag").unwrap());
+        assert_eq!(kvs.remove_prefix("diagnostics/").unwrap(), 0);
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            KvsEvent::PrefixRemoved { prefix, keys } => {
+                assert_eq!(prefix, "diagnostics/");
+                assert_eq!(keys.len(), 2);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+
+    #[test]
+    fn test_remove_key() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("baz", 1.0).unwrap();
+        assert!(kvs.key_exists("baz").unwrap());
+        kvs.remove_key("baz").unwrap();
+        assert!(!kvs.key_exists("baz").unwrap());
+    }
+
+    #[test]
+    fn test_reset_key() {
+        let kvs = new_kvs_with_mock_required();
+        kvs.set_value("mock_default_key", 5.0).unwrap();
+        assert!(kvs.reset_key("mock_default_key").unwrap());
+        assert!(kvs.is_value_default("mock_default_key").unwrap());
+        assert_eq!(kvs.get_value_as::<f64>("mock_default_key").unwrap(), 111.0);
+
+        // Keys never set are no error, unlike remove_key
+        assert!(!kvs.reset_key("mock_default_key").unwrap());
+        assert!(!kvs.reset_key("baz").unwrap());
+        assert_eq!(kvs.remove_key("baz"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_strict_keys() {
+        let (_dir, path) = mock_dir();
+        let kvs = GenericKvs::<KvsMockBackend>::open_with_options(
+            InstanceId::new(102),
+            OpenNeedDefaults::Required,
+            OpenNeedKvs::Required,
+            path,
+            KvsOptions {
+                strict_keys: true,
+                strict_exempt_prefixes: vec!["diag.".to_string()],
+                ..KvsOptions::default()
+            },
+        )
+        .unwrap();
+
+        kvs.set_value("mock_default_key", 1.0).unwrap();
+        kvs.set_value("diag.scratch", 2.0).unwrap();
+        assert_eq!(
+            kvs.set_value("adhoc", 3.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("mock_default_key", 4.0);
+        tx.set_value("adhoc", 5.0);
+        assert_eq!(tx.commit(), Err(ErrorCode::ValidationFailed));
+        assert_eq!(kvs.get_value_as::<f64>("mock_default_key").unwrap(), 1.0);
+        assert!(!kvs.key_exists("adhoc").unwrap());
+    }
+
+    #[test]
+    fn test_key_stats() {
+        let (_dir, path) = mock_dir();
+        let kvs = GenericKvs::<KvsMockBackend>::open_with_options(
+            InstanceId::new(103),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            path,
+            KvsOptions {
+                key_stats: KeyStatsMode::Memory,
+                ..KvsOptions::default()
+            },
+        )
+        .unwrap();
+
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.get_value("a").unwrap();
+        kvs.get_value_as::<f64>("a").unwrap();
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("b", 2.0);
+        tx.remove_key("a");
+        tx.commit().unwrap();
+
+        assert_eq!(
+            kvs.key_stats().unwrap(),
+            vec![
+                (
+                    "a".to_string(),
+                    KeyStats {
+                        reads: 2,
+                        writes: 2
+                    }
+                ),
+                (
+                    "b".to_string(),
+                    KeyStats {
+                        reads: 0,
+                        writes: 1
+                    }
+                ),
+            ]
+        );
+        assert!(new_kvs_with_mock().key_stats().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reset() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("reset_me", 5.0).unwrap();
+        assert!(kvs.key_exists("reset_me").unwrap());
+        kvs.reset().unwrap();
+        assert!(!kvs.key_exists("reset_me").unwrap());
+    }
+
+    #[test]
+    fn test_flush_with_mock_backend() {
+        let kvs = new_kvs_with_mock();
+        // Should not error, even though nothing is written
+        kvs.flush().unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_count_and_max_count() {
+        let kvs = new_kvs_with_mock();
+        // No real snapshots, so count should be 0
+        assert_eq!(kvs.snapshot_count(), 0);
+        assert_eq!(GenericKvs::<KvsMockBackend>::snapshot_max_count(), 3);
+    }
+
+    #[test]
+    fn test_snapshot_restore_invalid_id() {
+        let kvs = new_kvs_with_mock();
+        // id 1 is always invalid in this mock (no real snapshots)
+        let res = kvs.snapshot_restore(SnapshotId::new(1));
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_get_kvs_filename_and_hash_filename() {
+        let kvs = new_kvs_with_mock();
+        // No files exist, so should return FileNotFound
+        let res = kvs.get_kvs_filename(SnapshotId::new(1));
+        assert!(matches!(res, Err(ErrorCode::FileNotFound)));
+        let res = kvs.get_hash_filename(SnapshotId::new(1));
+        assert!(matches!(res, Err(ErrorCode::FileNotFound)));
+    }
+
+    #[test]
+    fn test_snapshot_path_relative_dir() {
+        let kvs = GenericKvs::<KvsMockBackend>::open(
+            InstanceId::new(103),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some("rel".to_string()),
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        assert_eq!(
+            kvs.snapshot_path(1, "json"),
+            PathBuf::from("rel").join("kvs_103_1.json")
+        );
+    }
+
+    #[test]
+    fn test_subscribe_set_remove_reset() {
+        let kvs = new_kvs_with_mock();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let events_cb = events.clone();
+        let id = kvs
+            .subscribe("net.*", move |event: &KvsEvent| {
+                events_cb.lock().unwrap().push(event.clone());
+            })
+            .unwrap();
+
+        kvs.set_value("net.ip", "10.0.0.1".to_string()).unwrap();
+        kvs.set_value("other", 1.0).unwrap();
+        kvs.remove_key("net.ip").unwrap();
+        kvs.set_value("net.mask", 24.0).unwrap();
+        kvs.reset().unwrap();
+        kvs.unsubscribe(id).unwrap();
+        kvs.set_value("net.gw", ()).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                KvsEvent::Set {
+                    key: "net.ip".to_string(),
+                    value: KvsValue::from("10.0.0.1".to_string()),
+                    reason: None,
+                },
+                KvsEvent::Removed {
+                    key: "net.ip".to_string()
+                },
+                KvsEvent::Set {
+                    key: "net.mask".to_string(),
+                    value: KvsValue::from(24.0),
+                    reason: None,
+                },
+                KvsEvent::Removed {
+                    key: "net.mask".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_set_value_with_reason() {
+        let kvs = new_kvs_with_mock();
+        let events = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let events_cb = events.clone();
+        kvs.subscribe("*", move |event: &KvsEvent| {
+            events_cb.lock().unwrap().push(event.clone());
+        })
+        .unwrap();
+
+        kvs.set_value_with_reason("volume", 10.0, "user request")
+            .unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("volume").unwrap(), 10.0);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![KvsEvent::Set {
+                key: "volume".to_string(),
+                value: KvsValue::from(10.0),
+                reason: Some("user request".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_export_import_csv() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("list", vec![KvsValue::from(1.0)]).unwrap();
+
+        let csv = kvs.export_csv().unwrap();
+        assert!(csv.contains("mock_default_key,number,111,111,true,\r\n"));
+        assert!(csv.contains("mock_key,number,123,,false,"));
+        assert!(!csv.contains("list"));
+
+        // Unchanged rows don't modify the KVS
+        assert_eq!(kvs.import_csv(&csv).unwrap(), 0);
+
+        let csv = "key,type,value,is_default\r\n\
+                   mock_key,number,123,true\r\n\
+                   mock_default_key,number,5,false\r\n\
+                   new,string,\"a,b\",false\r\n";
+        assert_eq!(kvs.import_csv(csv).unwrap(), 3);
+        assert!(!kvs.key_exists("mock_key").unwrap());
+        assert_eq!(kvs.get_value_as::<f64>("mock_default_key").unwrap(), 5.0);
+        assert_eq!(kvs.get_value_as::<String>("new").unwrap(), "a,b");
+    }
+
+    #[test]
+    fn test_auto_flush_tick() {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        let interval = Duration::from_millis(50);
+
+        kvs.set_value("key", 1.0).unwrap();
+        assert!(!kvs.tick().unwrap());
+        assert_eq!(
+            kvs.enable_auto_flush(Duration::ZERO),
+            Err(ErrorCode::ValidationFailed)
+        );
+        kvs.enable_auto_flush(interval).unwrap();
+        assert!(!kvs.tick().unwrap());
+
+        std::thread::sleep(interval);
+        assert!(kvs.tick().unwrap());
+        assert!(kvs.stats().unwrap().last_flush.is_some());
+        // Nothing changed since the flush
+        std::thread::sleep(interval);
+        assert!(!kvs.tick().unwrap());
+
+        kvs.set_value("key", 2.0).unwrap();
+        kvs.disable_auto_flush().unwrap();
+        std::thread::sleep(interval);
+        assert!(!kvs.tick().unwrap());
+    }
+
+    #[test]
+    fn test_flush_callback() {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+
+        let flushes = Arc::new(Mutex::new(Vec::new()));
+        let seen = flushes.clone();
+        kvs.set_flush_callback(move |info| seen.lock().unwrap().push(*info))
+            .unwrap();
+        kvs.set_value("key", 1.0).unwrap();
+        kvs.flush().unwrap();
+        kvs.flush().unwrap();
+        kvs.clear_flush_callback().unwrap();
+        kvs.flush().unwrap();
+
+        let flushes = flushes.lock().unwrap();
+        assert_eq!(flushes.len(), 2);
+        assert_eq!(flushes[0].generation, SnapshotGeneration(1));
+        assert_eq!(flushes[1].generation, SnapshotGeneration(2));
+        let file_size = |ext: &str| {
+            std::fs::metadata(dir.path().join(format!("kvs_0_0.{ext}")))
+                .unwrap()
+                .len()
+        };
+        assert_eq!(flushes[1].size, file_size("json") + file_size("hash"));
+    }
+
+    #[test]
+    fn test_reset_to_defaults() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("mock_default_key", 5.0).unwrap();
+        kvs.set_value("extra", true).unwrap();
+
+        kvs.reset_to_defaults().unwrap();
+        let mut keys = kvs.get_all_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["mock_default_key"]);
+        assert_eq!(kvs.get_value_as::<f64>("mock_default_key").unwrap(), 111.0);
+        assert!(!kvs.is_value_default("mock_default_key").unwrap());
+
+        kvs.reset().unwrap();
+        assert!(kvs.get_all_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_export_import_json() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("list", vec![KvsValue::from(1.0)]).unwrap();
+        let json = kvs.export_json().unwrap();
+        assert!(!json.contains("mock_default_key"));
+
+        // Unchanged keys don't modify the KVS
+        assert_eq!(kvs.import_json(&json, ImportMode::Overwrite).unwrap(), 0);
+
+        let import = r#"{"mock_key": 5.0, "new": true}"#;
+        assert_eq!(
+            kvs.import_json(import, ImportMode::KeepExisting).unwrap(),
+            1
+        );
+        assert_eq!(kvs.get_value_as::<f64>("mock_key").unwrap(), 123.0);
+        assert!(kvs.get_value_as::<bool>("new").unwrap());
+
+        assert_eq!(kvs.import_json(import, ImportMode::Overwrite).unwrap(), 1);
+        assert_eq!(kvs.get_value_as::<f64>("mock_key").unwrap(), 5.0);
+        assert!(kvs.key_exists("list").unwrap());
+
+        // Replace removes the keys missing in the document
+        assert_eq!(kvs.import_json(&json, ImportMode::Replace).unwrap(), 2);
+        assert_eq!(kvs.get_value_as::<f64>("mock_key").unwrap(), 123.0);
+        assert!(!kvs.key_exists("new").unwrap());
+        assert!(kvs.key_exists("list").unwrap());
+
+        assert_eq!(
+            kvs.import_json("{", ImportMode::Overwrite),
+            Err(ErrorCode::JsonParserError)
+        );
+    }
+
+    #[test]
+    fn test_get_value_error_cases() {
+        let kvs = new_kvs_with_mock();
+        // Key does not exist
+        let res = kvs.get_value("not_found");
+        assert!(matches!(res, Err(ErrorCode::KeyNotFound)));
+    }
+
+    #[test]
+    fn test_get_value_as_conversion_error() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("str_key", "string".to_string()).unwrap();
+        // Try to get as f64, should fail
+        let res: Result<f64, _> = kvs.get_value_as("str_key");
+        assert!(matches!(res, Err(ErrorCode::ConversionFailed)));
+    }
+
+    #[test]
+    fn test_get_value_as_checked() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("port", "8080".to_string()).unwrap();
+        kvs.set_value("ratio", 0.25).unwrap();
+        assert_eq!(kvs.get_value_as::<u16>("mock_key"), Ok(123));
+        assert_eq!(kvs.get_value_as::<i8>("mock_default_key"), Ok(111));
+        assert_eq!(kvs.get_value_as::<f32>("ratio"), Ok(0.25));
+        assert_eq!(
+            kvs.get_value_as::<i32>("ratio"),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            kvs.get_value_as::<u16>("port"),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(kvs.get_value_as::<i64>("mock_key"), Ok(123));
+        assert_eq!(
+            kvs.get_value_as::<u64>("ratio"),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(kvs.get_value_as::<Parsed<u16>>("port"), Ok(Parsed(8080)));
+        assert_eq!(
+            kvs.get_value_as::<Parsed<u8>>("port"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_is_value_default_error() {
+        let kvs = new_kvs_with_mock();
+        let res = kvs.is_value_default("not_found");
+        assert!(matches!(res, Err(ErrorCode::KeyNotFound)));
+    }
+
+    #[test]
+    fn test_kvs_open_and_set_get_value() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        let instance_id = InstanceId::new(42);
+        let kvs = Kvs::open(
+            instance_id.clone(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir_path.clone()),
+        )
+        .unwrap();
+        let _ = kvs.set_value("direct", KvsValue::String("abc".to_string()));
+        let value = kvs.get_value("direct").unwrap();
+        assert_eq!(value.get::<String>().unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_kvs_reset() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        let instance_id = InstanceId::new(43);
+        let kvs = Kvs::open(
+            instance_id.clone(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir_path.clone()),
+        )
+        .unwrap();
+        let _ = kvs.set_value("reset", 1.0);
+        assert!(kvs.get_value("reset").is_ok());
+        kvs.reset().unwrap();
+        assert!(matches!(
+            kvs.get_value("reset"),
+            Err(ErrorCode::KeyNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_kvs_key_exists_and_get_all_keys() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        let instance_id = InstanceId::new(44);
+        let kvs = Kvs::open(
+            instance_id.clone(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir_path.clone()),
+        )
+        .unwrap();
+        assert!(!kvs.key_exists("foo").unwrap());
+        let _ = kvs.set_value("foo", KvsValue::Boolean(true));
+        assert!(kvs.key_exists("foo").unwrap());
+        let keys = kvs.get_all_keys().unwrap();
+        assert!(keys.contains(&"foo".to_string()));
+    }
+
+    #[test]
+    fn test_kvs_remove_key() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        let instance_id = InstanceId::new(45);
+        let kvs = Kvs::open(
+            instance_id.clone(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir_path.clone()),
+        )
+        .unwrap();
+        let _ = kvs.set_value("bar", 2.0);
+        assert!(kvs.key_exists("bar").unwrap());
+        kvs.remove_key("bar").unwrap();
+        assert!(!kvs.key_exists("bar").unwrap());
+    }
+
+    #[test]
+    fn test_kvs_flush_and_snapshot() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        let instance_id = InstanceId::new(46);
+        let kvs = Kvs::open(
+            instance_id.clone(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir_path.clone()),
+        )
+        .unwrap();
+        let _ = kvs.set_value("snap", 3.0);
+        // Before flush, snapshot count should be 0 (no snapshots yet)
+        assert_eq!(kvs.snapshot_count(), 0);
+        kvs.flush().unwrap();
+        // After flush, snapshot count should be 1
+        assert_eq!(kvs.snapshot_count(), 1);
+        // Call flush again to rotate and create a snapshot
+        kvs.flush().unwrap();
+        assert_eq!(kvs.snapshot_count(), 2);
+        // Restore from snapshot if available
+        if kvs.snapshot_count() > 0 {
+            kvs.snapshot_restore(SnapshotId::new(1)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_snapshot_generations() {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+
+        for value in 1..=5 {
+            kvs.set_value("value", f64::from(value)).unwrap();
+            kvs.flush().unwrap();
+        }
+        let current = kvs.snapshot_generation(SnapshotId::new(0)).unwrap();
+        assert_eq!(current, SnapshotGeneration::new(5));
+
+        // The generation of a snapshot stays valid across rotations
+        let fourth = kvs.snapshot_generation(SnapshotId::new(1)).unwrap();
+        assert_eq!(fourth, SnapshotGeneration::new(4));
+        kvs.flush().unwrap();
+        assert_eq!(kvs.snapshot_id(fourth).unwrap(), SnapshotId::new(2));
+        assert_eq!(
+            kvs.snapshot_list().unwrap()[1].generation,
+            Some(SnapshotGeneration::new(4))
+        );
+
+        kvs.snapshot_restore_generation(fourth).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 4.0);
+
+        // Pruned and current generations can't be restored
+        assert_eq!(
+            kvs.snapshot_id(SnapshotGeneration::new(1)),
+            Err(ErrorCode::InvalidSnapshotId)
+        );
+        assert_eq!(
+            kvs.snapshot_restore_generation(SnapshotGeneration::new(6)),
+            Err(ErrorCode::InvalidSnapshotId)
+        );
+    }
+
+    #[test]
+    fn test_key_metadata() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .key_metadata(true)
+                .writer_id("calibration")
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+        let kvs = open();
+        assert_eq!(kvs.get_key_metadata("gain").unwrap(), None);
+
+        let before = SystemTime::now();
+        kvs.set_value("gain", 1.0).unwrap();
+        kvs.set_value("offset", 2.0).unwrap();
+        kvs.flush().unwrap();
+        let gain = kvs.get_key_metadata("gain").unwrap().unwrap();
+        assert!(gain.modified >= before);
+        assert_eq!(gain.writer.as_deref(), Some("calibration"));
+
+        // A restore only changes the metadata of restored keys
+        kvs.set_value("gain", 3.0).unwrap();
+        kvs.flush().unwrap();
+        let offset = kvs.get_key_metadata("offset").unwrap();
+        kvs.snapshot_restore(SnapshotId::new(1)).unwrap();
+        kvs.flush().unwrap();
+        assert!(kvs.get_key_metadata("gain").unwrap().unwrap().modified > gain.modified);
+        assert_eq!(kvs.get_key_metadata("offset").unwrap(), offset);
+        drop(kvs);
+
+        // The metadata survives reopening
+        let kvs = open();
+        let offset = kvs.get_key_metadata("offset").unwrap().unwrap();
+        assert_eq!(offset.writer.as_deref(), Some("calibration"));
+        assert!(offset.modified + std::time::Duration::from_millis(1) > before);
+    }
+
+    #[test]
+    fn test_growth_trend() {
+        let dir = tempdir().unwrap();
+        let open = |flag| {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .size_history(flag)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+        let kvs = open(false);
+        kvs.set_value("gain", 1.0).unwrap();
+        kvs.flush().unwrap();
+        assert_eq!(kvs.growth_trend(), Err(ErrorCode::ValidationFailed));
+        assert!(!dir.path().join("kvs_0_size_history.json").exists());
+        drop(kvs);
+
+        let kvs = open(true);
+        kvs.flush().unwrap();
+        assert_eq!(kvs.growth_trend().unwrap().sample_count, 1);
+        drop(kvs);
+        assert_eq!(open(true).growth_trend().unwrap().sample_count, 1);
+    }
+
+    #[test]
+    fn test_key_metadata_disabled() {
+        let dir = tempdir().unwrap();
+        let kvs = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .writer_id("calibration")
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        kvs.set_value("gain", 1.0).unwrap();
+        kvs.flush().unwrap();
+        assert_eq!(
+            kvs.get_key_metadata("gain"),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert!(!dir.path().join("kvs_0_metadata.json").exists());
+    }
+
+    #[test]
+    fn test_key_versions() {
+        let (_dir, path) = mock_dir();
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(path.unwrap())
+            .key_versions(true)
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            kvs.get_value_with_version("count"),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(kvs.set_value_if_version("count", 1.0, 0).unwrap(), 1);
+        kvs.set_value("count", 2.0).unwrap();
+        let (value, version) = kvs.get_value_with_version("count").unwrap();
+        assert_eq!((value, version), (KvsValue::from(2.0), 2));
+        assert_eq!(
+            kvs.set_value_if_version("count", 3.0, 1),
+            Err(ErrorCode::VersionConflict)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("count").unwrap(), 2.0);
+        kvs.remove_key("count").unwrap();
+        assert_eq!(kvs.set_value_if_version("count", 3.0, 3).unwrap(), 4);
+        assert_eq!(
+            kvs.set_value(kvs_version::VERSIONS_KEY, 1.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+    }
 
-            fn save_kvs(
-                _kvs: &KvsMap,
-                _destination_path: PathBuf,
-                _add_hash: bool,
-            ) -> Result<(), ErrorCode> {
-                Ok(())
-            }
-        }
+    #[test]
+    fn test_key_versions_disabled() {
+        let dir = tempdir().unwrap();
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        kvs.set_value("mock_key", 123.0).unwrap();
+        assert_eq!(
+            kvs.get_value_with_version("mock_key"),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.set_value_if_version("mock_key", 1.0, 0),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("mock_key").unwrap(), 123.0);
 
-        #[derive(Default, Clone)]
-        pub struct KvsMockBackendFail;
+        // Versions aren't written
+        kvs.flush().unwrap();
+        let json = fs::read_to_string(dir.path().join("kvs_0_0.json")).unwrap();
+        assert_eq!(json, r#"{"mock_key":123}"#);
+        assert!(!dir.path().join("kvs_0_versions.json").exists());
+    }
 
-        impl KvsBackend for KvsMockBackendFail {
-            fn load_kvs(
-                _source_path: PathBuf,
-                _verify_hash: bool,
-                _hash_source: Option<PathBuf>,
-            ) -> Result<KvsMap, ErrorCode> {
-                Err(ErrorCode::UnmappedError)
-            }
+    #[test]
+    fn test_key_versions_persisted() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = || {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .key_versions(true)
+                .write_ahead_log(true)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
 
-            fn save_kvs(
-                _kvs: &KvsMap,
-                _destination_path: PathBuf,
-                _add_hash: bool,
-            ) -> Result<(), ErrorCode> {
-                Err(ErrorCode::UnmappedError)
-            }
+        let kvs = open();
+        kvs.set_value("count", 1.0).unwrap();
+        kvs.set_value("count", 2.0).unwrap();
+        kvs.set_value("other", 1.0).unwrap();
+        kvs.flush().unwrap();
+        drop(kvs);
+        let json = fs::read_to_string(dir.path().join("kvs_0_0.json")).unwrap();
+        assert!(json.contains(r#""$versions":{"count":{"$u64":"2"},"other":{"$u64":"1"}}"#));
+
+        // Changes replayed from the write-ahead log get a version above the stored one
+        let kvs = open();
+        assert_eq!(kvs.get_value_with_version("count").unwrap().1, 2);
+        assert!(!kvs
+            .get_all_keys()
+            .unwrap()
+            .contains(&"$versions".to_string()));
+        kvs.set_value("other", 2.0).unwrap();
+        drop(kvs);
+        let kvs = open();
+        assert_eq!(kvs.get_value_with_version("other").unwrap().1, 2);
+        assert_eq!(kvs.set_value_if_version("count", 3.0, 2).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_key_versions_not_reissued_after_replay() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = || {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .key_versions(true)
+                .write_ahead_log(true)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+
+        let kvs = open();
+        kvs.set_value("padding", "x".repeat(1024)).unwrap();
+        kvs.flush().unwrap();
+        for value in 1..=3 {
+            kvs.set_value("count", f64::from(value)).unwrap();
+        }
+        drop(kvs);
+
+        // Three writes replayed from the write-ahead log
+        let kvs = open();
+        assert_eq!(kvs.get_value_with_version("count").unwrap().1, 3);
+        assert_eq!(
+            kvs.set_value_if_version("count", 9.0, 1),
+            Err(ErrorCode::VersionConflict)
+        );
+        for value in 4..=6 {
+            kvs.set_value("count", f64::from(value)).unwrap();
         }
+        kvs.flush_incremental().unwrap();
+        assert!(dir.path().join("kvs_0.delta").exists());
+        kvs.set_value("count", 7.0).unwrap();
+        drop(kvs);
+
+        // One delta journal record for three writes, then one write-ahead log record
+        let kvs = open();
+        assert_eq!(kvs.get_value_with_version("count").unwrap().1, 7);
+        assert_eq!(
+            kvs.set_value_if_version("count", 9.0, 5),
+            Err(ErrorCode::VersionConflict)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("count").unwrap(), 7.0);
     }
 
-    use mock_backend::KvsMockBackend;
-    use mock_backend::KvsMockBackendFail;
+    #[test]
+    fn test_key_versions_merge_journal() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = |role: JournalRole| {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .merge_journal(role)
+                .key_versions(true)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+        let app = open(JournalRole::Compactor);
+        let updater = open(JournalRole::Writer);
 
-    fn new_kvs_with_mock() -> GenericKvs<KvsMockBackend> {
-        let instance_id = InstanceId::new(100);
-        // Directory is not used by mock backend
-        GenericKvs::<KvsMockBackend>::open(
-            instance_id,
-            OpenNeedDefaults::Optional,
-            OpenNeedKvs::Optional,
-            None,
-        )
-        .unwrap()
+        assert_eq!(
+            app.set_value_if_version("mode", "eco".to_string(), 0),
+            Ok(1)
+        );
+
+        // The conditional write merges the change of the other process before comparing
+        assert_eq!(
+            updater.set_value_if_version("mode", "sport".to_string(), 0),
+            Err(ErrorCode::VersionConflict)
+        );
+        let (value, version) = updater.get_value_with_version("mode").unwrap();
+        assert_eq!((value, version), (KvsValue::from("eco".to_string()), 1));
+        assert_eq!(
+            updater.set_value_if_version("mode", "sport".to_string(), 1),
+            Ok(2)
+        );
+        assert_eq!(
+            app.set_value_if_version("mode", "off".to_string(), 1),
+            Err(ErrorCode::VersionConflict)
+        );
+        assert_eq!(
+            app.get_value_as::<String>("mode").unwrap(),
+            "sport".to_string()
+        );
     }
 
-    fn new_kvs_with_mock_required() -> GenericKvs<KvsMockBackend> {
-        let instance_id = InstanceId::new(101);
-        GenericKvs::<KvsMockBackend>::open(
-            instance_id,
-            OpenNeedDefaults::Required,
-            OpenNeedKvs::Required,
-            None,
-        )
-        .unwrap()
+    #[test]
+    fn test_compare_and_set() {
+        let kvs = new_kvs_with_mock();
+        let stored = KvsValue::from(123.0);
+        let default = KvsValue::from(111.0);
+
+        assert_eq!(
+            kvs.compare_and_set("mock_key", Some(&default), 1.0),
+            Ok(false)
+        );
+        assert_eq!(
+            kvs.compare_and_set("mock_key", Some(&stored), 1.0),
+            Ok(true)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("mock_key"), Ok(1.0));
+        assert_eq!(
+            kvs.compare_and_set("mock_default_key", None, 2.0),
+            Ok(false)
+        );
+        assert_eq!(
+            kvs.compare_and_set("mock_default_key", Some(&default), 2.0),
+            Ok(true)
+        );
+        assert_eq!(kvs.compare_and_set("new_key", None, true), Ok(true));
+        assert_eq!(kvs.compare_and_set("new_key", None, false), Ok(false));
+
+        // Concurrent increments don't get lost
+        kvs.set_value("counter", 0.0).unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        loop {
+                            let current = kvs.get_value("counter").unwrap();
+                            let next = f64::try_from(&current).unwrap() + 1.0;
+                            if kvs
+                                .compare_and_set("counter", Some(&current), next)
+                                .unwrap()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(kvs.get_value_as::<f64>("counter"), Ok(200.0));
     }
 
-    fn new_kvs_with_mock_required_fail() -> Result<GenericKvs<KvsMockBackendFail>, ErrorCode> {
-        let instance_id = InstanceId::new(102);
-        GenericKvs::<KvsMockBackendFail>::open(
-            instance_id,
-            OpenNeedDefaults::Required,
-            OpenNeedKvs::Required,
-            None,
-        )
+    #[test]
+    fn test_increment() {
+        let kvs = new_kvs_with_mock();
+        assert_eq!(kvs.increment("mock_key", 2.0), Ok(KvsValue::from(125.0)));
+        assert_eq!(
+            kvs.increment("mock_default_key", -11.0),
+            Ok(KvsValue::from(100.0))
+        );
+        assert_eq!(kvs.increment("created", 5i64), Ok(KvsValue::I64(5)));
+        assert_eq!(kvs.increment("created", -7.0), Ok(KvsValue::I64(-2)));
+        kvs.set_value("unsigned", u64::MAX - 1).unwrap();
+        assert_eq!(kvs.increment("unsigned", 1i64), Ok(KvsValue::U64(u64::MAX)));
+        assert_eq!(
+            kvs.increment("unsigned", 1i64),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            kvs.increment("created", 0.5),
+            Err(ErrorCode::ConversionFailed)
+        );
+        kvs.set_value("name", "audio".to_string()).unwrap();
+        assert_eq!(kvs.increment("name", 1.0), Err(ErrorCode::ConversionFailed));
+        assert_eq!(
+            kvs.increment("missing", true),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(kvs.get_value_as::<u64>("unsigned"), Ok(u64::MAX));
+
+        // Concurrent increments don't get lost
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        kvs.increment("counter", 1u64).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(kvs.get_value("counter"), Ok(KvsValue::U64(200)));
     }
 
     #[test]
-    fn test_open_kvs_with_mock_required_success() {
-        let kvs = new_kvs_with_mock_required();
-        // Should contain the mock_key from mock backend
-        assert!(kvs.key_exists("mock_key").unwrap());
-        let value = kvs.get_value("mock_key").unwrap();
-        assert_eq!(*value.get::<f64>().unwrap(), 123.0);
+    fn test_increment_i64_overflow() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("max", i64::MAX).unwrap();
+        kvs.set_value("min", i64::MIN).unwrap();
+
+        assert_eq!(kvs.increment("max", 1i64), Err(ErrorCode::ConversionFailed));
+        assert_eq!(kvs.increment("min", -1.0), Err(ErrorCode::ConversionFailed));
+        assert_eq!(
+            kvs.increment("max", u64::MAX),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(kvs.get_value("max"), Ok(KvsValue::I64(i64::MAX)));
+        assert_eq!(kvs.get_value("min"), Ok(KvsValue::I64(i64::MIN)));
+
+        assert_eq!(kvs.increment("max", -1i64), Ok(KvsValue::I64(i64::MAX - 1)));
     }
 
     #[test]
-    fn test_open_kvs_with_mock_required_fail() {
-        let res = new_kvs_with_mock_required_fail();
-        assert!(res.is_err());
-        // Should be ErrorCode::UnmappedError
-        assert!(matches!(res, Err(ErrorCode::UnmappedError)));
+    fn test_increment_type_mismatch() {
+        let kvs = new_kvs_with_mock();
+        kvs.set_value("name", "audio".to_string()).unwrap();
+
+        assert_eq!(
+            kvs.increment("name", 1i64),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            kvs.increment("mock_key", "1".to_string()),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            kvs.get_value("name"),
+            Ok(KvsValue::from("audio".to_string()))
+        );
+        assert_eq!(kvs.get_value("mock_key"), Ok(KvsValue::from(123.0)));
     }
 
     #[test]
-    fn test_open_kvs_with_mock_backend() {
+    fn test_array_append() {
         let kvs = new_kvs_with_mock();
-        // Should contain the mock_key from mock backend
-        assert!(kvs.key_exists("mock_key").unwrap());
-        let value = kvs.get_value("mock_key").unwrap();
-        assert_eq!(*value.get::<f64>().unwrap(), 123.0);
+        assert_eq!(kvs.array_len("events"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(kvs.array_append("events", "boot".to_string(), None), Ok(1));
+        assert_eq!(kvs.array_append("events", 1.0, None), Ok(2));
+        assert_eq!(kvs.array_len("events"), Ok(2));
+
+        // A maximum length drops the oldest elements
+        for idx in 0..5 {
+            assert_eq!(kvs.array_append("events", idx as f64, Some(3)), Ok(3));
+        }
+        assert_eq!(
+            kvs.get_value("events"),
+            Ok(KvsValue::from(vec![
+                KvsValue::from(2.0),
+                KvsValue::from(3.0),
+                KvsValue::from(4.0),
+            ]))
+        );
+        assert_eq!(
+            kvs.array_append("events", 5.0, Some(0)),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.array_append("mock_key", 1.0, None),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(kvs.array_len("mock_key"), Err(ErrorCode::ConversionFailed));
     }
 
     #[test]
-    fn test_set_and_get_value() {
+    fn test_merge_value() {
         let kvs = new_kvs_with_mock();
-        kvs.set_value("foo", 42.0).unwrap();
-        let value = kvs.get_value("foo").unwrap();
-        assert_eq!(*value.get::<f64>().unwrap(), 42.0);
+        let config = |members: Vec<(&str, KvsValue)>| {
+            KvsValue::Object(
+                members
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value))
+                    .collect(),
+            )
+        };
+        kvs.set_value(
+            "net",
+            config(vec![
+                ("mode", KvsValue::from("dhcp".to_string())),
+                ("ip", KvsValue::from("10.0.0.2".to_string())),
+            ]),
+        )
+        .unwrap();
+
+        let merged = kvs
+            .merge_value(
+                "net",
+                config(vec![
+                    ("mode", KvsValue::from("static".to_string())),
+                    ("ip", KvsValue::Null),
+                    ("mtu", KvsValue::from(1500.0)),
+                ]),
+            )
+            .unwrap();
+        let expected = config(vec![
+            ("mode", KvsValue::from("static".to_string())),
+            ("mtu", KvsValue::from(1500.0)),
+        ]);
+        assert_eq!(merged, expected);
+        assert_eq!(kvs.get_value("net"), Ok(expected));
+
+        // Keys without a value are created, other patches replace the value
+        assert_eq!(
+            kvs.merge_value("new", config(vec![("a", KvsValue::from(true))])),
+            Ok(config(vec![("a", KvsValue::from(true))]))
+        );
+        assert_eq!(kvs.merge_value("new", 1.0), Ok(KvsValue::from(1.0)));
     }
 
     #[test]
-    fn test_get_value_as() {
+    fn test_value_at_path() {
         let kvs = new_kvs_with_mock();
-        kvs.set_value("bar", 99.0).unwrap();
-        let v: f64 = kvs.get_value_as("bar").unwrap();
-        assert_eq!(v, 99.0);
+        kvs.set_value(
+            "network",
+            HashMap::from([(
+                "eth0".to_string(),
+                KvsValue::from(HashMap::from([("mtu".to_string(), KvsValue::from(1500.0))])),
+            )]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            kvs.get_value_at_path("network", "/eth0/mtu"),
+            Ok(KvsValue::from(1500.0))
+        );
+        kvs.set_value_at_path("network", "/eth0/mtu", 9000.0)
+            .unwrap();
+        kvs.set_value_at_path("network", "/eth0/up", true).unwrap();
+        assert_eq!(
+            kvs.get_value_at_path("network", "/eth0/mtu"),
+            Ok(KvsValue::from(9000.0))
+        );
+        assert_eq!(
+            kvs.get_value_at_path("network", "/eth0/up"),
+            Ok(KvsValue::from(true))
+        );
+        assert_eq!(
+            kvs.get_value_at_path("network", "/eth1"),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            kvs.set_value_at_path("network", "/eth1/mtu", 1500.0),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            kvs.set_value_at_path("missing", "/mtu", 1500.0),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            kvs.set_value_at_path("mock_key", "/mtu", 1500.0),
+            Err(ErrorCode::ConversionFailed)
+        );
+        kvs.set_value_at_path("missing", "", 1.0).unwrap();
+        assert_eq!(
+            kvs.get_value_at_path("missing", ""),
+            Ok(KvsValue::from(1.0))
+        );
     }
 
     #[test]
-    fn test_get_default_value_and_is_value_default() {
-        let kvs = new_kvs_with_mock_required();
-        println!("{:?}", kvs.default);
-        // mock_key is always present as default in mock backend
-        assert!(kvs.is_value_default("mock_default_key").unwrap());
-        let def = kvs.get_default_value("mock_default_key").unwrap();
-        assert_eq!(*def.get::<f64>().unwrap(), 111.0);
+    fn test_changed_defaults() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            let kvs = Kvs::open_with_dir(
+                InstanceId::new(0),
+                dir.path(),
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Optional,
+            )
+            .unwrap();
+            kvs.flush_on_exit(false);
+            kvs
+        };
+        let write_defaults =
+            |json: &str| std::fs::write(dir.path().join("kvs_0_default.json"), json).unwrap();
 
-        // mock_key is always present as default in mock backend
-        assert!(!kvs.is_value_default("mock_key").unwrap());
+        write_defaults(r#"{"volume": 5.0, "mode": "eco"}"#);
+        let kvs = open();
+        assert!(kvs.changed_defaults().is_empty());
+        kvs.flush().unwrap();
+        assert!(open().changed_defaults().is_empty());
+
+        // The hash is kept in the KVS file, not visible as key
+        let kvs_file = std::fs::read_to_string(dir.path().join("kvs_0_0.json")).unwrap();
+        assert!(kvs_file.contains(kvs_defaults_hash::DEFAULTS_HASH_KEY));
+        assert!(!open()
+            .get_all_keys()
+            .unwrap()
+            .contains(&kvs_defaults_hash::DEFAULTS_HASH_KEY.to_string()));
+        assert_eq!(
+            open().set_value(kvs_defaults_hash::DEFAULTS_HASH_KEY, 1.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+
+        // Changes are listed until the KVS file is written with the new defaults
+        write_defaults(r#"{"mode": "eco", "volume": 7.0, "theme": "dark"}"#);
+        assert_eq!(open().changed_defaults(), ["theme", "volume"]);
+        let kvs = open();
+        assert_eq!(kvs.changed_defaults(), ["theme", "volume"]);
+        kvs.flush().unwrap();
+        assert!(open().changed_defaults().is_empty());
+        assert!(!dir.path().join("kvs_0_defaults_hash.json").exists());
     }
 
     #[test]
-    fn test_key_exists_and_get_all_keys() {
-        let kvs = new_kvs_with_mock();
-        assert!(kvs.key_exists("mock_key").unwrap());
-        kvs.set_value("foo", true).unwrap();
-        assert!(kvs.key_exists("foo").unwrap());
-        let keys = kvs.get_all_keys().unwrap();
-        assert!(keys.contains(&"foo".to_string()));
-        assert!(keys.contains(&"mock_key".to_string()));
+    fn test_defaults_schemas() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            Kvs::open_with_dir(
+                InstanceId::new(0),
+                dir.path(),
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Optional,
+            )
+        };
+        let write_defaults =
+            |json: &str| std::fs::write(dir.path().join("kvs_0_default.json"), json).unwrap();
+
+        write_defaults(
+            r#"{"$schemas": {"net/*": {"type": "object", "required": ["ip"],
+                 "properties": {"port": {"type": "integer", "maximum": 65535}}}},
+                "net/eth0": {"ip": "10.0.0.1"}}"#,
+        );
+        let kvs = open().unwrap();
+        kvs.flush_on_exit(false);
+        assert!(kvs.get_default_value("$schemas").is_err());
+
+        let mut config = KvsMap::from([
+            ("ip".to_string(), KvsValue::from("10.0.0.2".to_string())),
+            ("port".to_string(), KvsValue::from(8080.0)),
+        ]);
+        kvs.set_value("net/eth1", config.clone()).unwrap();
+        config.insert("port".to_string(), KvsValue::from(70000.0));
+        assert_eq!(
+            kvs.set_value("net/eth1", config.clone()),
+            Err(ErrorCode::ValidationFailed)
+        );
+        config.remove("ip");
+        assert_eq!(
+            kvs.set_value("net/eth2", config),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.set_value("net/eth3", 1.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+        kvs.set_value("other", 1.0).unwrap();
+        drop(kvs);
+
+        // Defaults not conforming to their schema fail the open
+        write_defaults(r#"{"$schemas": {"net/*": {"type": "object"}}, "net/eth0": 1}"#);
+        assert_eq!(open().err(), Some(ErrorCode::ValidationFailed));
+    }
+
+    #[test]
+    fn test_file_lock_and_read_only() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = |mode: OpenMode| {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .open_mode(mode)
+                .file_lock(true)
+                .build()
+        };
+
+        let writer = open(OpenMode::Persistent).unwrap();
+        writer.set_value("key", 1.0).unwrap();
+        assert_eq!(
+            open(OpenMode::Persistent).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+        assert_eq!(
+            open(OpenMode::ReadOnly).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+        drop(writer);
+
+        let reader = open(OpenMode::ReadOnly).unwrap();
+        let second_reader = open(OpenMode::ReadOnly).unwrap();
+        assert_eq!(reader.get_value_as::<f64>("key").unwrap(), 1.0);
+        assert_eq!(
+            reader.set_value("key", 2.0),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(
+            second_reader.remove_key("key"),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(reader.flush(), Err(ErrorCode::PhysicalStorageFailure));
+        assert_eq!(
+            open(OpenMode::Persistent).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+        drop((reader, second_reader));
+        assert!(open(OpenMode::Persistent).is_ok());
+    }
+
+    #[test]
+    fn test_reset_key_read_only() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let writer = Kvs::open_with_dir(
+            InstanceId::new(0),
+            &dir_path,
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        writer.set_value("key", 1.0).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let reader = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .open_mode(OpenMode::ReadOnly)
+            .build()
+            .unwrap();
+        assert_eq!(
+            reader.reset_key("key"),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(reader.get_value_as::<f64>("key").unwrap(), 1.0);
     }
 
-    #[test]
-    fn test_remove_key() {
-        let kvs = new_kvs_with_mock();
-        kvs.set_value("baz", 1.0).unwrap();
-        assert!(kvs.key_exists("baz").unwrap());
-        kvs.remove_key("baz").unwrap();
-        assert!(!kvs.key_exists("baz").unwrap());
+    #[test]
+    fn test_import_read_only() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let writer = Kvs::open_with_dir(
+            InstanceId::new(0),
+            &dir_path,
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        writer.set_value("key", 1.0).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let reader = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .open_mode(OpenMode::ReadOnly)
+            .build()
+            .unwrap();
+        assert_eq!(
+            reader.import_json(r#"{"key": 2.0}"#, ImportMode::Overwrite),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(
+            reader.import_csv("key,type,value,is_default\r\nkey,number,2,false\r\n"),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(reader.get_value_as::<f64>("key").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_merge_journal() {
+        let dir = tempdir().unwrap();
+        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = |role: JournalRole| {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .merge_journal(role)
+                .file_lock(true)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+        let app = open(JournalRole::Compactor);
+        let updater = open(JournalRole::Writer);
+
+        app.set_value("volume", 1.0).unwrap();
+        updater.set_value("volume", 2.0).unwrap();
+        updater.set_value("firmware", "2.0".to_string()).unwrap();
+        app.set_value("mode", "eco".to_string()).unwrap();
+
+        // Writers don't write the KVS file, the compactor merges all records in journal order
+        updater.flush().unwrap();
+        assert!(!dir.path().join("kvs_0_0.json").exists());
+        assert_eq!(updater.key_count().unwrap(), 3);
+        app.flush().unwrap();
+        assert_eq!(app.get_value_as::<f64>("volume").unwrap(), 2.0);
+        assert_eq!(app.get_value_as::<String>("firmware").unwrap(), "2.0");
+
+        // After the compaction the writer reloads the KVS file
+        app.remove_key("firmware").unwrap();
+        assert_eq!(updater.merge_journal().unwrap(), 1);
+        assert!(!updater.key_exists("firmware").unwrap());
+        assert_eq!(updater.get_value_as::<String>("mode").unwrap(), "eco");
+
+        let reopened = open(JournalRole::Writer);
+        assert_eq!(reopened.get_all_keys().unwrap().len(), 2);
+        assert_eq!(
+            KvsBuilder::<Kvs>::new(InstanceId::new(1))
+                .dir(dir_path.clone())
+                .merge_journal(JournalRole::Writer)
+                .write_ahead_log(true)
+                .build()
+                .err(),
+            Some(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        assert_eq!(kvs.verify_integrity().unwrap(), IntegrityReport::default());
+
+        for value in 1..=4 {
+            kvs.set_value("value", f64::from(value)).unwrap();
+            kvs.flush().unwrap();
+        }
+        kvs.snapshot_create("backup").unwrap();
+        let report = kvs.verify_integrity().unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.checked_files, 5);
+
+        // All problems are reported instead of only the first one
+        let current = kvs.snapshot_path(0, "json");
+        fs::write(&current, "{").unwrap();
+        fs::remove_file(kvs.snapshot_path(1, "json")).unwrap();
+        fs::remove_file(kvs.snapshot_path(2, "hash")).unwrap();
+        let report = kvs.verify_integrity().unwrap();
+        assert_eq!(report.checked_files, 4);
+        assert_eq!(
+            report.issues,
+            vec![
+                IntegrityIssue::HashMismatch(current.clone()),
+                IntegrityIssue::ParseFailed(current, ErrorCode::JsonParserError),
+                IntegrityIssue::SnapshotGap(SnapshotId::new(2)),
+                IntegrityIssue::HashMissing(kvs.snapshot_path(2, "hash")),
+            ]
+        );
     }
 
     #[test]
-    fn test_reset() {
-        let kvs = new_kvs_with_mock();
-        kvs.set_value("reset_me", 5.0).unwrap();
-        assert!(kvs.key_exists("reset_me").unwrap());
-        kvs.reset().unwrap();
-        assert!(!kvs.key_exists("reset_me").unwrap());
+    fn test_validators() {
+        let dir = tempdir().unwrap();
+        let kvs = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .max_value_size(8)
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        kvs.register_validator("audio/*", |value| match value {
+            KvsValue::Number(n) if (0.0..=10.0).contains(n) => Ok(()),
+            _ => Err("expected a number in 0..=10".to_string()),
+        })
+        .unwrap();
+
+        kvs.set_value("audio/volume", 5.0).unwrap();
+        kvs.flush().unwrap();
+        assert_eq!(
+            kvs.set_value("audio/volume", 11.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.set_value("name", "too long".repeat(2)),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.set_values([("video/mode", 1.0), ("audio/volume", -1.0)]),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert!(!kvs.key_exists("video/mode").unwrap());
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume").unwrap(), 5.0);
+
+        // Restored values are checked against the current validators
+        kvs.unregister_validator("audio/*").unwrap();
+        kvs.set_value("audio/volume", 11.0).unwrap();
+        kvs.flush().unwrap();
+        kvs.register_validator("audio/*", |_| Err("locked".to_string()))
+            .unwrap();
+        assert_eq!(
+            kvs.snapshot_restore(SnapshotId::new(1)),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert!(kvs.unregister_validator("audio/*").unwrap());
+        assert!(!kvs.unregister_validator("audio/*").unwrap());
+        kvs.snapshot_restore(SnapshotId::new(1)).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume").unwrap(), 5.0);
     }
 
     #[test]
-    fn test_flush_with_mock_backend() {
-        let kvs = new_kvs_with_mock();
-        // Should not error, even though nothing is written
+    fn test_interrupted_flush() {
+        let dir = tempdir().unwrap();
+        let open = || {
+            let kvs = Kvs::open_with_dir(
+                InstanceId::new(0),
+                dir.path(),
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Required,
+            )
+            .unwrap();
+            kvs.flush_on_exit(false);
+            kvs
+        };
+        let kvs_path = dir.path().join("kvs_0_0.json");
+        let hash_path = dir.path().join("kvs_0_0.hash");
+        let staged_hash_path = dir.path().join("kvs_0_0.hash.tmp");
+
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.flush().unwrap();
+        drop(kvs);
+
+        // Interrupted before the KVS file was replaced: the staged hash is ignored
+        let data = br#"{"a":2.0}"#;
+        let hash = adler32::RollingAdler32::from_buffer(data).hash();
+        fs::write(&staged_hash_path, hash.to_be_bytes()).unwrap();
+        assert_eq!(open().get_value_as::<f64>("a").unwrap(), 1.0);
+
+        // Interrupted before the hash file was replaced: the staged hash matches
+        let tmp_path = dir.path().join("kvs_0_0.json.tmp");
+        fs::write(&tmp_path, data).unwrap();
+        fs::rename(&tmp_path, &kvs_path).unwrap();
+        let kvs = open();
+        assert_eq!(kvs.get_value_as::<f64>("a").unwrap(), 2.0);
+        assert_eq!(kvs.verify_integrity().unwrap().issues, Vec::new());
         kvs.flush().unwrap();
+        assert!(!staged_hash_path.exists());
+        let written = fs::read(&kvs_path).unwrap();
+        assert_eq!(
+            fs::read(&hash_path).unwrap(),
+            adler32::RollingAdler32::from_buffer(&written)
+                .hash()
+                .to_be_bytes()
+        );
+
+        // The rotation keeps the KVS file in place until it's replaced
+        kvs.snapshot_rotate().unwrap();
+        assert_eq!(
+            fs::read(&kvs_path).unwrap(),
+            fs::read(dir.path().join("kvs_0_1.json")).unwrap()
+        );
     }
 
     #[test]
-    fn test_snapshot_count_and_max_count() {
-        let kvs = new_kvs_with_mock();
-        // No real snapshots, so count should be 0
-        assert_eq!(kvs.snapshot_count(), 0);
-        assert_eq!(GenericKvs::<KvsMockBackend>::snapshot_max_count(), 3);
+    fn test_durability() {
+        let dir = tempdir().unwrap();
+        for (idx, durability) in [
+            Durability::None,
+            Durability::Flush,
+            Durability::Fsync,
+            Durability::FsyncDir,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let open = || {
+                let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+                    .dir(dir.path().to_string_lossy().to_string())
+                    .durability(durability)
+                    .write_ahead_log(true)
+                    .flush_on_exit(false)
+                    .build()
+                    .unwrap();
+                kvs
+            };
+            let kvs = open();
+            assert_eq!(
+                kvs.get_value_as::<f64>("flushed").ok(),
+                idx.checked_sub(1).map(|v| v as f64)
+            );
+            kvs.set_value("flushed", idx as f64).unwrap();
+            kvs.flush().unwrap();
+            kvs.set_value("logged", idx as f64).unwrap();
+            drop(kvs);
+
+            // The WAL record is persisted with the same durability
+            let kvs = open();
+            assert_eq!(kvs.get_value_as::<f64>("logged").unwrap(), idx as f64);
+            assert_eq!(kvs.verify_integrity().unwrap().issues, Vec::new());
+        }
     }
 
     #[test]
-    fn test_snapshot_restore_invalid_id() {
-        let kvs = new_kvs_with_mock();
-        // id 1 is always invalid in this mock (no real snapshots)
-        let res = kvs.snapshot_restore(SnapshotId::new(1));
-        assert!(res.is_err());
+    fn test_access_policy_enforced() {
+        let dir = tempdir().unwrap();
+        let open = |identity: Option<&str>| {
+            let builder = KvsBuilder::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .access_policy(
+                    AccessPolicy::new()
+                        .private("audio", &["audio"])
+                        .read_only("calibration", &["flasher"]),
+                )
+                .flush_on_exit(false);
+            let kvs: Kvs = match identity {
+                Some(identity) => builder.identity(identity),
+                None => builder,
+            }
+            .build()
+            .unwrap();
+            kvs
+        };
+
+        let flasher = open(Some("flasher"));
+        flasher.set_value("calibration/gain", 2.0).unwrap();
+        flasher.flush().unwrap();
+        assert_eq!(
+            flasher.get_value("audio/volume"),
+            Err(ErrorCode::PermissionDenied)
+        );
+
+        let audio = open(Some("audio"));
+        audio.set_value("audio/volume", 7.0).unwrap();
+        audio.set_value("shared", true).unwrap();
+        assert_eq!(audio.get_value_as::<f64>("audio/volume"), Ok(7.0));
+        assert_eq!(
+            audio.set_value("calibration/gain", 3.0),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            audio.remove_key("calibration/gain"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(audio.get_value_as::<f64>("calibration/gain"), Ok(2.0));
+        assert!(audio.export_json().is_ok());
+        audio.flush().unwrap();
+
+        // Keys of private namespaces are hidden from other identities
+        let hmi = open(Some("hmi"));
+        assert_eq!(
+            hmi.get_value("audio/volume"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            hmi.key_exists("audio/volume"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        let mut keys = hmi.get_all_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["calibration/gain", "shared"]);
+        assert_eq!(hmi.export_json(), Err(ErrorCode::PermissionDenied));
+        assert_eq!(hmi.reset(), Err(ErrorCode::PermissionDenied));
+
+        // Protected keys are neither counted nor measured
+        assert_eq!(hmi.key_count(), Ok(2));
+        assert_eq!(hmi.key_count_prefix("audio/"), Ok(0));
+        assert_eq!(hmi.any_key_with_prefix("audio/"), Ok(false));
+        assert_eq!(hmi.serialized_size(), Err(ErrorCode::PermissionDenied));
+        assert_eq!(hmi.stats().err(), Some(ErrorCode::PermissionDenied));
+        assert_eq!(audio.key_count_prefix("audio/"), Ok(1));
+        assert!(audio.any_key_with_prefix("audio/").unwrap());
+        assert!(audio.serialized_size().is_ok());
+
+        // Protected keys need an identity
+        let anonymous = open(None);
+        assert_eq!(anonymous.get_value_as::<bool>("shared"), Ok(true));
+        assert_eq!(
+            anonymous.get_value("audio/volume"),
+            Err(ErrorCode::AuthenticationFailed)
+        );
+        assert_eq!(
+            anonymous.set_values([("shared", false), ("calibration/gain", false)]),
+            Err(ErrorCode::AuthenticationFailed)
+        );
+        assert_eq!(anonymous.get_value_as::<bool>("shared"), Ok(true));
     }
 
     #[test]
-    fn test_get_kvs_filename_and_hash_filename() {
-        let kvs = new_kvs_with_mock();
-        // No files exist, so should return FileNotFound
-        let res = kvs.get_kvs_filename(SnapshotId::new(1));
-        assert!(matches!(res, Err(ErrorCode::FileNotFound)));
-        let res = kvs.get_hash_filename(SnapshotId::new(1));
-        assert!(matches!(res, Err(ErrorCode::FileNotFound)));
+    fn test_ownership_manifest() {
+        let dir = tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("kvs_0_owners.json"),
+            r#"{"calibration": ["flasher"]}"#,
+        )
+        .unwrap();
+        let open = |identity: &str| {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .identity(identity)
+                .flush_on_exit(false)
+                .build()
+                .unwrap()
+        };
+
+        let flasher = open("flasher");
+        flasher.set_value("calibration/gain", 2.0).unwrap();
+        assert_eq!(
+            flasher.set_value("mode", 1.0),
+            Err(ErrorCode::PermissionDenied)
+        );
+        flasher.flush().unwrap();
+
+        // Denied writes are reported to the observers
+        let hmi = open("hmi");
+        let denied = Arc::new(Mutex::new(Vec::new()));
+        let seen = denied.clone();
+        hmi.subscribe("*", move |event: &KvsEvent| {
+            if let KvsEvent::WriteDenied { key, identity } = event {
+                seen.lock().unwrap().push((key.clone(), identity.clone()));
+            }
+        })
+        .unwrap();
+        hmi.set_value("mode", 1.0).unwrap();
+        assert_eq!(
+            hmi.set_value("calibration/gain", 3.0),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            hmi.remove_prefix("calibration/"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            hmi.set_values([("mode", 1.0), ("calibration/offset", 1.0)]),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(hmi.get_value_as::<f64>("calibration/gain"), Ok(2.0));
+        assert_eq!(
+            *denied.lock().unwrap(),
+            vec![
+                ("calibration/gain".to_string(), Some("hmi".to_string())),
+                ("calibration/gain".to_string(), Some("hmi".to_string())),
+                ("calibration/offset".to_string(), Some("hmi".to_string())),
+            ]
+        );
+        drop(hmi);
+
+        // An invalid manifest fails the open
+        std::fs::write(dir.path().join("kvs_0_owners.json"), "[]").unwrap();
+        assert_eq!(
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .build()
+                .err(),
+            Some(ErrorCode::JsonParserError)
+        );
     }
 
     #[test]
-    fn test_get_value_error_cases() {
-        let kvs = new_kvs_with_mock();
-        // Key does not exist
-        let res = kvs.get_value("not_found");
-        assert!(matches!(res, Err(ErrorCode::KeyNotFound)));
+    fn test_migrations() {
+        let dir = tempdir().unwrap();
+        let builder = || {
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .flush_on_exit(false)
+        };
+        let rename = |data: &mut KvsMap| {
+            let value = data.remove("volume").ok_or("volume missing")?;
+            data.insert("audio/volume".to_string(), value);
+            Ok(())
+        };
+
+        let kvs = builder().build().unwrap();
+        kvs.set_value("volume", 7.0).unwrap();
+        kvs.flush().unwrap();
+        kvs.flush().unwrap();
+        assert_eq!(kvs.schema_version(), 0);
+        drop(kvs);
+
+        // A failing migration fails the open and leaves the file unchanged
+        assert_eq!(
+            builder()
+                .migration(0, 1, |_| Err("unsupported".to_string()))
+                .build()
+                .err(),
+            Some(ErrorCode::ValidationFailed)
+        );
+
+        // Migrations are applied on open and persisted with the version
+        let kvs = builder().migration(0, 1, rename).build().unwrap();
+        assert_eq!(kvs.schema_version(), 1);
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume"), Ok(7.0));
+        assert_eq!(
+            kvs.set_value("$version", 2.0),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert!(!kvs
+            .get_all_keys()
+            .unwrap()
+            .contains(&"$version".to_string()));
+        drop(kvs);
+
+        let kvs = builder().migration(0, 1, rename).build().unwrap();
+        assert_eq!(kvs.schema_version(), 1);
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume"), Ok(7.0));
+
+        // Registering on an open instance applies the migration right away
+        kvs.register_migration(1, 2, |data| {
+            data.insert("audio/muted".to_string(), KvsValue::Boolean(false));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(kvs.schema_version(), 2);
+        assert_eq!(kvs.get_value_as::<bool>("audio/muted"), Ok(false));
+        assert_eq!(
+            kvs.register_migration(2, 2, |_| Ok(())),
+            Err(ErrorCode::ValidationFailed)
+        );
+
+        // Snapshots of older versions are migrated on restore
+        assert_eq!(
+            kvs.open_snapshot_view(SnapshotId(1))
+                .unwrap()
+                .get_value("volume")
+                .ok()
+                .cloned(),
+            Some(KvsValue::from(7.0))
+        );
+        kvs.snapshot_restore(SnapshotId(1)).unwrap();
+        assert_eq!(kvs.schema_version(), 2);
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume"), Ok(7.0));
+        assert_eq!(kvs.get_value_as::<bool>("audio/muted"), Ok(false));
+        drop(kvs);
+
+        let kvs = builder().build().unwrap();
+        assert_eq!(kvs.schema_version(), 2);
+        assert!(kvs.export_json().unwrap().contains("\"$version\""));
     }
 
     #[test]
-    fn test_get_value_as_conversion_error() {
-        let kvs = new_kvs_with_mock();
-        kvs.set_value("str_key", "string".to_string()).unwrap();
-        // Try to get as f64, should fail
-        let res: Result<f64, _> = kvs.get_value_as("str_key");
-        assert!(matches!(res, Err(ErrorCode::ConversionFailed)));
+    fn test_compression() {
+        let dir = tempdir().unwrap();
+        let open = |compression: Compression| {
+            let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .compression(compression)
+                .flush_on_exit(false)
+                .build()
+                .unwrap();
+            kvs
+        };
+        let kvs_path = dir.path().join("kvs_0_0.json");
+
+        let kvs = open(Compression::Off);
+        for idx in 0..50 {
+            kvs.set_value(format!("sensor/{idx}/calibration"), "nominal".to_string())
+                .unwrap();
+        }
+        kvs.flush().unwrap();
+        let plain_size = fs::metadata(&kvs_path).unwrap().len();
+        drop(kvs);
+
+        let kvs = open(Compression::Lz4);
+        kvs.flush().unwrap();
+        let data = fs::read(&kvs_path).unwrap();
+        assert!(data.starts_with(b"KVZ"));
+        assert!((data.len() as u64) < plain_size / 4);
+        assert_eq!(kvs.verify_integrity().unwrap().issues, Vec::new());
+        drop(kvs);
+
+        // Compressed files are detected regardless of the setting
+        let kvs = open(Compression::Off);
+        assert_eq!(
+            kvs.get_value_as::<String>("sensor/7/calibration").unwrap(),
+            "nominal"
+        );
+        let view = kvs.open_snapshot_view(SnapshotId::new(0)).unwrap();
+        assert_eq!(view.len(), 50);
     }
 
     #[test]
-    fn test_is_value_default_error() {
+    fn test_get_value_ref() {
         let kvs = new_kvs_with_mock();
-        let res = kvs.is_value_default("not_found");
-        assert!(matches!(res, Err(ErrorCode::KeyNotFound)));
+        kvs.set_value("blob", KvsValue::bytes(vec![0; 1024]))
+            .unwrap();
+
+        let blob = kvs.get_value_ref("blob").unwrap();
+        assert!(blob.is_locked());
+        assert_eq!(*blob, KvsValue::bytes(vec![0; 1024]));
+        drop(blob);
+
+        let default = kvs.get_value_ref("mock_default_key").unwrap();
+        assert!(!default.is_locked());
+        assert_eq!(*default, KvsValue::from(111.0));
+        // Defaults are borrowed without a lock, the instance stays writable
+        kvs.set_value("blob", true).unwrap();
+        drop(default);
+
+        assert_eq!(
+            kvs.get_value_ref("missing").unwrap_err(),
+            ErrorCode::KeyNotFound
+        );
     }
 
     #[test]
-    fn test_kvs_open_and_set_get_value() {
+    fn test_open_named() {
         let dir = tempdir().unwrap();
-        let dir_path = dir.path().to_string_lossy().to_string();
+        let dir_string = Some(dir.path().to_string_lossy().to_string());
+        let open = |name: &str| {
+            let kvs = Kvs::open_named(
+                name,
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Optional,
+                dir_string.clone(),
+            )
+            .unwrap();
+            kvs.flush_on_exit(false);
+            kvs
+        };
 
-        let instance_id = InstanceId::new(42);
-        let kvs = Kvs::open(
-            instance_id.clone(),
-            OpenNeedDefaults::Optional,
-            OpenNeedKvs::Optional,
-            Some(dir_path.clone()),
-        )
-        .unwrap();
-        let _ = kvs.set_value("direct", KvsValue::String("abc".to_string()));
-        let value = kvs.get_value("direct").unwrap();
-        assert_eq!(value.get::<String>().unwrap(), "abc");
+        let kvs = open("body_ctrl");
+        kvs.set_value("lights", true).unwrap();
+        kvs.flush().unwrap();
+        assert!(dir.path().join("kvs_n-body+5Fctrl_0.json").exists());
+        drop(kvs);
+        assert!(open("body_ctrl").get_value_as::<bool>("lights").unwrap());
+
+        // Names sharing a prefix or looking like a number don't share files
+        for name in ["body", "body_ctrl_0", "0"] {
+            assert!(!open(name).key_exists("lights").unwrap());
+        }
+        assert_ne!(
+            InstanceId::named("0").filename_prefix(),
+            InstanceId::new(0).filename_prefix()
+        );
+        assert_eq!(InstanceId::named("body_ctrl").to_string(), "body_ctrl");
     }
 
     #[test]
-    fn test_kvs_reset() {
+    fn test_snapshot_retention() {
         let dir = tempdir().unwrap();
-        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = |retention: SnapshotRetention| {
+            let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .snapshot_retention(retention)
+                .flush_on_exit(false)
+                .build()
+                .unwrap();
+            kvs
+        };
 
-        let instance_id = InstanceId::new(43);
-        let kvs = Kvs::open(
-            instance_id.clone(),
-            OpenNeedDefaults::Optional,
-            OpenNeedKvs::Optional,
-            Some(dir_path.clone()),
-        )
-        .unwrap();
-        let _ = kvs.set_value("reset", 1.0);
-        assert!(kvs.get_value("reset").is_ok());
-        kvs.reset().unwrap();
-        assert!(matches!(
-            kvs.get_value("reset"),
-            Err(ErrorCode::KeyNotFound)
-        ));
+        let kvs = open(SnapshotRetention::default());
+        for i in 0..4 {
+            kvs.set_value("counter", i as f64).unwrap();
+            kvs.flush().unwrap();
+        }
+        assert_eq!(kvs.snapshot_list().unwrap().len(), 3);
+        drop(kvs);
+
+        // A lower count prunes the snapshots left over from the higher one
+        let kvs = open(SnapshotRetention {
+            max_count: 1,
+            ..Default::default()
+        });
+        assert_eq!(kvs.snapshot_retention().max_count, 1);
+        kvs.flush().unwrap();
+        assert_eq!(kvs.snapshot_list().unwrap().len(), 1);
+        assert!(!dir.path().join("kvs_0_2.json").exists());
+        drop(kvs);
+
+        // The oldest snapshots are pruned to meet the disk budget
+        let kvs = open(SnapshotRetention {
+            max_total_size: Some(100),
+            ..Default::default()
+        });
+        kvs.set_value("padding", "x".repeat(60)).unwrap();
+        kvs.flush().unwrap();
+        kvs.flush().unwrap();
+        let list = kvs.snapshot_list().unwrap();
+        assert_eq!(list.len(), 1);
+        assert!(list.iter().map(|info| info.size).sum::<u64>() <= 100);
+        drop(kvs);
+
+        // Outdated snapshots are pruned
+        let kvs = open(SnapshotRetention {
+            max_age: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        std::thread::sleep(Duration::from_millis(10));
+        kvs.flush().unwrap();
+        assert!(kvs.snapshot_list().unwrap().is_empty());
     }
 
     #[test]
-    fn test_kvs_key_exists_and_get_all_keys() {
+    fn test_flush_incremental() {
         let dir = tempdir().unwrap();
-        let dir_path = dir.path().to_string_lossy().to_string();
+        let open = || {
+            let kvs = Kvs::open_with_dir(
+                InstanceId::new(0),
+                dir.path(),
+                OpenNeedDefaults::Optional,
+                OpenNeedKvs::Optional,
+            )
+            .unwrap();
+            kvs.flush_on_exit(false);
+            kvs
+        };
+        let kvs_path = dir.path().join("kvs_0_0.json");
+        let delta_path = dir.path().join("kvs_0.delta");
 
-        let instance_id = InstanceId::new(44);
-        let kvs = Kvs::open(
-            instance_id.clone(),
-            OpenNeedDefaults::Optional,
-            OpenNeedKvs::Optional,
-            Some(dir_path.clone()),
-        )
-        .unwrap();
-        assert!(!kvs.key_exists("foo").unwrap());
-        let _ = kvs.set_value("foo", KvsValue::Boolean(true));
-        assert!(kvs.key_exists("foo").unwrap());
-        let keys = kvs.get_all_keys().unwrap();
-        assert!(keys.contains(&"foo".to_string()));
+        let kvs = open();
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.set_value("b", "padding".repeat(20)).unwrap();
+        kvs.set_value("c", true).unwrap();
+        kvs.flush().unwrap();
+        let base = fs::read(&kvs_path).unwrap();
+
+        // Only the delta journal is written, replayed on open
+        kvs.set_value("a", 2.0).unwrap();
+        kvs.remove_key("c").unwrap();
+        kvs.flush_incremental().unwrap();
+        assert_eq!(kvs.stats().unwrap().dirty_key_count, 0);
+        assert_eq!(fs::read(&kvs_path).unwrap(), base);
+        assert!(delta_path.exists());
+        drop(kvs);
+
+        let kvs = open();
+        assert_eq!(kvs.get_value_as::<f64>("a").unwrap(), 2.0);
+        assert!(!kvs.key_exists("c").unwrap());
+
+        // A full flush folds the delta journal into the KVS file
+        kvs.set_value("d", 4.0).unwrap();
+        kvs.flush().unwrap();
+        assert!(!delta_path.exists());
+        drop(kvs);
+        let kvs = open();
+        assert_eq!(kvs.get_value_as::<f64>("a").unwrap(), 2.0);
+        assert_eq!(kvs.get_value_as::<f64>("d").unwrap(), 4.0);
+
+        // Once the delta journal is as large as the KVS file it's compacted
+        kvs.set_value("b", "changed".repeat(40)).unwrap();
+        kvs.flush_incremental().unwrap();
+        assert!(delta_path.exists());
+        kvs.set_value("a", 3.0).unwrap();
+        kvs.flush_incremental().unwrap();
+        assert!(!delta_path.exists());
+        assert_ne!(fs::read(&kvs_path).unwrap(), base);
     }
 
     #[test]
-    fn test_kvs_remove_key() {
+    fn test_kvs_restore_hook() {
         let dir = tempdir().unwrap();
-        let dir_path = dir.path().to_string_lossy().to_string();
-
-        let instance_id = InstanceId::new(45);
         let kvs = Kvs::open(
-            instance_id.clone(),
+            InstanceId::new(48),
             OpenNeedDefaults::Optional,
             OpenNeedKvs::Optional,
-            Some(dir_path.clone()),
+            Some(dir.path().to_string_lossy().to_string()),
         )
         .unwrap();
-        let _ = kvs.set_value("bar", 2.0);
-        assert!(kvs.key_exists("bar").unwrap());
-        kvs.remove_key("bar").unwrap();
-        assert!(!kvs.key_exists("bar").unwrap());
+        kvs.flush_on_exit(false);
+        kvs.set_value("mode", "legacy".to_string()).unwrap();
+        kvs.snapshot_create("old").unwrap();
+        kvs.set_value("mode", "current".to_string()).unwrap();
+
+        // Veto
+        kvs.set_restore_hook(|data: &mut KvsMap| match data.get("mode") {
+            Some(KvsValue::String(mode)) if mode == "legacy" => Err("legacy mode".to_string()),
+            _ => Ok(()),
+        })
+        .unwrap();
+        assert_eq!(
+            kvs.snapshot_restore_tag("old"),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            kvs.get_value_as::<String>("mode").unwrap(),
+            "current".to_string()
+        );
+
+        // Transform
+        kvs.set_restore_hook(|data: &mut KvsMap| {
+            data.insert("mode".to_string(), KvsValue::from("migrated".to_string()));
+            Ok(())
+        })
+        .unwrap();
+        kvs.snapshot_restore_tag("old").unwrap();
+        assert_eq!(
+            kvs.get_value_as::<String>("mode").unwrap(),
+            "migrated".to_string()
+        );
+
+        // Consistency rules are checked after the hook
+        kvs.clear_restore_hook().unwrap();
+        kvs.add_rule("no_legacy", |ctx: &RuleContext| match ctx.get("mode") {
+            Some(KvsValue::String(mode)) if mode == "legacy" => Err("legacy mode".to_string()),
+            _ => Ok(()),
+        })
+        .unwrap();
+        assert_eq!(
+            kvs.snapshot_restore_tag("old"),
+            Err(ErrorCode::ValidationFailed)
+        );
+        kvs.remove_rule("no_legacy").unwrap();
+        kvs.snapshot_restore_tag("old").unwrap();
+        assert_eq!(
+            kvs.get_value_as::<String>("mode").unwrap(),
+            "legacy".to_string()
+        );
     }
 
     #[test]
-    fn test_kvs_flush_and_snapshot() {
+    fn test_kvs_write_stats() {
         let dir = tempdir().unwrap();
-        let dir_path = dir.path().to_string_lossy().to_string();
-
-        let instance_id = InstanceId::new(46);
-        let kvs = Kvs::open(
-            instance_id.clone(),
+        let kvs = Kvs::open_with_options(
+            InstanceId::new(47),
             OpenNeedDefaults::Optional,
             OpenNeedKvs::Optional,
-            Some(dir_path.clone()),
+            Some(dir.path().to_string_lossy().to_string()),
+            KvsOptions {
+                write_ahead_log: true,
+                write_amplification_threshold: Some(1.0),
+                ..KvsOptions::default()
+            },
         )
         .unwrap();
-        let _ = kvs.set_value("snap", 3.0);
-        // Before flush, snapshot count should be 0 (no snapshots yet)
-        assert_eq!(kvs.snapshot_count(), 0);
-        kvs.flush().unwrap();
-        // After flush, snapshot count should be 1
-        assert_eq!(kvs.snapshot_count(), 1);
-        // Call flush again to rotate and create a snapshot
+        kvs.flush_on_exit(false);
+
+        kvs.set_value("text", "abcd".to_string()).unwrap();
+        kvs.set_value("flag", true).unwrap();
+        kvs.remove_key("flag").unwrap();
+        let report = kvs.write_stats().unwrap();
+        assert_eq!(report.flushes, 0);
+        assert_eq!(report.pending.logical_bytes, (4 + 4) + (4 + 1) + 4);
+        let wal_size = fs::metadata(dir.path().join("kvs_47.wal")).unwrap().len();
+        assert_eq!(report.pending.written_bytes, wal_size);
+
         kvs.flush().unwrap();
-        assert_eq!(kvs.snapshot_count(), 2);
-        // Restore from snapshot if available
-        if kvs.snapshot_count() > 0 {
-            kvs.snapshot_restore(SnapshotId::new(1)).unwrap();
-        }
+        let report = kvs.write_stats().unwrap();
+        assert_eq!(report.flushes, 1);
+        assert_eq!(report.pending, WriteStats::default());
+        assert_eq!(report.last_flush, report.total);
+        assert_eq!(
+            report.last_flush.written_bytes,
+            wal_size + GenericKvs::<JsonBackend>::saved_size(&kvs.filename_prefix)
+        );
+        assert!(report.rolling_amplification.unwrap() > 1.0);
     }
 
     #[test]
@@ -0,0 +1,383 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Access control per key namespace
+//!
+//! An instance opened with an [`AccessPolicy`] checks every operation against the client
+//! identity it was opened with, see [`KvsBuilder::identity`](crate::kvs_builder::KvsBuilder::identity).
+//! Namespaces (keys `<namespace>/...`) can be read-only, writable only by the listed identities,
+//! or private, readable and writable only by the listed identities. Other keys aren't
+//! restricted; for nested namespaces the protection of the innermost one applies.
+//!
+//! Namespaces can also be owned by components: only the owners write them, and an owner only
+//! writes the namespaces it owns. Platform integrators declare the owners in the optional
+//! manifest `kvs_<instance_id>_owners.json` next to the KVS file, which maps namespaces to their
+//! owners, e.g. `{"calibration": ["flasher"], "audio": ["audio", "hmi"]}`. The manifest is loaded
+//! on open and replaces the protection of the namespaces it lists.
+//!
+//! Operations on a protected key fail with `ErrorCode::AuthenticationFailed` if the instance has
+//! no identity and with `ErrorCode::PermissionDenied` if the identity isn't listed. Denied writes
+//! of a key are reported to the observers as
+//! [`KvsEvent::WriteDenied`](crate::kvs_observer::KvsEvent::WriteDenied). Key listings leave out
+//! keys that can't be read. Operations on the whole data, e.g. reset, snapshot restore, export
+//! or a cursor, need access to all protected namespaces, writing them isn't permitted to owners.
+//!
+//! The identity belongs to the instance: handles opened with
+//! [`build_shared`](crate::kvs_builder::KvsBuilder::build_shared) share the identity of the first
+//! open, isolated handles have their own. The identity is declared by the opening code, not
+//! verified, so the policy guards components of one process against each other's mistakes.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_error;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use tinyjson::JsonValue;
+
+/// Protection of a key namespace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NamespaceProtection {
+    /// All identities can read, only the listed identities can write
+    ReadOnly(Vec<String>),
+
+    /// Only the listed identities can read and write
+    Private(Vec<String>),
+
+    /// All identities can read, only the listed owners can write, and they can't write outside
+    /// the namespaces they own
+    Owned(Vec<String>),
+}
+
+/// Protected key namespaces of an instance
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccessPolicy {
+    /// Protection by namespace
+    namespaces: Vec<(String, NamespaceProtection)>,
+}
+
+/// Return if a key belongs to a namespace or one of its nested namespaces, `""` contains all keys
+pub(crate) fn in_namespace(key: &str, namespace: &str) -> bool {
+    namespace.is_empty()
+        || key
+            .strip_prefix(namespace)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl AccessPolicy {
+    /// Create a policy without protected namespaces
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mark a namespace read-only
+    ///
+    /// # Parameters
+    ///   * `namespace`: Namespace, e.g. `calibration` for the keys `calibration/...`
+    ///   * `writers`: Identities that can still write
+    pub fn read_only<S: Into<String>>(self, namespace: S, writers: &[&str]) -> Self {
+        self.protect(namespace, NamespaceProtection::ReadOnly(owned(writers)))
+    }
+
+    /// Mark a namespace private
+    ///
+    /// # Parameters
+    ///   * `namespace`: Namespace, e.g. `audio` for the keys `audio/...`
+    ///   * `members`: Identities that can read and write
+    pub fn private<S: Into<String>>(self, namespace: S, members: &[&str]) -> Self {
+        self.protect(namespace, NamespaceProtection::Private(owned(members)))
+    }
+
+    /// Declare the owners of a namespace
+    ///
+    /// # Parameters
+    ///   * `namespace`: Namespace, e.g. `calibration` for the keys `calibration/...`
+    ///   * `owners`: Identities that write the namespace and no unowned keys
+    pub fn owned<S: Into<String>>(self, namespace: S, owners: &[&str]) -> Self {
+        self.protect(namespace, NamespaceProtection::Owned(owned(owners)))
+    }
+
+    /// Set the protection of a namespace, replacing a previous one
+    ///
+    /// # Parameters
+    ///   * `namespace`: Namespace
+    ///   * `protection`: Protection of the namespace
+    pub fn protect<S: Into<String>>(
+        mut self,
+        namespace: S,
+        protection: NamespaceProtection,
+    ) -> Self {
+        let namespace = namespace.into();
+        self.namespaces.retain(|(name, _)| *name != namespace);
+        self.namespaces.push((namespace, protection));
+        self
+    }
+
+    /// Return if no namespace is protected
+    pub fn is_empty(&self) -> bool {
+        self.namespaces.is_empty()
+    }
+
+    /// Add the ownership manifest to the policy
+    ///
+    /// # Parameters
+    ///   * `path`: Manifest file, no-op if it doesn't exist
+    ///
+    /// # Return Values
+    ///   * Ok: Policy with the owned namespaces of the manifest
+    ///   * `ErrorCode::JsonParserError`: Manifest isn't an object of namespaces and owner lists
+    ///   * `ErrorCode::KvsFileReadError`: Manifest couldn't be read
+    pub(crate) fn load_manifest(mut self, path: &Path) -> Result<Self, ErrorCode> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(self),
+            Err(e) => {
+                log_error!("ownership manifest could not be read: {e}");
+                return Err(ErrorCode::KvsFileReadError);
+            }
+        };
+        let json: JsonValue = data.parse()?;
+        let invalid = || {
+            log_error!("invalid ownership manifest: {}", path.display());
+            ErrorCode::JsonParserError
+        };
+        let namespaces: &HashMap<String, JsonValue> = json.get().ok_or_else(invalid)?;
+        for (namespace, owners) in namespaces {
+            let owners: &Vec<JsonValue> = owners.get().ok_or_else(invalid)?;
+            let owners = owners
+                .iter()
+                .map(|owner| owner.get::<String>().cloned().ok_or_else(invalid))
+                .collect::<Result<_, _>>()?;
+            self = self.protect(namespace.clone(), NamespaceProtection::Owned(owners));
+        }
+        Ok(self)
+    }
+
+    /// Return the namespaces an identity owns
+    fn owned_by<'a>(&'a self, identity: &'a str) -> impl Iterator<Item = &'a str> {
+        self.namespaces
+            .iter()
+            .filter_map(move |(namespace, protection)| match protection {
+                NamespaceProtection::Owned(owners) if owners.iter().any(|o| o == identity) => {
+                    Some(namespace.as_str())
+                }
+                _ => None,
+            })
+    }
+
+    /// Check an access without logging
+    fn permits(&self, identity: Option<&str>, key: &str, write: bool) -> Result<(), ErrorCode> {
+        if let Some(identity) = identity.filter(|_| write) {
+            let mut owned = self.owned_by(identity).peekable();
+            if owned.peek().is_some() && !owned.any(|namespace| in_namespace(key, namespace)) {
+                return Err(ErrorCode::PermissionDenied);
+            }
+        }
+        let protection = self
+            .namespaces
+            .iter()
+            .filter(|(namespace, _)| in_namespace(key, namespace))
+            .max_by_key(|(namespace, _)| namespace.len())
+            .map(|(_, protection)| protection);
+        let listed = match protection {
+            None => return Ok(()),
+            Some(NamespaceProtection::ReadOnly(_) | NamespaceProtection::Owned(_)) if !write => {
+                return Ok(())
+            }
+            Some(
+                NamespaceProtection::ReadOnly(listed)
+                | NamespaceProtection::Private(listed)
+                | NamespaceProtection::Owned(listed),
+            ) => listed,
+        };
+        match identity {
+            Some(identity) if listed.iter().any(|listed| listed == identity) => Ok(()),
+            Some(_) => Err(ErrorCode::PermissionDenied),
+            None => Err(ErrorCode::AuthenticationFailed),
+        }
+    }
+
+    /// Check that an identity may access a key
+    ///
+    /// # Parameters
+    ///   * `identity`: Identity of the instance, `None` if it has none
+    ///   * `key`: Accessed key
+    ///   * `write`: The key is set or removed
+    ///
+    /// # Return Values
+    ///   * Ok: Access permitted
+    ///   * `ErrorCode::AuthenticationFailed`: Key is protected and there's no identity
+    ///   * `ErrorCode::PermissionDenied`: Key is protected and the identity isn't listed
+    pub(crate) fn check(
+        &self,
+        identity: Option<&str>,
+        key: &str,
+        write: bool,
+    ) -> Result<(), ErrorCode> {
+        self.permits(identity, key, write).inspect_err(|_| {
+            let access = if write { "write" } else { "read" };
+            log_error!("identity {identity:?} isn't permitted to {access} protected key: {key}");
+        })
+    }
+
+    /// Return if an identity may read a key, used to filter key listings
+    pub(crate) fn readable(&self, identity: Option<&str>, key: &str) -> bool {
+        self.permits(identity, key, false).is_ok()
+    }
+
+    /// Check that an identity may access all protected namespaces
+    ///
+    /// # Return Values
+    ///   * Ok: Access permitted
+    ///   * `ErrorCode::AuthenticationFailed`: A namespace is protected and there's no identity
+    ///   * `ErrorCode::PermissionDenied`: The identity isn't listed for a protected namespace
+    pub(crate) fn check_all(&self, identity: Option<&str>, write: bool) -> Result<(), ErrorCode> {
+        for (namespace, _) in self.namespaces.iter() {
+            self.check(identity, &format!("{namespace}/"), write)?;
+        }
+        // The whole data includes keys outside the namespaces of an owner
+        if let Some(identity) = identity.filter(|_| write) {
+            if self
+                .owned_by(identity)
+                .any(|namespace| !namespace.is_empty())
+            {
+                log_error!("owner {identity} isn't permitted to write the whole data");
+                return Err(ErrorCode::PermissionDenied);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn owned(identities: &[&str]) -> Vec<String> {
+    identities
+        .iter()
+        .map(|identity| identity.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_policy() {
+        let policy = AccessPolicy::new()
+            .read_only("calibration", &["flasher"])
+            .private("audio", &["audio"])
+            .private("audio/shared", &["audio", "hmi"]);
+        assert!(!policy.is_empty());
+
+        // Read-only namespaces can be read by everyone
+        assert_eq!(policy.check(None, "calibration/gain", false), Ok(()));
+        assert_eq!(
+            policy.check(None, "calibration/gain", true),
+            Err(ErrorCode::AuthenticationFailed)
+        );
+        assert_eq!(
+            policy.check(Some("hmi"), "calibration/gain", true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(Some("flasher"), "calibration/gain", true),
+            Ok(())
+        );
+
+        // The innermost namespace applies, names are matched up to the separator
+        assert!(!policy.readable(Some("hmi"), "audio/volume"));
+        assert!(policy.readable(Some("hmi"), "audio/shared/volume"));
+        assert!(policy.readable(Some("hmi"), "audiovolume"));
+        assert!(policy.readable(None, "other"));
+
+        assert_eq!(policy.check_all(Some("audio"), false), Ok(()));
+        assert_eq!(
+            policy.check_all(Some("audio"), true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(AccessPolicy::new().check_all(None, true), Ok(()));
+    }
+
+    #[test]
+    fn test_owners_confined_to_namespaces() {
+        let policy = AccessPolicy::new()
+            .owned("calibration", &["flasher"])
+            .owned("audio", &["audio"])
+            .private("audio/shared", &["audio", "hmi"]);
+
+        // Owned namespaces can be read by everyone, written only by their owners
+        assert_eq!(policy.check(None, "calibration/gain", false), Ok(()));
+        assert_eq!(
+            policy.check(Some("hmi"), "calibration/gain", true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(None, "calibration/gain", true),
+            Err(ErrorCode::AuthenticationFailed)
+        );
+        assert_eq!(
+            policy.check(Some("flasher"), "calibration/gain", true),
+            Ok(())
+        );
+
+        // Owners don't write outside their namespaces, other identities do
+        assert_eq!(
+            policy.check(Some("flasher"), "audio/volume", true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            policy.check(Some("flasher"), "other", true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(policy.check(Some("flasher"), "other", false), Ok(()));
+        assert_eq!(policy.check(Some("hmi"), "other", true), Ok(()));
+        assert_eq!(
+            policy.check(Some("audio"), "audio/shared/mix", true),
+            Ok(())
+        );
+        assert_eq!(policy.check(Some("hmi"), "audio/shared/mix", true), Ok(()));
+
+        // Writes of the whole data reach keys outside the namespaces of an owner
+        assert_eq!(
+            policy.check_all(Some("audio"), true),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            AccessPolicy::new()
+                .owned("", &["flasher"])
+                .check_all(Some("flasher"), true),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_load_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("kvs_0_owners.json");
+
+        // A missing manifest leaves the policy unchanged
+        let policy = AccessPolicy::new().private("calibration", &["flasher"]);
+        assert_eq!(policy.clone().load_manifest(&path), Ok(policy.clone()));
+
+        fs::write(&path, r#"{"calibration": ["flasher", "tester"]}"#).unwrap();
+        assert_eq!(
+            policy.load_manifest(&path),
+            Ok(AccessPolicy::new().owned("calibration", &["flasher", "tester"]))
+        );
+
+        for manifest in ["{", "[]", r#"{"audio": "audio"}"#, r#"{"audio": [1]}"#] {
+            fs::write(&path, manifest).unwrap();
+            assert_eq!(
+                AccessPolicy::new().load_manifest(&path),
+                Err(ErrorCode::JsonParserError)
+            );
+        }
+    }
+}
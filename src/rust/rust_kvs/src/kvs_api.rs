@@ -15,19 +15,46 @@ use std::path::PathBuf;
 use core::fmt;
 
 use crate::error_code::ErrorCode;
+use crate::kvs_access::AccessPolicy;
+use crate::kvs_cipher::KvsCipher;
+use crate::kvs_fairness::FairnessPolicy;
+use crate::kvs_migration::Migrations;
 use crate::kvs_value::KvsValue;
+use crate::kvs_worker::WorkerConfig;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 /// Instance ID
+///
+/// Instances are identified by a number or by a name, e.g. the name of the owning component.
 #[derive(Clone, Debug, PartialEq)]
-pub struct InstanceId(usize);
+pub struct InstanceId(InstanceKey);
+
+/// Number or name of an instance
+#[derive(Clone, Debug, PartialEq)]
+enum InstanceKey {
+    Numeric(usize),
+    Named(String),
+}
 
 /// Snapshot ID
 #[derive(Clone, Debug, PartialEq)]
 pub struct SnapshotId(pub usize);
 
+/// Stable snapshot identifier
+///
+/// Each flush assigns the next generation to the written KVS file. Unlike the [`SnapshotId`],
+/// which counts the rotations since a snapshot was taken, the generation of a snapshot never
+/// changes, so it stays a valid reference until the snapshot is pruned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SnapshotGeneration(pub u64);
+
 impl fmt::Display for InstanceId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        match &self.0 {
+            InstanceKey::Numeric(id) => write!(f, "{id}"),
+            InstanceKey::Named(name) => write!(f, "{name}"),
+        }
     }
 }
 
@@ -37,10 +64,44 @@ impl fmt::Display for SnapshotId {
     }
 }
 
+impl fmt::Display for SnapshotGeneration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 impl InstanceId {
     /// Create a new instance ID
     pub fn new(id: usize) -> Self {
-        Self(id)
+        Self(InstanceKey::Numeric(id))
+    }
+
+    /// Create a named instance ID
+    ///
+    /// Any name can be used. The files of a named instance are prefixed `kvs_n-<name>`, where
+    /// all characters except ASCII letters, digits and `-` are escaped as `+` followed by the two
+    /// hex digits of each UTF-8 byte, e.g. `kvs_n-body+5Fctrl` for `body_ctrl`. So different
+    /// names never share files with each other or with numeric instances.
+    pub fn named<S: Into<String>>(name: S) -> Self {
+        Self(InstanceKey::Named(name.into()))
+    }
+
+    /// Return the filename prefix of the instance files, `kvs_<id>` or `kvs_n-<escaped name>`
+    pub(crate) fn filename_prefix(&self) -> String {
+        match &self.0 {
+            InstanceKey::Numeric(id) => format!("kvs_{id}"),
+            InstanceKey::Named(name) => {
+                let mut prefix = String::from("kvs_n-");
+                for byte in name.bytes() {
+                    if byte.is_ascii_alphanumeric() || byte == b'-' {
+                        prefix.push(byte as char);
+                    } else {
+                        prefix.push_str(&format!("+{byte:02X}"));
+                    }
+                }
+                prefix
+            }
+        }
     }
 }
 
@@ -51,6 +112,156 @@ impl SnapshotId {
     }
 }
 
+impl SnapshotGeneration {
+    /// Create a new snapshot generation
+    pub fn new(generation: u64) -> Self {
+        SnapshotGeneration(generation)
+    }
+}
+
+/// Descriptor of an existing snapshot
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotInfo {
+    /// Snapshot ID to restore the snapshot
+    pub id: SnapshotId,
+
+    /// Stable generation, `None` if the snapshot was written without one
+    pub generation: Option<SnapshotGeneration>,
+
+    /// Time the snapshot data was written
+    pub created: SystemTime,
+
+    /// Size of the snapshot file in bytes
+    pub size: u64,
+
+    /// Adler-32 checksum from the hash file, `None` if the hash file is missing or invalid
+    pub hash: Option<u32>,
+}
+
+/// Outcome of a store compaction
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactionReport {
+    /// Size of the KVS file, hash file and write-ahead log in bytes before the compaction
+    pub size_before: u64,
+
+    /// Size of the same files in bytes after the compaction
+    pub size_after: u64,
+
+    /// Tag of the snapshot holding the data from before the compaction
+    pub snapshot_tag: String,
+}
+
+/// Problem found by [`verify_integrity`](crate::kvs::GenericKvs::verify_integrity)
+#[derive(Debug, PartialEq)]
+pub enum IntegrityIssue {
+    /// KVS or snapshot file couldn't be read
+    Unreadable(PathBuf),
+
+    /// Hash file is missing or malformed
+    HashMissing(PathBuf),
+
+    /// File content doesn't match its hash file
+    HashMismatch(PathBuf),
+
+    /// File content couldn't be decrypted or parsed
+    ParseFailed(PathBuf, ErrorCode),
+
+    /// Snapshot exists but the newer snapshot before it is missing
+    SnapshotGap(SnapshotId),
+
+    /// Snapshot doesn't have an older generation than the newer snapshot before it
+    GenerationOrder(SnapshotId),
+}
+
+/// Result of an integrity verification
+#[derive(Debug, Default, PartialEq)]
+pub struct IntegrityReport {
+    /// Count of verified KVS and snapshot files
+    pub checked_files: usize,
+
+    /// Found problems, empty if all files are intact
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    /// Return if no problem was found
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// KVS file written by a flush
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlushInfo {
+    /// Generation assigned to the written KVS file
+    pub generation: SnapshotGeneration,
+
+    /// Size of the KVS file and its hash file in bytes
+    pub size: u64,
+}
+
+/// Flush callback
+///
+/// Called after every flush that wrote the KVS file, without any KVS lock held.
+pub type KvsFlushCallback = Arc<dyn Fn(&FlushInfo) + Send + Sync>;
+
+/// Growth estimate of a KVS instance from its size history
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrowthTrend {
+    /// Count of size samples the estimate is based on
+    pub sample_count: usize,
+
+    /// Size of the KVS file and hash file at the last sample in bytes
+    pub current_size: u64,
+
+    /// Growth in bytes per day, negative if the store shrinks, `None` with fewer than two samples
+    pub bytes_per_day: Option<f64>,
+}
+
+impl GrowthTrend {
+    /// Project when the store reaches a size, e.g. its flash quota
+    ///
+    /// # Parameters
+    ///   * `limit`: Size in bytes
+    ///
+    /// # Return Values
+    ///   * Some: Time from the last sample until the limit is reached, zero if already reached
+    ///   * None: The store doesn't grow or there is no growth estimate
+    pub fn time_to_size(&self, limit: u64) -> Option<Duration> {
+        if self.current_size >= limit {
+            return Some(Duration::ZERO);
+        }
+        let rate = self.bytes_per_day.filter(|rate| *rate > 0.0)?;
+        let days = (limit - self.current_size) as f64 / rate;
+        Duration::try_from_secs_f64(days * 24.0 * 60.0 * 60.0).ok()
+    }
+}
+
+/// Usage statistics of a KVS instance
+#[derive(Clone, Debug, PartialEq)]
+pub struct KvsStats {
+    /// Count of stored keys, defaults aren't included
+    pub key_count: usize,
+
+    /// Size of the stored data serialized in the storage format in bytes, before encryption
+    pub serialized_size: u64,
+
+    /// Time of the last flush that wrote the KVS file, `None` if not flushed since open
+    pub last_flush: Option<SystemTime>,
+
+    /// Size of all snapshot files (rotated and tagged, including hash files) in bytes
+    pub snapshot_disk_usage: u64,
+
+    /// Count of keys set or removed since the last flush
+    pub dirty_key_count: usize,
+
+    /// Count of change events waiting in the queues of queued subscriptions
+    pub notification_queue_depth: usize,
+
+    /// Count of change events queued subscriptions lost on overflow
+    pub notifications_dropped: u64,
+}
+
 /// Need-Defaults flag
 pub enum OpenNeedDefaults {
     /// Optional: Open defaults only if available
@@ -89,6 +300,294 @@ impl From<bool> for OpenNeedKvs {
     }
 }
 
+/// Default count of write-ahead log records after which the log is compacted
+pub const WAL_COMPACT_THRESHOLD: usize = 1024;
+
+/// Default maximum number of snapshots
+///
+/// Feature: `FEAT_REQ__KVS__snapshots`
+pub const KVS_MAX_SNAPSHOTS: usize = 3;
+
+/// Retention policy of the snapshots of an instance
+///
+/// Snapshots are pruned on flush, oldest first, until all limits are met. The KVS file itself is
+/// never pruned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SnapshotRetention {
+    /// Maximum number of snapshots, 0 keeps no snapshots
+    pub max_count: usize,
+
+    /// Maximum age of a snapshot since it was flushed, `None` to keep snapshots of any age
+    pub max_age: Option<Duration>,
+
+    /// Maximum total size of the snapshot and hash files in bytes, `None` for no budget
+    pub max_total_size: Option<u64>,
+}
+
+impl Default for SnapshotRetention {
+    fn default() -> Self {
+        Self {
+            max_count: KVS_MAX_SNAPSHOTS,
+            max_age: None,
+            max_total_size: None,
+        }
+    }
+}
+
+/// Storage format of the KVS file
+///
+/// The format is detected on load, so a KVS can be switched to another format by opening it
+/// with the new format and flushing it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageFormat {
+    /// JSON document (default)
+    #[default]
+    Json,
+
+    /// CBOR (RFC 8949) document, faster to parse and keeps all number bits
+    Cbor,
+}
+
+/// Compression of the KVS file and snapshots
+///
+/// Compressed files are detected on load, so a KVS can be switched to another compression by
+/// opening it with the new setting and flushing it. The write-ahead log and the delta journal
+/// aren't compressed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Files are written uncompressed (default)
+    #[default]
+    Off,
+
+    /// LZ4 block format, fast with a good ratio for repetitive JSON
+    Lz4,
+}
+
+/// Synchronization of written files with the storage device
+///
+/// Stronger levels survive a power loss at the cost of latency and flash wear. Files are always
+/// replaced through a temporary file, so a crashed process never leaves a truncated file
+/// behind, independent of the level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Files aren't synchronized, changes written shortly before a power loss may be lost
+    None,
+
+    /// File data is synchronized (`fdatasync`), metadata not needed to read it may lag
+    Flush,
+
+    /// Files are synchronized with all their metadata (`fsync`)
+    Fsync,
+
+    /// Like `Fsync`, the directory is also synchronized so replaced files persist (default)
+    #[default]
+    FsyncDir,
+}
+
+/// Storage of the KVS data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Data is loaded from and flushed to the KVS file (default)
+    #[default]
+    Persistent,
+
+    /// Data is kept in memory only, the filesystem is never accessed
+    ///
+    /// The KVS starts empty with the embedded defaults only, flush is a no-op and file-based
+    /// snapshots aren't available. The data can be serialized with
+    /// [`flush_to`](crate::kvs::GenericKvs::flush_to).
+    Ephemeral,
+
+    /// Data is loaded from the KVS file and can't be changed
+    ///
+    /// Setting or removing keys, restoring snapshots and flushing fail with
+    /// `ErrorCode::PhysicalStorageFailure`. With [`KvsOptions::file_lock`] a shared lock is
+    /// taken, so several read-only instances can be open while no writer is.
+    ReadOnly,
+}
+
+/// Role of an instance in the merge journal shared by cooperating processes
+///
+/// Every mutation is appended to `kvs_<instance_id>.journal`. Records are applied in the order
+/// they were appended, so the last writer of a key wins and all processes merge to the same data.
+/// Only one process may be the compactor. Appends are serialized with a file lock, on QNX file
+/// locking isn't supported and only one process may write the journal at a time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum JournalRole {
+    /// No merge journal, flush writes the KVS file (default)
+    #[default]
+    Off,
+
+    /// Mutations are appended to the journal, flush applies the records of other processes
+    Writer,
+
+    /// Like `Writer`, flush also merges the journal into the KVS file and empties it
+    Compactor,
+}
+
+/// Precedence of embedded defaults against the on-disk defaults file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DefaultsPrecedence {
+    /// Use the on-disk defaults file if it exists, else the embedded defaults (default)
+    #[default]
+    OnDisk,
+
+    /// Always use the embedded defaults, the on-disk defaults file is ignored
+    Embedded,
+
+    /// Merge both, on-disk values override embedded values of the same key
+    MergeOnDisk,
+}
+
+/// Handling of stored keys by [`import_json`](crate::kvs::GenericKvs::import_json)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportMode {
+    /// The document replaces the stored data, keys not in the document are removed
+    Replace,
+
+    /// Only keys not stored yet are set, stored values are kept
+    KeepExisting,
+
+    /// All keys of the document are set, other stored keys are kept
+    Overwrite,
+}
+
+/// Collection of per-key access statistics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyStatsMode {
+    /// No statistics are collected (default)
+    #[default]
+    Off,
+
+    /// Counters are kept in memory only
+    Memory,
+
+    /// Counters are persisted on flush and loaded on open
+    Persistent,
+}
+
+/// Additional settings to open a KVS
+///
+/// The default settings open the KVS the same way as [`KvsApi::open`].
+#[derive(Clone, Debug)]
+pub struct KvsOptions {
+    /// Storage of the KVS data
+    pub open_mode: OpenMode,
+
+    /// Journal every mutation to a write-ahead log that is replayed on open
+    pub write_ahead_log: bool,
+
+    /// Count of write-ahead log records after which the log is compacted into the KVS file
+    pub wal_compact_threshold: usize,
+
+    /// Format used when the KVS file is written
+    pub storage_format: StorageFormat,
+
+    /// Compression used when the KVS file is written
+    pub compression: Compression,
+
+    /// Synchronization of the written files with the storage device
+    pub durability: Durability,
+
+    /// Defaults document (JSON or CBOR) compiled into the binary, e.g. with `include_bytes!`
+    pub embedded_defaults: Option<&'static [u8]>,
+
+    /// Precedence of the embedded defaults against the on-disk defaults file
+    pub defaults_precedence: DefaultsPrecedence,
+
+    /// Reject setting keys without a static default value
+    pub strict_keys: bool,
+
+    /// Key prefixes that may be set without a default value in strict mode
+    pub strict_exempt_prefixes: Vec<String>,
+
+    /// Collection of per-key read and write counters
+    pub key_stats: KeyStatsMode,
+
+    /// Count per-key versions for conditional writes, kept in the KVS file
+    pub key_versions: bool,
+
+    /// Sample the store size in `kvs_<instance_id>_size_history.json` for the growth trend
+    pub size_history: bool,
+
+    /// Initial flush-on-exit flag, can be changed later with [`KvsApi::flush_on_exit`]
+    pub flush_on_exit: bool,
+
+    /// Rolling write amplification above which a warning is logged on flush, `None` to never warn
+    pub write_amplification_threshold: Option<f64>,
+
+    /// Cipher the KVS file and snapshots are encrypted with, `None` to store them in plain text
+    pub cipher: Option<Arc<dyn KvsCipher>>,
+
+    /// Admission policy of concurrent readers and writers
+    pub fairness_policy: FairnessPolicy,
+
+    /// Settings of the worker threads of the instance
+    pub worker: WorkerConfig,
+
+    /// Lock the instance against other processes while it's open, not supported on QNX where the
+    /// instance is opened without the lock
+    pub file_lock: bool,
+
+    /// Role in the merge journal shared by cooperating processes
+    pub journal_role: JournalRole,
+
+    /// Record the change time and writer of every key in `kvs_<instance_id>_metadata.json`
+    pub key_metadata: bool,
+
+    /// Writer ID recorded in the metadata of changed keys
+    pub writer_id: Option<String>,
+
+    /// Maximum logical size of a single value in bytes, `None` for no limit
+    pub max_value_size: Option<u64>,
+
+    /// Retention policy of the snapshots
+    pub snapshot_retention: SnapshotRetention,
+
+    /// Client identity the access policy is checked against
+    pub identity: Option<String>,
+
+    /// Protected key namespaces
+    pub access_policy: AccessPolicy,
+
+    /// Schema migrations applied on open
+    pub migrations: Migrations,
+}
+
+impl Default for KvsOptions {
+    fn default() -> Self {
+        Self {
+            open_mode: OpenMode::Persistent,
+            write_ahead_log: false,
+            wal_compact_threshold: WAL_COMPACT_THRESHOLD,
+            storage_format: StorageFormat::Json,
+            compression: Compression::Off,
+            durability: Durability::FsyncDir,
+            embedded_defaults: None,
+            defaults_precedence: DefaultsPrecedence::OnDisk,
+            strict_keys: false,
+            strict_exempt_prefixes: Vec::new(),
+            key_stats: KeyStatsMode::Off,
+            key_versions: false,
+            size_history: false,
+            flush_on_exit: true,
+            write_amplification_threshold: None,
+            cipher: None,
+            fairness_policy: FairnessPolicy::Unbounded,
+            worker: WorkerConfig::default(),
+            file_lock: false,
+            journal_role: JournalRole::Off,
+            key_metadata: false,
+            writer_id: None,
+            max_value_size: None,
+            snapshot_retention: SnapshotRetention::default(),
+            identity: None,
+            access_policy: AccessPolicy::default(),
+            migrations: Migrations::default(),
+        }
+    }
+}
+
 pub trait KvsApi {
     fn open(
         instance_id: InstanceId,
@@ -99,6 +598,24 @@ pub trait KvsApi {
     where
         Self: Sized;
 
+    /// Open the KVS with additional settings
+    ///
+    /// Implementations without support for the additional settings fall back to
+    /// [`open`](Self::open).
+    fn open_with_options(
+        instance_id: InstanceId,
+        need_defaults: OpenNeedDefaults,
+        need_kvs: OpenNeedKvs,
+        dir: Option<String>,
+        options: KvsOptions,
+    ) -> Result<Self, ErrorCode>
+    where
+        Self: Sized,
+    {
+        let _ = options;
+        Self::open(instance_id, need_defaults, need_kvs, dir)
+    }
+
     fn reset(&self) -> Result<(), ErrorCode>;
     fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode>;
     fn key_exists(&self, key: &str) -> Result<bool, ErrorCode>;
@@ -0,0 +1,335 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Async KVS handle
+//!
+//! Flushing or restoring a large KVS blocks for the file IO. [`GenericAsyncKvs`] provides `async`
+//! variants of these operations for services running on an async runtime. The blocking work runs
+//! on a worker thread of the instance, started with its
+//! [`WorkerConfig`](crate::kvs_worker::WorkerConfig) under the role `io`, so the executor keeps
+//! polling other tasks. Operations are executed one after another in the order they were
+//! started.
+//!
+//! The futures don't depend on a specific runtime, they work with tokio, async-std or a plain
+//! `block_on`. In-memory operations like `get_value` are fast and are called directly on the
+//! handle, which dereferences to [`GenericKvs`].
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_api::{CompactionReport, KvsApi, SnapshotId};
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_log::log_error;
+use crate::kvs_shared::GenericSharedKvs;
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::JoinHandle;
+
+/// Blocking work queued for the worker thread
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Result slot shared by a queued job and its future
+struct Completion<T> {
+    /// Result, `None` while the job is pending
+    result: Option<Result<T, ErrorCode>>,
+
+    /// Waker of the task awaiting the result
+    waker: Option<Waker>,
+}
+
+/// Sets the result of a job, an error if the job is dropped without running or panics
+struct Completer<T> {
+    completion: Arc<Mutex<Completion<T>>>,
+}
+
+impl<T> Completer<T> {
+    fn complete(&self, result: Result<T, ErrorCode>) {
+        let mut completion = self
+            .completion
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if completion.result.is_none() {
+            completion.result = Some(result);
+        }
+        if let Some(waker) = completion.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        self.complete(Err(ErrorCode::UnmappedError));
+    }
+}
+
+/// Future of a job running on the worker thread
+struct JobFuture<T> {
+    completion: Arc<Mutex<Completion<T>>>,
+}
+
+impl<T> Future for JobFuture<T> {
+    type Output = Result<T, ErrorCode>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut completion = self
+            .completion
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match completion.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                completion.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// KVS handle with `async` file IO
+pub struct GenericAsyncKvs<J: KvsBackend> {
+    /// Shared KVS instance, a clone is moved into every job
+    kvs: GenericSharedKvs<J>,
+
+    /// Queue of the worker thread, `None` after drop
+    jobs: Option<mpsc::Sender<Job>>,
+
+    /// Worker thread
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<J> GenericAsyncKvs<J>
+where
+    J: KvsBackend + 'static,
+    GenericKvs<J>: Send + Sync,
+{
+    /// Take ownership of a KVS instance and start its IO worker thread
+    ///
+    /// # Parameters
+    ///   * `kvs`: KVS instance, also a shared handle
+    ///
+    /// # Return Values
+    ///   * Ok: Async handle
+    ///   * `ErrorCode::UnmappedError`: Worker thread couldn't be spawned
+    pub fn new<K: Into<GenericSharedKvs<J>>>(kvs: K) -> Result<Self, ErrorCode> {
+        let kvs = kvs.into();
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let worker = kvs.worker_config().spawn("io", move || {
+            for job in queue {
+                job();
+            }
+        })?;
+        Ok(Self {
+            kvs,
+            jobs: Some(jobs),
+            worker: Some(worker),
+        })
+    }
+
+    /// Run blocking work on the worker thread
+    fn run<T, F>(&self, f: F) -> JobFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&GenericKvs<J>) -> Result<T, ErrorCode> + Send + 'static,
+    {
+        let completion = Arc::new(Mutex::new(Completion {
+            result: None,
+            waker: None,
+        }));
+        let completer = Completer {
+            completion: completion.clone(),
+        };
+        let kvs = self.kvs.clone();
+        let job: Job = Box::new(move || completer.complete(f(&kvs)));
+        if let Some(jobs) = &self.jobs {
+            // A failed send drops the job, which completes the future with an error
+            let _ = jobs.send(job);
+        }
+        JobFuture { completion }
+    }
+
+    /// Flush the KVS on the worker thread
+    ///
+    /// See [`flush`](KvsApi::flush).
+    pub async fn flush(&self) -> Result<(), ErrorCode> {
+        self.run(|kvs| kvs.flush()).await
+    }
+
+    /// Restore a snapshot on the worker thread
+    ///
+    /// See [`snapshot_restore`](KvsApi::snapshot_restore).
+    pub async fn snapshot_restore(&self, id: SnapshotId) -> Result<(), ErrorCode> {
+        self.run(move |kvs| kvs.snapshot_restore(id)).await
+    }
+
+    /// Create a tagged snapshot on the worker thread
+    ///
+    /// See [`snapshot_create`](GenericKvs::snapshot_create).
+    pub async fn snapshot_create(&self, tag: &str) -> Result<(), ErrorCode> {
+        let tag = tag.to_string();
+        self.run(move |kvs| kvs.snapshot_create(&tag)).await
+    }
+
+    /// Restore a tagged snapshot on the worker thread
+    ///
+    /// See [`snapshot_restore_tag`](GenericKvs::snapshot_restore_tag).
+    pub async fn snapshot_restore_tag(&self, tag: &str) -> Result<(), ErrorCode> {
+        let tag = tag.to_string();
+        self.run(move |kvs| kvs.snapshot_restore_tag(&tag)).await
+    }
+
+    /// Compact the store on the worker thread
+    ///
+    /// See [`compact`](GenericKvs::compact).
+    pub async fn compact(&self) -> Result<CompactionReport, ErrorCode> {
+        self.run(|kvs| kvs.compact()).await
+    }
+}
+
+impl<J: KvsBackend> Deref for GenericAsyncKvs<J> {
+    type Target = GenericKvs<J>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.kvs
+    }
+}
+
+impl<J: KvsBackend> Drop for GenericAsyncKvs<J> {
+    fn drop(&mut self) {
+        // Closing the queue lets the worker finish the pending jobs and exit
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                log_error!("KVS IO worker thread panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::{self, Thread};
+    use tempfile::{tempdir, TempDir};
+
+    /// Wakes the thread blocked in `block_on`
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor polling a future on the current thread
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn open(dir: &TempDir) -> AsyncKvs {
+        let kvs = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .flush_on_exit(false)
+            .worker_config(WorkerConfig {
+                name: Some("test".to_string()),
+                ..Default::default()
+            })
+            .build()
+            .unwrap();
+        AsyncKvs::new(kvs).unwrap()
+    }
+
+    #[test]
+    fn test_async_flush() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 1.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+        drop(kvs);
+        assert_eq!(open(&dir).get_value_as::<f64>("value").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_async_restore() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 1.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+        kvs.set_value("value", 2.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+
+        block_on(kvs.snapshot_restore(SnapshotId::new(1))).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_async_restore_invalid_id() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 1.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+        assert_eq!(
+            block_on(kvs.snapshot_restore(SnapshotId::new(3))),
+            Err(ErrorCode::InvalidSnapshotId)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_async_tagged_snapshot() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 2.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+        block_on(kvs.snapshot_create("backup")).unwrap();
+        kvs.set_value("value", 3.0).unwrap();
+
+        block_on(kvs.snapshot_restore_tag("backup")).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_async_tagged_snapshot_missing() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 3.0).unwrap();
+        assert_eq!(
+            block_on(kvs.snapshot_restore_tag("backup")),
+            Err(ErrorCode::KvsFileReadError)
+        );
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_async_compact() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("value", 1.0).unwrap();
+        block_on(kvs.flush()).unwrap();
+        let report = block_on(kvs.compact()).unwrap();
+        assert_eq!(report.snapshot_tag, "pre-compaction");
+        assert_eq!(kvs.get_value_as::<f64>("value").unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Periodic auto-flush
+//!
+//! With auto-flush enabled changed data is flushed at the latest one interval after the previous
+//! flush, or after enabling, instead of only on explicit flushes and on drop.
+//!
+//! A [`GenericKvs`] can't be referenced by a thread it owns, so it's driven by the caller:
+//! [`tick`](GenericKvs::tick) flushes when the interval elapsed and keys changed, e.g. called from
+//! the main loop. A [`GenericSharedKvs`](crate::kvs_shared::GenericSharedKvs) starts a worker
+//! thread named `<name>-autoflush` that ticks the instance. The worker doesn't keep the
+//! instance alive, it ends when the instance is dropped or auto-flush is disabled.
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_log::log_error;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Auto-flush interval and the time it was enabled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Schedule {
+    /// Maximum time between a flush and the next flush of changed data
+    pub(crate) interval: Duration,
+
+    /// Time auto-flush was enabled, distinguishes schedules with the same interval
+    enabled: SystemTime,
+}
+
+impl Schedule {
+    /// Return the time until the next flush is due
+    ///
+    /// # Parameters
+    ///   * `last_flush`: Time of the last flush, `None` if the instance wasn't flushed yet
+    ///   * `now`: Current time
+    pub(crate) fn remaining(&self, last_flush: Option<SystemTime>, now: SystemTime) -> Duration {
+        let since = last_flush.map_or(self.enabled, |last| last.max(self.enabled));
+        self.interval
+            .saturating_sub(now.duration_since(since).unwrap_or_default())
+    }
+}
+
+/// Auto-flush setting of a KVS instance
+#[derive(Default)]
+pub(crate) struct AutoFlush {
+    /// Current schedule, `None` if auto-flush is disabled
+    schedule: Mutex<Option<Schedule>>,
+}
+
+impl AutoFlush {
+    /// Enable auto-flush, replacing a previous schedule
+    ///
+    /// # Return Values
+    ///   * Ok: New schedule
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn enable(&self, interval: Duration) -> Result<Schedule, ErrorCode> {
+        let schedule = Schedule {
+            interval,
+            enabled: SystemTime::now(),
+        };
+        *self
+            .schedule
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = Some(schedule);
+        Ok(schedule)
+    }
+
+    /// Disable auto-flush
+    pub(crate) fn disable(&self) -> Result<(), ErrorCode> {
+        *self
+            .schedule
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)? = None;
+        Ok(())
+    }
+
+    /// Return the current schedule, `None` if auto-flush is disabled
+    pub(crate) fn schedule(&self) -> Result<Option<Schedule>, ErrorCode> {
+        Ok(*self
+            .schedule
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?)
+    }
+}
+
+/// Start the worker ticking a shared instance while the schedule is current
+///
+/// # Return Values
+///   * Ok: Worker started
+///   * `ErrorCode::UnmappedError`: Thread couldn't be spawned
+pub(crate) fn spawn_worker<J>(kvs: &Arc<GenericKvs<J>>, schedule: Schedule) -> Result<(), ErrorCode>
+where
+    J: KvsBackend + 'static,
+    GenericKvs<J>: Send + Sync,
+{
+    let weak = Arc::downgrade(kvs);
+    kvs.worker_config().spawn("autoflush", move || loop {
+        let Some(kvs) = weak.upgrade() else {
+            break;
+        };
+        let wait = match kvs.auto_flush_remaining(schedule) {
+            Ok(Some(remaining)) if remaining.is_zero() => {
+                if let Err(e) = kvs.tick() {
+                    log_error!("auto-flush failed: {e:?}");
+                }
+                schedule.interval
+            }
+            Ok(Some(remaining)) => remaining,
+            Ok(None) | Err(_) => break,
+        };
+        drop(kvs);
+        thread::sleep(wait);
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_enable_disable() {
+        let auto_flush = AutoFlush::default();
+        assert_eq!(auto_flush.schedule().unwrap(), None);
+        let schedule = auto_flush.enable(secs(10)).unwrap();
+        assert_eq!(auto_flush.schedule().unwrap(), Some(schedule));
+        auto_flush.disable().unwrap();
+        assert_eq!(auto_flush.schedule().unwrap(), None);
+        auto_flush.disable().unwrap();
+        assert_eq!(auto_flush.schedule().unwrap(), None);
+    }
+
+    #[test]
+    fn test_enable_replaces_schedule() {
+        let auto_flush = AutoFlush::default();
+        let first = auto_flush.enable(secs(10)).unwrap();
+        let second = auto_flush.enable(secs(20)).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(auto_flush.schedule().unwrap(), Some(second));
+    }
+
+    #[test]
+    fn test_remaining_since_enabled() {
+        let schedule = AutoFlush::default().enable(secs(10)).unwrap();
+        let enabled = schedule.enabled;
+        assert_eq!(schedule.remaining(None, enabled + secs(4)), secs(6));
+        assert_eq!(schedule.remaining(None, enabled + secs(12)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_remaining_since_flush() {
+        let schedule = AutoFlush::default().enable(secs(10)).unwrap();
+        let enabled = schedule.enabled;
+        // Flushes before enabling don't count, later flushes restart the interval
+        assert_eq!(
+            schedule.remaining(Some(enabled - secs(60)), enabled + secs(4)),
+            secs(6)
+        );
+        assert_eq!(
+            schedule.remaining(Some(enabled + secs(8)), enabled + secs(12)),
+            secs(6)
+        );
+    }
+
+    #[test]
+    fn test_remaining_with_clock_before_flush() {
+        let schedule = AutoFlush::default().enable(secs(10)).unwrap();
+        let enabled = schedule.enabled;
+        assert_eq!(
+            schedule.remaining(Some(enabled + secs(8)), enabled + secs(2)),
+            secs(10)
+        );
+    }
+}
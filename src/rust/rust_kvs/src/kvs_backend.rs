@@ -10,6 +10,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::error_code::ErrorCode;
+use crate::kvs_api::{Compression, Durability, StorageFormat};
+use crate::kvs_cipher::KvsCipher;
 use crate::kvs_value::KvsMap;
 
 use std::path::PathBuf;
@@ -25,4 +27,58 @@ pub trait KvsBackend {
 
     /// Store KvsMap at given file path.
     fn save_kvs(kvs: &KvsMap, destination_path: PathBuf, add_hash: bool) -> Result<(), ErrorCode>;
+
+    /// Store KvsMap at given file path in the given format.
+    ///
+    /// Backends with a single format ignore the format and fall back to `save_kvs`.
+    fn save_kvs_as(
+        kvs: &KvsMap,
+        destination_path: PathBuf,
+        add_hash: bool,
+        format: StorageFormat,
+    ) -> Result<(), ErrorCode> {
+        let _ = format;
+        Self::save_kvs(kvs, destination_path, add_hash)
+    }
+
+    /// Load KvsMap from given file, decrypting it with the given cipher.
+    ///
+    /// Backends without encryption support fail with `ErrorCode::EncryptionFailed` if a cipher
+    /// is given.
+    fn load_kvs_with_cipher(
+        source_path: PathBuf,
+        verify_hash: bool,
+        hash_source: Option<PathBuf>,
+        cipher: Option<&dyn KvsCipher>,
+    ) -> Result<KvsMap, ErrorCode> {
+        match cipher {
+            None => Self::load_kvs(source_path, verify_hash, hash_source),
+            Some(_) => Err(ErrorCode::EncryptionFailed),
+        }
+    }
+
+    /// Store KvsMap at given file path in the given format and compression, encrypting it with
+    /// the given cipher. Written files are synchronized according to the durability, backends
+    /// may ignore it.
+    ///
+    /// Backends without encryption support fail with `ErrorCode::EncryptionFailed` if a cipher
+    /// is given, backends without compression support with `ErrorCode::SerializationFailed` if
+    /// a compression is given.
+    fn save_kvs_with_cipher(
+        kvs: &KvsMap,
+        destination_path: PathBuf,
+        add_hash: bool,
+        format: StorageFormat,
+        compression: Compression,
+        _durability: Durability,
+        cipher: Option<&dyn KvsCipher>,
+    ) -> Result<(), ErrorCode> {
+        if compression != Compression::Off {
+            return Err(ErrorCode::SerializationFailed);
+        }
+        match cipher {
+            None => Self::save_kvs_as(kvs, destination_path, add_hash, format),
+            Some(_) => Err(ErrorCode::EncryptionFailed),
+        }
+    }
 }
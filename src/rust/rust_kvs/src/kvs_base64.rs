@@ -0,0 +1,105 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Base64 (RFC 4648) encoding of byte values in text formats
+//!
+//! The standard alphabet with padding is written. On decode padding is optional, whitespace and
+//! other characters are rejected.
+
+/// Standard alphabet
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode bytes as base64 text
+pub(crate) fn encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (idx, byte)| {
+            bits | (*byte as u32) << (16 - 8 * idx)
+        });
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * idx)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Return the 6-bit value of a base64 character
+fn decode_char(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some((c - b'A') as u32),
+        b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+        b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode base64 text
+///
+/// Returns `None` for invalid characters or lengths.
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    let text = text
+        .strip_suffix(b"==")
+        .or_else(|| text.strip_suffix(b"="))
+        .unwrap_or(text);
+    if text.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut bits = 0u32;
+        for (idx, c) in chunk.iter().enumerate() {
+            bits |= decode_char(*c)? << (18 - 6 * idx);
+        }
+        for idx in 0..chunk.len() - 1 {
+            out.push((bits >> (16 - 8 * idx)) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_rfc4648_vectors() {
+        for (data, text) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(data.as_bytes()), text);
+            assert_eq!(decode(text), Some(data.as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_base64_roundtrip_and_invalid() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&data)), Some(data));
+        assert_eq!(decode("Zm9vYg"), Some(b"foob".to_vec()));
+        assert_eq!(decode("Zm9v Yg=="), None);
+        assert_eq!(decode("Z"), None);
+        assert_eq!(decode("Zm9v=Yg="), None);
+    }
+}
@@ -10,7 +10,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::error_code::ErrorCode;
-use crate::kvs_api::{InstanceId, KvsApi};
+use crate::kvs::GenericKvs;
+use crate::kvs_access::AccessPolicy;
+use crate::kvs_api::KvsApi;
+use crate::kvs_api::KvsOptions;
+use crate::kvs_api::{Compression, DefaultsPrecedence, Durability, InstanceId, KeyStatsMode};
+use crate::kvs_api::{JournalRole, OpenMode, SnapshotRetention, StorageFormat};
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_cipher::KvsCipher;
+use crate::kvs_fairness::FairnessPolicy;
+use crate::kvs_shared::{self, GenericSharedKvs};
+use crate::kvs_value::KvsMap;
+use crate::kvs_worker::WorkerConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 /// Key-value-storage builder
 pub struct KvsBuilder<T: KvsApi> {
@@ -26,6 +40,12 @@ pub struct KvsBuilder<T: KvsApi> {
     /// Working directory
     dir: Option<String>,
 
+    /// Additional settings
+    options: KvsOptions,
+
+    /// Shared handles bypass the registry
+    isolated: bool,
+
     /// Phantom data for drop check
     _phantom: std::marker::PhantomData<T>,
 }
@@ -47,6 +67,8 @@ impl<T: KvsApi> KvsBuilder<T> {
             need_defaults: false,
             need_kvs: false,
             dir: None,
+            options: KvsOptions::default(),
+            isolated: false,
             _phantom: std::marker::PhantomData,
         }
     }
@@ -86,9 +108,425 @@ impl<T: KvsApi> KvsBuilder<T> {
         self
     }
 
+    /// Replace all additional settings at once
+    ///
+    /// Useful if the settings are loaded from a configuration. Setters called afterwards change
+    /// single settings, setters called before are overwritten.
+    ///
+    /// # Parameters
+    ///   * `options`: Additional settings, `KvsOptions::default()` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn options(mut self, options: KvsOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Configure if mutations are journaled in a write-ahead log
+    ///
+    /// With the write-ahead log enabled, every mutation is persisted before it's applied and
+    /// replayed on the next open, also if the KVS wasn't flushed.
+    ///
+    /// # Parameters
+    ///   * `flag`: Yes = `true`, no = `false` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn write_ahead_log(mut self, flag: bool) -> Self {
+        self.options.write_ahead_log = flag;
+        self
+    }
+
+    /// Configure the format the KVS file is written in
+    ///
+    /// The format of existing files is detected on load, independent of this setting.
+    ///
+    /// # Parameters
+    ///   * `format`: Storage format, `StorageFormat::Json` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn storage_format(mut self, format: StorageFormat) -> Self {
+        self.options.storage_format = format;
+        self
+    }
+
+    /// Configure the compression of the KVS file and snapshots
+    ///
+    /// Compressed files are detected on load, independent of this setting.
+    ///
+    /// # Parameters
+    ///   * `compression`: Compression, `Compression::Off` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.options.compression = compression;
+        self
+    }
+
+    /// Configure how written files are synchronized to the storage
+    ///
+    /// Weaker settings reduce flush latency and flash wear, but data of a flush returning
+    /// successfully can be lost on power loss. A crashed process never leaves a truncated file
+    /// behind, independent of the setting.
+    ///
+    /// # Parameters
+    ///   * `durability`: Durability, `Durability::FsyncDir` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.options.durability = durability;
+        self
+    }
+
+    /// Configure defaults compiled into the binary
+    ///
+    /// The document has the same format as a defaults file (JSON or CBOR), e.g.
+    /// `.embedded_defaults(include_bytes!("kvs_0_default.json"))`. With embedded defaults a
+    /// missing on-disk defaults file is no error, also if defaults are required.
+    ///
+    /// # Parameters
+    ///   * `data`: Defaults document
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn embedded_defaults(mut self, data: &'static [u8]) -> Self {
+        self.options.embedded_defaults = Some(data);
+        self
+    }
+
+    /// Configure the precedence of embedded defaults against the on-disk defaults file
+    ///
+    /// # Parameters
+    ///   * `precedence`: Precedence, `DefaultsPrecedence::OnDisk` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn defaults_precedence(mut self, precedence: DefaultsPrecedence) -> Self {
+        self.options.defaults_precedence = precedence;
+        self
+    }
+
+    /// Configure if only keys declared in the defaults can be set
+    ///
+    /// In strict mode setting a key without a static default value fails with
+    /// `ErrorCode::ValidationFailed`, unless it starts with an exempted prefix.
+    ///
+    /// # Parameters
+    ///   * `flag`: Yes = `true`, no = `false` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn strict_keys(mut self, flag: bool) -> Self {
+        self.options.strict_keys = flag;
+        self
+    }
+
+    /// Exempt a key prefix from strict mode, e.g. for diagnostic scratch space
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn strict_exempt_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.options.strict_exempt_prefixes.push(prefix.into());
+        self
+    }
+
+    /// Configure the collection of per-key read and write counters
+    ///
+    /// # Parameters
+    ///   * `mode`: Statistics mode, `KeyStatsMode::Off` (default)
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn key_stats(mut self, mode: KeyStatsMode) -> Self {
+        self.options.key_stats = mode;
+        self
+    }
+
+    /// Configure the per-key versions of conditional writes
+    ///
+    /// See [`set_value_if_version`](crate::kvs::GenericKvs::set_value_if_version). The versions
+    /// are written to the KVS file.
+    ///
+    /// # Parameters
+    ///   * `flag`: Count the changes of every key, `false` by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn key_versions(mut self, flag: bool) -> Self {
+        self.options.key_versions = flag;
+        self
+    }
+
+    /// Configure the size history of the growth trend
+    ///
+    /// See [`growth_trend`](crate::kvs::GenericKvs::growth_trend). The samples are written to
+    /// `kvs_<instance_id>_size_history.json`.
+    ///
+    /// # Parameters
+    ///   * `flag`: Sample the store size after flushes, `false` by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn size_history(mut self, flag: bool) -> Self {
+        self.options.size_history = flag;
+        self
+    }
+
+    /// Configure the metadata of changed keys
+    ///
+    /// See [`get_key_metadata`](crate::kvs::GenericKvs::get_key_metadata). The metadata is written
+    /// to `kvs_<instance_id>_metadata.json` on flushes after a change.
+    ///
+    /// # Parameters
+    ///   * `flag`: Record the change time and writer of every key, `false` by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn key_metadata(mut self, flag: bool) -> Self {
+        self.options.key_metadata = flag;
+        self
+    }
+
+    /// Configure the writer ID recorded in the metadata of changed keys
+    ///
+    /// Only used with [`key_metadata`](Self::key_metadata) enabled.
+    ///
+    /// # Parameters
+    ///   * `id`: Writer ID, e.g. the name of the application or tester, none by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn writer_id<S: Into<String>>(mut self, id: S) -> Self {
+        self.options.writer_id = Some(id.into());
+        self
+    }
+
+    /// Configure the maximum size of a single value
+    ///
+    /// Writes of larger values fail with `ErrorCode::ValidationFailed`, see
+    /// [`kvs_validator`](crate::kvs_validator). The size is the logical size: 8 bytes per number,
+    /// 1 per boolean, the length of strings and byte arrays and the sum of the elements, object
+    /// keys included, of arrays and objects.
+    ///
+    /// # Parameters
+    ///   * `bytes`: Maximum value size, no limit by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn max_value_size(mut self, bytes: u64) -> Self {
+        self.options.max_value_size = Some(bytes);
+        self
+    }
+
+    /// Configure the retention policy of the snapshots
+    ///
+    /// # Parameters
+    ///   * `retention`: Retention policy, 3 snapshots of any age and size by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn snapshot_retention(mut self, retention: SnapshotRetention) -> Self {
+        self.options.snapshot_retention = retention;
+        self
+    }
+
+    /// Set the client identity of the handle
+    ///
+    /// Operations are checked against the access policy with this identity, see
+    /// [`kvs_access`](crate::kvs_access).
+    ///
+    /// # Parameters
+    ///   * `identity`: Client identity, e.g. the component name
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn identity<S: Into<String>>(mut self, identity: S) -> Self {
+        self.options.identity = Some(identity.into());
+        self
+    }
+
+    /// Configure the protected key namespaces
+    ///
+    /// The owned namespaces of the ownership manifest are added on open, see
+    /// [`kvs_access`](crate::kvs_access).
+    ///
+    /// # Parameters
+    ///   * `policy`: Access policy, no protected namespaces by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn access_policy(mut self, policy: AccessPolicy) -> Self {
+        self.options.access_policy = policy;
+        self
+    }
+
+    /// Register a schema migration applied on open
+    ///
+    /// The migrations starting at the stored schema version are applied in a chain and the
+    /// migrated data is written right away, see [`kvs_migration`](crate::kvs_migration). A
+    /// migration with the same source version is replaced, one that doesn't raise the version
+    /// fails the open with `ErrorCode::ValidationFailed` when it's reached.
+    ///
+    /// # Parameters
+    ///   * `from`: Schema version the migration starts at, 0 for files without a version
+    ///   * `to`: Schema version after the migration
+    ///   * `migration`: Transformation of the data
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn migration<F>(mut self, from: u64, to: u64, migration: F) -> Self
+    where
+        F: Fn(&mut KvsMap) -> Result<(), String> + Send + Sync + 'static,
+    {
+        self.options
+            .migrations
+            .register(from, to, Arc::new(migration));
+        self
+    }
+
+    /// Configure if the KVS is flushed when it's dropped
+    ///
+    /// Sets the initial state, which can be changed on the opened KVS with
+    /// [`flush_on_exit`](KvsApi::flush_on_exit).
+    ///
+    /// # Parameters
+    ///   * `flag`: Yes = `true` (default), no = `false`
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn flush_on_exit(mut self, flag: bool) -> Self {
+        self.options.flush_on_exit = flag;
+        self
+    }
+
+    /// Configure the write amplification that is warned about
+    ///
+    /// On every flush the ratio of written to logically changed bytes over the last flushes is
+    /// compared with the threshold, see [`write_stats`](crate::kvs::GenericKvs::write_stats).
+    ///
+    /// # Parameters
+    ///   * `threshold`: Write amplification factor, no warnings by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn write_amplification_threshold(mut self, threshold: f64) -> Self {
+        self.options.write_amplification_threshold = Some(threshold);
+        self
+    }
+
+    /// Configure the cipher the KVS file and snapshots are encrypted with
+    ///
+    /// Existing plain text files can't be opened with a cipher, see
+    /// [`kvs_cipher`](crate::kvs_cipher).
+    ///
+    /// # Parameters
+    ///   * `cipher`: Cipher holding the provisioned key
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn cipher(mut self, cipher: Arc<dyn KvsCipher>) -> Self {
+        self.options.cipher = Some(cipher);
+        self
+    }
+
+    /// Configure the admission policy of concurrent readers and writers
+    ///
+    /// See [`kvs_fairness`](crate::kvs_fairness) for the latency and throughput trade-offs.
+    ///
+    /// # Parameters
+    ///   * `policy`: Fairness policy, [`FairnessPolicy::Unbounded`] by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn fairness_policy(mut self, policy: FairnessPolicy) -> Self {
+        self.options.fairness_policy = policy;
+        self
+    }
+
+    /// Configure the worker threads of the instance
+    ///
+    /// See [`kvs_worker`](crate::kvs_worker) for the supported settings.
+    ///
+    /// # Parameters
+    ///   * `config`: Thread name, stack size and scheduling settings
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn worker_config(mut self, config: WorkerConfig) -> Self {
+        self.options.worker = config;
+        self
+    }
+
+    /// Configure where the KVS data is stored
+    ///
+    /// # Parameters
+    ///   * `mode`: Storage of the KVS data, [`OpenMode::Persistent`] by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn open_mode(mut self, mode: OpenMode) -> Self {
+        self.options.open_mode = mode;
+        self
+    }
+
+    /// Configure if the instance is locked against other processes
+    ///
+    /// An instance is locked exclusively, in [`OpenMode::ReadOnly`] shared. Opening an instance
+    /// locked by another process fails with `ErrorCode::ResourceBusy`. On QNX file locking isn't
+    /// supported, the instance is opened without the lock and only one process may use it.
+    ///
+    /// # Parameters
+    ///   * `flag`: Lock the instance while it's open, `false` by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn file_lock(mut self, flag: bool) -> Self {
+        self.options.file_lock = flag;
+        self
+    }
+
+    /// Configure the role in the merge journal shared by cooperating processes
+    ///
+    /// Mutations are appended to a journal instead of rewriting the KVS file, so cooperating
+    /// processes don't overwrite each other's changes, see [`JournalRole`]. With file locking
+    /// the cooperating processes share the lock. On QNX file locking isn't supported, the journal
+    /// is used unlocked and only one process may write it at a time.
+    ///
+    /// # Parameters
+    ///   * `role`: Journal role, [`JournalRole::Off`] by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn merge_journal(mut self, role: JournalRole) -> Self {
+        self.options.journal_role = role;
+        self
+    }
+
+    /// Configure if a shared handle bypasses the registry of shared instances
+    ///
+    /// Only affects [`build_shared`](KvsBuilder::build_shared). An isolated handle has its own
+    /// in-memory data and doesn't see unflushed writes of other handles of the same instance.
+    ///
+    /// # Parameters
+    ///   * `flag`: Open an isolated handle, `false` by default
+    ///
+    /// # Return Values
+    ///   * KvsBuilder instance
+    pub fn isolated(mut self, flag: bool) -> Self {
+        self.isolated = flag;
+        self
+    }
+
     /// Finalize the builder and open the key-value-storage
     ///
-    /// Calls `Kvs::open` with the configured settings.
+    /// Calls `Kvs::open_with_options` with the configured settings.
     ///
     /// # Features
     ///   * `FEAT_REQ__KVS__default_values`
@@ -103,15 +541,50 @@ impl<T: KvsApi> KvsBuilder<T> {
     ///   * `ErrorCode::KvsHashFileReadError`: KVS hash file read error
     ///   * `ErrorCode::UnmappedError`: Generic error
     pub fn build(self) -> Result<T, ErrorCode> {
-        T::open(
+        T::open_with_options(
             self.instance_id,
             self.need_defaults.into(),
             self.need_kvs.into(),
             self.dir,
+            self.options,
         )
     }
 }
 
+impl<J> KvsBuilder<GenericKvs<J>>
+where
+    J: KvsBackend + 'static,
+    GenericKvs<J>: Send + Sync,
+{
+    /// Finalize the builder and open a shared handle of the key-value-storage
+    ///
+    /// If the instance is already open through another shared handle in this process, a handle
+    /// to the same data is returned and the builder settings are ignored. All such handles read
+    /// each other's writes, see [`kvs_shared`](crate::kvs_shared).
+    ///
+    /// # Features
+    ///   * `FEAT_REQ__KVS__thread_safety`
+    ///
+    /// # Return Values
+    ///   * Ok: Shared KVS handle
+    ///   * Errors of [`build`](KvsBuilder::build)
+    pub fn build_shared(self) -> Result<GenericSharedKvs<J>, ErrorCode> {
+        if self.isolated {
+            return self.build().map(GenericSharedKvs::new);
+        }
+
+        let dir = PathBuf::from(self.dir.clone().unwrap_or_default());
+        let dir = fs::canonicalize(if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &dir
+        })
+        .unwrap_or(dir);
+        let prefix = dir.join(self.instance_id.filename_prefix());
+        kvs_shared::open_registered(prefix, || self.build())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,8 +624,24 @@ mod tests {
         let builder = KvsBuilder::<MockKvs>::new(InstanceId::new(1))
             .need_defaults(true)
             .need_kvs(true)
-            .dir("/tmp/test_kvs2");
+            .dir("/tmp/test_kvs2")
+            .write_ahead_log(true);
         let kvs = builder.build();
         assert!(kvs.is_ok());
     }
+
+    #[test]
+    fn test_builder_options() {
+        let builder = KvsBuilder::<MockKvs>::new(InstanceId::new(1))
+            .write_ahead_log(true)
+            .options(KvsOptions {
+                durability: Durability::None,
+                ..KvsOptions::default()
+            })
+            .storage_format(StorageFormat::Cbor);
+        assert!(!builder.options.write_ahead_log);
+        assert_eq!(builder.options.durability, Durability::None);
+        assert_eq!(builder.options.storage_format, StorageFormat::Cbor);
+        assert!(builder.build().is_ok());
+    }
 }
@@ -0,0 +1,476 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! CBOR (RFC 8949) encoding of the KVS data
+//!
+//! Only the subset needed for [`KvsValue`] is implemented. Documents always start with the
+//! self-describe tag (55799), which is used to detect the format on load. Numbers are written
+//! as 64-bit floats, on decode all float encodings are accepted. `I64` and `U64` values are
+//! written as integers, an `U64` value that also fits into `i64` is wrapped in the private tag
+//! [`TAG_U64`] to keep its type. `Bytes` values are byte strings. Indefinite length items are not
+//! supported.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_value::{KvsMap, KvsValue};
+
+/// Encoded self-describe tag 55799
+const CBOR_MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+/// Maximum nesting depth of arrays and objects accepted on decode
+const MAX_DEPTH: usize = 128;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+/// Tag of `U64` values not above `i64::MAX`, untagged integers in that range are `I64`
+const TAG_U64: u64 = 55800;
+
+const SIMPLE_FALSE: u8 = 20;
+const SIMPLE_TRUE: u8 = 21;
+const SIMPLE_NULL: u8 = 22;
+const SIMPLE_F16: u8 = 25;
+const SIMPLE_F32: u8 = 26;
+const SIMPLE_F64: u8 = 27;
+
+/// Return if the data is a CBOR document written by [`encode`]
+pub(crate) fn is_cbor(data: &[u8]) -> bool {
+    data.starts_with(&CBOR_MAGIC)
+}
+
+/// Write an item header with the shortest argument encoding
+fn write_header(out: &mut Vec<u8>, major: u8, arg: u64) {
+    let major = major << 5;
+    if arg < 24 {
+        out.push(major | arg as u8);
+    } else if arg <= u8::MAX as u64 {
+        out.push(major | 24);
+        out.push(arg as u8);
+    } else if arg <= u16::MAX as u64 {
+        out.push(major | 25);
+        out.extend_from_slice(&(arg as u16).to_be_bytes());
+    } else if arg <= u32::MAX as u64 {
+        out.push(major | 26);
+        out.extend_from_slice(&(arg as u32).to_be_bytes());
+    } else {
+        out.push(major | 27);
+        out.extend_from_slice(&arg.to_be_bytes());
+    }
+}
+
+fn write_text(out: &mut Vec<u8>, text: &str) {
+    write_header(out, MAJOR_TEXT, text.len() as u64);
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn write_value(out: &mut Vec<u8>, value: &KvsValue) {
+    match value {
+        KvsValue::Number(n) => {
+            out.push((MAJOR_SIMPLE << 5) | SIMPLE_F64);
+            out.extend_from_slice(&n.to_be_bytes());
+        }
+        KvsValue::I64(n) if *n >= 0 => write_header(out, MAJOR_UNSIGNED, *n as u64),
+        KvsValue::I64(n) => write_header(out, MAJOR_NEGATIVE, !*n as u64),
+        KvsValue::U64(n) => {
+            if *n <= i64::MAX as u64 {
+                write_header(out, MAJOR_TAG, TAG_U64);
+            }
+            write_header(out, MAJOR_UNSIGNED, *n);
+        }
+        KvsValue::Boolean(b) => {
+            let simple = if *b { SIMPLE_TRUE } else { SIMPLE_FALSE };
+            out.push((MAJOR_SIMPLE << 5) | simple);
+        }
+        KvsValue::String(s) => write_text(out, s),
+        KvsValue::Bytes(b) => {
+            write_header(out, MAJOR_BYTES, b.len() as u64);
+            out.extend_from_slice(b);
+        }
+        KvsValue::Null => out.push((MAJOR_SIMPLE << 5) | SIMPLE_NULL),
+        KvsValue::Array(arr) => {
+            write_header(out, MAJOR_ARRAY, arr.len() as u64);
+            for item in arr {
+                write_value(out, item);
+            }
+        }
+        KvsValue::Object(map) => write_map(out, map),
+    }
+}
+
+fn write_map(out: &mut Vec<u8>, map: &KvsMap) {
+    // Sorted keys keep the output (and its hash) deterministic
+    let mut keys: Vec<&String> = map.keys().collect();
+    keys.sort();
+
+    write_header(out, MAJOR_MAP, keys.len() as u64);
+    for key in keys {
+        write_text(out, key);
+        write_value(out, &map[key]);
+    }
+}
+
+/// Encode the KVS data as CBOR document
+pub(crate) fn encode(map: &KvsMap) -> Vec<u8> {
+    let mut out = CBOR_MAGIC.to_vec();
+    write_map(&mut out, map);
+    out
+}
+
+/// Decoder state
+struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], ErrorCode> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(ErrorCode::SerializationFailed)?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Read an item header, returns major type, additional info and argument
+    fn header(&mut self) -> Result<(u8, u8, u64), ErrorCode> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let arg = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            _ => return Err(ErrorCode::SerializationFailed),
+        };
+        Ok((major, info, arg))
+    }
+
+    fn text(&mut self, len: u64) -> Result<String, ErrorCode> {
+        let len = usize::try_from(len).map_err(|_| ErrorCode::SerializationFailed)?;
+        Ok(String::from_utf8(self.take(len)?.to_vec())?)
+    }
+
+    fn map(&mut self, len: u64, depth: usize) -> Result<KvsMap, ErrorCode> {
+        let mut map = KvsMap::new();
+        for _ in 0..len {
+            let (major, _, key_len) = self.header()?;
+            if major != MAJOR_TEXT {
+                return Err(ErrorCode::SerializationFailed);
+            }
+            let key = self.text(key_len)?;
+            let value = self.value(depth + 1)?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+
+    fn value(&mut self, depth: usize) -> Result<KvsValue, ErrorCode> {
+        if depth > MAX_DEPTH {
+            return Err(ErrorCode::SerializationFailed);
+        }
+
+        let (major, info, arg) = self.header()?;
+        match major {
+            MAJOR_UNSIGNED => Ok(match i64::try_from(arg) {
+                Ok(n) => KvsValue::I64(n),
+                Err(_) => KvsValue::U64(arg),
+            }),
+            MAJOR_NEGATIVE => match i64::try_from(arg) {
+                Ok(n) => Ok(KvsValue::I64(!n)),
+                Err(_) => Ok(KvsValue::Number(-1.0 - arg as f64)),
+            },
+            MAJOR_TAG if arg == TAG_U64 => match self.header()? {
+                (MAJOR_UNSIGNED, _, n) => Ok(KvsValue::U64(n)),
+                _ => Err(ErrorCode::SerializationFailed),
+            },
+            MAJOR_BYTES => {
+                let len = usize::try_from(arg).map_err(|_| ErrorCode::SerializationFailed)?;
+                Ok(KvsValue::Bytes(self.take(len)?.to_vec()))
+            }
+            MAJOR_TEXT => Ok(KvsValue::String(self.text(arg)?)),
+            MAJOR_ARRAY => {
+                let mut arr = Vec::new();
+                for _ in 0..arg {
+                    arr.push(self.value(depth + 1)?);
+                }
+                Ok(KvsValue::Array(arr))
+            }
+            MAJOR_MAP => Ok(KvsValue::Object(self.map(arg, depth)?)),
+            MAJOR_SIMPLE => match info {
+                SIMPLE_FALSE => Ok(KvsValue::Boolean(false)),
+                SIMPLE_TRUE => Ok(KvsValue::Boolean(true)),
+                SIMPLE_NULL => Ok(KvsValue::Null),
+                SIMPLE_F16 => Ok(KvsValue::Number(f16_to_f64(arg as u16))),
+                SIMPLE_F32 => Ok(KvsValue::Number(f32::from_bits(arg as u32) as f64)),
+                SIMPLE_F64 => Ok(KvsValue::Number(f64::from_bits(arg))),
+                _ => Err(ErrorCode::SerializationFailed),
+            },
+            _ => Err(ErrorCode::SerializationFailed),
+        }
+    }
+}
+
+/// Convert an IEEE 754 half-precision float
+fn f16_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exp = ((bits >> 10) & 0x1f) as i32;
+    let mant = (bits & 0x3ff) as f64;
+    match exp {
+        0 => sign * mant * 2f64.powi(-24),
+        0x1f if mant == 0.0 => sign * f64::INFINITY,
+        0x1f => f64::NAN,
+        _ => sign * (1.0 + mant / 1024.0) * 2f64.powi(exp - 15),
+    }
+}
+
+/// Decode a CBOR document written by [`encode`]
+///
+/// # Return Values
+///   * Ok: KVS data
+///   * `ErrorCode::SerializationFailed`: Data is no valid CBOR document of a map
+///   * `ErrorCode::ConversionFailed`: Invalid UTF-8 in a key or string
+pub(crate) fn decode(data: &[u8]) -> Result<KvsMap, ErrorCode> {
+    let data = data
+        .strip_prefix(&CBOR_MAGIC)
+        .ok_or(ErrorCode::SerializationFailed)?;
+    let mut decoder = Decoder { data, pos: 0 };
+
+    let (major, _, len) = decoder.header()?;
+    if major != MAJOR_MAP {
+        return Err(ErrorCode::SerializationFailed);
+    }
+    let map = decoder.map(len, 0)?;
+    if decoder.pos != data.len() {
+        return Err(ErrorCode::SerializationFailed);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Prefix a document body with the self-describe tag
+    fn document(body: &[u8]) -> Vec<u8> {
+        let mut data = CBOR_MAGIC.to_vec();
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn roundtrip(map: KvsMap) {
+        let data = encode(&map);
+        assert!(is_cbor(&data));
+        assert_eq!(decode(&data).unwrap(), map);
+    }
+
+    #[test]
+    fn test_roundtrip_scalars() {
+        roundtrip(KvsMap::from([
+            ("number".to_string(), KvsValue::from(-123.456)),
+            (
+                "big".to_string(),
+                KvsValue::from(9007199254740993u64 as f64),
+            ),
+            ("bool".to_string(), KvsValue::from(true)),
+            ("string".to_string(), KvsValue::from("Ünicode".to_string())),
+            ("null".to_string(), KvsValue::Null),
+        ]));
+    }
+
+    #[test]
+    fn test_roundtrip_integers() {
+        roundtrip(KvsMap::from([
+            ("i64_min".to_string(), KvsValue::I64(i64::MIN)),
+            ("i64_max".to_string(), KvsValue::I64(i64::MAX)),
+            ("i64".to_string(), KvsValue::I64(42)),
+            ("u64_small".to_string(), KvsValue::U64(42)),
+            ("u64_max".to_string(), KvsValue::U64(u64::MAX)),
+        ]));
+    }
+
+    #[test]
+    fn test_roundtrip_bytes() {
+        roundtrip(KvsMap::from([
+            ("bytes".to_string(), KvsValue::bytes([0, 1, 255])),
+            ("empty".to_string(), KvsValue::bytes([])),
+            ("long".to_string(), KvsValue::bytes(vec![7; 300])),
+        ]));
+    }
+
+    #[test]
+    fn test_roundtrip_containers() {
+        roundtrip(KvsMap::new());
+        roundtrip(KvsMap::from([
+            (
+                "array".to_string(),
+                KvsValue::from(vec![KvsValue::from(1.0), KvsValue::from(false)]),
+            ),
+            (
+                "object".to_string(),
+                KvsValue::from(HashMap::from([("sub".to_string(), KvsValue::Null)])),
+            ),
+        ]));
+    }
+
+    #[test]
+    fn test_encoding_deterministic() {
+        let map = KvsMap::from([
+            ("b".to_string(), KvsValue::from(1.0)),
+            ("a".to_string(), KvsValue::from(2.0)),
+        ]);
+        let mut reversed = KvsMap::new();
+        reversed.insert("a".to_string(), KvsValue::from(2.0));
+        reversed.insert("b".to_string(), KvsValue::from(1.0));
+        assert_eq!(encode(&map), encode(&reversed));
+        assert_eq!(
+            encode(&map),
+            document(&[
+                0xa2, 0x61, b'a', 0xfb, 0x40, 0, 0, 0, 0, 0, 0, 0, 0x61, b'b', 0xfb, 0x3f, 0xf0, 0,
+                0, 0, 0, 0, 0
+            ])
+        );
+    }
+
+    #[test]
+    fn test_decode_integers_and_floats() {
+        // {"a": 1000, "b": -5, "c": 1.5 (f16), "d": 0.25 (f32)}
+        let map = decode(&document(&[
+            0xa4, 0x61, b'a', 0x19, 0x03, 0xe8, 0x61, b'b', 0x24, 0x61, b'c', 0xf9, 0x3e, 0x00,
+            0x61, b'd', 0xfa, 0x3e, 0x80, 0x00, 0x00,
+        ]))
+        .unwrap();
+        assert_eq!(map["a"], KvsValue::I64(1000));
+        assert_eq!(map["b"], KvsValue::I64(-5));
+        assert_eq!(map["c"], KvsValue::from(1.5));
+        assert_eq!(map["d"], KvsValue::from(0.25));
+    }
+
+    #[test]
+    fn test_decode_large_negative_integer() {
+        // {"a": -1 - u64::MAX} doesn't fit into i64
+        let mut body = vec![0xa1, 0x61, b'a', 0x3b];
+        body.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(
+            decode(&document(&body)).unwrap()["a"],
+            KvsValue::Number(-1.0 - u64::MAX as f64)
+        );
+    }
+
+    #[test]
+    fn test_decode_f16_special_values() {
+        assert_eq!(f16_to_f64(0x0001), 2f64.powi(-24));
+        assert_eq!(f16_to_f64(0x7c00), f64::INFINITY);
+        assert_eq!(f16_to_f64(0xfc00), f64::NEG_INFINITY);
+        assert!(f16_to_f64(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn test_decode_without_magic() {
+        assert!(!is_cbor(b"{}"));
+        assert_eq!(decode(b"{}"), Err(ErrorCode::SerializationFailed));
+        assert_eq!(decode(&[0xa0]), Err(ErrorCode::SerializationFailed));
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        // String shorter than its length
+        assert_eq!(
+            decode(&document(&[0xa1, 0x61, b'a', 0x65, b'x'])),
+            Err(ErrorCode::SerializationFailed)
+        );
+        // Missing argument bytes
+        assert_eq!(
+            decode(&document(&[0xa1, 0x61, b'a', 0x19, 0x03])),
+            Err(ErrorCode::SerializationFailed)
+        );
+        // Missing map entry
+        assert_eq!(
+            decode(&document(&[0xa2, 0x61, b'a', 0xf6])),
+            Err(ErrorCode::SerializationFailed)
+        );
+        assert_eq!(decode(&document(&[])), Err(ErrorCode::SerializationFailed));
+    }
+
+    #[test]
+    fn test_decode_trailing_data() {
+        assert_eq!(
+            decode(&document(&[0xa0, 0x00])),
+            Err(ErrorCode::SerializationFailed)
+        );
+    }
+
+    #[test]
+    fn test_decode_root_not_map() {
+        assert_eq!(
+            decode(&document(&[0x80])),
+            Err(ErrorCode::SerializationFailed)
+        );
+    }
+
+    #[test]
+    fn test_decode_non_text_key() {
+        assert_eq!(
+            decode(&document(&[0xa1, 0x01, 0xf6])),
+            Err(ErrorCode::SerializationFailed)
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        assert_eq!(
+            decode(&document(&[0xa1, 0x61, 0xff, 0xf6])),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            decode(&document(&[0xa1, 0x61, b'a', 0x61, 0xff])),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported_items() {
+        // Indefinite length array, unknown tag, unknown simple value, tagged non-integer
+        for body in [
+            [0xa1, 0x61, b'a', 0x9f, 0xff].as_slice(),
+            &[0xa1, 0x61, b'a', 0xc1, 0x00],
+            &[0xa1, 0x61, b'a', 0xf7],
+            &[0xa1, 0x61, b'a', 0xd9, 0xd9, 0xf8, 0xf6],
+        ] {
+            assert_eq!(decode(&document(body)), Err(ErrorCode::SerializationFailed));
+        }
+    }
+
+    #[test]
+    fn test_decode_nesting_too_deep() {
+        let mut body = vec![0xa1, 0x61, b'a'];
+        body.extend(std::iter::repeat_n(0x81, MAX_DEPTH + 1));
+        body.push(0xf6);
+        assert_eq!(
+            decode(&document(&body)),
+            Err(ErrorCode::SerializationFailed)
+        );
+
+        let mut body = vec![0xa1, 0x61, b'a'];
+        body.extend(std::iter::repeat_n(0x81, MAX_DEPTH - 1));
+        body.push(0xf6);
+        assert!(decode(&document(&body)).is_ok());
+    }
+}
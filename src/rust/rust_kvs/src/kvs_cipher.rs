@@ -0,0 +1,136 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encryption at rest
+//!
+//! With a cipher configured the KVS file and all snapshots are encrypted as a whole before they
+//! are written and decrypted after they are read. The cipher is pluggable, so the platform can
+//! provide an authenticated cipher such as AES-GCM backed by its crypto library or HSM, and the
+//! key provisioning stays outside of the KVS.
+//!
+//! The hash file is computed over the encrypted content. The defaults file is shipped read-only
+//! with the application and isn't encrypted. The write-ahead log can't be combined with
+//! encryption as its records are written in plain text.
+
+use crate::error_code::ErrorCode;
+use core::fmt;
+
+/// Cipher used to encrypt the KVS file and snapshots
+///
+/// Implementations should authenticate the ciphertext, so tampered or foreign files are rejected
+/// with `ErrorCode::EncryptionFailed` on decryption instead of being parsed.
+pub trait KvsCipher: Send + Sync {
+    /// Encrypt the serialized KVS data
+    ///
+    /// # Parameters
+    ///   * `plaintext`: Serialized KVS data
+    ///
+    /// # Return Values
+    ///   * Ok: Encrypted data including everything needed for decryption, e.g. nonce and tag
+    ///   * `ErrorCode::EncryptionFailed`: Encryption failed
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, ErrorCode>;
+
+    /// Decrypt data returned by [`encrypt`](Self::encrypt)
+    ///
+    /// # Parameters
+    ///   * `ciphertext`: Encrypted data as read from the file
+    ///
+    /// # Return Values
+    ///   * Ok: Serialized KVS data
+    ///   * `ErrorCode::EncryptionFailed`: Decryption or authentication failed
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, ErrorCode>;
+}
+
+/// Ciphers hold key material, only their presence is printed
+impl fmt::Debug for dyn KvsCipher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("KvsCipher")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::fs;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    /// Toy cipher: XOR with the key, prefixed with an encrypted marker to detect a wrong key
+    struct XorCipher(u8);
+
+    const MARKER: &[u8] = b"KVS!";
+
+    impl KvsCipher for XorCipher {
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+            Ok(MARKER
+                .iter()
+                .chain(plaintext)
+                .map(|byte| byte ^ self.0)
+                .collect())
+        }
+
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+            let data: Vec<u8> = ciphertext.iter().map(|byte| byte ^ self.0).collect();
+            match data.strip_prefix(MARKER) {
+                Some(plaintext) => Ok(plaintext.to_vec()),
+                None => Err(ErrorCode::EncryptionFailed),
+            }
+        }
+    }
+
+    fn open(dir: &str, cipher: Option<u8>, wal: bool) -> Result<Kvs, ErrorCode> {
+        let mut builder = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+            .dir(dir)
+            .write_ahead_log(wal)
+            .flush_on_exit(false);
+        if let Some(key) = cipher {
+            builder = builder.cipher(Arc::new(XorCipher(key)));
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_encrypted_kvs_and_snapshots() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path().to_string_lossy().to_string();
+
+        let kvs = open(&dir, Some(0x5a), false).unwrap();
+        kvs.set_value("secret", "first".to_string()).unwrap();
+        kvs.flush().unwrap();
+        kvs.set_value("secret", "second".to_string()).unwrap();
+        kvs.flush().unwrap();
+        drop(kvs);
+
+        let file = fs::read(format!("{dir}/kvs_0_0.json")).unwrap();
+        assert!(!String::from_utf8_lossy(&file).contains("second"));
+
+        let kvs = open(&dir, Some(0x5a), false).unwrap();
+        assert_eq!(kvs.get_value_as::<String>("secret").unwrap(), "second");
+        kvs.snapshot_restore(SnapshotId::new(1)).unwrap();
+        assert_eq!(kvs.get_value_as::<String>("secret").unwrap(), "first");
+        drop(kvs);
+
+        assert_eq!(
+            open(&dir, Some(0x33), false).err(),
+            Some(ErrorCode::EncryptionFailed)
+        );
+    }
+
+    #[test]
+    fn test_encryption_rejects_write_ahead_log() {
+        let dir = tempdir().unwrap();
+        let dir = dir.path().to_string_lossy().to_string();
+        assert_eq!(
+            open(&dir, Some(0x5a), true).err(),
+            Some(ErrorCode::EncryptionFailed)
+        );
+    }
+}
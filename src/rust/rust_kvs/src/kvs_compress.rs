@@ -0,0 +1,312 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Compression of the KVS file and snapshots
+//!
+//! A compressed document starts with the magic `KVZ`, followed by one byte of the algorithm and
+//! the uncompressed size as 64-bit big-endian integer. The magic is detected on load, so
+//! compressed and uncompressed files can be read regardless of the configured
+//! [`Compression`]. The document is compressed before it's encrypted.
+//!
+//! The only algorithm is the LZ4 block format, implemented here to keep the crate free of
+//! further dependencies. Only the block format is used, the header replaces the LZ4 frame.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Compression;
+
+/// Start of every compressed document
+const MAGIC: [u8; 3] = *b"KVZ";
+
+/// Algorithm byte of the LZ4 block format
+const ALGORITHM_LZ4: u8 = 1;
+
+/// Size of magic, algorithm and uncompressed size
+const HEADER_LEN: usize = MAGIC.len() + 1 + 8;
+
+/// Minimum match length of the LZ4 format
+const MIN_MATCH: usize = 4;
+
+/// A match must start at least this many bytes before the end of the block
+const MF_LIMIT: usize = 12;
+
+/// The last bytes of a block are always literals
+const LAST_LITERALS: usize = 5;
+
+/// Maximum distance of a match
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+/// Size of the match finder hash table as power of two
+const HASH_LOG: u32 = 12;
+
+/// Maximum ratio of uncompressed to compressed size of an LZ4 block
+const MAX_RATIO: usize = 255;
+
+/// Return if the data is a compressed document written by [`compress`]
+pub(crate) fn is_compressed(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Compress a document, returned unchanged with `Compression::Off`
+pub(crate) fn compress(data: Vec<u8>, compression: Compression) -> Vec<u8> {
+    match compression {
+        Compression::Off => data,
+        Compression::Lz4 => {
+            let mut out = Vec::with_capacity(HEADER_LEN + data.len() / 2);
+            out.extend_from_slice(&MAGIC);
+            out.push(ALGORITHM_LZ4);
+            out.extend_from_slice(&(data.len() as u64).to_be_bytes());
+            lz4_compress(&data, &mut out);
+            out
+        }
+    }
+}
+
+/// Decompress a document written by [`compress`]
+///
+/// # Return Values
+///   * Ok: Uncompressed document
+///   * `ErrorCode::SerializationFailed`: Unknown algorithm or corrupted data
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>, ErrorCode> {
+    let header = data
+        .get(..HEADER_LEN)
+        .ok_or(ErrorCode::SerializationFailed)?;
+    if !is_compressed(header) || header[MAGIC.len()] != ALGORITHM_LZ4 {
+        return Err(ErrorCode::SerializationFailed);
+    }
+    let mut size = [0u8; 8];
+    size.copy_from_slice(&header[MAGIC.len() + 1..]);
+    let size =
+        usize::try_from(u64::from_be_bytes(size)).map_err(|_| ErrorCode::SerializationFailed)?;
+    lz4_decompress(&data[HEADER_LEN..], size).ok_or(ErrorCode::SerializationFailed)
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+fn hash(sequence: u32) -> usize {
+    (sequence.wrapping_mul(2654435761) >> (32 - HASH_LOG)) as usize
+}
+
+/// Write the extension bytes of a literal or match length
+fn write_length(out: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        out.push(255);
+        length -= 255;
+    }
+    out.push(length as u8);
+}
+
+/// Write a sequence of literals and an optional match of `(offset, length)`
+fn write_sequence(out: &mut Vec<u8>, literals: &[u8], found: Option<(usize, usize)>) {
+    let match_length = found.map_or(0, |(_, length)| length - MIN_MATCH);
+    out.push(((literals.len().min(15) as u8) << 4) | match_length.min(15) as u8);
+    if literals.len() >= 15 {
+        write_length(out, literals.len() - 15);
+    }
+    out.extend_from_slice(literals);
+    if let Some((offset, _)) = found {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if match_length >= 15 {
+            write_length(out, match_length - 15);
+        }
+    }
+}
+
+/// Compress data into an LZ4 block
+fn lz4_compress(data: &[u8], out: &mut Vec<u8>) {
+    let mut table = vec![usize::MAX; 1 << HASH_LOG];
+    let mut anchor = 0;
+    let mut pos = 0;
+    let match_limit = data.len().saturating_sub(MF_LIMIT);
+    let end_limit = data.len().saturating_sub(LAST_LITERALS);
+    while pos < match_limit {
+        let sequence = read_u32(data, pos);
+        let slot = &mut table[hash(sequence)];
+        let candidate = std::mem::replace(slot, pos);
+        if candidate == usize::MAX
+            || pos - candidate > MAX_OFFSET
+            || read_u32(data, candidate) != sequence
+        {
+            pos += 1;
+            continue;
+        }
+        let mut length = MIN_MATCH;
+        while pos + length < end_limit && data[candidate + length] == data[pos + length] {
+            length += 1;
+        }
+        write_sequence(out, &data[anchor..pos], Some((pos - candidate, length)));
+        pos += length;
+        anchor = pos;
+    }
+    write_sequence(out, &data[anchor..], None);
+}
+
+/// Read the extension bytes of a literal or match length
+fn read_length(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut length = 0usize;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        length = length.checked_add(byte as usize)?;
+        if byte != 255 {
+            return Some(length);
+        }
+    }
+}
+
+/// Decompress an LZ4 block, `None` if it's corrupted or doesn't have the expected size
+fn lz4_decompress(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    if size > data.len().saturating_mul(MAX_RATIO) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(size);
+    let mut pos = 0;
+    loop {
+        let token = *data.get(pos)?;
+        pos += 1;
+
+        let mut literal_length = (token >> 4) as usize;
+        if literal_length == 15 {
+            literal_length = literal_length.checked_add(read_length(data, &mut pos)?)?;
+        }
+        let literals = data.get(pos..pos.checked_add(literal_length)?)?;
+        out.extend_from_slice(literals);
+        pos += literal_length;
+        if pos == data.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes([*data.get(pos)?, *data.get(pos + 1)?]) as usize;
+        pos += 2;
+        let mut match_length = (token & 15) as usize + MIN_MATCH;
+        if token & 15 == 15 {
+            match_length = match_length.checked_add(read_length(data, &mut pos)?)?;
+        }
+        if offset == 0 || offset > out.len() || out.len() + match_length > size {
+            return None;
+        }
+        let start = out.len() - offset;
+        for idx in start..start + match_length {
+            out.push(out[idx]);
+        }
+    }
+    (out.len() == size).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Repetitive JSON document
+    fn json() -> Vec<u8> {
+        br#"{"sensor":{"offset":1.5,"gain":0.25},"name":"front-left"}"#.repeat(64)
+    }
+
+    /// Incompressible pseudo-random bytes
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x1234_5678u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn roundtrip(data: Vec<u8>) {
+        let compressed = compress(data.clone(), Compression::Lz4);
+        assert!(is_compressed(&compressed));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_roundtrip_short() {
+        roundtrip(Vec::new());
+        roundtrip(b"{}".to_vec());
+        roundtrip(b"0123456789ab".to_vec());
+    }
+
+    #[test]
+    fn test_roundtrip_long_match() {
+        roundtrip(b"a".repeat(70000));
+    }
+
+    #[test]
+    fn test_roundtrip_incompressible() {
+        roundtrip(noise(5000));
+    }
+
+    #[test]
+    fn test_json_compressed() {
+        let json = json();
+        let compressed = compress(json.clone(), Compression::Lz4);
+        assert!(compressed.len() < json.len() / 8);
+        assert_eq!(decompress(&compressed).unwrap(), json);
+    }
+
+    #[test]
+    fn test_compression_off() {
+        let json = json();
+        let uncompressed = compress(json.clone(), Compression::Off);
+        assert!(!is_compressed(&uncompressed));
+        assert_eq!(uncompressed, json);
+    }
+
+    #[test]
+    fn test_truncated_rejected() {
+        let compressed = compress(json(), Compression::Lz4);
+        let truncated = &compressed[..compressed.len() - 3];
+        assert_eq!(decompress(truncated), Err(ErrorCode::SerializationFailed));
+        assert_eq!(
+            decompress(&compressed[..HEADER_LEN - 1]),
+            Err(ErrorCode::SerializationFailed)
+        );
+    }
+
+    #[test]
+    fn test_unknown_algorithm_rejected() {
+        let mut unknown = compress(json(), Compression::Lz4);
+        unknown[3] = 9;
+        assert_eq!(decompress(&unknown), Err(ErrorCode::SerializationFailed));
+        assert_eq!(decompress(&json()), Err(ErrorCode::SerializationFailed));
+    }
+
+    #[test]
+    fn test_size_mismatch_rejected() {
+        let mut oversized = compress(json(), Compression::Lz4);
+        oversized[4..12].copy_from_slice(&u64::MAX.to_be_bytes());
+        assert_eq!(decompress(&oversized), Err(ErrorCode::SerializationFailed));
+
+        let mut undersized = compress(json(), Compression::Lz4);
+        undersized[4..12].copy_from_slice(&1u64.to_be_bytes());
+        assert_eq!(decompress(&undersized), Err(ErrorCode::SerializationFailed));
+    }
+
+    #[test]
+    fn test_invalid_match_offset_rejected() {
+        // Four literals, a match of four bytes and four final literals
+        let block = |offset: u16| {
+            let mut data = compress(b"abcd".to_vec(), Compression::Lz4)[..HEADER_LEN].to_vec();
+            data[4..12].copy_from_slice(&12u64.to_be_bytes());
+            data.push(0x40);
+            data.extend_from_slice(b"abcd");
+            data.extend_from_slice(&offset.to_le_bytes());
+            data.extend_from_slice(&[0x40, b'a', b'b', b'c', b'd']);
+            data
+        };
+        assert_eq!(decompress(&block(4)).unwrap(), b"abcd".repeat(3));
+        assert_eq!(decompress(&block(0)), Err(ErrorCode::SerializationFailed));
+        assert_eq!(decompress(&block(5)), Err(ErrorCode::SerializationFailed));
+    }
+}
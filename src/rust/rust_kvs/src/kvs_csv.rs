@@ -0,0 +1,379 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Tabular CSV (RFC 4180) export and import of scalar keys
+//!
+//! Columns: `key,type,value,default,is_default,last_modified`. Only `number`, `i64`, `u64`,
+//! `bool`, `string`, `bytes` (base64) and `null` values are exported, arrays and objects are skipped. Lines end with CRLF so the
+//! files open directly in spreadsheet applications. On import only `key`, `type`, `value` and
+//! `is_default` are evaluated, other columns may be missing or changed.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_base64;
+use crate::kvs_log::log_error;
+use crate::kvs_value::KvsValue;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Column names of the header line
+const CSV_HEADER: [&str; 6] = [
+    "key",
+    "type",
+    "value",
+    "default",
+    "is_default",
+    "last_modified",
+];
+
+/// Exported row
+pub(crate) struct CsvRow<'a> {
+    /// Key
+    pub(crate) key: &'a str,
+
+    /// Current value, the default if the key isn't set
+    pub(crate) value: &'a KvsValue,
+
+    /// Default value
+    pub(crate) default: Option<&'a KvsValue>,
+
+    /// Value isn't set and the default is returned
+    pub(crate) is_default: bool,
+
+    /// Time the value was last persisted
+    pub(crate) modified: Option<SystemTime>,
+}
+
+/// Imported row
+#[derive(Debug, PartialEq)]
+pub(crate) struct CsvEntry {
+    /// Key
+    pub(crate) key: String,
+
+    /// Value, `None` if the key should be reset to its default
+    pub(crate) value: Option<KvsValue>,
+}
+
+/// Return the type name and text of a scalar value, `None` for arrays and objects
+fn scalar(value: &KvsValue) -> Option<(&'static str, String)> {
+    match value {
+        KvsValue::Number(n) => Some(("number", n.to_string())),
+        KvsValue::I64(n) => Some(("i64", n.to_string())),
+        KvsValue::U64(n) => Some(("u64", n.to_string())),
+        KvsValue::Boolean(b) => Some(("bool", b.to_string())),
+        KvsValue::String(s) => Some(("string", s.clone())),
+        KvsValue::Bytes(b) => Some(("bytes", kvs_base64::encode(b))),
+        KvsValue::Null => Some(("null", String::new())),
+        KvsValue::Array(_) | KvsValue::Object(_) => None,
+    }
+}
+
+/// Parse the text of a scalar value
+fn parse_scalar(ty: &str, text: &str) -> Result<KvsValue, ErrorCode> {
+    match ty {
+        "number" => text
+            .trim()
+            .parse::<f64>()
+            .map(KvsValue::Number)
+            .map_err(|_| ErrorCode::ConversionFailed),
+        "i64" => text
+            .trim()
+            .parse::<i64>()
+            .map(KvsValue::I64)
+            .map_err(|_| ErrorCode::ConversionFailed),
+        "u64" => text
+            .trim()
+            .parse::<u64>()
+            .map(KvsValue::U64)
+            .map_err(|_| ErrorCode::ConversionFailed),
+        "bool" => match text.trim() {
+            "true" | "TRUE" | "1" => Ok(KvsValue::Boolean(true)),
+            "false" | "FALSE" | "0" => Ok(KvsValue::Boolean(false)),
+            _ => Err(ErrorCode::ConversionFailed),
+        },
+        "string" => Ok(KvsValue::String(text.to_string())),
+        "bytes" => kvs_base64::decode(text.trim())
+            .map(KvsValue::Bytes)
+            .ok_or(ErrorCode::ConversionFailed),
+        "null" => Ok(KvsValue::Null),
+        _ => Err(ErrorCode::ConversionFailed),
+    }
+}
+
+/// Format a time as ISO 8601 UTC timestamp
+fn format_time(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Append a field, quoted if needed
+fn write_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\r', '\n']) || field.starts_with(' ') || field.ends_with(' ') {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+fn write_record(out: &mut String, fields: &[&str]) {
+    for (idx, field) in fields.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+        write_field(out, field);
+    }
+    out.push_str("\r\n");
+}
+
+/// Create the CSV document of the scalar rows
+pub(crate) fn write(rows: &[CsvRow]) -> String {
+    let mut out = String::new();
+    write_record(&mut out, &CSV_HEADER);
+
+    for row in rows {
+        let Some((ty, value)) = scalar(row.value) else {
+            continue;
+        };
+        let default = row
+            .default
+            .and_then(scalar)
+            .map(|(_, text)| text)
+            .unwrap_or_default();
+        let modified = row.modified.map(format_time).unwrap_or_default();
+        write_record(
+            &mut out,
+            &[
+                row.key,
+                ty,
+                &value,
+                &default,
+                if row.is_default { "true" } else { "false" },
+                &modified,
+            ],
+        );
+    }
+    out
+}
+
+/// Split a CSV document into records
+fn read_records(csv: &str) -> Result<Vec<Vec<String>>, ErrorCode> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = csv.trim_start_matches('\u{feff}').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(ErrorCode::ConversionFailed);
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    // Spreadsheets append empty lines
+    records.retain(|record| !(record.len() == 1 && record[0].is_empty()));
+    Ok(records)
+}
+
+/// Parse a CSV document created by [`write`]
+///
+/// # Return Values
+///   * Ok: Entries in file order
+///   * `ErrorCode::ConversionFailed`: Missing column, unknown type or invalid value
+pub(crate) fn read(csv: &str) -> Result<Vec<CsvEntry>, ErrorCode> {
+    let mut records = read_records(csv)?.into_iter();
+    let header = records.next().ok_or(ErrorCode::ConversionFailed)?;
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|col| col.trim() == name)
+            .ok_or_else(|| {
+                log_error!("CSV column '{name}' missing");
+                ErrorCode::ConversionFailed
+            })
+    };
+    let (key_col, type_col, value_col) = (column("key")?, column("type")?, column("value")?);
+    let is_default_col = header.iter().position(|col| col.trim() == "is_default");
+
+    let mut entries = Vec::new();
+    for (line, record) in records.enumerate() {
+        let field = |col: usize| record.get(col).map(String::as_str).unwrap_or_default();
+        let key = field(key_col);
+        if key.is_empty() {
+            log_error!("CSV record {} has no key", line + 1);
+            return Err(ErrorCode::ConversionFailed);
+        }
+
+        let is_default = is_default_col
+            .map(|col| matches!(field(col).trim(), "true" | "TRUE" | "1"))
+            .unwrap_or(false);
+        let value = if is_default {
+            None
+        } else {
+            Some(
+                parse_scalar(field(type_col).trim(), field(value_col)).inspect_err(|_| {
+                    log_error!("CSV record {} has an invalid value", line + 1);
+                })?,
+            )
+        };
+        entries.push(CsvEntry {
+            key: key.to_string(),
+            value,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_csv_write_read_roundtrip() {
+        let number = KvsValue::from(1.5);
+        let text = KvsValue::from("a, \"quoted\"\nvalue".to_string());
+        let flag = KvsValue::from(true);
+        let counter = KvsValue::U64(u64::MAX);
+        let blob = KvsValue::bytes([0x00, 0xff]);
+        let array = KvsValue::from(vec![]);
+        let rows = [
+            CsvRow {
+                key: "number",
+                value: &number,
+                default: Some(&number),
+                is_default: true,
+                modified: None,
+            },
+            CsvRow {
+                key: "text",
+                value: &text,
+                default: None,
+                is_default: false,
+                modified: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            },
+            CsvRow {
+                key: "array",
+                value: &array,
+                default: None,
+                is_default: false,
+                modified: None,
+            },
+            CsvRow {
+                key: "flag",
+                value: &flag,
+                default: None,
+                is_default: false,
+                modified: None,
+            },
+            CsvRow {
+                key: "counter",
+                value: &counter,
+                default: None,
+                is_default: false,
+                modified: None,
+            },
+            CsvRow {
+                key: "blob",
+                value: &blob,
+                default: None,
+                is_default: false,
+                modified: None,
+            },
+        ];
+
+        let csv = write(&rows);
+        assert!(csv.starts_with("key,type,value,default,is_default,last_modified\r\n"));
+        assert!(csv.contains("2023-11-14T22:13:20Z"));
+        assert!(!csv.contains("array"));
+        assert!(csv.contains("blob,bytes,AP8=,"));
+
+        assert_eq!(
+            read(&csv).unwrap(),
+            vec![
+                CsvEntry {
+                    key: "number".to_string(),
+                    value: None,
+                },
+                CsvEntry {
+                    key: "text".to_string(),
+                    value: Some(text.clone()),
+                },
+                CsvEntry {
+                    key: "flag".to_string(),
+                    value: Some(flag.clone()),
+                },
+                CsvEntry {
+                    key: "counter".to_string(),
+                    value: Some(counter.clone()),
+                },
+                CsvEntry {
+                    key: "blob".to_string(),
+                    value: Some(blob.clone()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csv_read_invalid() {
+        assert_eq!(read(""), Err(ErrorCode::ConversionFailed));
+        assert_eq!(read("key,value\r\n"), Err(ErrorCode::ConversionFailed));
+        assert_eq!(
+            read("key,type,value\r\na,number,abc\r\n"),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            read("key,type,value\r\na,string,\"open\r\n"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+}
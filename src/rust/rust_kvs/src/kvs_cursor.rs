@@ -0,0 +1,156 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Iteration over stored keys without copying them
+//!
+//! [`get_all_keys`](crate::kvs_api::KvsApi::get_all_keys) allocates every key up front, which is
+//! expensive for stores with thousands of entries. A cursor borrows the KVS data instead and
+//! yields keys (and optionally values) one at a time. The data stays locked while the cursor
+//! exists, so other threads' reads and writes block until it's dropped. Defaults aren't included.
+
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::sync::MutexGuard;
+
+/// Locked view of the stored key-value pairs
+pub struct KvsCursor<'a> {
+    /// Locked KVS data
+    data: MutexGuard<'a, KvsMap>,
+}
+
+impl<'a> KvsCursor<'a> {
+    pub(crate) fn new(data: MutexGuard<'a, KvsMap>) -> Self {
+        Self { data }
+    }
+
+    /// Return an iterator over the stored keys in arbitrary order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(String::as_str)
+    }
+
+    /// Return an iterator over the stored key-value pairs in arbitrary order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &KvsValue)> {
+        self.data.iter().map(|(key, value)| (key.as_str(), value))
+    }
+
+    /// Return the count of stored keys
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Return if no key is stored
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::collections::HashSet;
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tempfile::{tempdir, TempDir};
+
+    fn open(dir: &TempDir) -> Kvs {
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs
+    }
+
+    #[test]
+    fn test_cursor_empty() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let cursor = kvs.cursor().unwrap();
+        assert!(cursor.is_empty());
+        assert_eq!(cursor.len(), 0);
+        assert_eq!(cursor.keys().count(), 0);
+    }
+
+    #[test]
+    fn test_cursor_keys() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.set_value("b", true).unwrap();
+
+        let cursor = kvs.cursor().unwrap();
+        assert!(!cursor.is_empty());
+        assert_eq!(cursor.len(), 2);
+        assert_eq!(
+            cursor.keys().collect::<HashSet<_>>(),
+            HashSet::from(["a", "b"])
+        );
+    }
+
+    #[test]
+    fn test_cursor_entries() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("a", 1.0).unwrap();
+        kvs.set_value("b", true).unwrap();
+
+        let cursor = kvs.cursor().unwrap();
+        let mut entries: Vec<(&str, &KvsValue)> = cursor.entries().collect();
+        entries.sort_by_key(|(key, _)| *key);
+        assert_eq!(
+            entries,
+            vec![("a", &KvsValue::from(1.0)), ("b", &KvsValue::from(true))]
+        );
+    }
+
+    #[test]
+    fn test_cursor_excludes_defaults() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("kvs_0_default.json"), r#"{"d": 1.0}"#).unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("a", 1.0).unwrap();
+        assert_eq!(kvs.cursor().unwrap().keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn test_cursor_locks_data() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let cursor = kvs.cursor().unwrap();
+        let (done_tx, done_rx) = mpsc::channel();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                kvs.set_value("a", 1.0).unwrap();
+                done_tx.send(()).unwrap();
+            });
+            // The writer waits until the cursor is dropped
+            assert!(done_rx.recv_timeout(Duration::from_millis(50)).is_err());
+            drop(cursor);
+            done_rx.recv().unwrap();
+        });
+        assert_eq!(kvs.get_value_as::<f64>("a"), Ok(1.0));
+    }
+
+    #[test]
+    fn test_cursor_needs_access_to_all_namespaces() {
+        let dir = tempdir().unwrap();
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .access_policy(AccessPolicy::new().private("audio", &["audio"]))
+            .identity("hmi")
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        assert_eq!(kvs.cursor().err(), Some(ErrorCode::PermissionDenied));
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Dynamic default values
+//!
+//! Some defaults can't be written to a defaults file, e.g. values derived from the VIN or the
+//! hardware revision. A default provider is a callback consulted for keys without a static
+//! default. Its results can be cached, so the callback runs at most once per key. The provider is
+//! called without any KVS lock held, so it may read other keys of the KVS.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::sync::{Arc, Mutex};
+
+/// Default provider callback
+///
+/// Returns the default value of a key or `None` if the key has no default.
+pub type KvsDefaultProvider = Arc<dyn Fn(&str) -> Option<KvsValue> + Send + Sync>;
+
+/// Registered default provider of a KVS instance
+#[derive(Default)]
+pub(crate) struct DefaultProviders {
+    /// Provider and cache flag
+    provider: Mutex<Option<(KvsDefaultProvider, bool)>>,
+
+    /// Cached provider results
+    cache: Mutex<KvsMap>,
+}
+
+impl DefaultProviders {
+    /// Replace the provider, `None` removes it
+    ///
+    /// Cached results of the previous provider are discarded.
+    pub(crate) fn set(
+        &self,
+        provider: Option<(KvsDefaultProvider, bool)>,
+    ) -> Result<(), ErrorCode> {
+        let mut current = self
+            .provider
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        self.cache.lock()?.clear();
+        *current = provider;
+        Ok(())
+    }
+
+    /// Return the default value of a key from the provider
+    ///
+    /// # Return Values
+    ///   * Ok(Some): Default value of the provider
+    ///   * Ok(None): No provider registered or the provider has no default for the key
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn get(&self, key: &str) -> Result<Option<KvsValue>, ErrorCode> {
+        let current = self
+            .provider
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clone();
+        let Some((provider, cache)) = current else {
+            return Ok(None);
+        };
+        if cache {
+            if let Some(value) = self.cache.lock()?.get(key) {
+                return Ok(Some(value.clone()));
+            }
+        }
+
+        let value = provider(key);
+        if let (true, Some(value)) = (cache, &value) {
+            // Skip caching if the provider was replaced while it was running
+            let current = self
+                .provider
+                .lock()
+                .map_err(|_| ErrorCode::MutexLockFailed)?;
+            if current
+                .as_ref()
+                .is_some_and(|(current, _)| Arc::ptr_eq(current, &provider))
+            {
+                self.cache.lock()?.insert(key.to_string(), value.clone());
+            }
+        }
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting_provider(calls: Arc<AtomicUsize>) -> KvsDefaultProvider {
+        Arc::new(move |key: &str| {
+            calls.fetch_add(1, Ordering::Relaxed);
+            (key == "hw_rev").then(|| KvsValue::from(3.0))
+        })
+    }
+
+    #[test]
+    fn test_default_provider_cached() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let providers = DefaultProviders::default();
+        assert_eq!(providers.get("hw_rev"), Ok(None));
+
+        providers
+            .set(Some((counting_provider(calls.clone()), true)))
+            .unwrap();
+        assert_eq!(providers.get("hw_rev"), Ok(Some(KvsValue::from(3.0))));
+        assert_eq!(providers.get("hw_rev"), Ok(Some(KvsValue::from(3.0))));
+        assert_eq!(providers.get("other"), Ok(None));
+        assert_eq!(providers.get("other"), Ok(None));
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+
+        providers.set(None).unwrap();
+        assert_eq!(providers.get("hw_rev"), Ok(None));
+    }
+
+    #[test]
+    fn test_default_provider_uncached() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let providers = DefaultProviders::default();
+        providers
+            .set(Some((counting_provider(calls.clone()), false)))
+            .unwrap();
+        assert_eq!(providers.get("hw_rev"), Ok(Some(KvsValue::from(3.0))));
+        assert_eq!(providers.get("hw_rev"), Ok(Some(KvsValue::from(3.0))));
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
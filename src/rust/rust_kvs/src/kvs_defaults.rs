@@ -0,0 +1,246 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Type annotated defaults documents
+//!
+//! A defaults file (JSON or CBOR) is either a plain object of default values or a typed document
+//! identified by the member `"$schema": "kvs-defaults/1"`. In a typed document every value is an
+//! object `{"t": <type>, "v": <value>}`, array elements and object members are annotated the same
+//! way. The types are `f64`, `i64`, `u64`, `bool`, `str`, `bytes`, `null`, `arr` and `obj`.
+//! Integers beyond the exact `f64` range are given as decimal strings, bytes as base64 string. A
+//! value not matching its annotation fails the load, so a broken defaults file is detected when
+//! the KVS is opened and not when the key is first read.
+//!
+//! ```json
+//! {
+//!   "$schema": "kvs-defaults/1",
+//!   "volume": {"t": "f64", "v": 12},
+//!   "presets": {"t": "arr", "v": [{"t": "str", "v": "radio"}]}
+//! }
+//! ```
+//!
+//! The member `"$schemas"` isn't annotated in either kind of document, see
+//! [`kvs_schema`](crate::kvs_schema).
+
+use crate::error_code::ErrorCode;
+use crate::kvs_base64;
+use crate::kvs_log::log_error;
+use crate::kvs_schema::SCHEMAS_KEY;
+use crate::kvs_value::{KvsMap, KvsValue};
+
+/// Member identifying a typed defaults document
+const SCHEMA_KEY: &str = "$schema";
+
+/// Schema name of typed defaults documents
+const SCHEMA_TYPED: &str = "kvs-defaults/1";
+
+/// Check a type annotated value and return the plain value
+fn typed_value(key: &str, typed: KvsValue) -> Result<KvsValue, ErrorCode> {
+    let KvsValue::Object(mut entry) = typed else {
+        log_error!("default '{key}' has no type annotation");
+        return Err(ErrorCode::ValidationFailed);
+    };
+    let (Some(KvsValue::String(ty)), Some(value)) = (entry.remove("t"), entry.remove("v")) else {
+        log_error!("default '{key}' must be an object with members 't' and 'v'");
+        return Err(ErrorCode::ValidationFailed);
+    };
+    if !entry.is_empty() {
+        log_error!("default '{key}' must be an object with members 't' and 'v'");
+        return Err(ErrorCode::ValidationFailed);
+    }
+
+    match (ty.as_str(), value) {
+        ("f64", value @ KvsValue::Number(_))
+        | ("bool", value @ KvsValue::Boolean(_))
+        | ("str", value @ KvsValue::String(_))
+        | ("null", value @ KvsValue::Null)
+        | ("i64", value @ KvsValue::I64(_))
+        | ("u64", value @ KvsValue::U64(_))
+        | ("bytes", value @ KvsValue::Bytes(_)) => Ok(value),
+        ("bytes", KvsValue::String(text)) => kvs_base64::decode(&text)
+            .map(KvsValue::Bytes)
+            .ok_or_else(|| type_mismatch(key, "bytes", &KvsValue::String(text))),
+        ("i64", value) => integer(&value)
+            .and_then(|n| i64::try_from(n).ok())
+            .map(KvsValue::I64)
+            .ok_or_else(|| type_mismatch(key, "i64", &value)),
+        ("u64", value) => integer(&value)
+            .and_then(|n| u64::try_from(n).ok())
+            .map(KvsValue::U64)
+            .ok_or_else(|| type_mismatch(key, "u64", &value)),
+        ("arr", KvsValue::Array(arr)) => Ok(KvsValue::Array(
+            arr.into_iter()
+                .enumerate()
+                .map(|(idx, item)| typed_value(&format!("{key}[{idx}]"), item))
+                .collect::<Result<_, _>>()?,
+        )),
+        ("obj", KvsValue::Object(map)) => Ok(KvsValue::Object(typed_map(key, map)?)),
+        (ty, value) => Err(type_mismatch(key, ty, &value)),
+    }
+}
+
+fn type_mismatch(key: &str, ty: &str, value: &KvsValue) -> ErrorCode {
+    log_error!("default '{key}' doesn't match type '{ty}': {value:?}");
+    ErrorCode::ValidationFailed
+}
+
+/// Return the integer of a number without fraction in the exact `f64` range or a decimal string
+fn integer(value: &KvsValue) -> Option<i128> {
+    /// Largest integer up to which all integers are exactly representable as `f64`
+    const MAX_EXACT: f64 = 9007199254740992.0;
+
+    match value {
+        KvsValue::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_EXACT => Some(*n as i128),
+        KvsValue::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn typed_map(prefix: &str, map: KvsMap) -> Result<KvsMap, ErrorCode> {
+    map.into_iter()
+        .map(|(key, value)| {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            typed_value(&path, value).map(|value| (key, value))
+        })
+        .collect()
+}
+
+/// Return the default values of a loaded defaults document
+///
+/// Plain documents are returned unchanged, typed documents are checked against their annotations.
+/// The schemas member is kept as is.
+///
+/// # Return Values
+///   * Ok: Default values
+///   * `ErrorCode::ValidationFailed`: Unknown schema, missing annotation or type mismatch
+pub(crate) fn resolve(mut map: KvsMap) -> Result<KvsMap, ErrorCode> {
+    match map.remove(SCHEMA_KEY) {
+        None => Ok(map),
+        Some(KvsValue::String(schema)) if schema == SCHEMA_TYPED => {
+            let schemas = map.remove(SCHEMAS_KEY);
+            let mut values = typed_map("", map)?;
+            values.extend(schemas.map(|schemas| (SCHEMAS_KEY.to_string(), schemas)));
+            Ok(values)
+        }
+        Some(schema) => {
+            log_error!("unsupported defaults schema: {schema:?}");
+            Err(ErrorCode::ValidationFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(ty: &str, value: KvsValue) -> KvsValue {
+        KvsValue::from(KvsMap::from([
+            ("t".to_string(), KvsValue::from(ty.to_string())),
+            ("v".to_string(), value),
+        ]))
+    }
+
+    fn document(entries: Vec<(&str, KvsValue)>) -> KvsMap {
+        let mut map = KvsMap::from([(
+            SCHEMA_KEY.to_string(),
+            KvsValue::from(SCHEMA_TYPED.to_string()),
+        )]);
+        map.extend(entries.into_iter().map(|(k, v)| (k.to_string(), v)));
+        map
+    }
+
+    #[test]
+    fn test_resolve_plain_document() {
+        let map = KvsMap::from([("a".to_string(), KvsValue::from(1.0))]);
+        assert_eq!(resolve(map.clone()), Ok(map));
+    }
+
+    #[test]
+    fn test_resolve_typed_document() {
+        let map = document(vec![
+            ("number", typed("f64", KvsValue::from(1.5))),
+            ("null", typed("null", KvsValue::Null)),
+            ("i64", typed("i64", KvsValue::from(-3.0))),
+            ("bytes", typed("bytes", KvsValue::from("AP8=".to_string()))),
+            (
+                "u64",
+                typed("u64", KvsValue::from("18446744073709551615".to_string())),
+            ),
+            (
+                "array",
+                typed(
+                    "arr",
+                    KvsValue::from(vec![typed("str", KvsValue::from("x".to_string()))]),
+                ),
+            ),
+            (
+                "object",
+                typed(
+                    "obj",
+                    KvsValue::from(KvsMap::from([(
+                        "flag".to_string(),
+                        typed("bool", KvsValue::from(true)),
+                    )])),
+                ),
+            ),
+        ]);
+
+        assert_eq!(
+            resolve(map),
+            Ok(KvsMap::from([
+                ("number".to_string(), KvsValue::from(1.5)),
+                ("null".to_string(), KvsValue::Null),
+                ("i64".to_string(), KvsValue::I64(-3)),
+                ("bytes".to_string(), KvsValue::Bytes(vec![0x00, 0xff])),
+                ("u64".to_string(), KvsValue::U64(u64::MAX)),
+                (
+                    "array".to_string(),
+                    KvsValue::from(vec![KvsValue::from("x".to_string())])
+                ),
+                (
+                    "object".to_string(),
+                    KvsValue::from(KvsMap::from([("flag".to_string(), KvsValue::from(true))]))
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_resolve_typed_document_invalid() {
+        let mismatch = document(vec![("a", typed("bool", KvsValue::from(1.0)))]);
+        assert_eq!(resolve(mismatch), Err(ErrorCode::ValidationFailed));
+
+        let fraction = document(vec![("a", typed("i64", KvsValue::from(1.5)))]);
+        assert_eq!(resolve(fraction), Err(ErrorCode::ValidationFailed));
+
+        let negative = document(vec![("a", typed("u64", KvsValue::from(-1.0)))]);
+        assert_eq!(resolve(negative), Err(ErrorCode::ValidationFailed));
+
+        let unknown = document(vec![("a", typed("i8", KvsValue::from(1.0)))]);
+        assert_eq!(resolve(unknown), Err(ErrorCode::ValidationFailed));
+
+        let missing = document(vec![("a", KvsValue::from(1.0))]);
+        assert_eq!(resolve(missing), Err(ErrorCode::ValidationFailed));
+
+        let nested = document(vec![(
+            "a",
+            typed("arr", KvsValue::from(vec![KvsValue::from(1.0)])),
+        )]);
+        assert_eq!(resolve(nested), Err(ErrorCode::ValidationFailed));
+
+        let schema = KvsMap::from([(SCHEMA_KEY.to_string(), KvsValue::from("other".to_string()))]);
+        assert_eq!(resolve(schema), Err(ErrorCode::ValidationFailed));
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defaults change detection
+//!
+//! A device can end up with a defaults file of another software version than its KVS file. A hash
+//! of the defaults and of every default value is kept in the reserved member `"$defaults_hash"`
+//! of the KVS file, written with the data. When the KVS is opened and the defaults hash differs
+//! from the one in the KVS file, a `DefaultsChanged` warning is logged and the keys whose default
+//! was added, removed or changed are available from
+//! [`changed_defaults`](crate::kvs::GenericKvs::changed_defaults) until the KVS is closed.
+//!
+//! The hashes are Adler-32 checksums of the CBOR encoding, which has sorted object keys, so they
+//! don't depend on the order of the defaults file. Without defaults no hash is written. The member
+//! `"$defaults_hash"` can't be used as key.
+
+use crate::kvs_cbor;
+use crate::kvs_log::log_warning;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::collections::{BTreeSet, HashMap};
+
+/// Member of the KVS file holding the defaults hash
+pub(crate) const DEFAULTS_HASH_KEY: &str = "$defaults_hash";
+
+/// Hashes of a set of defaults
+#[derive(Debug, PartialEq)]
+pub(crate) struct DefaultsHash {
+    /// Hash of all defaults
+    hash: u32,
+
+    /// Hash of every default value by key
+    keys: HashMap<String, u32>,
+}
+
+/// Adler-32 checksum of the CBOR encoding
+fn checksum(map: &KvsMap) -> u32 {
+    adler32::RollingAdler32::from_buffer(&kvs_cbor::encode(map)).hash()
+}
+
+/// Convert a stored hash
+fn hash_from(value: &KvsValue) -> Option<u32> {
+    u32::try_from(u64::try_from(value).ok()?).ok()
+}
+
+/// Remove the defaults hash from loaded data
+///
+/// A malformed hash is ignored.
+///
+/// # Return Values
+///   * Recorded defaults hash, `None` if the data has none
+pub(crate) fn take_defaults_hash(data: &mut KvsMap) -> Option<DefaultsHash> {
+    let value = data.remove(DEFAULTS_HASH_KEY)?;
+    let parsed = match &value {
+        KvsValue::Object(obj) => match (obj.get("hash").and_then(hash_from), obj.get("keys")) {
+            (Some(hash), Some(KvsValue::Object(keys))) => Some((hash, keys)),
+            _ => None,
+        },
+        _ => None,
+    };
+    let Some((hash, keys)) = parsed else {
+        log_warning!("ignoring invalid defaults hash: {value:?}");
+        return None;
+    };
+    Some(DefaultsHash {
+        hash,
+        keys: keys
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), hash_from(value)?)))
+            .collect(),
+    })
+}
+
+impl DefaultsHash {
+    /// Hash a set of defaults
+    pub(crate) fn new(defaults: &KvsMap) -> Self {
+        let keys = defaults
+            .iter()
+            .map(|(key, value)| {
+                let entry = KvsMap::from([(key.clone(), value.clone())]);
+                (key.clone(), checksum(&entry))
+            })
+            .collect();
+        Self {
+            hash: checksum(defaults),
+            keys,
+        }
+    }
+
+    /// Compare the hash with the hash recorded in the KVS file
+    ///
+    /// A missing record counts as unchanged.
+    ///
+    /// # Parameters
+    ///   * `recorded`: Defaults hash loaded from the KVS file
+    ///
+    /// # Return Values
+    ///   * Keys whose default was added, removed or changed, sorted
+    pub(crate) fn changed_keys(&self, recorded: Option<&Self>) -> Vec<String> {
+        let Some(recorded) = recorded.filter(|recorded| recorded.hash != self.hash) else {
+            return Vec::new();
+        };
+        let keys: BTreeSet<&String> = recorded.keys.keys().chain(self.keys.keys()).collect();
+        let changed: Vec<String> = keys
+            .into_iter()
+            .filter(|key| recorded.keys.get(*key) != self.keys.get(*key))
+            .cloned()
+            .collect();
+        log_warning!(
+            "DefaultsChanged: defaults differ from the previous open in {} keys",
+            changed.len()
+        );
+        changed
+    }
+
+    /// Add the hash to data that's written to the KVS file, no member is added without defaults
+    pub(crate) fn store(&self, data: &mut KvsMap) {
+        if self.keys.is_empty() {
+            return;
+        }
+        let keys = self
+            .keys
+            .iter()
+            .map(|(key, hash)| (key.clone(), KvsValue::U64(u64::from(*hash))))
+            .collect();
+        let value = KvsMap::from([
+            ("hash".to_string(), KvsValue::U64(u64::from(self.hash))),
+            ("keys".to_string(), KvsValue::Object(keys)),
+        ]);
+        data.insert(DEFAULTS_HASH_KEY.to_string(), KvsValue::Object(value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_hash_stored_and_taken() {
+        let defaults = KvsMap::from([
+            ("a".to_string(), KvsValue::from(1.0)),
+            ("b".to_string(), KvsValue::from(true)),
+        ]);
+        let mut data = KvsMap::from([("a".to_string(), KvsValue::from(2.0))]);
+        DefaultsHash::new(&defaults).store(&mut data);
+        assert_eq!(
+            take_defaults_hash(&mut data),
+            Some(DefaultsHash::new(&defaults))
+        );
+        assert_eq!(data, KvsMap::from([("a".to_string(), KvsValue::from(2.0))]));
+        assert_eq!(take_defaults_hash(&mut data), None);
+
+        DefaultsHash::new(&KvsMap::new()).store(&mut data);
+        assert_eq!(data.len(), 1);
+    }
+
+    #[test]
+    fn test_changed_defaults_listed() {
+        let defaults = KvsMap::from([
+            ("a".to_string(), KvsValue::from(1.0)),
+            ("b".to_string(), KvsValue::from(true)),
+        ]);
+        let recorded = DefaultsHash::new(&defaults);
+        assert!(recorded.changed_keys(None).is_empty());
+        assert!(DefaultsHash::new(&defaults)
+            .changed_keys(Some(&recorded))
+            .is_empty());
+
+        let mut updated = defaults.clone();
+        updated.insert("a".to_string(), KvsValue::from(2.0));
+        updated.remove("b");
+        updated.insert("c".to_string(), KvsValue::Null);
+        assert_eq!(
+            DefaultsHash::new(&updated).changed_keys(Some(&recorded)),
+            vec!["a", "b", "c"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_defaults_hash_ignored() {
+        for value in [
+            KvsValue::from(1.0),
+            KvsValue::Object(HashMap::from([("hash".to_string(), KvsValue::from(1.0))])),
+            KvsValue::Object(HashMap::from([
+                ("hash".to_string(), KvsValue::U64(u64::MAX)),
+                ("keys".to_string(), KvsValue::Object(HashMap::new())),
+            ])),
+        ] {
+            let mut data = KvsMap::from([(DEFAULTS_HASH_KEY.to_string(), value)]);
+            assert_eq!(take_defaults_hash(&mut data), None);
+            assert!(data.is_empty());
+        }
+    }
+}
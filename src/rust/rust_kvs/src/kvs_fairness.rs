@@ -0,0 +1,209 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Reader/writer fairness
+//!
+//! Reads and writes queue on the same data lock. With many reading threads a writer, e.g. a
+//! flush, can wait long for its turn as the lock isn't fair. A fairness policy admits callers
+//! to the data lock through a gate:
+//!
+//!   * [`FairnessPolicy::Unbounded`]: No gate, callers compete for the lock directly (default)
+//!   * [`FairnessPolicy::WriterPreferring`]: While a writer waits or writes, new readers are
+//!     held back, so the writer competes only with readers that were already admitted
+//!   * [`FairnessPolicy::BoundedReaders`]: At most the given count of readers is admitted at
+//!     once, which bounds the queue a writer has to wait behind
+//!
+//! Writers are never held back by the gate, they are serialized by the data lock.
+
+use crate::error_code::ErrorCode;
+use std::sync::{Condvar, Mutex};
+
+/// Admission policy of readers and writers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FairnessPolicy {
+    /// Readers and writers compete for the data lock directly (default)
+    #[default]
+    Unbounded,
+
+    /// New readers wait while a writer is pending
+    WriterPreferring,
+
+    /// At most the given count of readers is admitted at once, 0 is treated as 1
+    BoundedReaders(usize),
+}
+
+/// Admitted readers and pending writers
+#[derive(Default)]
+struct GateState {
+    /// Admitted readers
+    readers: usize,
+
+    /// Pending or active writers
+    writers: usize,
+}
+
+/// Gate in front of the data lock
+pub(crate) struct AccessGate {
+    /// Admission policy
+    policy: FairnessPolicy,
+
+    /// Admission state
+    state: Mutex<GateState>,
+
+    /// Signalled when a reader or writer leaves
+    changed: Condvar,
+}
+
+/// Admission to the data lock, released on drop
+pub(crate) struct GateGuard<'a> {
+    /// Gate the guard was admitted by
+    gate: &'a AccessGate,
+
+    /// Guard of a writer
+    writer: bool,
+}
+
+impl AccessGate {
+    pub(crate) fn new(policy: FairnessPolicy) -> Self {
+        Self {
+            policy,
+            state: Mutex::new(GateState::default()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Admit a reader, blocks as long as the policy requires
+    ///
+    /// # Return Values
+    ///   * Ok: Guard to hold while reading, `None` without a gate
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn read(&self) -> Result<Option<GateGuard<'_>>, ErrorCode> {
+        let max_readers = match self.policy {
+            FairnessPolicy::Unbounded => return Ok(None),
+            FairnessPolicy::WriterPreferring => usize::MAX,
+            FairnessPolicy::BoundedReaders(max) => max.max(1),
+        };
+        let writer_preferring = self.policy == FairnessPolicy::WriterPreferring;
+
+        let state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        let mut state = self
+            .changed
+            .wait_while(state, |state| {
+                state.readers >= max_readers || (writer_preferring && state.writers > 0)
+            })
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        state.readers += 1;
+        Ok(Some(GateGuard {
+            gate: self,
+            writer: false,
+        }))
+    }
+
+    /// Announce a writer, never blocks on other callers
+    ///
+    /// # Return Values
+    ///   * Ok: Guard to hold while writing, `None` without a gate
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn write(&self) -> Result<Option<GateGuard<'_>>, ErrorCode> {
+        if self.policy == FairnessPolicy::Unbounded {
+            return Ok(None);
+        }
+        self.state
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .writers += 1;
+        Ok(Some(GateGuard {
+            gate: self,
+            writer: true,
+        }))
+    }
+}
+
+impl Drop for GateGuard<'_> {
+    fn drop(&mut self) {
+        // The counters stay consistent on poisoning, the guard must be released regardless
+        let mut state = self
+            .gate
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if self.writer {
+            state.writers -= 1;
+        } else {
+            state.readers -= 1;
+        }
+        drop(state);
+        self.gate.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_unbounded_has_no_gate() {
+        let gate = AccessGate::new(FairnessPolicy::Unbounded);
+        assert!(gate.read().unwrap().is_none());
+        assert!(gate.write().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_writer_preferring_holds_back_readers() {
+        let gate = Arc::new(AccessGate::new(FairnessPolicy::WriterPreferring));
+        let reader = gate.read().unwrap();
+        let writer = gate.write().unwrap();
+        drop(reader);
+
+        let (tx, rx) = mpsc::channel();
+        let thread_gate = gate.clone();
+        let handle = thread::spawn(move || {
+            let _reader = thread_gate.read().unwrap();
+            tx.send(()).unwrap();
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(writer);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_bounded_readers() {
+        let gate = Arc::new(AccessGate::new(FairnessPolicy::BoundedReaders(2)));
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (gate, active, max_active) = (gate.clone(), active.clone(), max_active.clone());
+                thread::spawn(move || {
+                    let _reader = gate.read().unwrap();
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    active.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(max_active.load(Ordering::SeqCst) <= 2);
+
+        // Writers are never held back
+        let _readers = (gate.read().unwrap(), gate.read().unwrap());
+        assert!(gate.write().unwrap().is_some());
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feature flag evaluation
+//!
+//! A flag is a value stored in the KVS (or its defaults) that decides per device if a feature is
+//! enabled:
+//!
+//!   * `true` / `false`: Enabled or disabled for all devices
+//!   * `{"percentage": 25}`: Enabled for a stable share of 0 to 100 percent of the devices
+//!   * `{"allowlist": ["vin-1", "vin-2"]}`: Enabled for the listed device IDs
+//!
+//! `percentage` and `allowlist` can be combined, the flag is then enabled if either matches. The
+//! rollout bucket of a device is derived from the flag key and the device ID with FNV-1a, so a
+//! device keeps its decision across restarts and different flags roll out to different devices.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_error;
+use crate::kvs_value::KvsValue;
+
+/// Count of rollout buckets, allows percentages with two decimals
+const BUCKETS: u64 = 10_000;
+
+/// Return the 64-bit FNV-1a hash of the data
+fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    data.iter().fold(OFFSET, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// Return the rollout bucket of a device for a flag, in `0..BUCKETS`
+fn bucket(key: &str, device_id: &str) -> u64 {
+    fnv1a(format!("{key}\0{device_id}").as_bytes()) % BUCKETS
+}
+
+fn invalid_flag(key: &str, reason: &str) -> ErrorCode {
+    log_error!("invalid flag '{key}': {reason}");
+    ErrorCode::ConversionFailed
+}
+
+/// Evaluate a flag value for a device
+///
+/// # Parameters
+///   * `key`: Flag key, part of the rollout bucket
+///   * `value`: Flag value
+///   * `device_id`: Stable ID of the device, e.g. the VIN
+///
+/// # Return Values
+///   * Ok: Flag is enabled (`true`) or disabled (`false`) for the device
+///   * `ErrorCode::ConversionFailed`: Value isn't a valid flag
+pub fn evaluate(key: &str, value: &KvsValue, device_id: &str) -> Result<bool, ErrorCode> {
+    let obj = match value {
+        KvsValue::Boolean(enabled) => return Ok(*enabled),
+        KvsValue::Object(obj) => obj,
+        _ => return Err(invalid_flag(key, "neither a boolean nor an object")),
+    };
+    if let Some(member) = obj
+        .keys()
+        .find(|member| !matches!(member.as_str(), "percentage" | "allowlist"))
+    {
+        return Err(invalid_flag(key, &format!("unknown member '{member}'")));
+    }
+
+    let listed = match obj.get("allowlist") {
+        None => false,
+        Some(KvsValue::Array(ids)) => {
+            let mut listed = false;
+            for id in ids {
+                match id {
+                    KvsValue::String(id) => listed |= id == device_id,
+                    _ => return Err(invalid_flag(key, "allowlist entries must be strings")),
+                }
+            }
+            listed
+        }
+        Some(_) => return Err(invalid_flag(key, "allowlist must be an array")),
+    };
+
+    let percentage = match obj.get("percentage") {
+        None => 0.0,
+        Some(KvsValue::Number(n)) => *n,
+        Some(KvsValue::I64(n)) => *n as f64,
+        Some(KvsValue::U64(n)) => *n as f64,
+        Some(_) => return Err(invalid_flag(key, "percentage must be a number")),
+    };
+    if !(0.0..=100.0).contains(&percentage) {
+        return Err(invalid_flag(key, "percentage must be within 0 and 100"));
+    }
+    let rolled_out = (bucket(key, device_id) as f64) < percentage * (BUCKETS as f64 / 100.0);
+
+    Ok(listed || rolled_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs_value::KvsMap;
+
+    fn flag(entries: Vec<(&str, KvsValue)>) -> KvsValue {
+        KvsValue::from(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect::<KvsMap>(),
+        )
+    }
+
+    #[test]
+    fn test_fnv1a() {
+        assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn test_evaluate_bool_and_allowlist() {
+        assert_eq!(evaluate("f", &KvsValue::from(true), "dev"), Ok(true));
+        assert_eq!(evaluate("f", &KvsValue::from(false), "dev"), Ok(false));
+
+        let allowlist = flag(vec![(
+            "allowlist",
+            KvsValue::from(vec![KvsValue::from("dev-1".to_string())]),
+        )]);
+        assert_eq!(evaluate("f", &allowlist, "dev-1"), Ok(true));
+        assert_eq!(evaluate("f", &allowlist, "dev-2"), Ok(false));
+    }
+
+    #[test]
+    fn test_evaluate_percentage() {
+        let none = flag(vec![("percentage", KvsValue::from(0.0))]);
+        let all = flag(vec![("percentage", KvsValue::from(100.0))]);
+        let half = flag(vec![("percentage", KvsValue::from(50.0))]);
+
+        let devices: Vec<String> = (0..1000).map(|idx| format!("device-{idx}")).collect();
+        let enabled = devices
+            .iter()
+            .filter(|dev| evaluate("f", &half, dev).unwrap())
+            .count();
+        assert!((400..600).contains(&enabled), "{enabled} devices enabled");
+        for dev in &devices {
+            assert_eq!(evaluate("f", &none, dev), Ok(false));
+            assert_eq!(evaluate("f", &all, dev), Ok(true));
+            // Stable decision
+            assert_eq!(evaluate("f", &half, dev), evaluate("f", &half, dev));
+        }
+    }
+
+    #[test]
+    fn test_evaluate_invalid() {
+        for value in [
+            KvsValue::from(1.0),
+            flag(vec![("percentage", KvsValue::from(101.0))]),
+            flag(vec![("percentage", KvsValue::from("50".to_string()))]),
+            flag(vec![("allowlist", KvsValue::from(true))]),
+            flag(vec![(
+                "allowlist",
+                KvsValue::from(vec![KvsValue::from(1.0)]),
+            )]),
+            flag(vec![("enabled", KvsValue::from(true))]),
+        ] {
+            assert_eq!(
+                evaluate("f", &value, "dev"),
+                Err(ErrorCode::ConversionFailed)
+            );
+        }
+    }
+}
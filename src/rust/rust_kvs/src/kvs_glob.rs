@@ -0,0 +1,88 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Glob-style key patterns
+//!
+//! `*` matches any sequence of characters including `/` and `.`, `?` matches a single character.
+//! All other characters match themselves, there is no escaping.
+
+/// Return if a key matches a glob pattern
+pub(crate) fn glob_matches(pattern: &str, key: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let key: Vec<char> = key.chars().collect();
+
+    let (mut p, mut k) = (0, 0);
+    // Position after the last `*` and the key position it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, k));
+            }
+            Some(c) if *c == '?' || *c == key[k] => {
+                p += 1;
+                k += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` consume one more character
+                Some((star_p, star_k)) => {
+                    p = star_p;
+                    k = star_k + 1;
+                    backtrack = Some((star_p, star_k + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_literal_pattern() {
+        assert!(glob_matches("", ""));
+        assert!(glob_matches("net.ip", "net.ip"));
+        assert!(!glob_matches("", "a"));
+        assert!(!glob_matches("a", ""));
+        assert!(!glob_matches("net.ip", "net.ip6"));
+    }
+
+    #[test]
+    fn test_star_matches_any_sequence() {
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*", "a/b.c"));
+        assert!(glob_matches("diagnostics/*", "diagnostics/dtc/1"));
+        assert!(glob_matches("*.ip", "net.eth0.ip"));
+        assert!(!glob_matches("diagnostics/*", "diag/x"));
+        assert!(!glob_matches("*.ip", "net.ip6"));
+    }
+
+    #[test]
+    fn test_question_mark_matches_one_character() {
+        assert!(glob_matches("net.?", "net.a"));
+        assert!(glob_matches("ü?", "üß"));
+        assert!(!glob_matches("net.?", "net.ab"));
+        assert!(!glob_matches("net.?", "net."));
+    }
+
+    #[test]
+    fn test_star_backtracks() {
+        assert!(glob_matches("a*b*c", "abbbc"));
+        assert!(glob_matches("a*b*c", "axbyc"));
+        assert!(glob_matches("**a", "bba"));
+        assert!(!glob_matches("a*b*c", "abcb"));
+        assert!(!glob_matches("a*b", "acbc"));
+    }
+}
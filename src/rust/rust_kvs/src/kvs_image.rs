@@ -0,0 +1,326 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host-side creation of KVS instance images
+//!
+//! [`KvsImageBuilder`] assembles all files of an instance for factory provisioning without a
+//! device: the KVS file, its hash file, the defaults file and a manifest. The output only
+//! depends on the inputs: object keys are sorted, no timestamps are written and tar headers use
+//! fixed ownership, permissions and modification time. So identical inputs always produce
+//! byte-identical images.
+
+use crate::error_code::ErrorCode;
+use crate::json_backend::canonical_json;
+use crate::kvs_api::{Durability, InstanceId, StorageFormat};
+use crate::kvs_cbor;
+use crate::kvs_log::log_error;
+use crate::kvs_platform::atomic_replace;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::path::Path;
+
+/// Tar block size
+const TAR_BLOCK: usize = 512;
+
+/// File mode of all files in a tar image
+const TAR_MODE: &str = "0000644";
+
+/// Builder for the files of a KVS instance
+pub struct KvsImageBuilder {
+    /// Instance ID
+    instance_id: InstanceId,
+
+    /// Stored values
+    values: KvsMap,
+
+    /// Default values
+    defaults: KvsMap,
+
+    /// Format of the KVS file
+    storage_format: StorageFormat,
+}
+
+/// Append a tar header field with octal number
+fn tar_octal(header: &mut [u8], offset: usize, len: usize, value: u64) {
+    let text = format!("{value:0width$o}", width = len - 1);
+    header[offset..offset + len - 1].copy_from_slice(text.as_bytes());
+}
+
+/// Create a tar (ustar) archive of the files
+fn tar_archive(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, ErrorCode> {
+    let mut out = Vec::new();
+    for (name, data) in files {
+        if name.len() >= 100 {
+            log_error!("filename too long for tar image: {name}");
+            return Err(ErrorCode::SerializationFailed);
+        }
+
+        let mut header = [0u8; TAR_BLOCK];
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        header[100..100 + TAR_MODE.len()].copy_from_slice(TAR_MODE.as_bytes());
+        tar_octal(&mut header, 108, 8, 0); // uid
+        tar_octal(&mut header, 116, 8, 0); // gid
+        tar_octal(&mut header, 124, 12, data.len() as u64);
+        tar_octal(&mut header, 136, 12, 0); // mtime
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+
+        // Checksum is calculated with the checksum field set to spaces
+        header[148..156].fill(b' ');
+        let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+        let text = format!("{checksum:06o}\0 ");
+        header[148..156].copy_from_slice(text.as_bytes());
+
+        out.extend_from_slice(&header);
+        out.extend_from_slice(data);
+        out.resize(out.len().next_multiple_of(TAR_BLOCK), 0);
+    }
+    out.resize(out.len() + 2 * TAR_BLOCK, 0);
+    Ok(out)
+}
+
+impl KvsImageBuilder {
+    /// Create a builder for an empty instance
+    ///
+    /// # Parameters
+    ///   * `instance_id`: Instance ID the image is created for
+    pub fn new(instance_id: InstanceId) -> Self {
+        Self {
+            instance_id,
+            values: KvsMap::new(),
+            defaults: KvsMap::new(),
+            storage_format: StorageFormat::Json,
+        }
+    }
+
+    /// Add a stored value
+    ///
+    /// # Parameters
+    ///   * `key`: Key
+    ///   * `value`: Value
+    pub fn value<S: Into<String>, V: Into<KvsValue>>(mut self, key: S, value: V) -> Self {
+        self.values.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a default value
+    ///
+    /// # Parameters
+    ///   * `key`: Key
+    ///   * `value`: Default value
+    pub fn default_value<S: Into<String>, V: Into<KvsValue>>(mut self, key: S, value: V) -> Self {
+        self.defaults.insert(key.into(), value.into());
+        self
+    }
+
+    /// Configure the format of the KVS file
+    ///
+    /// # Parameters
+    ///   * `format`: Storage format, `StorageFormat::Json` (default)
+    pub fn storage_format(mut self, format: StorageFormat) -> Self {
+        self.storage_format = format;
+        self
+    }
+
+    /// Return all files of the image
+    ///
+    /// Files are `kvs_<id>_0.json`, `kvs_<id>_0.hash`, `kvs_<id>_default.json` and the
+    /// manifest `kvs_<id>_manifest.json` listing size and Adler-32 checksum of the other files.
+    ///
+    /// # Return Values
+    ///   * Ok: Filenames and contents, sorted by filename
+    ///   * `ErrorCode::JsonGeneratorError`: A value can't be represented as JSON
+    pub fn files(&self) -> Result<Vec<(String, Vec<u8>)>, ErrorCode> {
+        let prefix = self.instance_id.filename_prefix();
+        let kvs = match self.storage_format {
+            StorageFormat::Json => canonical_json(&self.values)?,
+            StorageFormat::Cbor => kvs_cbor::encode(&self.values),
+        };
+        let hash = adler32::RollingAdler32::from_buffer(&kvs).hash();
+
+        let mut files = vec![
+            (format!("{prefix}_0.json"), kvs),
+            (format!("{prefix}_0.hash"), hash.to_be_bytes().to_vec()),
+            (
+                format!("{prefix}_default.json"),
+                canonical_json(&self.defaults)?,
+            ),
+        ];
+
+        let entries = files
+            .iter()
+            .map(|(name, data)| {
+                let checksum = adler32::RollingAdler32::from_buffer(data).hash();
+                KvsValue::from(KvsMap::from([
+                    ("name".to_string(), KvsValue::from(name.clone())),
+                    ("size".to_string(), KvsValue::from(data.len() as f64)),
+                    (
+                        "adler32".to_string(),
+                        KvsValue::from(format!("{checksum:08x}")),
+                    ),
+                ]))
+            })
+            .collect::<Vec<_>>();
+        let format = match self.storage_format {
+            StorageFormat::Json => "json",
+            StorageFormat::Cbor => "cbor",
+        };
+        let manifest = KvsMap::from([
+            (
+                "instance_id".to_string(),
+                KvsValue::from(self.instance_id.to_string()),
+            ),
+            (
+                "storage_format".to_string(),
+                KvsValue::from(format.to_string()),
+            ),
+            ("files".to_string(), KvsValue::from(entries)),
+        ]);
+        files.push((
+            format!("{prefix}_manifest.json"),
+            canonical_json(&manifest)?,
+        ));
+
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(files)
+    }
+
+    /// Write the image files into a directory
+    ///
+    /// Existing files of the instance are replaced.
+    ///
+    /// # Parameters
+    ///   * `dir`: Existing target directory
+    ///
+    /// # Return Values
+    ///   * Ok: Image written
+    ///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+    ///   * `ErrorCode::JsonGeneratorError`: A value can't be represented as JSON
+    ///   * `ErrorCode::UnmappedError`: Generic error
+    pub fn write_dir<P: AsRef<Path>>(&self, dir: P) -> Result<(), ErrorCode> {
+        for (name, data) in self.files()? {
+            atomic_replace(&dir.as_ref().join(name), &data, Durability::default())?;
+        }
+        Ok(())
+    }
+
+    /// Return the image as tar archive
+    ///
+    /// # Return Values
+    ///   * Ok: Tar archive
+    ///   * `ErrorCode::JsonGeneratorError`: A value can't be represented as JSON
+    pub fn to_tar(&self) -> Result<Vec<u8>, ErrorCode> {
+        tar_archive(&self.files()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use tempfile::tempdir;
+
+    fn builder() -> KvsImageBuilder {
+        KvsImageBuilder::new(InstanceId::new(2))
+            .value("number", 1.5)
+            .value("text", "calibrated".to_string())
+            .value(
+                "object",
+                KvsMap::from([
+                    ("b".to_string(), KvsValue::from(true)),
+                    ("a".to_string(), KvsValue::Null),
+                ]),
+            )
+            .default_value("number", 0.0)
+    }
+
+    #[test]
+    fn test_image_is_reproducible() {
+        let reversed = KvsImageBuilder::new(InstanceId::new(2))
+            .default_value("number", 0.0)
+            .value(
+                "object",
+                KvsMap::from([
+                    ("a".to_string(), KvsValue::Null),
+                    ("b".to_string(), KvsValue::from(true)),
+                ]),
+            )
+            .value("text", "calibrated".to_string())
+            .value("number", 1.5);
+
+        let tar = builder().to_tar().unwrap();
+        assert_eq!(tar, reversed.to_tar().unwrap());
+        assert_eq!(tar.len() % TAR_BLOCK, 0);
+        assert!(tar.starts_with(b"kvs_2_0.hash\0"));
+
+        let files = builder().files().unwrap();
+        let kvs = &files
+            .iter()
+            .find(|(name, _)| name == "kvs_2_0.json")
+            .unwrap()
+            .1;
+        assert_eq!(
+            String::from_utf8(kvs.clone()).unwrap(),
+            r#"{"number":1.5,"object":{"a":null,"b":true},"text":"calibrated"}"#
+        );
+    }
+
+    #[test]
+    fn test_image_opens_as_kvs() {
+        for format in [StorageFormat::Json, StorageFormat::Cbor] {
+            let dir = tempdir().unwrap();
+            builder()
+                .storage_format(format)
+                .write_dir(dir.path())
+                .unwrap();
+            assert!(dir.path().join("kvs_2_manifest.json").exists());
+
+            let kvs: Kvs = KvsBuilder::new(InstanceId::new(2))
+                .dir(dir.path().to_string_lossy().to_string())
+                .need_defaults(true)
+                .need_kvs(true)
+                .build()
+                .unwrap();
+            kvs.flush_on_exit(false);
+            assert_eq!(kvs.get_value_as::<f64>("number").unwrap(), 1.5);
+            assert_eq!(
+                kvs.get_default_value("number").unwrap(),
+                KvsValue::from(0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn test_image_missing_dir() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            builder().write_dir(dir.path().join("missing")),
+            Err(ErrorCode::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn test_image_invalid_number() {
+        let image = KvsImageBuilder::new(InstanceId::new(2)).value("number", f64::NAN);
+        assert_eq!(image.files().err(), Some(ErrorCode::JsonGeneratorError));
+        assert_eq!(image.to_tar().err(), Some(ErrorCode::JsonGeneratorError));
+    }
+
+    #[test]
+    fn test_tar_filename_too_long() {
+        let name = "a".repeat(100);
+        assert_eq!(
+            tar_archive(&[(name, Vec::new())]),
+            Err(ErrorCode::SerializationFailed)
+        );
+        assert_eq!(tar_archive(&[]).unwrap(), vec![0; TAR_BLOCK * 2]);
+    }
+}
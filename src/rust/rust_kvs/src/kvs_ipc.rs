@@ -0,0 +1,830 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Unix-domain-socket service frontend (feature `ipc`)
+//!
+//! Processes sharing a store open the files independently and only see each other's changes
+//! after flushes. A [`KvsIpcServer`] serves one instance to other processes instead, which use a
+//! [`KvsIpcClient`]: all clients work on the same data and see each other's writes right away,
+//! only the serving process writes the files.
+//!
+//! The protocol exchanges one JSON object per line. A client first authenticates with
+//! `{"op":"hello","client":<identity>,"token":<token>}`, then sends requests like
+//! `{"op":"get","key":<key>}`. Every request is answered with `{"ok":<result>}` or
+//! `{"error":<ErrorCode name>}`. Values use the tagged JSON encoding of the KVS file. A request
+//! line longer than [`MAX_REQUEST_LEN`] is answered with `SerializationFailed` and closes the
+//! connection.
+//!
+//! Peer credentials can't be queried without `unsafe` platform calls, so clients identify
+//! themselves with a token from the server's [`IpcConfig`]. Access is granted per key namespace
+//! (keys `<namespace>/...`), the grant of the longest matching namespace applies. Keys without a
+//! grant can't be read or written and are left out of key listings.
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_access::in_namespace;
+use crate::kvs_api::KvsApi;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_log::{log_error, log_info, log_warning};
+use crate::kvs_shared::GenericSharedKvs;
+use crate::kvs_value::KvsValue;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tinyjson::JsonValue;
+
+/// Maximum length of a request line in bytes, including the line break
+pub const MAX_REQUEST_LEN: usize = 1024 * 1024;
+
+/// Access of a client to a key namespace
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum IpcAccess {
+    /// Values can be read
+    Read,
+
+    /// Values can be read, set and removed
+    ReadWrite,
+}
+
+/// Access granted to a client for a key namespace
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IpcGrant {
+    /// Client identity
+    pub client: String,
+
+    /// Namespace, e.g. `audio` for the keys `audio/...`, `""` for all keys
+    pub namespace: String,
+
+    /// Granted access
+    pub access: IpcAccess,
+}
+
+/// Clients of a server and their access
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IpcConfig {
+    /// Token of every client identity
+    pub clients: HashMap<String, String>,
+
+    /// Granted namespace access, clients without a grant can't access any key
+    pub grants: Vec<IpcGrant>,
+}
+
+impl IpcConfig {
+    /// Return the access of a client to a key, `None` if nothing is granted
+    ///
+    /// # Parameters
+    ///   * `client`: Client identity
+    ///   * `key`: Key to access
+    pub fn access(&self, client: &str, key: &str) -> Option<IpcAccess> {
+        self.grants
+            .iter()
+            .filter(|grant| grant.client == client && in_namespace(key, &grant.namespace))
+            .max_by_key(|grant| grant.namespace.len())
+            .map(|grant| grant.access)
+    }
+}
+
+/// Return the error code of its name in a response
+fn error_from_name(name: &str) -> ErrorCode {
+    match name {
+        "FileNotFound" => ErrorCode::FileNotFound,
+        "KvsFileReadError" => ErrorCode::KvsFileReadError,
+        "KvsHashFileReadError" => ErrorCode::KvsHashFileReadError,
+        "JsonParserError" => ErrorCode::JsonParserError,
+        "JsonGeneratorError" => ErrorCode::JsonGeneratorError,
+        "PhysicalStorageFailure" => ErrorCode::PhysicalStorageFailure,
+        "IntegrityCorrupted" => ErrorCode::IntegrityCorrupted,
+        "ValidationFailed" => ErrorCode::ValidationFailed,
+        "EncryptionFailed" => ErrorCode::EncryptionFailed,
+        "ResourceBusy" => ErrorCode::ResourceBusy,
+        "OutOfStorageSpace" => ErrorCode::OutOfStorageSpace,
+        "QuotaExceeded" => ErrorCode::QuotaExceeded,
+        "AuthenticationFailed" => ErrorCode::AuthenticationFailed,
+        "PermissionDenied" => ErrorCode::PermissionDenied,
+        "KeyNotFound" => ErrorCode::KeyNotFound,
+        "SerializationFailed" => ErrorCode::SerializationFailed,
+        "InvalidSnapshotId" => ErrorCode::InvalidSnapshotId,
+        "VersionConflict" => ErrorCode::VersionConflict,
+        "ConversionFailed" => ErrorCode::ConversionFailed,
+        "MutexLockFailed" => ErrorCode::MutexLockFailed,
+        _ => ErrorCode::UnmappedError,
+    }
+}
+
+/// Return a string member of a request
+fn string_field<'a>(
+    request: &'a HashMap<String, JsonValue>,
+    name: &str,
+) -> Result<&'a str, ErrorCode> {
+    match request.get(name) {
+        Some(JsonValue::String(value)) => Ok(value),
+        _ => Err(ErrorCode::SerializationFailed),
+    }
+}
+
+/// Execute a request of a client
+///
+/// # Parameters
+///   * `kvs`: Served instance
+///   * `config`: Clients and their access
+///   * `client`: Identity of the connection, set by a successful `hello`
+///   * `request`: Request object
+fn handle<J: KvsBackend>(
+    kvs: &GenericKvs<J>,
+    config: &IpcConfig,
+    client: &mut Option<String>,
+    request: &HashMap<String, JsonValue>,
+) -> Result<JsonValue, ErrorCode> {
+    let op = string_field(request, "op")?;
+    if op == "hello" {
+        let identity = string_field(request, "client")?;
+        let token = string_field(request, "token")?;
+        if config.clients.get(identity).map(String::as_str) != Some(token) {
+            log_warning!("IPC client '{identity}' failed to authenticate");
+            return Err(ErrorCode::AuthenticationFailed);
+        }
+        *client = Some(identity.to_string());
+        return Ok(JsonValue::Null);
+    }
+
+    let client = client.as_deref().ok_or(ErrorCode::AuthenticationFailed)?;
+    let check = |key: &str, needed: IpcAccess| {
+        if config
+            .access(client, key)
+            .is_some_and(|access| access >= needed)
+        {
+            Ok(())
+        } else {
+            log_warning!("IPC client '{client}' isn't permitted to access '{key}'");
+            Err(ErrorCode::PermissionDenied)
+        }
+    };
+    match op {
+        "get" => {
+            let key = string_field(request, "key")?;
+            check(key, IpcAccess::Read)?;
+            Ok(JsonValue::from(kvs.get_value(key)?))
+        }
+        "exists" => {
+            let key = string_field(request, "key")?;
+            check(key, IpcAccess::Read)?;
+            Ok(JsonValue::Boolean(kvs.key_exists(key)?))
+        }
+        "set" => {
+            let key = string_field(request, "key")?;
+            check(key, IpcAccess::ReadWrite)?;
+            let value = request
+                .get("value")
+                .cloned()
+                .ok_or(ErrorCode::SerializationFailed)?;
+            kvs.set_value(key, KvsValue::from(value))?;
+            Ok(JsonValue::Null)
+        }
+        "remove" => {
+            let key = string_field(request, "key")?;
+            check(key, IpcAccess::ReadWrite)?;
+            kvs.remove_key(key)?;
+            Ok(JsonValue::Null)
+        }
+        "keys" => Ok(JsonValue::Array(
+            kvs.get_all_keys()?
+                .into_iter()
+                .filter(|key| config.access(client, key).is_some())
+                .map(JsonValue::String)
+                .collect(),
+        )),
+        "flush" => {
+            if !config
+                .grants
+                .iter()
+                .any(|grant| grant.client == client && grant.access == IpcAccess::ReadWrite)
+            {
+                return Err(ErrorCode::PermissionDenied);
+            }
+            kvs.flush()?;
+            Ok(JsonValue::Null)
+        }
+        _ => Err(ErrorCode::SerializationFailed),
+    }
+}
+
+/// Write the response to a request
+fn respond(writer: &mut UnixStream, result: Result<JsonValue, ErrorCode>) -> Result<(), ErrorCode> {
+    let response = match result {
+        Ok(value) => ("ok", value),
+        Err(e) => ("error", JsonValue::String(format!("{e:?}"))),
+    };
+    let response =
+        JsonValue::Object(HashMap::from([(response.0.to_string(), response.1)])).stringify()?;
+    writeln!(writer, "{response}")?;
+    Ok(())
+}
+
+/// Answer the requests of a connection until it's closed or a request is too long
+fn serve_client<J: KvsBackend>(
+    kvs: &GenericKvs<J>,
+    config: &IpcConfig,
+    stream: UnixStream,
+) -> Result<(), ErrorCode> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut client = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        // One byte more than the limit tells a line of the maximum length from a longer one
+        let len = (&mut reader)
+            .take(MAX_REQUEST_LEN as u64 + 1)
+            .read_line(&mut line)?;
+        if len == 0 {
+            return Ok(());
+        }
+        if len > MAX_REQUEST_LEN {
+            log_warning!("IPC request exceeds {MAX_REQUEST_LEN} bytes, closing the connection");
+            respond(&mut writer, Err(ErrorCode::SerializationFailed))?;
+            return Err(ErrorCode::SerializationFailed);
+        }
+        let result = match line.trim_end().parse::<JsonValue>() {
+            Ok(JsonValue::Object(request)) => handle(kvs, config, &mut client, &request),
+            Ok(_) => Err(ErrorCode::SerializationFailed),
+            Err(e) => Err(ErrorCode::from(e)),
+        };
+        respond(&mut writer, result)?;
+    }
+}
+
+/// State shared by a server and its threads
+#[derive(Default)]
+struct ServerState {
+    /// Server is being dropped
+    stopped: AtomicBool,
+
+    /// Open connections by ID, shut down when the server is dropped
+    connections: Mutex<HashMap<u64, UnixStream>>,
+
+    /// ID of the next connection
+    next_id: AtomicU64,
+}
+
+/// Server of a KVS instance on a Unix domain socket
+///
+/// Clients are accepted until the server is dropped, which closes all connections and removes
+/// the socket file. See the [module documentation](self) for the protocol.
+pub struct KvsIpcServer {
+    /// Socket path
+    path: PathBuf,
+
+    /// State shared with the worker threads
+    state: Arc<ServerState>,
+
+    /// Thread accepting clients
+    accept: Option<JoinHandle<()>>,
+}
+
+impl KvsIpcServer {
+    /// Serve a KVS instance on a socket
+    ///
+    /// A socket file left by a previous server is replaced. Clients are accepted on the worker
+    /// thread `<name>-ipc`, every connection is served by a worker thread `<name>-ipc-client`.
+    ///
+    /// # Parameters
+    ///   * `kvs`: Served instance
+    ///   * `path`: Socket path
+    ///   * `config`: Clients and their access
+    ///
+    /// # Return Values
+    ///   * Ok: Server started
+    ///   * `ErrorCode::ResourceBusy`: Another server is listening on the socket
+    ///   * `ErrorCode::UnmappedError`: Socket couldn't be bound or thread couldn't be spawned
+    pub fn start<J>(
+        kvs: GenericSharedKvs<J>,
+        path: impl AsRef<Path>,
+        config: IpcConfig,
+    ) -> Result<Self, ErrorCode>
+    where
+        J: KvsBackend + 'static,
+        GenericKvs<J>: Send + Sync,
+    {
+        let path = path.as_ref().to_path_buf();
+        if UnixStream::connect(&path).is_ok() {
+            log_error!("another server is listening on {}", path.display());
+            return Err(ErrorCode::ResourceBusy);
+        }
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+
+        let state = Arc::new(ServerState::default());
+        let config = Arc::new(config);
+        let worker = kvs.worker_config().clone();
+        let accept = {
+            let state = state.clone();
+            let spawner = worker.clone();
+            spawner.spawn("ipc", move || {
+                for stream in listener.incoming() {
+                    if state.stopped.load(Ordering::Acquire) {
+                        break;
+                    }
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let Ok(connection) = stream.try_clone() else {
+                        continue;
+                    };
+                    let id = state.next_id.fetch_add(1, Ordering::Relaxed);
+                    if let Ok(mut connections) = state.connections.lock() {
+                        connections.insert(id, connection);
+                    }
+
+                    let (kvs, config, state) = (kvs.clone(), config.clone(), state.clone());
+                    let spawned = worker.spawn("ipc-client", move || {
+                        if let Err(e) = serve_client(&kvs, &config, stream) {
+                            log_warning!("IPC connection failed: {e:?}");
+                        }
+                        if let Ok(mut connections) = state.connections.lock() {
+                            connections.remove(&id);
+                        }
+                    });
+                    if let Err(e) = spawned {
+                        log_error!("IPC connection can't be served: {e:?}");
+                    }
+                }
+            })?
+        };
+
+        log_info!("serving KVS on {}", path.display());
+        Ok(Self {
+            path,
+            state,
+            accept: Some(accept),
+        })
+    }
+
+    /// Return the socket path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for KvsIpcServer {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::Release);
+        // Wake up the accept loop, it ends on the next connection
+        let _ = UnixStream::connect(&self.path);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        if let Ok(mut connections) = self.state.connections.lock() {
+            for (_, connection) in connections.drain() {
+                let _ = connection.shutdown(Shutdown::Both);
+            }
+        }
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Client of a [`KvsIpcServer`]
+pub struct KvsIpcClient {
+    /// Reading half of the connection
+    reader: BufReader<UnixStream>,
+
+    /// Writing half of the connection
+    writer: UnixStream,
+}
+
+impl KvsIpcClient {
+    /// Connect and authenticate to a server
+    ///
+    /// # Parameters
+    ///   * `path`: Socket path
+    ///   * `client`: Client identity
+    ///   * `token`: Token of the identity
+    ///
+    /// # Return Values
+    ///   * Ok: Authenticated client
+    ///   * `ErrorCode::FileNotFound`: No server at the socket path
+    ///   * `ErrorCode::AuthenticationFailed`: Unknown identity or wrong token
+    pub fn connect(path: impl AsRef<Path>, client: &str, token: &str) -> Result<Self, ErrorCode> {
+        let stream = UnixStream::connect(path)?;
+        let mut ipc = Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        ipc.request(
+            "hello",
+            vec![
+                ("client", JsonValue::String(client.to_string())),
+                ("token", JsonValue::String(token.to_string())),
+            ],
+        )?;
+        Ok(ipc)
+    }
+
+    /// Send a request and return the result of the response
+    fn request(
+        &mut self,
+        op: &str,
+        fields: Vec<(&str, JsonValue)>,
+    ) -> Result<JsonValue, ErrorCode> {
+        let mut request = HashMap::from([("op".to_string(), JsonValue::String(op.to_string()))]);
+        request.extend(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value)),
+        );
+        writeln!(self.writer, "{}", JsonValue::Object(request).stringify()?)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            log_error!("IPC server closed the connection");
+            return Err(ErrorCode::UnmappedError);
+        }
+        let JsonValue::Object(mut response) = line.parse::<JsonValue>()? else {
+            return Err(ErrorCode::SerializationFailed);
+        };
+        match (response.remove("ok"), response.remove("error")) {
+            (Some(value), _) => Ok(value),
+            (None, Some(JsonValue::String(name))) => Err(error_from_name(&name)),
+            _ => Err(ErrorCode::SerializationFailed),
+        }
+    }
+
+    /// Send a request for a key
+    fn key_request(&mut self, op: &str, key: &str) -> Result<JsonValue, ErrorCode> {
+        self.request(op, vec![("key", JsonValue::String(key.to_string()))])
+    }
+
+    /// Get the value of a key, the default value if it isn't stored
+    ///
+    /// # Return Values
+    ///   * Ok: Value
+    ///   * `ErrorCode::KeyNotFound`: Key has no value and no default
+    ///   * `ErrorCode::PermissionDenied`: Key can't be read by the client
+    pub fn get_value(&mut self, key: &str) -> Result<KvsValue, ErrorCode> {
+        self.key_request("get", key).map(KvsValue::from)
+    }
+
+    /// Check if a key is stored
+    ///
+    /// # Return Values
+    ///   * Ok: `true` if the key is stored
+    ///   * `ErrorCode::PermissionDenied`: Key can't be read by the client
+    pub fn key_exists(&mut self, key: &str) -> Result<bool, ErrorCode> {
+        match self.key_request("exists", key)? {
+            JsonValue::Boolean(exists) => Ok(exists),
+            _ => Err(ErrorCode::SerializationFailed),
+        }
+    }
+
+    /// Assign a value to a key
+    ///
+    /// # Return Values
+    ///   * Ok: Value set
+    ///   * `ErrorCode::PermissionDenied`: Key can't be written by the client
+    ///   * Errors of [`set_value`](KvsApi::set_value) of the served instance
+    pub fn set_value<V: Into<KvsValue>>(&mut self, key: &str, value: V) -> Result<(), ErrorCode> {
+        self.request(
+            "set",
+            vec![
+                ("key", JsonValue::String(key.to_string())),
+                ("value", JsonValue::from(value.into())),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Remove a key
+    ///
+    /// # Return Values
+    ///   * Ok: Key removed
+    ///   * `ErrorCode::KeyNotFound`: Key isn't stored
+    ///   * `ErrorCode::PermissionDenied`: Key can't be written by the client
+    pub fn remove_key(&mut self, key: &str) -> Result<(), ErrorCode> {
+        self.key_request("remove", key)?;
+        Ok(())
+    }
+
+    /// Get the stored keys the client can read
+    ///
+    /// # Return Values
+    ///   * Ok: Keys in arbitrary order
+    pub fn get_all_keys(&mut self) -> Result<Vec<String>, ErrorCode> {
+        match self.request("keys", Vec::new())? {
+            JsonValue::Array(keys) => keys
+                .into_iter()
+                .map(|key| match key {
+                    JsonValue::String(key) => Ok(key),
+                    _ => Err(ErrorCode::SerializationFailed),
+                })
+                .collect(),
+            _ => Err(ErrorCode::SerializationFailed),
+        }
+    }
+
+    /// Flush the served instance
+    ///
+    /// # Return Values
+    ///   * Ok: Instance flushed
+    ///   * `ErrorCode::PermissionDenied`: Client has no write access to any namespace
+    pub fn flush(&mut self) -> Result<(), ErrorCode> {
+        self.request("flush", Vec::new())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn grant(client: &str, namespace: &str, access: IpcAccess) -> IpcGrant {
+        IpcGrant {
+            client: client.to_string(),
+            namespace: namespace.to_string(),
+            access,
+        }
+    }
+
+    /// Clients `audio` writing its namespace and `diag` reading all keys and writing its own
+    fn config() -> IpcConfig {
+        IpcConfig {
+            clients: HashMap::from([
+                ("audio".to_string(), "secret".to_string()),
+                ("diag".to_string(), "token".to_string()),
+            ]),
+            grants: vec![
+                grant("audio", "audio", IpcAccess::ReadWrite),
+                grant("diag", "", IpcAccess::Read),
+                grant("diag", "diag", IpcAccess::ReadWrite),
+            ],
+        }
+    }
+
+    fn open(dir: &TempDir) -> SharedKvs {
+        let kvs: SharedKvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .isolated(true)
+            .build_shared()
+            .unwrap();
+        kvs.flush_on_exit(false);
+        kvs
+    }
+
+    fn start(dir: &TempDir, kvs: &SharedKvs) -> (KvsIpcServer, PathBuf) {
+        let socket = dir.path().join("kvs.sock");
+        let server = KvsIpcServer::start(kvs.clone(), &socket, config()).unwrap();
+        (server, socket)
+    }
+
+    fn request(fields: &[(&str, &str)]) -> HashMap<String, JsonValue> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), JsonValue::String(value.to_string())))
+            .collect()
+    }
+
+    #[test]
+    fn test_access_longest_namespace() {
+        let config = config();
+        assert_eq!(
+            config.access("diag", "diag/dtc"),
+            Some(IpcAccess::ReadWrite)
+        );
+        assert_eq!(config.access("diag", "audio/volume"), Some(IpcAccess::Read));
+        assert_eq!(
+            config.access("audio", "audio/volume"),
+            Some(IpcAccess::ReadWrite)
+        );
+        assert_eq!(config.access("audio", "audiovolume"), None);
+        assert_eq!(config.access("other", "audio/volume"), None);
+    }
+
+    #[test]
+    fn test_server_socket_busy() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (server, socket) = start(&dir, &kvs);
+        assert_eq!(server.path(), socket);
+        assert_eq!(
+            KvsIpcServer::start(kvs.clone(), &socket, config()).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+    }
+
+    #[test]
+    fn test_connect_authentication() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        assert_eq!(
+            KvsIpcClient::connect(&socket, "audio", "wrong").err(),
+            Some(ErrorCode::AuthenticationFailed)
+        );
+        assert_eq!(
+            KvsIpcClient::connect(&socket, "other", "secret").err(),
+            Some(ErrorCode::AuthenticationFailed)
+        );
+        assert!(KvsIpcClient::connect(&socket, "audio", "secret").is_ok());
+    }
+
+    #[test]
+    fn test_connect_without_server() {
+        let dir = tempdir().unwrap();
+        assert_eq!(
+            KvsIpcClient::connect(dir.path().join("kvs.sock"), "audio", "secret").err(),
+            Some(ErrorCode::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn test_write_own_namespace() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+
+        audio.set_value("audio/volume", 7u64).unwrap();
+        assert_eq!(kvs.get_value_as::<u64>("audio/volume").unwrap(), 7);
+        assert_eq!(audio.get_value("audio/volume").unwrap(), KvsValue::U64(7));
+        audio.remove_key("audio/volume").unwrap();
+        assert!(!kvs.key_exists("audio/volume").unwrap());
+        assert_eq!(
+            audio.remove_key("audio/volume"),
+            Err(ErrorCode::KeyNotFound)
+        );
+    }
+
+    #[test]
+    fn test_write_outside_namespace_denied() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+        let mut diag = KvsIpcClient::connect(&socket, "diag", "token").unwrap();
+        kvs.set_value("audio/volume", 7u64).unwrap();
+
+        assert_eq!(
+            audio.set_value("audiovolume", 1.0),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            diag.remove_key("audio/volume"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            diag.set_value("audio/volume", 1.0),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert!(!kvs.key_exists("audiovolume").unwrap());
+        assert_eq!(kvs.get_value_as::<u64>("audio/volume").unwrap(), 7);
+    }
+
+    #[test]
+    fn test_read_access() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+        let mut diag = KvsIpcClient::connect(&socket, "diag", "token").unwrap();
+        kvs.set_value("audio/volume", 7u64).unwrap();
+        kvs.set_value("system/mode", "normal".to_string()).unwrap();
+
+        assert_eq!(diag.get_value("audio/volume").unwrap(), KvsValue::U64(7));
+        assert!(diag.key_exists("system/mode").unwrap());
+        assert_eq!(diag.get_value("diag/dtc"), Err(ErrorCode::KeyNotFound));
+        assert!(!diag.key_exists("diag/dtc").unwrap());
+        assert_eq!(
+            audio.get_value("system/mode"),
+            Err(ErrorCode::PermissionDenied)
+        );
+        assert_eq!(
+            audio.key_exists("system/mode"),
+            Err(ErrorCode::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn test_keys_filtered_by_access() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+        let mut diag = KvsIpcClient::connect(&socket, "diag", "token").unwrap();
+        kvs.set_value("audio/volume", 7u64).unwrap();
+        kvs.set_value("system/mode", "normal".to_string()).unwrap();
+        diag.set_value("diag/dtc", vec![KvsValue::from(1.0)])
+            .unwrap();
+
+        assert_eq!(audio.get_all_keys().unwrap(), vec!["audio/volume"]);
+        let mut keys = diag.get_all_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["audio/volume", "diag/dtc", "system/mode"]);
+    }
+
+    #[test]
+    fn test_flush_needs_write_grant() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let mut config = config();
+        config
+            .clients
+            .insert("reader".to_string(), "pass".to_string());
+        config.grants.push(grant("reader", "", IpcAccess::Read));
+        let socket = dir.path().join("kvs.sock");
+        let _server = KvsIpcServer::start(kvs.clone(), &socket, config).unwrap();
+
+        let mut reader = KvsIpcClient::connect(&socket, "reader", "pass").unwrap();
+        assert_eq!(reader.flush(), Err(ErrorCode::PermissionDenied));
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+        audio.set_value("audio/volume", 7u64).unwrap();
+        audio.flush().unwrap();
+        assert!(dir.path().join("kvs_0_0.json").exists());
+    }
+
+    #[test]
+    fn test_request_before_hello() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let config = config();
+        let mut client = None;
+        assert_eq!(
+            handle(
+                &kvs,
+                &config,
+                &mut client,
+                &request(&[("op", "get"), ("key", "audio/volume")])
+            ),
+            Err(ErrorCode::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn test_malformed_requests() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let config = config();
+        let mut client = Some("diag".to_string());
+        for fields in [
+            &[("key", "diag/dtc")][..],
+            &[("op", "unknown")],
+            &[("op", "get")],
+            &[("op", "set"), ("key", "diag/dtc")],
+        ] {
+            assert_eq!(
+                handle(&kvs, &config, &mut client, &request(fields)),
+                Err(ErrorCode::SerializationFailed)
+            );
+        }
+    }
+
+    #[test]
+    fn test_request_too_long() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (_server, socket) = start(&dir, &kvs);
+        let mut stream = UnixStream::connect(&socket).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+        // A request of the maximum length is parsed
+        let request = format!("{}\n", " ".repeat(MAX_REQUEST_LEN - 1));
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response, "{\"error\":\"JsonParserError\"}\n");
+
+        // A longer request closes the connection before authentication
+        stream.write_all(&vec![b'x'; MAX_REQUEST_LEN + 1]).unwrap();
+        response.clear();
+        reader.read_line(&mut response).unwrap();
+        assert_eq!(response, "{\"error\":\"SerializationFailed\"}\n");
+        response.clear();
+        assert_eq!(reader.read_line(&mut response).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_server_drop_closes_connections() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let (server, socket) = start(&dir, &kvs);
+        let mut audio = KvsIpcClient::connect(&socket, "audio", "secret").unwrap();
+        drop(server);
+        assert!(!socket.exists());
+        assert!(audio.get_value("audio/volume").is_err());
+    }
+}
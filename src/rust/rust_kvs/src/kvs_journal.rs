@@ -0,0 +1,409 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Merge journal of cooperating processes
+//!
+//! Two processes, e.g. the main application and an updater, can write the same instance if both
+//! open it with a [`JournalRole`](crate::kvs_api::JournalRole). Every mutation is appended to the
+//! shared journal `kvs_<instance_id>.journal` instead of rewriting the KVS file, so no process
+//! overwrites the changes of another one:
+//!
+//!   * [`JournalRole::Writer`](crate::kvs_api::JournalRole::Writer): `flush` applies the records
+//!     the other processes appended, the KVS file is never written
+//!   * [`JournalRole::Compactor`](crate::kvs_api::JournalRole::Compactor): `flush` applies the
+//!     records of the other processes, writes the merged data to the KVS file and empties the
+//!     journal. Only one process may be the compactor.
+//!
+//! Appends are serialized with an advisory lock on the journal file, so the order of the records
+//! in the file is their sequence. File locking isn't supported on QNX: the journal is used without
+//! the lock and a warning is logged once, so only one process may write it at a time there.
+//! Records are applied in that order, the last writer of a key wins, and every process ends up
+//! with the same data. Each compaction starts a new epoch, which is recorded in the first line of
+//! the journal. A process that sees a new epoch reloads the KVS file before applying the journal.
+//!
+//! Lines use the format of the write-ahead log. The journal can't be combined with the
+//! write-ahead log or encryption.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::{log_error, log_warning};
+use crate::kvs_platform::{lock_file, lock_unsupported};
+use crate::kvs_value::KvsMap;
+use crate::kvs_wal::{apply_record, decode_line, encode_line, encode_record, WalRecord};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Mutex, Once};
+use tinyjson::JsonValue;
+
+/// Operation of the epoch line
+const EPOCH_OP: &str = "epoch";
+
+/// Warning about missing file lock support, logged once per process
+static LOCK_UNSUPPORTED: Once = Once::new();
+
+/// Position of a process in the journal
+#[derive(Default)]
+struct ReadState {
+    /// Epoch of the journal when it was last read
+    epoch: u64,
+
+    /// Length of the journal that was read
+    offset: usize,
+}
+
+/// Locked journal file, unlocked on drop
+pub(crate) struct JournalLock {
+    file: File,
+}
+
+/// Records read from the journal
+pub(crate) struct JournalRead {
+    /// The journal was compacted since it was last read, the KVS file must be reloaded
+    pub(crate) compacted: bool,
+
+    /// Records not read before, in journal order
+    records: Vec<HashMap<String, JsonValue>>,
+}
+
+impl JournalRead {
+    /// Return if nothing changed since the journal was last read
+    pub(crate) fn is_empty(&self) -> bool {
+        !self.compacted && self.records.is_empty()
+    }
+
+    /// Apply the records in journal order
+    ///
+    /// # Return Values
+    ///   * Count of applied records, malformed records are skipped
+    pub(crate) fn apply(&self, map: &mut KvsMap) -> usize {
+        self.records
+            .iter()
+            .filter(|record| apply_record(record, map).is_some())
+            .count()
+    }
+}
+
+/// Merge journal of a KVS instance
+pub(crate) struct MergeJournal {
+    /// Journal filename
+    path: PathBuf,
+
+    /// The process merges the journal into the KVS file
+    compactor: bool,
+
+    /// Position of this process
+    state: Mutex<ReadState>,
+}
+
+/// Return the epoch of a journal line, `None` if it's a record
+fn line_epoch(record: &HashMap<String, JsonValue>) -> Option<u64> {
+    let op: &String = record.get("op")?.get()?;
+    if op != EPOCH_OP {
+        return None;
+    }
+    record
+        .get(EPOCH_OP)?
+        .get::<f64>()
+        .map(|epoch| *epoch as u64)
+}
+
+impl MergeJournal {
+    /// Create the journal handle
+    ///
+    /// # Parameters
+    ///   * `path`: Journal filename
+    ///   * `compactor`: The process merges the journal into the KVS file
+    pub(crate) fn new(path: PathBuf, compactor: bool) -> Self {
+        Self {
+            path,
+            compactor,
+            state: Mutex::new(ReadState::default()),
+        }
+    }
+
+    /// Return if the process merges the journal into the KVS file
+    pub(crate) fn is_compactor(&self) -> bool {
+        self.compactor
+    }
+
+    /// Open and lock the journal file, blocks while another process holds a conflicting lock
+    ///
+    /// Without file lock support the journal is returned unlocked.
+    ///
+    /// # Parameters
+    ///   * `exclusive`: Lock for appending or compacting instead of reading
+    ///
+    /// # Return Values
+    ///   * Ok: Locked journal
+    ///   * `ErrorCode::PhysicalStorageFailure`: Journal couldn't be opened or locked
+    pub(crate) fn lock(&self, exclusive: bool) -> Result<JournalLock, ErrorCode> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&self.path)
+            .map_err(|e| {
+                log_error!("journal {:?} could not be opened: {e}", self.path);
+                ErrorCode::PhysicalStorageFailure
+            })?;
+        match lock_file(&file, !exclusive, true) {
+            Ok(()) => (),
+            Err(e) if lock_unsupported(&e) => LOCK_UNSUPPORTED.call_once(|| {
+                log_warning!("file locking isn't supported, the journal is used unlocked");
+            }),
+            Err(e) => {
+                log_error!("journal {:?} could not be locked: {e}", self.path);
+                return Err(ErrorCode::PhysicalStorageFailure);
+            }
+        }
+        Ok(JournalLock { file })
+    }
+
+    /// Append a record
+    ///
+    /// # Return Values
+    ///   * Ok: Record appended and synced
+    ///   * `ErrorCode::JsonGeneratorError`: Record couldn't be serialized
+    ///   * `ErrorCode::PhysicalStorageFailure`: Record couldn't be written
+    pub(crate) fn append(&self, record: &WalRecord) -> Result<(), ErrorCode> {
+        self.append_locked(&mut self.lock(true)?, record)
+    }
+
+    /// Append a record while the exclusive lock of the journal is held
+    ///
+    /// # Parameters
+    ///   * `lock`: Exclusive lock of the journal
+    ///   * `record`: Record to append
+    ///
+    /// # Return Values
+    ///   * Ok: Record appended and synced
+    ///   * `ErrorCode::JsonGeneratorError`: Record couldn't be serialized
+    ///   * `ErrorCode::PhysicalStorageFailure`: Record couldn't be written
+    pub(crate) fn append_locked(
+        &self,
+        lock: &mut JournalLock,
+        record: &WalRecord,
+    ) -> Result<(), ErrorCode> {
+        let line = encode_line(&encode_record(record))?;
+        lock.file
+            .write_all(line.as_bytes())
+            .and_then(|_| lock.file.sync_data())
+            .map_err(|_| ErrorCode::PhysicalStorageFailure)
+    }
+
+    /// Read the records appended since the journal was last read
+    ///
+    /// A torn line of a crashed process ends the journal, it's dropped on the next compaction.
+    ///
+    /// # Parameters
+    ///   * `lock`: Lock of the journal
+    ///
+    /// # Return Values
+    ///   * Ok: New records
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Journal couldn't be read
+    pub(crate) fn read(&self, lock: &mut JournalLock) -> Result<JournalRead, ErrorCode> {
+        let mut data = Vec::new();
+        lock.file
+            .read_to_end(&mut data)
+            .map_err(|_| ErrorCode::PhysicalStorageFailure)?;
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+
+        let mut epoch = 0;
+        let mut lines = Vec::new();
+        let mut end = 0;
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            let Some(record) = line
+                .strip_suffix(b"\n")
+                .and_then(|line| std::str::from_utf8(line).ok())
+                .and_then(decode_line)
+            else {
+                break;
+            };
+            if end == 0 {
+                if let Some(line_epoch) = line_epoch(&record) {
+                    epoch = line_epoch;
+                    end += line.len();
+                    continue;
+                }
+            }
+            lines.push((end, record));
+            end += line.len();
+        }
+
+        let compacted = epoch != state.epoch;
+        let start = if compacted { 0 } else { state.offset };
+        state.epoch = epoch;
+        state.offset = end;
+        Ok(JournalRead {
+            compacted,
+            records: lines
+                .into_iter()
+                .filter(|(pos, _)| *pos >= start)
+                .map(|(_, record)| record)
+                .collect(),
+        })
+    }
+
+    /// Empty the journal after it was merged into the KVS file and start a new epoch
+    ///
+    /// # Parameters
+    ///   * `lock`: Exclusive lock of the journal
+    ///
+    /// # Return Values
+    ///   * Ok: Journal emptied
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Journal couldn't be written
+    pub(crate) fn reset(&self, lock: &mut JournalLock) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        let epoch = state.epoch + 1;
+        let header = encode_line(&JsonValue::Object(HashMap::from([
+            ("op".to_string(), JsonValue::String(EPOCH_OP.to_string())),
+            (EPOCH_OP.to_string(), JsonValue::Number(epoch as f64)),
+        ])))?;
+        lock.file
+            .set_len(0)
+            .and_then(|_| lock.file.write_all(header.as_bytes()))
+            .and_then(|_| lock.file.sync_data())
+            .map_err(|_| ErrorCode::PhysicalStorageFailure)?;
+        state.epoch = epoch;
+        state.offset = header.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kvs_value::KvsValue;
+    use tempfile::{tempdir, TempDir};
+
+    fn read(journal: &MergeJournal) -> JournalRead {
+        journal.read(&mut journal.lock(false).unwrap()).unwrap()
+    }
+
+    /// Compactor and writer sharing a journal
+    fn journals(dir: &TempDir) -> (MergeJournal, MergeJournal) {
+        let path = dir.path().join("kvs_0.journal");
+        (
+            MergeJournal::new(path.clone(), true),
+            MergeJournal::new(path, false),
+        )
+    }
+
+    #[test]
+    fn test_last_writer_wins() {
+        let dir = tempdir().unwrap();
+        let (compactor, writer) = journals(&dir);
+        compactor
+            .append(&WalRecord::Set("a", &KvsValue::from(1.0)))
+            .unwrap();
+        writer
+            .append(&WalRecord::Set("a", &KvsValue::from(2.0)))
+            .unwrap();
+        writer.append(&WalRecord::Remove("b")).unwrap();
+
+        // Both processes merge to the same data
+        for journal in [&compactor, &writer] {
+            let mut map = KvsMap::from([("b".to_string(), KvsValue::Null)]);
+            assert_eq!(read(journal).apply(&mut map), 3);
+            assert_eq!(map, KvsMap::from([("a".to_string(), KvsValue::from(2.0))]));
+        }
+    }
+
+    #[test]
+    fn test_only_new_records_read() {
+        let dir = tempdir().unwrap();
+        let (compactor, writer) = journals(&dir);
+        assert!(read(&compactor).is_empty());
+        writer.append(&WalRecord::Remove("a")).unwrap();
+        assert_eq!(read(&compactor).records.len(), 1);
+        assert!(read(&compactor).is_empty());
+        writer.append(&WalRecord::Remove("b")).unwrap();
+        assert_eq!(read(&compactor).records.len(), 1);
+    }
+
+    #[test]
+    fn test_compaction_starts_epoch() {
+        let dir = tempdir().unwrap();
+        let (compactor, writer) = journals(&dir);
+        writer.append(&WalRecord::Remove("a")).unwrap();
+        read(&compactor);
+        read(&writer);
+
+        let mut lock = compactor.lock(true).unwrap();
+        compactor.reset(&mut lock).unwrap();
+        drop(lock);
+        writer.append(&WalRecord::Remove("b")).unwrap();
+
+        // The writer sees the compaction, the compactor only the new record
+        let update = read(&writer);
+        assert!(update.compacted);
+        assert!(!update.is_empty());
+        assert_eq!(update.records.len(), 1);
+        let update = read(&compactor);
+        assert!(!update.compacted);
+        assert_eq!(update.records.len(), 1);
+    }
+
+    #[test]
+    fn test_torn_line_ends_journal() {
+        let dir = tempdir().unwrap();
+        let (compactor, writer) = journals(&dir);
+        writer.append(&WalRecord::Remove("a")).unwrap();
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("kvs_0.journal"))
+            .unwrap();
+        file.write_all(br#"{"op":"remove","ke"#).unwrap();
+
+        let mut map = KvsMap::from([("a".to_string(), KvsValue::Null)]);
+        assert_eq!(read(&compactor).apply(&mut map), 1);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_record_skipped() {
+        let dir = tempdir().unwrap();
+        let (compactor, writer) = journals(&dir);
+        let unknown = JsonValue::Object(HashMap::from([(
+            "op".to_string(),
+            JsonValue::String("unknown".to_string()),
+        )]));
+        fs::write(
+            dir.path().join("kvs_0.journal"),
+            encode_line(&unknown).unwrap(),
+        )
+        .unwrap();
+        writer.append(&WalRecord::Remove("a")).unwrap();
+
+        let mut map = KvsMap::from([("a".to_string(), KvsValue::Null)]);
+        let update = read(&compactor);
+        assert_eq!(update.records.len(), 2);
+        assert_eq!(update.apply(&mut map), 1);
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let dir = tempdir().unwrap();
+        let journal = MergeJournal::new(dir.path().join("missing").join("kvs_0.journal"), true);
+        assert_eq!(
+            journal.lock(false).err(),
+            Some(ErrorCode::PhysicalStorageFailure)
+        );
+        assert_eq!(
+            journal.append(&WalRecord::Remove("a")),
+            Err(ErrorCode::PhysicalStorageFailure)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Multi-process locking
+//!
+//! With file locking enabled an instance locks `kvs_<instance_id>.lock` for as long as it's open,
+//! so two processes can't flush the same instance and overwrite each other's files. A writable
+//! instance takes an exclusive lock, a [`OpenMode::ReadOnly`](crate::kvs_api::OpenMode) instance
+//! a shared lock, so any count of readers or a single writer can open an instance. Opening never
+//! waits, a conflicting lock fails the open with `ErrorCode::ResourceBusy`.
+//!
+//! The locks are advisory (`flock` on POSIX, `LockFileEx` on Windows) and only exclude other
+//! KVS instances with file locking enabled. File locking isn't supported on QNX: the instance is
+//! opened without the lock and a warning is logged, so only a single process may use the
+//! instance there. The locks are tied to the open lock file, so two instances within one process
+//! exclude each other as well, and they are released by the OS when the process dies.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::{log_error, log_warning};
+use crate::kvs_platform::{lock_file, lock_unsupported};
+use std::fs::{self, File, TryLockError};
+use std::path::Path;
+
+/// Lock on the lock file of an instance, released on drop
+pub(crate) struct ProcessLock {
+    /// Locked lock file
+    _file: File,
+}
+
+impl ProcessLock {
+    /// Lock the lock file of an instance without waiting
+    ///
+    /// Without file lock support the lock file is opened but not locked.
+    ///
+    /// # Parameters
+    ///   * `path`: Lock file, created if missing
+    ///   * `shared`: Take a shared lock instead of an exclusive one
+    ///
+    /// # Return Values
+    ///   * Ok: Lock held until the returned value is dropped
+    ///   * `ErrorCode::ResourceBusy`: Instance is locked by another process or instance
+    ///   * `ErrorCode::PhysicalStorageFailure`: Lock file couldn't be opened or locked
+    pub(crate) fn acquire(path: &Path, shared: bool) -> Result<Self, ErrorCode> {
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(|e| {
+                log_error!("lock file {path:?} could not be opened: {e}");
+                ErrorCode::PhysicalStorageFailure
+            })?;
+        match lock_file(&file, shared, false) {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(e) if lock_unsupported(&e) => {
+                log_warning!("file locking isn't supported, {path:?} isn't locked");
+                Ok(Self { _file: file })
+            }
+            Err(TryLockError::WouldBlock) => {
+                log_error!("KVS {path:?} is locked by another instance");
+                Err(ErrorCode::ResourceBusy)
+            }
+            Err(TryLockError::Error(e)) => {
+                log_error!("lock file {path:?} could not be locked: {e}");
+                Err(ErrorCode::PhysicalStorageFailure)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_exclusive_lock_excludes_all() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.lock");
+        let _writer = ProcessLock::acquire(&path, false).unwrap();
+        assert_eq!(
+            ProcessLock::acquire(&path, false).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+        assert_eq!(
+            ProcessLock::acquire(&path, true).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+    }
+
+    #[test]
+    fn test_shared_locks_exclude_writer() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.lock");
+        let _reader = ProcessLock::acquire(&path, true).unwrap();
+        let _second_reader = ProcessLock::acquire(&path, true).unwrap();
+        assert_eq!(
+            ProcessLock::acquire(&path, false).err(),
+            Some(ErrorCode::ResourceBusy)
+        );
+    }
+
+    #[test]
+    fn test_lock_released_on_drop() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.lock");
+        drop(ProcessLock::acquire(&path, false).unwrap());
+        let reader = ProcessLock::acquire(&path, true).unwrap();
+        drop(reader);
+        ProcessLock::acquire(&path, false).unwrap();
+    }
+
+    #[test]
+    fn test_lock_file_kept() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.lock");
+        fs::write(&path, "owner").unwrap();
+        drop(ProcessLock::acquire(&path, false).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "owner");
+    }
+
+    #[test]
+    fn test_missing_directory() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("kvs_0.lock");
+        assert_eq!(
+            ProcessLock::acquire(&path, false).err(),
+            Some(ErrorCode::PhysicalStorageFailure)
+        );
+    }
+}
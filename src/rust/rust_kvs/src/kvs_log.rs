@@ -0,0 +1,247 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Logging facade
+//!
+//! All diagnostics of the crate go through this facade. Without a callback errors and warnings
+//! are printed to stderr with an `error:` or `warning:` prefix and infos to stdout, debug
+//! messages are dropped. Integrators route the messages into their logging framework, e.g. Score
+//! logging, with [`set_log_callback`].
+//!
+//! Opening, flushing, snapshot creation and restores log a debug message with their duration.
+//!
+//! The callback is process-wide and shared by all KVS instances. It's called on the thread that
+//! logs, possibly with KVS locks held, so it must not call back into a KVS.
+
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+/// Severity of a log message, ordered from most to least severe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    /// Failed operation
+    Error,
+
+    /// Unexpected condition the operation recovered from
+    Warning,
+
+    /// Progress of an operation, e.g. opened instances
+    Info,
+
+    /// Operation details and durations
+    Debug,
+}
+
+/// Log callback receiving the level and the message without level prefix
+pub type LogCallback = Arc<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+/// Installed callback and the least severe level passed to it
+static LOGGER: RwLock<Option<(LogLevel, LogCallback)>> = RwLock::new(None);
+
+/// Route the log messages of all KVS instances to a callback
+///
+/// Replaces a previously installed callback.
+///
+/// # Parameters
+///   * `level`: Least severe level passed to the callback, e.g. `LogLevel::Warning` drops infos
+///     and debug messages
+///   * `callback`: Callback receiving the level and message
+pub fn set_log_callback<F>(level: LogLevel, callback: F)
+where
+    F: Fn(LogLevel, &str) + Send + Sync + 'static,
+{
+    let mut logger = LOGGER
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *logger = Some((level, Arc::new(callback)));
+}
+
+/// Remove the log callback, messages are printed again
+pub fn clear_log_callback() {
+    let mut logger = LOGGER
+        .write()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *logger = None;
+}
+
+/// Dispatch a log message to the callback or print it
+pub(crate) fn log(level: LogLevel, args: fmt::Arguments) {
+    let callback = LOGGER
+        .read()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clone();
+    match (callback, level) {
+        (Some((max_level, callback)), _) => {
+            if level <= max_level {
+                callback(level, &args.to_string());
+            }
+        }
+        (None, LogLevel::Error) => eprintln!("error: {args}"),
+        (None, LogLevel::Warning) => eprintln!("warning: {args}"),
+        (None, LogLevel::Info) => println!("{args}"),
+        (None, LogLevel::Debug) => {}
+    }
+}
+
+/// Logs the duration of an operation as debug message when dropped
+pub(crate) struct OpTimer<'a> {
+    /// Operation name, e.g. `flush`
+    operation: &'static str,
+
+    /// Filename prefix of the instance
+    instance: &'a Path,
+
+    /// Start of the operation
+    start: Instant,
+}
+
+impl<'a> OpTimer<'a> {
+    /// Start timing an operation
+    pub(crate) fn start(operation: &'static str, instance: &'a Path) -> Self {
+        Self {
+            operation,
+            instance,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for OpTimer<'_> {
+    fn drop(&mut self) {
+        log(
+            LogLevel::Debug,
+            format_args!(
+                "{} of {:?} took {:?}",
+                self.operation,
+                self.instance,
+                self.start.elapsed()
+            ),
+        );
+    }
+}
+
+/// Log an error message
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::kvs_log::log($crate::kvs_log::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+/// Log a warning message
+macro_rules! log_warning {
+    ($($arg:tt)*) => {
+        $crate::kvs_log::log($crate::kvs_log::LogLevel::Warning, format_args!($($arg)*))
+    };
+}
+
+/// Log an info message
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::kvs_log::log($crate::kvs_log::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+/// Log a debug message
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::kvs_log::log($crate::kvs_log::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {log_debug, log_error, log_info, log_warning};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Serializes the tests, the callback is process-wide
+    static CALLBACK: Mutex<()> = Mutex::new(());
+
+    /// Install a callback collecting the messages starting with a prefix
+    ///
+    /// Other tests log concurrently through the same callback, their messages are ignored.
+    fn collect(level: LogLevel, prefix: &'static str) -> Arc<Mutex<Vec<(LogLevel, String)>>> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_cb = seen.clone();
+        set_log_callback(level, move |level, msg| {
+            if msg.starts_with(prefix) {
+                seen_cb.lock().unwrap().push((level, msg.to_string()));
+            }
+        });
+        seen
+    }
+
+    #[test]
+    fn test_log_callback_levels() {
+        let _guard = CALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+        let seen = collect(LogLevel::Info, "levels test");
+        log_error!("levels test {}", 1);
+        log_warning!("levels test {}", 2);
+        log_info!("levels test {}", 3);
+        log_debug!("levels test {}", 4);
+        clear_log_callback();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                (LogLevel::Error, "levels test 1".to_string()),
+                (LogLevel::Warning, "levels test 2".to_string()),
+                (LogLevel::Info, "levels test 3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_callback_errors_only() {
+        let _guard = CALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+        let seen = collect(LogLevel::Error, "errors test");
+        log_warning!("errors test warning");
+        log_error!("errors test error");
+        clear_log_callback();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![(LogLevel::Error, "errors test error".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_log_callback_replaced_and_cleared() {
+        let _guard = CALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+        let first = collect(LogLevel::Debug, "replace test");
+        let second = collect(LogLevel::Debug, "replace test");
+        log_error!("replace test 1");
+        clear_log_callback();
+        log_error!("replace test 2");
+
+        assert!(first.lock().unwrap().is_empty());
+        assert_eq!(
+            *second.lock().unwrap(),
+            vec![(LogLevel::Error, "replace test 1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_op_timer_logs_duration() {
+        let _guard = CALLBACK.lock().unwrap_or_else(|e| e.into_inner());
+        let seen = collect(LogLevel::Debug, "timer test");
+        drop(OpTimer::start("timer test", Path::new("kvs_7")));
+        clear_log_callback();
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].0, LogLevel::Debug);
+        assert!(seen[0].1.starts_with(r#"timer test of "kvs_7" took "#));
+    }
+}
@@ -0,0 +1,267 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-key change metadata
+//!
+//! Audits need to know when and by whom a value, e.g. a calibration value, was changed. With
+//! [`KvsOptions::key_metadata`](crate::kvs_api::KvsOptions::key_metadata) enabled every change of
+//! a key, by a set, removal, transaction or restore, records the time and the writer ID
+//! configured with [`KvsBuilder::writer_id`](crate::kvs_builder::KvsBuilder::writer_id). The
+//! metadata is read with [`get_key_metadata`](crate::kvs::GenericKvs::get_key_metadata).
+//!
+//! The metadata is written to `kvs_<instance_id>_metadata.json` on flushes after a change, merged
+//! with the metadata another process flushed to the same file, the newer change of a key wins. It isn't
+//! part of the snapshots: a restore records a change for every key whose value it changes and
+//! keeps the metadata of the other keys.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Durability;
+use crate::kvs_log::log_error;
+use crate::kvs_platform::atomic_replace;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tinyjson::JsonValue;
+
+/// Last change of a key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMetadata {
+    /// Time of the last change, millisecond resolution once flushed
+    pub modified: SystemTime,
+
+    /// Writer ID of the instance that changed the key, `None` if none was configured
+    pub writer: Option<String>,
+}
+
+/// Change metadata of a KVS instance
+pub(crate) struct KeyMetadataStore {
+    /// Metadata file, `None` if the metadata isn't persisted
+    path: Option<PathBuf>,
+
+    /// Synchronization of the written file
+    durability: Durability,
+
+    /// Writer ID recorded with the changes of this instance
+    writer: Option<String>,
+
+    /// Metadata by key
+    entries: Mutex<HashMap<String, KeyMetadata>>,
+
+    /// A change was recorded since the metadata was written
+    changed: AtomicBool,
+}
+
+/// Parse a metadata file of `{"modified": <ms since epoch>, "writer": <id>}` objects
+///
+/// Malformed entries are skipped.
+fn parse_metadata(data: &str) -> Result<HashMap<String, KeyMetadata>, ErrorCode> {
+    let json: JsonValue = data.parse()?;
+    let obj: &HashMap<String, JsonValue> = json.get().ok_or(ErrorCode::JsonParserError)?;
+    Ok(obj
+        .iter()
+        .filter_map(|(key, entry)| {
+            let entry: &HashMap<String, JsonValue> = entry.get()?;
+            let millis = *entry.get("modified")?.get::<f64>()? as u64;
+            let metadata = KeyMetadata {
+                modified: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+                writer: entry
+                    .get("writer")
+                    .and_then(|writer| writer.get::<String>())
+                    .cloned(),
+            };
+            Some((key.clone(), metadata))
+        })
+        .collect())
+}
+
+/// Return the milliseconds since the Unix epoch
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl KeyMetadataStore {
+    /// Create the store
+    ///
+    /// # Parameters
+    ///   * `path`: Metadata file to load and persist to, `None` to keep the metadata in memory
+    ///   * `durability`: Synchronization of the written file
+    ///   * `writer`: Writer ID recorded with the changes
+    pub(crate) fn new(
+        path: Option<PathBuf>,
+        durability: Durability,
+        writer: Option<String>,
+    ) -> Self {
+        let entries = Self::load(path.as_ref());
+        Self {
+            path,
+            durability,
+            writer,
+            entries: Mutex::new(entries),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Read a metadata file, a missing or corrupt file has no metadata
+    fn load(path: Option<&PathBuf>) -> HashMap<String, KeyMetadata> {
+        path.and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| match parse_metadata(&data) {
+                Ok(entries) => Some(entries),
+                Err(e) => {
+                    log_error!("key metadata could not be parsed, starting empty: {e:?}");
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    /// Record a change of a key by this instance
+    pub(crate) fn touch(&self, key: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                key.to_string(),
+                KeyMetadata {
+                    modified: SystemTime::now(),
+                    writer: self.writer.clone(),
+                },
+            );
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Return the metadata of a key
+    ///
+    /// # Return Values
+    ///   * Ok: Metadata, `None` if no change of the key was recorded
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn get(&self, key: &str) -> Result<Option<KeyMetadata>, ErrorCode> {
+        let entries = self
+            .entries
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        Ok(entries.get(key).cloned())
+    }
+
+    /// Merge the metadata with the metadata file and write it
+    ///
+    /// No-op if the metadata isn't persisted or no change was recorded since it was written.
+    pub(crate) fn save(&self) -> Result<(), ErrorCode> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        if !self.changed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        for (key, flushed) in Self::load(Some(path)) {
+            match entries.get(&key) {
+                Some(entry) if entry.modified >= flushed.modified => {}
+                _ => {
+                    entries.insert(key, flushed);
+                }
+            }
+        }
+        let json = JsonValue::Object(
+            entries
+                .iter()
+                .map(|(key, metadata)| {
+                    let mut entry = HashMap::from([(
+                        "modified".to_string(),
+                        JsonValue::Number(unix_millis(metadata.modified) as f64),
+                    )]);
+                    if let Some(writer) = &metadata.writer {
+                        entry.insert("writer".to_string(), JsonValue::String(writer.clone()));
+                    }
+                    (key.clone(), JsonValue::Object(entry))
+                })
+                .collect(),
+        );
+        atomic_replace(path, json.stringify()?.as_bytes(), self.durability)?;
+        self.changed.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_metadata_merged_with_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_metadata.json");
+
+        let first = KeyMetadataStore::new(
+            Some(path.clone()),
+            Durability::default(),
+            Some("flasher".to_string()),
+        );
+        let second = KeyMetadataStore::new(Some(path.clone()), Durability::default(), None);
+        first.touch("a");
+        first.touch("b");
+        std::thread::sleep(Duration::from_millis(2));
+        second.touch("a");
+        assert_eq!(first.get("c").unwrap(), None);
+
+        // The newer change of another instance wins
+        second.save().unwrap();
+        first.save().unwrap();
+        let loaded = KeyMetadataStore::new(Some(path.clone()), Durability::default(), None);
+        assert_eq!(loaded.get("a").unwrap().unwrap().writer, None);
+        let b = loaded.get("b").unwrap().unwrap();
+        assert_eq!(b.writer.as_deref(), Some("flasher"));
+        assert_eq!(
+            unix_millis(b.modified),
+            unix_millis(first.get("b").unwrap().unwrap().modified)
+        );
+
+        // Corrupt files are no error
+        fs::write(&path, "{").unwrap();
+        assert_eq!(
+            KeyMetadataStore::new(Some(path), Durability::default(), None)
+                .get("a")
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unchanged_metadata_not_written() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_metadata.json");
+        let store = KeyMetadataStore::new(Some(path.clone()), Durability::default(), None);
+
+        store.save().unwrap();
+        assert!(!path.exists());
+        store.touch("a");
+        store.save().unwrap();
+        let written = fs::read_to_string(&path).unwrap();
+
+        // Without a new change the file isn't rewritten
+        fs::write(&path, "{}").unwrap();
+        store.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+        store.touch("b");
+        store.save().unwrap();
+        assert_ne!(fs::read_to_string(&path).unwrap(), written);
+        let loaded = KeyMetadataStore::new(Some(path), Durability::default(), None);
+        assert!(loaded.get("a").unwrap().is_some());
+        assert!(loaded.get("b").unwrap().is_some());
+    }
+}
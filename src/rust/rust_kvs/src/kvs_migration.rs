@@ -0,0 +1,261 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Schema migrations of the stored data
+//!
+//! The KVS file carries the schema version of its data in the reserved member `"$version"`, a
+//! file without it has version 0. A migration transforms the data from one version to a higher
+//! one, e.g. renames keys or restructures values. Migrations registered with
+//! [`KvsBuilder::migration`](crate::kvs_builder::KvsBuilder::migration) are applied on open,
+//! starting at the stored version and following the chain of registered versions.
+//! [`register_migration`](crate::kvs::GenericKvs::register_migration) registers and applies a
+//! migration on an open instance.
+//!
+//! Migrated data is flushed right away together with its new version, so log records written
+//! afterwards never meet the old version. A failing migration leaves the data unchanged and
+//! fails the open. Snapshots keep the version they were written with and are migrated when
+//! they're restored. The member `"$version"` can't be used as key.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_error;
+use crate::kvs_value::{KvsMap, KvsValue};
+use core::fmt;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Member of the KVS file holding the schema version
+pub(crate) const VERSION_KEY: &str = "$version";
+
+/// Migration
+///
+/// Transforms the data in place or returns `Err` with a reason if it can't be migrated. It's
+/// called with the KVS data locked and must not call back into the KVS.
+pub type KvsMigration = Arc<dyn Fn(&mut KvsMap) -> Result<(), String> + Send + Sync>;
+
+/// Registered migrations
+#[derive(Clone, Default)]
+pub struct Migrations {
+    /// Migrations as `(from, to, migration)`, at most one per source version
+    steps: Vec<(u64, u64, KvsMigration)>,
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.steps.iter().map(|(from, to, _)| (from, to)))
+            .finish()
+    }
+}
+
+impl Migrations {
+    /// Register a migration, replacing one with the same source version
+    pub(crate) fn register(&mut self, from: u64, to: u64, migration: KvsMigration) {
+        self.steps.retain(|(source, _, _)| *source != from);
+        self.steps.push((from, to, migration));
+    }
+
+    /// Apply the chain of migrations starting at a version
+    ///
+    /// # Return Values
+    ///   * Ok: Migrated data and its version, `None` if no migration starts at `version`
+    ///   * `ErrorCode::ValidationFailed`: A migration failed or doesn't raise the version
+    pub(crate) fn apply(
+        &self,
+        data: &KvsMap,
+        mut version: u64,
+    ) -> Result<Option<(KvsMap, u64)>, ErrorCode> {
+        let mut migrated: Option<KvsMap> = None;
+        while let Some((from, to, migration)) =
+            self.steps.iter().find(|(from, _, _)| *from == version)
+        {
+            if to <= from {
+                log_error!("migration from version {from} to {to} doesn't raise the version");
+                return Err(ErrorCode::ValidationFailed);
+            }
+            let data = migrated.get_or_insert_with(|| data.clone());
+            migration(data).map_err(|reason| {
+                log_error!("migration from version {from} to {to} failed: {reason}");
+                ErrorCode::ValidationFailed
+            })?;
+            version = *to;
+        }
+        Ok(migrated.map(|data| (data, version)))
+    }
+}
+
+/// Remove the schema version from loaded data
+///
+/// # Return Values
+///   * Ok: Schema version, 0 if the data has none
+///   * `ErrorCode::JsonParserError`: Version isn't an unsigned integer
+pub(crate) fn take_version(data: &mut KvsMap) -> Result<u64, ErrorCode> {
+    match data.remove(VERSION_KEY) {
+        None => Ok(0),
+        Some(KvsValue::U64(version)) => Ok(version),
+        Some(KvsValue::I64(version)) => u64::try_from(version).map_err(|_| {
+            log_error!("invalid schema version: {version}");
+            ErrorCode::JsonParserError
+        }),
+        Some(KvsValue::Number(version)) if version >= 0.0 && version.fract() == 0.0 => {
+            Ok(version as u64)
+        }
+        Some(version) => {
+            log_error!("invalid schema version: {version:?}");
+            Err(ErrorCode::JsonParserError)
+        }
+    }
+}
+
+/// Return the data to persist with its schema version, version 0 isn't written
+pub(crate) fn with_version(data: &KvsMap, version: u64) -> Cow<'_, KvsMap> {
+    if version == 0 {
+        return Cow::Borrowed(data);
+    }
+    let mut data = data.clone();
+    data.insert(VERSION_KEY.to_string(), KvsValue::U64(version));
+    Cow::Owned(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Migrations renaming `volume` (0 to 1) and adding `audio/muted` (1 to 3)
+    fn audio_migrations() -> Migrations {
+        let mut migrations = Migrations::default();
+        migrations.register(
+            0,
+            1,
+            Arc::new(|data| {
+                let value = data.remove("volume").ok_or("volume missing")?;
+                data.insert("audio/volume".to_string(), value);
+                Ok(())
+            }),
+        );
+        migrations.register(
+            1,
+            3,
+            Arc::new(|data| {
+                data.insert("audio/muted".to_string(), KvsValue::Boolean(false));
+                Ok(())
+            }),
+        );
+        migrations
+    }
+
+    #[test]
+    fn test_migration_chain() {
+        let data = KvsMap::from([("volume".to_string(), KvsValue::from(7.0))]);
+        let (migrated, version) = audio_migrations().apply(&data, 0).unwrap().unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(
+            migrated,
+            KvsMap::from([
+                ("audio/volume".to_string(), KvsValue::from(7.0)),
+                ("audio/muted".to_string(), KvsValue::Boolean(false)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_migration_chain_starts_at_version() {
+        let (migrated, version) = audio_migrations()
+            .apply(&KvsMap::new(), 1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(version, 3);
+        assert_eq!(
+            migrated,
+            KvsMap::from([("audio/muted".to_string(), KvsValue::Boolean(false))])
+        );
+    }
+
+    #[test]
+    fn test_no_migration_at_version() {
+        assert!(audio_migrations()
+            .apply(&KvsMap::new(), 3)
+            .unwrap()
+            .is_none());
+        assert!(Migrations::default()
+            .apply(&KvsMap::new(), 0)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_failing_migration() {
+        assert_eq!(
+            audio_migrations().apply(&KvsMap::new(), 0),
+            Err(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_migration_not_raising_version() {
+        let mut migrations = audio_migrations();
+        migrations.register(5, 5, Arc::new(|_| Ok(())));
+        migrations.register(6, 4, Arc::new(|_| Ok(())));
+        assert_eq!(
+            migrations.apply(&KvsMap::new(), 5),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            migrations.apply(&KvsMap::new(), 6),
+            Err(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_migration_replaced() {
+        let mut migrations = audio_migrations();
+        migrations.register(0, 2, Arc::new(|_| Ok(())));
+        assert_eq!(format!("{migrations:?}"), "[(1, 3), (0, 2)]");
+        let (_, version) = migrations.apply(&KvsMap::new(), 0).unwrap().unwrap();
+        assert_eq!(version, 2);
+    }
+
+    #[test]
+    fn test_version_stored_and_taken() {
+        let data = KvsMap::from([("a".to_string(), KvsValue::from(1.0))]);
+        let mut stored = with_version(&data, 3).into_owned();
+        assert_eq!(stored[VERSION_KEY], KvsValue::U64(3));
+        assert_eq!(take_version(&mut stored), Ok(3));
+        assert_eq!(stored, data);
+    }
+
+    #[test]
+    fn test_version_0_not_stored() {
+        let data = KvsMap::new();
+        assert!(matches!(with_version(&data, 0), Cow::Borrowed(_)));
+        assert_eq!(take_version(&mut data.clone()), Ok(0));
+    }
+
+    #[test]
+    fn test_version_number_encodings() {
+        for value in [KvsValue::I64(4), KvsValue::from(4.0)] {
+            let mut stored = KvsMap::from([(VERSION_KEY.to_string(), value)]);
+            assert_eq!(take_version(&mut stored), Ok(4));
+        }
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        for value in [
+            KvsValue::from(-1.0),
+            KvsValue::from(1.5),
+            KvsValue::I64(-1),
+            KvsValue::from("1".to_string()),
+        ] {
+            let mut stored = KvsMap::from([(VERSION_KEY.to_string(), value)]);
+            assert_eq!(take_version(&mut stored), Err(ErrorCode::JsonParserError));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Key namespaces
+//!
+//! Several software components can share one KVS instance without key collisions by working in
+//! their own namespace. A namespace prefixes every key with `<name>/`, so `set_value("volume")`
+//! in namespace `audio` stores the key `audio/volume`. Namespaces can be nested.
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_api::KvsApi;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_value::KvsValue;
+
+/// Separator between namespace and key
+const SEPARATOR: char = '/';
+
+/// View of a KVS scoped to a key prefix
+pub struct KvsNamespace<'a, J: KvsBackend> {
+    /// KVS the namespace belongs to
+    kvs: &'a GenericKvs<J>,
+
+    /// Key prefix including the trailing separator
+    prefix: String,
+}
+
+impl<'a, J: KvsBackend> KvsNamespace<'a, J> {
+    /// Create a namespace below a prefix
+    pub(crate) fn new(kvs: &'a GenericKvs<J>, parent: &str, name: &str) -> Self {
+        Self {
+            kvs,
+            prefix: format!("{parent}{name}{SEPARATOR}"),
+        }
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    /// Return the key prefix of the namespace, e.g. `audio/`
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Return a nested namespace
+    ///
+    /// # Parameters
+    ///   * `name`: Name of the nested namespace
+    pub fn namespace(&self, name: &str) -> KvsNamespace<'a, J> {
+        KvsNamespace::new(self.kvs, &self.prefix, name)
+    }
+
+    /// Get the keys of the namespace without the prefix
+    ///
+    /// Keys of nested namespaces are included with their nested prefix.
+    ///
+    /// # Return Values
+    ///   * Ok: Keys in arbitrary order
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn get_all_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        Ok(self
+            .kvs
+            .get_keys_with_prefix(&self.prefix)?
+            .into_iter()
+            .map(|key| key[self.prefix.len()..].to_string())
+            .collect())
+    }
+
+    /// Check if a key exists in the namespace
+    ///
+    /// See [`KvsApi::key_exists`].
+    pub fn key_exists(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.kvs.key_exists(&self.full_key(key))
+    }
+
+    /// Get the value of a key in the namespace
+    ///
+    /// Defaults are looked up with the full key. See [`KvsApi::get_value`].
+    pub fn get_value(&self, key: &str) -> Result<KvsValue, ErrorCode> {
+        self.kvs.get_value(&self.full_key(key))
+    }
+
+    /// Get the value of a key in the namespace as a specific type
+    ///
+    /// See [`KvsApi::get_value_as`].
+    pub fn get_value_as<T>(&self, key: &str) -> Result<T, ErrorCode>
+    where
+        for<'b> T: TryFrom<&'b KvsValue> + Clone,
+        for<'b> <T as TryFrom<&'b KvsValue>>::Error: std::fmt::Debug,
+    {
+        self.kvs.get_value_as(&self.full_key(key))
+    }
+
+    /// Assign a value to a key in the namespace
+    ///
+    /// See [`KvsApi::set_value`].
+    pub fn set_value<V: Into<KvsValue>>(&self, key: &str, value: V) -> Result<(), ErrorCode> {
+        self.kvs.set_value(self.full_key(key), value)
+    }
+
+    /// Remove a key from the namespace
+    ///
+    /// See [`KvsApi::remove_key`].
+    pub fn remove_key(&self, key: &str) -> Result<(), ErrorCode> {
+        self.kvs.remove_key(&self.full_key(key))
+    }
+
+    /// Remove all keys of the namespace including nested namespaces
+    ///
+    /// See [`GenericKvs::remove_prefix`].
+    pub fn clear(&self) -> Result<usize, ErrorCode> {
+        self.kvs.remove_prefix(&self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn open(dir: &TempDir) -> Kvs {
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs
+    }
+
+    #[test]
+    fn test_keys_prefixed() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let audio = kvs.namespace("audio");
+        assert_eq!(audio.prefix(), "audio/");
+        audio.set_value("volume", 10.0).unwrap();
+        assert_eq!(audio.get_value_as::<f64>("volume").unwrap(), 10.0);
+        assert_eq!(kvs.get_value_as::<f64>("audio/volume").unwrap(), 10.0);
+    }
+
+    #[test]
+    fn test_namespaces_isolated() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let audio = kvs.namespace("audio");
+        let nav = kvs.namespace("nav");
+        audio.set_value("volume", 10.0).unwrap();
+        nav.set_value("volume", 3.0).unwrap();
+        assert_eq!(nav.get_value("volume").unwrap(), KvsValue::from(3.0));
+        audio.remove_key("volume").unwrap();
+        assert!(!audio.key_exists("volume").unwrap());
+        assert!(nav.key_exists("volume").unwrap());
+    }
+
+    #[test]
+    fn test_nested_namespace() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let audio = kvs.namespace("audio");
+        let eq = audio.namespace("eq");
+        assert_eq!(eq.prefix(), "audio/eq/");
+        eq.set_value("bass", 1.0).unwrap();
+        audio.set_value("volume", 10.0).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("audio/eq/bass").unwrap(), 1.0);
+
+        let mut keys = audio.get_all_keys().unwrap();
+        keys.sort();
+        assert_eq!(keys, vec!["eq/bass", "volume"]);
+        assert_eq!(eq.get_all_keys().unwrap(), vec!["bass"]);
+    }
+
+    #[test]
+    fn test_similar_prefix_not_included() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("audiobook", 1.0).unwrap();
+        let audio = kvs.namespace("audio");
+        assert!(audio.get_all_keys().unwrap().is_empty());
+        assert_eq!(audio.clear().unwrap(), 0);
+        assert!(kvs.key_exists("audiobook").unwrap());
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("volume", 1.0).unwrap();
+        let audio = kvs.namespace("audio");
+        assert!(!audio.key_exists("volume").unwrap());
+        assert_eq!(audio.get_value("volume"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(audio.remove_key("volume"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_type_mismatch() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let audio = kvs.namespace("audio");
+        audio.set_value("muted", true).unwrap();
+        assert_eq!(
+            audio.get_value_as::<f64>("muted"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let audio = kvs.namespace("audio");
+        audio.set_value("volume", 10.0).unwrap();
+        audio.namespace("eq").set_value("bass", 1.0).unwrap();
+        kvs.set_value("nav/volume", 3.0).unwrap();
+
+        assert_eq!(audio.clear().unwrap(), 2);
+        assert!(audio.get_all_keys().unwrap().is_empty());
+        assert!(kvs.key_exists("nav/volume").unwrap());
+    }
+}
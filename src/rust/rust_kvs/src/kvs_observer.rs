@@ -0,0 +1,642 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Key-change notifications
+//!
+//! Observers subscribe with a key pattern and a callback. The callback is invoked for every set
+//! or removed key matching the pattern and for every denied write of a matching key. Callbacks
+//! are called after the KVS data is unlocked, so they may call back into the KVS.
+//!
+//! A plain subscription calls the observer on the thread that changed the key, so a slow observer
+//! delays the writer and every subscriber after it. A queued subscription
+//! ([`subscribe_queued`](crate::kvs::GenericKvs::subscribe_queued)) gets a bounded queue and a
+//! delivery thread of its own. Writers only append to the queue and never wait for the observer;
+//! when the queue is full the subscription's [`OverflowPolicy`] decides which events are lost.
+//! The queued and discarded events are reported in [`KvsStats`](crate::kvs_api::KvsStats).
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::{log_error, log_warning};
+use crate::kvs_transaction::KvsOperation;
+use crate::kvs_value::{KvsMap, KvsValue};
+use crate::kvs_worker::WorkerConfig;
+use std::collections::VecDeque;
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Change of a single key
+#[derive(Clone, Debug, PartialEq)]
+pub enum KvsEvent {
+    /// Key was assigned a value
+    Set {
+        /// Changed key
+        key: String,
+
+        /// New value
+        value: KvsValue,
+
+        /// Change reason given with
+        /// [`set_value_with_reason`](crate::kvs::GenericKvs::set_value_with_reason)
+        reason: Option<String>,
+    },
+
+    /// Key was removed
+    Removed {
+        /// Removed key
+        key: String,
+    },
+
+    /// Keys were removed with [`remove_prefix`](crate::kvs::GenericKvs::remove_prefix)
+    PrefixRemoved {
+        /// Prefix given to `remove_prefix`
+        prefix: String,
+
+        /// Removed keys
+        keys: Vec<String>,
+    },
+
+    /// Write of a key was denied by the access policy, see [`kvs_access`](crate::kvs_access)
+    WriteDenied {
+        /// Key that wasn't set or removed
+        key: String,
+
+        /// Identity of the instance, `None` if it has none
+        identity: Option<String>,
+    },
+}
+
+impl KvsEvent {
+    /// Return the key the event refers to
+    pub fn key(&self) -> &str {
+        match self {
+            KvsEvent::Set { key, .. } => key,
+            KvsEvent::Removed { key } => key,
+            KvsEvent::PrefixRemoved { prefix, .. } => prefix,
+            KvsEvent::WriteDenied { key, .. } => key,
+        }
+    }
+
+    /// Return if the event concerns a key matching a subscription pattern
+    ///
+    /// A prefix removal matches if any of the removed keys matches.
+    fn matches(&self, pattern: &str) -> bool {
+        match self {
+            KvsEvent::PrefixRemoved { keys, .. } => {
+                keys.iter().any(|key| key_matches(pattern, key))
+            }
+            _ => key_matches(pattern, self.key()),
+        }
+    }
+}
+
+/// Create the events for a list of applied operations
+pub(crate) fn operation_events(ops: &[KvsOperation]) -> Vec<KvsEvent> {
+    ops.iter()
+        .map(|op| match op {
+            KvsOperation::Set(key, value) => KvsEvent::Set {
+                key: key.clone(),
+                value: value.clone(),
+                reason: None,
+            },
+            KvsOperation::Remove(key) => KvsEvent::Removed { key: key.clone() },
+        })
+        .collect()
+}
+
+/// Create the events for replacing the whole KVS data
+///
+/// Unchanged keys don't create an event.
+pub(crate) fn replace_events(old: &KvsMap, new: &KvsMap) -> Vec<KvsEvent> {
+    let removed = old
+        .keys()
+        .filter(|key| !new.contains_key(*key))
+        .map(|key| KvsEvent::Removed { key: key.clone() });
+    let set = new
+        .iter()
+        .filter(|(key, value)| old.get(*key) != Some(*value))
+        .map(|(key, value)| KvsEvent::Set {
+            key: key.clone(),
+            value: value.clone(),
+            reason: None,
+        });
+    removed.chain(set).collect()
+}
+
+/// Subscription ID returned by [`subscribe`](crate::kvs::GenericKvs::subscribe)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(usize);
+
+/// Observer callback
+pub type KvsObserver = Arc<dyn Fn(&KvsEvent) + Send + Sync>;
+
+/// Key pattern of a subscription
+///
+/// A pattern ending with `*` matches all keys starting with the text before the `*`, any other
+/// pattern matches the key exactly.
+fn key_matches(pattern: &str, key: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => pattern == key,
+    }
+}
+
+/// Behavior of a subscriber queue that is full when an event arrives
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued event
+    #[default]
+    DropOldest,
+
+    /// Replace the queued event of the same key, discard the oldest event if there is none
+    CoalescePerKey,
+
+    /// Remove the subscription, queued events are discarded
+    Disconnect,
+}
+
+/// Queued events of a subscriber
+#[derive(Default)]
+struct QueueState {
+    /// Events not delivered yet, oldest first
+    events: VecDeque<KvsEvent>,
+
+    /// Count of events discarded on overflow
+    dropped: u64,
+
+    /// The subscription was removed, the delivery thread exits
+    closed: bool,
+}
+
+/// Bounded event queue of a subscriber with its own delivery thread
+struct EventQueue {
+    /// Queued events
+    state: Mutex<QueueState>,
+
+    /// Signals queued events or closing to the delivery thread
+    ready: Condvar,
+
+    /// Maximum count of queued events
+    capacity: usize,
+
+    /// Behavior on overflow
+    policy: OverflowPolicy,
+}
+
+impl EventQueue {
+    /// Queue an event without blocking
+    ///
+    /// # Return Values
+    ///   * `false`: Queue overflowed with `OverflowPolicy::Disconnect` and was closed
+    fn push(&self, event: &KvsEvent) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return true;
+        };
+        if state.closed {
+            return true;
+        }
+        if state.events.len() >= self.capacity {
+            let pos = match self.policy {
+                OverflowPolicy::Disconnect => {
+                    state.closed = true;
+                    state.events.clear();
+                    self.ready.notify_one();
+                    return false;
+                }
+                OverflowPolicy::CoalescePerKey => state
+                    .events
+                    .iter()
+                    .position(|queued| queued.key() == event.key())
+                    .unwrap_or(0),
+                OverflowPolicy::DropOldest => 0,
+            };
+            state.events.remove(pos);
+            state.dropped += 1;
+        }
+        state.events.push_back(event.clone());
+        self.ready.notify_one();
+        true
+    }
+
+    /// Deliver queued events until the queue is closed
+    fn deliver(&self, observer: KvsObserver) {
+        loop {
+            let event = {
+                let Ok(mut state) = self.state.lock() else {
+                    return;
+                };
+                loop {
+                    if state.closed {
+                        return;
+                    }
+                    if let Some(event) = state.events.pop_front() {
+                        break event;
+                    }
+                    state = match self.ready.wait(state) {
+                        Ok(state) => state,
+                        Err(_) => return,
+                    };
+                }
+            };
+            observer(&event);
+        }
+    }
+
+    /// Stop the delivery thread, queued events are discarded
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+            state.events.clear();
+        }
+        self.ready.notify_one();
+    }
+}
+
+/// Delivery of the events of a subscription
+enum Delivery {
+    /// Observer called by the thread that changed the key
+    Direct(KvsObserver),
+
+    /// Events queued for the delivery thread of the subscription
+    Queued(Arc<EventQueue>),
+}
+
+/// Registered observer
+struct Subscription {
+    id: SubscriptionId,
+    pattern: String,
+    delivery: Delivery,
+}
+
+/// Queue statistics of all queued subscriptions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct QueueStats {
+    /// Count of events waiting for delivery
+    pub(crate) depth: usize,
+
+    /// Count of events discarded on overflow since the subscriptions were registered
+    pub(crate) dropped: u64,
+}
+
+/// Registered observers of a KVS instance
+#[derive(Default)]
+pub(crate) struct Observers {
+    /// Subscriptions in registration order
+    subscriptions: Mutex<Vec<Subscription>>,
+
+    /// Last assigned subscription ID
+    last_id: AtomicUsize,
+
+    /// Count of subscriptions, allows skipping event creation without locking
+    count: AtomicUsize,
+}
+
+impl Observers {
+    /// Return if any observer is registered
+    pub(crate) fn is_empty(&self) -> bool {
+        self.count.load(atomic::Ordering::Relaxed) == 0
+    }
+
+    /// Add a subscription
+    fn add(&self, pattern: String, delivery: Delivery) -> Result<SubscriptionId, ErrorCode> {
+        let mut subscriptions = self
+            .subscriptions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let id = SubscriptionId(self.last_id.fetch_add(1, atomic::Ordering::Relaxed) + 1);
+        subscriptions.push(Subscription {
+            id,
+            pattern,
+            delivery,
+        });
+        self.count
+            .store(subscriptions.len(), atomic::Ordering::Relaxed);
+        Ok(id)
+    }
+
+    /// Register an observer
+    ///
+    /// # Return Values
+    ///   * Ok: Subscription ID to unsubscribe
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn subscribe(
+        &self,
+        pattern: String,
+        observer: KvsObserver,
+    ) -> Result<SubscriptionId, ErrorCode> {
+        self.add(pattern, Delivery::Direct(observer))
+    }
+
+    /// Register an observer called from its own delivery thread through a bounded queue
+    ///
+    /// # Parameters
+    ///   * `pattern`: Key pattern
+    ///   * `observer`: Callback receiving the change event
+    ///   * `capacity`: Maximum count of queued events
+    ///   * `policy`: Behavior on overflow
+    ///   * `worker`: Settings of the delivery thread
+    ///
+    /// # Return Values
+    ///   * Ok: Subscription ID to unsubscribe
+    ///   * `ErrorCode::ValidationFailed`: Capacity is 0
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::UnmappedError`: Delivery thread couldn't be spawned
+    pub(crate) fn subscribe_queued(
+        &self,
+        pattern: String,
+        observer: KvsObserver,
+        capacity: usize,
+        policy: OverflowPolicy,
+        worker: &WorkerConfig,
+    ) -> Result<SubscriptionId, ErrorCode> {
+        if capacity == 0 {
+            return Err(ErrorCode::ValidationFailed);
+        }
+        let queue = Arc::new(EventQueue {
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+            capacity,
+            policy,
+        });
+        let delivery = queue.clone();
+        // The thread exits when the queue is closed, it isn't joined so an observer may
+        // unsubscribe itself
+        worker.spawn("notify", move || delivery.deliver(observer))?;
+        let res = self.add(pattern, Delivery::Queued(queue.clone()));
+        if res.is_err() {
+            queue.close();
+        }
+        res
+    }
+
+    /// Remove subscriptions and stop their delivery threads
+    ///
+    /// # Return Values
+    ///   * Count of removed subscriptions
+    fn remove(subscriptions: &mut Vec<Subscription>, ids: &[SubscriptionId]) -> usize {
+        let len = subscriptions.len();
+        subscriptions.retain(|subscription| {
+            if !ids.contains(&subscription.id) {
+                return true;
+            }
+            if let Delivery::Queued(queue) = &subscription.delivery {
+                queue.close();
+            }
+            false
+        });
+        len - subscriptions.len()
+    }
+
+    /// Remove an observer
+    ///
+    /// # Return Values
+    ///   * Ok: Observer removed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: Unknown subscription ID or disconnected on overflow
+    pub(crate) fn unsubscribe(&self, id: SubscriptionId) -> Result<(), ErrorCode> {
+        let mut subscriptions = self
+            .subscriptions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let removed = Self::remove(&mut subscriptions, &[id]);
+        self.count
+            .store(subscriptions.len(), atomic::Ordering::Relaxed);
+        if removed == 0 {
+            Err(ErrorCode::KeyNotFound)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Return the queue statistics of the queued subscriptions
+    ///
+    /// # Return Values
+    ///   * Ok: Queue statistics
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn queue_stats(&self) -> Result<QueueStats, ErrorCode> {
+        let subscriptions = self
+            .subscriptions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let mut stats = QueueStats::default();
+        for subscription in subscriptions.iter() {
+            if let Delivery::Queued(queue) = &subscription.delivery {
+                let state = queue.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+                stats.depth += state.events.len();
+                stats.dropped += state.dropped;
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Dispatch events to all matching observers
+    ///
+    /// Direct observers are called on the current thread, queued observers only get the events
+    /// queued. Must not be called with the KVS data locked.
+    pub(crate) fn notify(&self, events: &[KvsEvent]) {
+        if events.is_empty() || self.is_empty() {
+            return;
+        }
+
+        let mut direct: Vec<(String, KvsObserver)> = Vec::new();
+        {
+            let mut subscriptions = match self.subscriptions.lock() {
+                Ok(subscriptions) => subscriptions,
+                Err(e) => {
+                    log_error!("Mutex lock failed: {e:?}");
+                    return;
+                }
+            };
+            let mut disconnected = Vec::new();
+            for subscription in subscriptions.iter() {
+                match &subscription.delivery {
+                    Delivery::Direct(observer) => {
+                        direct.push((subscription.pattern.clone(), observer.clone()))
+                    }
+                    Delivery::Queued(queue) => {
+                        let overflow = events
+                            .iter()
+                            .filter(|event| event.matches(&subscription.pattern))
+                            .any(|event| !queue.push(event));
+                        if overflow {
+                            log_warning!(
+                                "subscription {:?} disconnected, its event queue overflowed",
+                                subscription.id
+                            );
+                            disconnected.push(subscription.id);
+                        }
+                    }
+                }
+            }
+            if !disconnected.is_empty() {
+                Self::remove(&mut subscriptions, &disconnected);
+                self.count
+                    .store(subscriptions.len(), atomic::Ordering::Relaxed);
+            }
+        }
+
+        for event in events {
+            for (pattern, observer) in direct.iter() {
+                if event.matches(pattern) {
+                    observer(event);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Observers {
+    fn drop(&mut self) {
+        if let Ok(subscriptions) = self.subscriptions.get_mut() {
+            for subscription in subscriptions.iter() {
+                if let Delivery::Queued(queue) = &subscription.delivery {
+                    queue.close();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_matches() {
+        assert!(key_matches("a", "a"));
+        assert!(!key_matches("a", "ab"));
+        assert!(key_matches("net.*", "net.ip"));
+        assert!(!key_matches("net.*", "diag.ip"));
+        assert!(key_matches("*", "anything"));
+    }
+
+    #[test]
+    fn test_replace_events() {
+        let old = KvsMap::from([
+            ("same".to_string(), KvsValue::from(1.0)),
+            ("changed".to_string(), KvsValue::from(1.0)),
+            ("removed".to_string(), KvsValue::from(1.0)),
+        ]);
+        let new = KvsMap::from([
+            ("same".to_string(), KvsValue::from(1.0)),
+            ("changed".to_string(), KvsValue::from(2.0)),
+            ("added".to_string(), KvsValue::from(3.0)),
+        ]);
+
+        let mut keys: Vec<String> = replace_events(&old, &new)
+            .iter()
+            .map(|event| event.key().to_string())
+            .collect();
+        keys.sort();
+        assert_eq!(keys, vec!["added", "changed", "removed"]);
+    }
+
+    #[test]
+    fn test_subscribe_notify_unsubscribe() {
+        let observers = Observers::default();
+        assert!(observers.is_empty());
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_cb = seen.clone();
+        let id = observers
+            .subscribe(
+                "net.*".to_string(),
+                Arc::new(move |event: &KvsEvent| {
+                    seen_cb.lock().unwrap().push(event.key().to_string());
+                }),
+            )
+            .unwrap();
+
+        observers.notify(&[
+            KvsEvent::Set {
+                key: "net.ip".to_string(),
+                value: KvsValue::Null,
+                reason: None,
+            },
+            KvsEvent::Removed {
+                key: "diag.mode".to_string(),
+            },
+        ]);
+        assert_eq!(*seen.lock().unwrap(), vec!["net.ip".to_string()]);
+
+        observers.unsubscribe(id).unwrap();
+        assert!(observers.is_empty());
+        assert_eq!(observers.unsubscribe(id), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_queue_overflow_policies() {
+        let set = |key: &str, value: f64| KvsEvent::Set {
+            key: key.to_string(),
+            value: KvsValue::from(value),
+            reason: None,
+        };
+        let queue = |policy| EventQueue {
+            state: Mutex::new(QueueState::default()),
+            ready: Condvar::new(),
+            capacity: 2,
+            policy,
+        };
+        let queued = |queue: &EventQueue| -> Vec<KvsEvent> {
+            queue.state.lock().unwrap().events.iter().cloned().collect()
+        };
+
+        let drop_oldest = queue(OverflowPolicy::DropOldest);
+        for event in [set("a", 1.0), set("b", 1.0), set("a", 2.0)] {
+            assert!(drop_oldest.push(&event));
+        }
+        assert_eq!(queued(&drop_oldest), vec![set("b", 1.0), set("a", 2.0)]);
+        assert_eq!(drop_oldest.state.lock().unwrap().dropped, 1);
+
+        let coalesce = queue(OverflowPolicy::CoalescePerKey);
+        for event in [set("a", 1.0), set("b", 1.0), set("b", 2.0)] {
+            assert!(coalesce.push(&event));
+        }
+        assert_eq!(queued(&coalesce), vec![set("a", 1.0), set("b", 2.0)]);
+
+        let disconnect = queue(OverflowPolicy::Disconnect);
+        assert!(disconnect.push(&set("a", 1.0)));
+        assert!(disconnect.push(&set("b", 1.0)));
+        assert!(!disconnect.push(&set("c", 1.0)));
+        assert!(queued(&disconnect).is_empty());
+    }
+
+    #[test]
+    fn test_slow_queued_observer_does_not_block() {
+        let observers = Observers::default();
+        let (release, blocked) = std::sync::mpsc::channel::<()>();
+        let blocked = Mutex::new(blocked);
+        let id = observers
+            .subscribe_queued(
+                "*".to_string(),
+                Arc::new(move |_: &KvsEvent| {
+                    let _ = blocked.lock().unwrap().recv();
+                }),
+                1,
+                OverflowPolicy::Disconnect,
+                &WorkerConfig::default(),
+            )
+            .unwrap();
+
+        // The first event blocks the observer, the second is queued and the third overflows
+        let event = KvsEvent::Removed {
+            key: "a".to_string(),
+        };
+        observers.notify(std::slice::from_ref(&event));
+        while observers.queue_stats().unwrap().depth != 0 {
+            std::thread::yield_now();
+        }
+        observers.notify(std::slice::from_ref(&event));
+        assert_eq!(observers.queue_stats().unwrap().depth, 1);
+        observers.notify(std::slice::from_ref(&event));
+        assert!(observers.is_empty());
+        assert_eq!(observers.unsubscribe(id), Err(ErrorCode::KeyNotFound));
+        release.send(()).unwrap();
+    }
+}
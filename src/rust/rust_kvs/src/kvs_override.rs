@@ -0,0 +1,272 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Session-scoped temporary overrides
+//!
+//! A diagnostics session can shadow values without changing them. Overrides are kept in memory
+//! only: they are never written to the KVS file, snapshots or write-ahead log, and they revert
+//! when the session is dropped or the process restarts. `get_value`, `get_value_as`,
+//! `get_values` and `key_exists` see overrides, key listings and cursors show the stored data.
+//! If sessions overlap, the most recently started session wins.
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Override values of all active sessions
+#[derive(Default)]
+pub(crate) struct Overrides {
+    /// Override values by session ID, oldest session first
+    sessions: Mutex<Vec<(u64, KvsMap)>>,
+
+    /// ID of the next session
+    next_id: AtomicU64,
+}
+
+impl Overrides {
+    /// Start a session without overrides and return its ID
+    fn begin(&self) -> Result<u64, ErrorCode> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .push((id, KvsMap::new()));
+        Ok(id)
+    }
+
+    /// Drop a session and its overrides
+    fn end(&self, id: u64) {
+        // A poisoned lock still holds consistent data, the session must be removed regardless
+        let mut sessions = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        sessions.retain(|(session, _)| *session != id);
+    }
+
+    /// Apply a change to the overrides of a session
+    fn with_session<R>(&self, id: u64, f: impl FnOnce(&mut KvsMap) -> R) -> Result<R, ErrorCode> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let (_, values) = sessions
+            .iter_mut()
+            .find(|(session, _)| *session == id)
+            .ok_or(ErrorCode::UnmappedError)?;
+        Ok(f(values))
+    }
+
+    /// Return the override of a key from the most recent session overriding it
+    pub(crate) fn get(&self, key: &str) -> Result<Option<KvsValue>, ErrorCode> {
+        Ok(self
+            .sessions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .iter()
+            .rev()
+            .find_map(|(_, values)| values.get(key).cloned()))
+    }
+}
+
+/// Guard of an override session
+///
+/// Created with [`begin_override_session`](GenericKvs::begin_override_session). All overrides
+/// set through the guard are discarded when it's dropped.
+pub struct KvsOverrideSession<'a, J: KvsBackend> {
+    /// KVS the overrides apply to
+    kvs: &'a GenericKvs<J>,
+
+    /// Session ID
+    id: u64,
+}
+
+impl<'a, J: KvsBackend> KvsOverrideSession<'a, J> {
+    /// Start a session for a KVS
+    pub(crate) fn new(kvs: &'a GenericKvs<J>) -> Result<Self, ErrorCode> {
+        let id = kvs.overrides().begin()?;
+        Ok(Self { kvs, id })
+    }
+
+    /// Shadow the value of a key until the session ends
+    ///
+    /// # Parameters
+    ///   * `key`: Key to override
+    ///   * `value`: Value readers see instead of the stored value
+    ///
+    /// # Return Values
+    ///   * Ok: Override set
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn set_value<S: Into<String>, V: Into<KvsValue>>(
+        &self,
+        key: S,
+        value: V,
+    ) -> Result<(), ErrorCode> {
+        self.kvs.overrides().with_session(self.id, |values| {
+            values.insert(key.into(), value.into());
+        })
+    }
+
+    /// Revert the override of a key before the session ends
+    ///
+    /// # Parameters
+    ///   * `key`: Overridden key
+    ///
+    /// # Return Values
+    ///   * Ok: `true` if the key was overridden by this session
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn remove_override(&self, key: &str) -> Result<bool, ErrorCode> {
+        self.kvs
+            .overrides()
+            .with_session(self.id, |values| values.remove(key).is_some())
+    }
+
+    /// Return the keys overridden by this session in arbitrary order
+    ///
+    /// # Return Values
+    ///   * Ok: Overridden keys
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn overridden_keys(&self) -> Result<Vec<String>, ErrorCode> {
+        self.kvs
+            .overrides()
+            .with_session(self.id, |values| values.keys().cloned().collect())
+    }
+}
+
+impl<J: KvsBackend> Drop for KvsOverrideSession<'_, J> {
+    fn drop(&mut self) {
+        self.kvs.overrides().end(self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::fs;
+    use tempfile::{tempdir, TempDir};
+
+    fn open(dir: &TempDir) -> Kvs {
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs
+    }
+
+    #[test]
+    fn test_override_shadows_value() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("level", 1.0).unwrap();
+
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("level", 5.0).unwrap();
+        session.set_value("trace", true).unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("level").unwrap(), 5.0);
+        assert!(kvs.key_exists("trace").unwrap());
+        assert_eq!(
+            kvs.get_values(&["level", "trace"]).unwrap(),
+            vec![Some(KvsValue::from(5.0)), Some(KvsValue::from(true))]
+        );
+    }
+
+    #[test]
+    fn test_override_not_listed() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("trace", true).unwrap();
+        assert!(kvs.get_all_keys().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_override_reverted_on_drop() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("level", 1.0).unwrap();
+
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("level", 5.0).unwrap();
+        session.set_value("trace", true).unwrap();
+        drop(session);
+        assert_eq!(kvs.get_value_as::<f64>("level").unwrap(), 1.0);
+        assert!(!kvs.key_exists("trace").unwrap());
+        assert_eq!(kvs.get_value("trace"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_override_type_mismatch() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("level", 1.0).unwrap();
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("level", "high".to_string()).unwrap();
+        assert_eq!(
+            kvs.get_value_as::<f64>("level"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_nested_sessions() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let outer = kvs.begin_override_session().unwrap();
+        outer.set_value("level", 5.0).unwrap();
+
+        let inner = kvs.begin_override_session().unwrap();
+        inner.set_value("level", 9.0).unwrap();
+        assert_eq!(kvs.get_value("level").unwrap(), KvsValue::from(9.0));
+        drop(inner);
+        assert_eq!(kvs.get_value("level").unwrap(), KvsValue::from(5.0));
+    }
+
+    #[test]
+    fn test_override_not_persisted() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("level", 1.0).unwrap();
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("level", 5.0).unwrap();
+        session.set_value("trace", true).unwrap();
+        kvs.flush().unwrap();
+
+        let file = fs::read_to_string(dir.path().join("kvs_0_0.json")).unwrap();
+        assert!(!file.contains("trace"));
+        drop(session);
+        drop(kvs);
+        assert_eq!(open(&dir).get_value_as::<f64>("level").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_remove_override() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("level", 1.0).unwrap();
+        let session = kvs.begin_override_session().unwrap();
+        session.set_value("level", 5.0).unwrap();
+        session.set_value("trace", true).unwrap();
+
+        assert!(session.remove_override("trace").unwrap());
+        assert!(!session.remove_override("trace").unwrap());
+        assert!(!session.remove_override("missing").unwrap());
+        assert_eq!(session.overridden_keys().unwrap(), vec!["level"]);
+        assert!(session.remove_override("level").unwrap());
+        assert_eq!(kvs.get_value_as::<f64>("level").unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Platform specific file operations
+//!
+//! The storage backends only use the functions of this module for operations where the
+//! crash-consistency depends on OS semantics. The implementation is selected at compile time:
+//!   * POSIX (Linux and others): `fsync` on file and parent directory
+//!   * QNX (`target_os = "nto"`): `fsync` on file, directory sync is best effort
+//!   * Windows: `rename` replaces the target atomically (`MoveFileExW` with
+//!     `MOVEFILE_REPLACE_EXISTING`), directory handles can't be synchronized
+//!   * Other targets: no directory sync available
+//!
+//! How far written files are synchronized is selected per instance with [`Durability`].
+//!
+//! Files are locked with [`lock_file`] for the process lock and the merge journal:
+//!   * POSIX (Linux and others): `flock`
+//!   * Windows: `LockFileEx`
+//!   * QNX and other targets without `flock`: out of scope, locking fails with
+//!     `ErrorKind::Unsupported`. QNX only offers `fcntl` record locks, which need `unsafe` code
+//!     or a libc dependency and are released as soon as any handle of the file is closed. The
+//!     callers continue without the lock, see [`lock_unsupported`], which is only safe for a
+//!     single process.
+//!
+//! Paths are always composed with [`PathBuf`] operations instead of string formatting to keep
+//! the separator handling portable.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Durability;
+use std::ffi::OsString;
+use std::fs::{self, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Suffix of the temporary file used by [`atomic_replace`]
+const TMP_SUFFIX: &str = ".tmp";
+
+/// Return the directory containing `path`
+///
+/// A bare filename refers to the current working directory.
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Append a suffix to the last component of a path
+///
+/// `dir/kvs_0` with suffix `_1.json` results in `dir/kvs_0_1.json`.
+///
+/// # Parameters
+///   * `path`: Path to extend
+///   * `suffix`: Suffix to append to the filename
+///
+/// # Return Values
+///   * Extended path
+pub(crate) fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Return the temporary filename used while replacing `path`
+fn tmp_path(path: &Path) -> PathBuf {
+    path_with_suffix(path, TMP_SUFFIX)
+}
+
+/// Persist the directory entries of `dir`
+///
+/// # Parameters
+///   * `dir`: Directory to synchronize
+///
+/// # Return Values
+///   * Ok: Directory entries are persisted
+///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+#[cfg(all(unix, not(target_os = "nto")))]
+pub(crate) fn sync_dir(dir: &Path) -> Result<(), ErrorCode> {
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Persist the directory entries of `dir`
+///
+/// Not every QNX filesystem accepts `fsync` on a directory descriptor. The power-safe
+/// filesystem commits directory entries together with the file data, so unsupported
+/// operations are not treated as an error.
+///
+/// # Parameters
+///   * `dir`: Directory to synchronize
+///
+/// # Return Values
+///   * Ok: Directory entries are persisted or sync isn't supported
+///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+#[cfg(target_os = "nto")]
+pub(crate) fn sync_dir(dir: &Path) -> Result<(), ErrorCode> {
+    match fs::File::open(dir).and_then(|file| file.sync_all()) {
+        Ok(()) => Ok(()),
+        Err(err)
+            if matches!(
+                err.kind(),
+                std::io::ErrorKind::InvalidInput
+                    | std::io::ErrorKind::PermissionDenied
+                    | std::io::ErrorKind::Unsupported
+            ) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Persist the directory entries of `dir`
+///
+/// Directory handles can't be synchronized on this platform (e.g. Windows), this is a no-op.
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_dir: &Path) -> Result<(), ErrorCode> {
+    Ok(())
+}
+
+/// Lock a file until the handle is closed
+///
+/// # Parameters
+///   * `file`: File to lock
+///   * `shared`: Take a shared lock instead of an exclusive one
+///   * `wait`: Block while a conflicting lock is held instead of failing
+///
+/// # Return Values
+///   * Ok: File locked
+///   * `TryLockError::WouldBlock`: Conflicting lock is held, only without `wait`
+///   * `TryLockError::Error`: File couldn't be locked
+#[cfg(not(target_os = "nto"))]
+pub(crate) fn lock_file(file: &fs::File, shared: bool, wait: bool) -> Result<(), TryLockError> {
+    match (shared, wait) {
+        (false, false) => file.try_lock(),
+        (true, false) => file.try_lock_shared(),
+        (false, true) => file.lock().map_err(TryLockError::Error),
+        (true, true) => file.lock_shared().map_err(TryLockError::Error),
+    }
+}
+
+/// Lock a file until the handle is closed
+///
+/// File locking is out of scope on QNX, see the [module documentation](self).
+///
+/// # Return Values
+///   * `TryLockError::Error`: Always, with `ErrorKind::Unsupported`
+#[cfg(target_os = "nto")]
+pub(crate) fn lock_file(_file: &fs::File, _shared: bool, _wait: bool) -> Result<(), TryLockError> {
+    Err(TryLockError::Error(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file locking isn't supported on QNX",
+    )))
+}
+
+/// Return if locking failed because the platform doesn't support file locks
+///
+/// Callers continue without the lock and log a warning instead of failing.
+pub(crate) fn lock_unsupported(err: &TryLockError) -> bool {
+    matches!(err, TryLockError::Error(e) if e.kind() == std::io::ErrorKind::Unsupported)
+}
+
+/// Synchronize a written file as far as the durability requires
+pub(crate) fn sync_file(file: &fs::File, durability: Durability) -> std::io::Result<()> {
+    match durability {
+        Durability::None => Ok(()),
+        Durability::Flush => file.sync_data(),
+        Durability::Fsync | Durability::FsyncDir => file.sync_all(),
+    }
+}
+
+/// Persist the directory entry of `path` if the durability requires it
+fn sync_parent(path: &Path, durability: Durability) -> Result<(), ErrorCode> {
+    match durability {
+        Durability::FsyncDir => sync_dir(&parent_dir(path)),
+        _ => Ok(()),
+    }
+}
+
+/// Atomically replace the content of a file
+///
+/// The data is written to a temporary file next to `path`, synchronized and then renamed over
+/// `path`. Readers either see the old or the new content, never a truncated file.
+///
+/// # Parameters
+///   * `path`: File to replace
+///   * `data`: New file content
+///   * `durability`: Synchronization of the file and its directory
+///
+/// # Return Values
+///   * Ok: File replaced
+///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+pub(crate) fn atomic_replace(
+    path: &Path,
+    data: &[u8],
+    durability: Durability,
+) -> Result<(), ErrorCode> {
+    stage_replace(path, data, durability)?;
+    commit_replace(path, durability)
+}
+
+/// Write the new content of a file without replacing it yet
+///
+/// The data is written to the temporary file of `path` and synchronized. Files that must be
+/// consistent with each other, like a KVS file and its hash file, are replaced in a fixed order
+/// with the later ones staged first. After a crash between the replacements the staged content
+/// is still available with [`staged_content`].
+///
+/// # Parameters
+///   * `path`: File to replace
+///   * `data`: New file content
+///   * `durability`: Synchronization of the staged file
+///
+/// # Return Values
+///   * Ok: Content staged
+///   * `ErrorCode::FileNotFound`: Directory doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+pub(crate) fn stage_replace(
+    path: &Path,
+    data: &[u8],
+    durability: Durability,
+) -> Result<(), ErrorCode> {
+    let tmp = tmp_path(path);
+    let res = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(data)?;
+        sync_file(&file, durability)
+    });
+    if let Err(err) = res {
+        let _ = fs::remove_file(&tmp);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+/// Replace a file with the content staged by [`stage_replace`]
+///
+/// # Parameters
+///   * `path`: File to replace
+///   * `durability`: Synchronization of the directory
+///
+/// # Return Values
+///   * Ok: File replaced
+///   * `ErrorCode::FileNotFound`: Nothing staged or directory doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+pub(crate) fn commit_replace(path: &Path, durability: Durability) -> Result<(), ErrorCode> {
+    let tmp = tmp_path(path);
+    if let Err(err) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err.into());
+    }
+    sync_parent(path, durability)
+}
+
+/// Return the content staged for a file, `None` if nothing is staged
+pub(crate) fn staged_content(path: &Path) -> Option<Vec<u8>> {
+    fs::read(tmp_path(path)).ok()
+}
+
+/// Make `to` a copy of `from` without changing `from`
+///
+/// A hard link is created where the filesystem supports it, so no data is written. A later
+/// [`atomic_replace`] of `from` doesn't change `to`, as it replaces the directory entry.
+///
+/// # Parameters
+///   * `from`: Existing file
+///   * `to`: Copy to create, an existing file is replaced
+///   * `durability`: Synchronization of the copy and its directory
+///
+/// # Return Values
+///   * Ok: Copy created
+///   * `ErrorCode::FileNotFound`: `from` doesn't exist
+///   * `ErrorCode::UnmappedError`: Generic error
+pub(crate) fn link_or_copy(
+    from: &Path,
+    to: &Path,
+    durability: Durability,
+) -> Result<(), ErrorCode> {
+    match fs::remove_file(to) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+        _ => {}
+    }
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to)?;
+        sync_file(&fs::File::open(to)?, durability)?;
+    }
+    sync_parent(to, durability)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_atomic_replace_creates_and_replaces() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.json");
+
+        atomic_replace(&path, b"first", Durability::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+
+        atomic_replace(&path, b"second", Durability::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!tmp_path(&path).exists());
+    }
+
+    #[test]
+    fn test_staged_replace_and_link() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("file.hash");
+        let copy = dir.path().join("copy.hash");
+
+        atomic_replace(&path, b"old", Durability::default()).unwrap();
+        stage_replace(&path, b"new", Durability::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"old");
+        assert_eq!(staged_content(&path).unwrap(), b"new");
+
+        // The copy keeps the old content when the file is replaced
+        link_or_copy(&path, &copy, Durability::default()).unwrap();
+        commit_replace(&path, Durability::default()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read(&copy).unwrap(), b"old");
+        assert_eq!(staged_content(&path), None);
+    }
+
+    #[test]
+    fn test_lock_unsupported() {
+        let unsupported = io::Error::new(io::ErrorKind::Unsupported, "no locks");
+        assert!(lock_unsupported(&TryLockError::Error(unsupported)));
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert!(!lock_unsupported(&TryLockError::Error(denied)));
+        assert!(!lock_unsupported(&TryLockError::WouldBlock));
+    }
+
+    #[test]
+    fn test_atomic_replace_missing_dir() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("file.json");
+
+        assert_eq!(
+            atomic_replace(&path, b"data", Durability::default()),
+            Err(ErrorCode::FileNotFound)
+        );
+    }
+
+    #[test]
+    fn test_path_with_suffix() {
+        assert_eq!(
+            path_with_suffix(Path::new("kvs_0"), "_1.json"),
+            PathBuf::from("kvs_0_1.json")
+        );
+        assert_eq!(
+            path_with_suffix(&Path::new("rel").join("kvs_0"), "_1.json"),
+            Path::new("rel").join("kvs_0_1.json")
+        );
+    }
+
+    #[test]
+    fn test_parent_dir_of_bare_filename() {
+        assert_eq!(parent_dir(Path::new("file.json")), PathBuf::from("."));
+        assert_eq!(parent_dir(Path::new("dir/file.json")), PathBuf::from("dir"));
+    }
+}
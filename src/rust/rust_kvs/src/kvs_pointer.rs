@@ -0,0 +1,293 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON Pointer (RFC 6901) access into nested values
+//!
+//! A pointer is empty for the whole value or a sequence of `/`-prefixed reference tokens, e.g.
+//! `/network/eth0/mtu`. In tokens `~1` stands for `/` and `~0` for `~`. Array elements are
+//! referenced by their index without leading zeros, `-` references the element after the last
+//! one when setting a value.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_error;
+use crate::kvs_value::KvsValue;
+
+/// Split a pointer into its unescaped reference tokens
+///
+/// # Return Values
+///   * Ok: Reference tokens, empty for the whole value
+///   * `ErrorCode::ValidationFailed`: Pointer doesn't start with `/` or has an invalid escape
+fn tokens(pointer: &str) -> Result<Vec<String>, ErrorCode> {
+    if pointer.is_empty() {
+        return Ok(Vec::new());
+    }
+    let invalid = || {
+        log_error!("invalid JSON pointer: {pointer}");
+        ErrorCode::ValidationFailed
+    };
+    let rest = pointer.strip_prefix('/').ok_or_else(invalid)?;
+    rest.split('/')
+        .map(|token| {
+            let mut unescaped = String::with_capacity(token.len());
+            let mut chars = token.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '~' => match chars.next() {
+                        Some('0') => unescaped.push('~'),
+                        Some('1') => unescaped.push('/'),
+                        _ => return Err(invalid()),
+                    },
+                    c => unescaped.push(c),
+                }
+            }
+            Ok(unescaped)
+        })
+        .collect()
+}
+
+/// Parse an array index token
+///
+/// # Return Values
+///   * Index, `None` if the token isn't an index
+fn index(token: &str) -> Option<usize> {
+    if token.is_empty()
+        || (token.len() > 1 && token.starts_with('0'))
+        || !token.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+    token.parse().ok()
+}
+
+/// Return the member or element a token references
+fn child<'a>(value: &'a KvsValue, token: &str) -> Result<&'a KvsValue, ErrorCode> {
+    match value {
+        KvsValue::Object(object) => object.get(token).ok_or(ErrorCode::KeyNotFound),
+        KvsValue::Array(array) => index(token)
+            .and_then(|idx| array.get(idx))
+            .ok_or(ErrorCode::KeyNotFound),
+        _ => Err(ErrorCode::ConversionFailed),
+    }
+}
+
+/// Return the nested value a pointer references
+///
+/// # Return Values
+///   * Ok: Referenced value
+///   * `ErrorCode::ValidationFailed`: Invalid pointer
+///   * `ErrorCode::KeyNotFound`: Member or element doesn't exist
+///   * `ErrorCode::ConversionFailed`: A token references into a value that isn't a container
+pub(crate) fn get<'a>(value: &'a KvsValue, pointer: &str) -> Result<&'a KvsValue, ErrorCode> {
+    tokens(pointer)?
+        .iter()
+        .try_fold(value, |value, token| child(value, token))
+}
+
+/// Set the nested value a pointer references
+///
+/// The container of the referenced value must exist. Members are added to objects, elements
+/// are replaced or appended to arrays.
+///
+/// # Return Values
+///   * Ok: Value set
+///   * `ErrorCode::ValidationFailed`: Invalid pointer
+///   * `ErrorCode::KeyNotFound`: Container doesn't exist or array index is out of range
+///   * `ErrorCode::ConversionFailed`: A token references into a value that isn't a container
+pub(crate) fn set(value: &mut KvsValue, pointer: &str, new: KvsValue) -> Result<(), ErrorCode> {
+    let mut tokens = tokens(pointer)?;
+    let Some(last) = tokens.pop() else {
+        *value = new;
+        return Ok(());
+    };
+    let mut parent = value;
+    for token in tokens.iter() {
+        parent = match parent {
+            KvsValue::Object(object) => object.get_mut(token).ok_or(ErrorCode::KeyNotFound)?,
+            KvsValue::Array(array) => index(token)
+                .and_then(|idx| array.get_mut(idx))
+                .ok_or(ErrorCode::KeyNotFound)?,
+            _ => return Err(ErrorCode::ConversionFailed),
+        };
+    }
+    match parent {
+        KvsValue::Object(object) => {
+            object.insert(last, new);
+        }
+        KvsValue::Array(array) if last == "-" => array.push(new),
+        KvsValue::Array(array) => match index(&last) {
+            Some(idx) if idx < array.len() => array[idx] = new,
+            Some(idx) if idx == array.len() => array.push(new),
+            _ => return Err(ErrorCode::KeyNotFound),
+        },
+        _ => return Err(ErrorCode::ConversionFailed),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Nested value with an object, an array and a member name that needs escaping
+    fn network() -> KvsValue {
+        KvsValue::Object(HashMap::from([
+            (
+                "network".to_string(),
+                KvsValue::Object(HashMap::from([(
+                    "eth0".to_string(),
+                    KvsValue::Object(HashMap::from([("mtu".to_string(), KvsValue::from(1500.0))])),
+                )])),
+            ),
+            (
+                "dns".to_string(),
+                KvsValue::from(vec![KvsValue::from("10.0.0.1".to_string())]),
+            ),
+            ("a/b~c".to_string(), KvsValue::Boolean(true)),
+        ]))
+    }
+
+    #[test]
+    fn test_get_nested() {
+        let value = network();
+        assert_eq!(
+            get(&value, "/network/eth0/mtu"),
+            Ok(&KvsValue::from(1500.0))
+        );
+        assert_eq!(
+            get(&value, "/dns/0"),
+            Ok(&KvsValue::from("10.0.0.1".to_string()))
+        );
+        assert_eq!(get(&value, ""), Ok(&value));
+    }
+
+    #[test]
+    fn test_get_escaped_token() {
+        assert_eq!(get(&network(), "/a~1b~0c"), Ok(&KvsValue::Boolean(true)));
+    }
+
+    #[test]
+    fn test_get_missing() {
+        let value = network();
+        assert_eq!(get(&value, "/network/eth1"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(get(&value, "/dns/1"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(get(&value, "/dns/-"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_get_invalid_index() {
+        let value = network();
+        assert_eq!(get(&value, "/dns/01"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(get(&value, "/dns/+0"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(get(&value, "/dns/"), Err(ErrorCode::KeyNotFound));
+    }
+
+    #[test]
+    fn test_get_into_scalar() {
+        assert_eq!(
+            get(&network(), "/network/eth0/mtu/x"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_invalid_pointer() {
+        let mut value = network();
+        assert_eq!(get(&value, "network"), Err(ErrorCode::ValidationFailed));
+        assert_eq!(get(&value, "/a~2"), Err(ErrorCode::ValidationFailed));
+        assert_eq!(get(&value, "/a~"), Err(ErrorCode::ValidationFailed));
+        assert_eq!(
+            set(&mut value, "dns", KvsValue::Null),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(value, network());
+    }
+
+    #[test]
+    fn test_set_member() {
+        let mut value = network();
+        set(&mut value, "/network/eth0/mtu", KvsValue::from(9000.0)).unwrap();
+        set(&mut value, "/network/eth0/up", KvsValue::Boolean(true)).unwrap();
+        assert_eq!(
+            get(&value, "/network/eth0/mtu"),
+            Ok(&KvsValue::from(9000.0))
+        );
+        assert_eq!(
+            get(&value, "/network/eth0/up"),
+            Ok(&KvsValue::Boolean(true))
+        );
+    }
+
+    #[test]
+    fn test_set_element() {
+        let mut value = network();
+        set(&mut value, "/dns/-", KvsValue::from("10.0.0.2".to_string())).unwrap();
+        set(&mut value, "/dns/0", KvsValue::Null).unwrap();
+        set(&mut value, "/dns/2", KvsValue::Boolean(false)).unwrap();
+        assert_eq!(
+            get(&value, "/dns"),
+            Ok(&KvsValue::from(vec![
+                KvsValue::Null,
+                KvsValue::from("10.0.0.2".to_string()),
+                KvsValue::Boolean(false),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_set_out_of_range() {
+        let mut value = network();
+        assert_eq!(
+            set(&mut value, "/dns/5", KvsValue::Null),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            set(&mut value, "/dns/x", KvsValue::Null),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(value, network());
+    }
+
+    #[test]
+    fn test_set_missing_container() {
+        let mut value = network();
+        assert_eq!(
+            set(&mut value, "/wifi/ssid", KvsValue::Null),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            set(&mut value, "/dns/3/name", KvsValue::Null),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(value, network());
+    }
+
+    #[test]
+    fn test_set_into_scalar() {
+        let mut value = network();
+        assert_eq!(
+            set(&mut value, "/network/eth0/mtu/x", KvsValue::Null),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(
+            set(&mut value, "/a~1b~0c/x/y", KvsValue::Null),
+            Err(ErrorCode::ConversionFailed)
+        );
+        assert_eq!(value, network());
+    }
+
+    #[test]
+    fn test_set_whole_value() {
+        let mut value = network();
+        set(&mut value, "", KvsValue::Null).unwrap();
+        assert_eq!(value, KvsValue::Null);
+    }
+}
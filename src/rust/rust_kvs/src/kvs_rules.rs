@@ -0,0 +1,277 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cross-key consistency rules
+//!
+//! Some keys are only valid in combination, e.g. `net.mode = "static"` requires `net.ip`. A rule
+//! is a named closure that checks the whole KVS state and returns a message if it's violated.
+//! Rules are evaluated on transaction commit against the state after the commit and on flush
+//! against the current state. Single `set_value` calls aren't checked, so a consistent state can
+//! be reached step by step before it's flushed.
+//!
+//! Rules are called with the KVS data locked and must not call back into the KVS.
+//!
+//! Restoring a snapshot can reintroduce values that violate current constraints. Restored data
+//! is passed to the restore hook first, which may transform it or veto the restore, and then has
+//! to pass strict mode and the rules before it replaces the current data.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_error;
+use crate::kvs_value::{KvsMap, KvsValue};
+use core::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Consistency rule
+///
+/// Returns `Err` with a description of the violation if the state is inconsistent.
+pub type KvsRule = Arc<dyn Fn(&RuleContext) -> Result<(), String> + Send + Sync>;
+
+/// Restore hook
+///
+/// Called with the data of a snapshot before it's restored. The hook may modify the data or
+/// return `Err` with a reason to veto the restore. It's called without any KVS lock held.
+pub type KvsRestoreHook = Arc<dyn Fn(&mut KvsMap) -> Result<(), String> + Send + Sync>;
+
+/// KVS state a rule is evaluated against
+pub struct RuleContext<'a> {
+    /// Stored values
+    data: &'a KvsMap,
+
+    /// Static default values
+    defaults: &'a KvsMap,
+}
+
+impl<'a> RuleContext<'a> {
+    pub(crate) fn new(data: &'a KvsMap, defaults: &'a KvsMap) -> Self {
+        Self { data, defaults }
+    }
+
+    /// Return the value of a key, falling back to its static default
+    pub fn get(&self, key: &str) -> Option<&KvsValue> {
+        self.data.get(key).or_else(|| self.defaults.get(key))
+    }
+
+    /// Return if a key has a stored value, defaults aren't considered
+    pub fn is_set(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    /// Return the stored keys
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.data.keys()
+    }
+}
+
+/// Violated rule
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleViolation {
+    /// Name the rule was registered with
+    pub rule: String,
+
+    /// Description returned by the rule
+    pub message: String,
+}
+
+impl fmt::Display for RuleViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+/// Registered rules of a KVS instance
+#[derive(Default)]
+pub(crate) struct Rules {
+    /// Rules by name in registration order
+    rules: Mutex<Vec<(String, KvsRule)>>,
+}
+
+impl Rules {
+    /// Register a rule, an existing rule with the same name is replaced
+    pub(crate) fn add(&self, name: String, rule: KvsRule) -> Result<(), ErrorCode> {
+        let mut rules = self.rules.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        match rules.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = rule,
+            None => rules.push((name, rule)),
+        }
+        Ok(())
+    }
+
+    /// Remove a rule, returns if it was registered
+    pub(crate) fn remove(&self, name: &str) -> Result<bool, ErrorCode> {
+        let mut rules = self.rules.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        let count = rules.len();
+        rules.retain(|(n, _)| n != name);
+        Ok(rules.len() != count)
+    }
+
+    /// Return if no rule is registered
+    pub(crate) fn is_empty(&self) -> Result<bool, ErrorCode> {
+        Ok(self
+            .rules
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .is_empty())
+    }
+
+    /// Evaluate all rules
+    ///
+    /// # Return Values
+    ///   * Ok: Violations in registration order, empty if the state is consistent
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn evaluate(&self, ctx: &RuleContext) -> Result<Vec<RuleViolation>, ErrorCode> {
+        let rules = self
+            .rules
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .clone();
+        Ok(rules
+            .into_iter()
+            .filter_map(|(rule, check)| {
+                check(ctx)
+                    .err()
+                    .map(|message| RuleViolation { rule, message })
+            })
+            .collect())
+    }
+}
+
+/// Log violations and turn them into an error
+pub(crate) fn reject(violations: &[RuleViolation]) -> Result<(), ErrorCode> {
+    if violations.is_empty() {
+        return Ok(());
+    }
+    for violation in violations {
+        log_error!("consistency rule violated: {violation}");
+    }
+    Err(ErrorCode::ValidationFailed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn static_ip_rule() -> KvsRule {
+        Arc::new(|ctx: &RuleContext| {
+            if ctx.get("net.mode") == Some(&KvsValue::from("static".to_string()))
+                && !ctx.is_set("net.ip")
+            {
+                return Err("net.mode 'static' requires net.ip".to_string());
+            }
+            Ok(())
+        })
+    }
+
+    fn static_defaults() -> KvsMap {
+        KvsMap::from([("net.mode".to_string(), KvsValue::from("static".to_string()))])
+    }
+
+    fn static_ip_violation() -> RuleViolation {
+        RuleViolation {
+            rule: "static_ip".to_string(),
+            message: "net.mode 'static' requires net.ip".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_no_rules_no_violations() {
+        let rules = Rules::default();
+        assert!(rules
+            .evaluate(&RuleContext::new(&KvsMap::new(), &static_defaults()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_violation_reported() {
+        let rules = Rules::default();
+        rules
+            .add("static_ip".to_string(), static_ip_rule())
+            .unwrap();
+        let violations = rules
+            .evaluate(&RuleContext::new(&KvsMap::new(), &static_defaults()))
+            .unwrap();
+        assert_eq!(violations, vec![static_ip_violation()]);
+        assert_eq!(reject(&violations), Err(ErrorCode::ValidationFailed));
+    }
+
+    #[test]
+    fn test_consistent_state_passes() {
+        let rules = Rules::default();
+        rules
+            .add("static_ip".to_string(), static_ip_rule())
+            .unwrap();
+        let data = KvsMap::from([("net.ip".to_string(), KvsValue::from("10.0.0.2".to_string()))]);
+        let violations = rules
+            .evaluate(&RuleContext::new(&data, &static_defaults()))
+            .unwrap();
+        assert_eq!(reject(&violations), Ok(()));
+    }
+
+    #[test]
+    fn test_stored_value_overrides_default() {
+        let rules = Rules::default();
+        rules
+            .add("static_ip".to_string(), static_ip_rule())
+            .unwrap();
+        let data = KvsMap::from([("net.mode".to_string(), KvsValue::from("dhcp".to_string()))]);
+        let defaults = static_defaults();
+        let ctx = RuleContext::new(&data, &defaults);
+        assert!(rules.evaluate(&ctx).unwrap().is_empty());
+        assert!(ctx.is_set("net.mode"));
+        assert!(!ctx.is_set("net.ip"));
+        assert_eq!(ctx.keys().collect::<Vec<_>>(), vec!["net.mode"]);
+    }
+
+    #[test]
+    fn test_violations_in_registration_order() {
+        let rules = Rules::default();
+        rules
+            .add("first".to_string(), Arc::new(|_| Err("1".to_string())))
+            .unwrap();
+        rules
+            .add("static_ip".to_string(), static_ip_rule())
+            .unwrap();
+        rules
+            .add("last".to_string(), Arc::new(|_| Err("2".to_string())))
+            .unwrap();
+        let violations = rules
+            .evaluate(&RuleContext::new(&KvsMap::new(), &static_defaults()))
+            .unwrap();
+        let names: Vec<&str> = violations.iter().map(|v| v.rule.as_str()).collect();
+        assert_eq!(names, vec!["first", "static_ip", "last"]);
+        assert_eq!(
+            violations[1].to_string(),
+            "static_ip: net.mode 'static' requires net.ip"
+        );
+    }
+
+    #[test]
+    fn test_rule_replaced() {
+        let rules = Rules::default();
+        rules.add("a".to_string(), static_ip_rule()).unwrap();
+        rules.add("a".to_string(), Arc::new(|_| Ok(()))).unwrap();
+        assert!(rules
+            .evaluate(&RuleContext::new(&KvsMap::new(), &static_defaults()))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_rule_removed() {
+        let rules = Rules::default();
+        assert!(rules.is_empty().unwrap());
+        rules.add("a".to_string(), static_ip_rule()).unwrap();
+        assert!(!rules.is_empty().unwrap());
+        assert!(rules.remove("a").unwrap());
+        assert!(!rules.remove("a").unwrap());
+        assert!(rules.is_empty().unwrap());
+    }
+}
@@ -0,0 +1,344 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON Schemas of structured values
+//!
+//! A defaults file can attach a JSON Schema to keys in the member `"$schemas"`, an object of glob
+//! key patterns and schemas. Every new value of a matching key, and the default value itself, has
+//! to conform to the schema, else the write fails with `ErrorCode::ValidationFailed` and the
+//! JSON Pointer of the offending field is logged.
+//!
+//! ```json
+//! {
+//!   "$schemas": {
+//!     "net/*": {
+//!       "type": "object",
+//!       "required": ["ip"],
+//!       "properties": {"ip": {"type": "string"}, "port": {"type": "integer", "maximum": 65535}},
+//!       "additionalProperties": false
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! The supported keywords are `type`, `enum`, `const`, `properties`, `required`,
+//! `additionalProperties`, `items`, `minItems`, `maxItems`, `minLength`, `maxLength`, `minimum`,
+//! `maximum`, `exclusiveMinimum` and `exclusiveMaximum`, other keywords are ignored. Byte arrays
+//! don't match any type.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_glob::glob_matches;
+use crate::kvs_log::log_error;
+use crate::kvs_value::{KvsMap, KvsValue};
+
+/// Member of the defaults document holding the schemas
+pub(crate) const SCHEMAS_KEY: &str = "$schemas";
+
+/// Schemas of a KVS instance by glob key pattern
+#[derive(Default)]
+pub(crate) struct KeySchemas {
+    schemas: Vec<(String, KvsValue)>,
+}
+
+/// Return the numeric value of a number
+fn number(value: &KvsValue) -> Option<f64> {
+    match value {
+        KvsValue::Number(n) => Some(*n),
+        KvsValue::I64(n) => Some(*n as f64),
+        KvsValue::U64(n) => Some(*n as f64),
+        _ => None,
+    }
+}
+
+/// Compare two values as JSON, numbers of different representations are equal by value
+fn json_eq(a: &KvsValue, b: &KvsValue) -> bool {
+    match (a, b) {
+        (KvsValue::Array(a), KvsValue::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| json_eq(a, b))
+        }
+        (KvsValue::Object(a), KvsValue::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, a)| b.get(key).is_some_and(|b| json_eq(a, b)))
+        }
+        _ => match (number(a), number(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
+
+/// Return if a value has a JSON Schema type
+fn has_type(value: &KvsValue, ty: &str) -> bool {
+    match (ty, value) {
+        ("object", KvsValue::Object(_))
+        | ("array", KvsValue::Array(_))
+        | ("string", KvsValue::String(_))
+        | ("boolean", KvsValue::Boolean(_))
+        | ("null", KvsValue::Null)
+        | ("integer", KvsValue::I64(_) | KvsValue::U64(_)) => true,
+        ("integer", KvsValue::Number(n)) => n.fract() == 0.0,
+        ("number", value) => number(value).is_some(),
+        _ => false,
+    }
+}
+
+/// Return a keyword of a schema as unsigned integer
+fn limit(schema: &KvsMap, keyword: &str) -> Option<usize> {
+    schema.get(keyword).and_then(number).map(|n| n as usize)
+}
+
+/// Check a value against a schema
+///
+/// # Parameters
+///   * `schema`: Schema object or boolean
+///   * `value`: Value to check
+///   * `path`: JSON Pointer of the value
+///
+/// # Return Values
+///   * Ok: Value conforms
+///   * Err: JSON Pointer of the offending field and the violation
+fn validate(schema: &KvsValue, value: &KvsValue, path: &str) -> Result<(), (String, String)> {
+    let fail = |message: String| Err((path.to_string(), message));
+    let schema = match schema {
+        KvsValue::Boolean(true) => return Ok(()),
+        KvsValue::Object(schema) => schema,
+        _ => return fail("value not allowed".to_string()),
+    };
+
+    match schema.get("type") {
+        Some(KvsValue::String(ty)) if !has_type(value, ty) => {
+            return fail(format!("expected type '{ty}'"));
+        }
+        Some(KvsValue::Array(types))
+            if !types
+                .iter()
+                .any(|ty| matches!(ty, KvsValue::String(ty) if has_type(value, ty))) =>
+        {
+            return fail(format!("expected one of the types {types:?}"));
+        }
+        _ => {}
+    }
+    if let Some(KvsValue::Array(allowed)) = schema.get("enum") {
+        if !allowed.iter().any(|allowed| json_eq(allowed, value)) {
+            return fail("value not in enum".to_string());
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if !json_eq(expected, value) {
+            return fail("value doesn't match const".to_string());
+        }
+    }
+
+    match value {
+        KvsValue::Object(obj) => {
+            if let Some(KvsValue::Array(required)) = schema.get("required") {
+                for name in required {
+                    if let KvsValue::String(name) = name {
+                        if !obj.contains_key(name) {
+                            return fail(format!("missing required property '{name}'"));
+                        }
+                    }
+                }
+            }
+            let properties = match schema.get("properties") {
+                Some(KvsValue::Object(properties)) => Some(properties),
+                _ => None,
+            };
+            let mut names: Vec<&String> = obj.keys().collect();
+            names.sort();
+            for name in names {
+                let member_path = format!("{path}/{name}");
+                match (
+                    properties.and_then(|p| p.get(name)),
+                    schema.get("additionalProperties"),
+                ) {
+                    (Some(sub), _) | (None, Some(sub)) => validate(sub, &obj[name], &member_path)?,
+                    (None, None) => {}
+                }
+            }
+        }
+        KvsValue::Array(arr) => {
+            if let Some(min) = limit(schema, "minItems").filter(|min| arr.len() < *min) {
+                return fail(format!("fewer than {min} items"));
+            }
+            if let Some(max) = limit(schema, "maxItems").filter(|max| arr.len() > *max) {
+                return fail(format!("more than {max} items"));
+            }
+            if let Some(items) = schema.get("items") {
+                for (idx, item) in arr.iter().enumerate() {
+                    validate(items, item, &format!("{path}/{idx}"))?;
+                }
+            }
+        }
+        KvsValue::String(s) => {
+            let len = s.chars().count();
+            if let Some(min) = limit(schema, "minLength").filter(|min| len < *min) {
+                return fail(format!("shorter than {min} characters"));
+            }
+            if let Some(max) = limit(schema, "maxLength").filter(|max| len > *max) {
+                return fail(format!("longer than {max} characters"));
+            }
+        }
+        _ => {
+            if let Some(n) = number(value) {
+                for keyword in ["minimum", "maximum", "exclusiveMinimum", "exclusiveMaximum"] {
+                    let Some(bound) = schema.get(keyword).and_then(number) else {
+                        continue;
+                    };
+                    let holds = match keyword {
+                        "minimum" => n >= bound,
+                        "maximum" => n <= bound,
+                        "exclusiveMinimum" => n > bound,
+                        _ => n < bound,
+                    };
+                    if !holds {
+                        return fail(format!("{n} violates {keyword} {bound}"));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl KeySchemas {
+    /// Remove the schemas from the default values and check the defaults against them
+    ///
+    /// # Return Values
+    ///   * Ok: Schemas, empty if the defaults have none
+    ///   * `ErrorCode::ValidationFailed`: Malformed schemas or a default value doesn't conform
+    pub(crate) fn take(defaults: &mut KvsMap) -> Result<Self, ErrorCode> {
+        let schemas: Vec<(String, KvsValue)> = match defaults.remove(SCHEMAS_KEY) {
+            None => Vec::new(),
+            Some(KvsValue::Object(map)) => map.into_iter().collect(),
+            Some(other) => {
+                log_error!("defaults member '{SCHEMAS_KEY}' must be an object: {other:?}");
+                return Err(ErrorCode::ValidationFailed);
+            }
+        };
+        if let Some((pattern, _)) = schemas
+            .iter()
+            .find(|(_, schema)| !matches!(schema, KvsValue::Object(_) | KvsValue::Boolean(_)))
+        {
+            log_error!("schema of '{pattern}' must be an object or a boolean");
+            return Err(ErrorCode::ValidationFailed);
+        }
+
+        let schemas = Self { schemas };
+        for (key, value) in defaults.iter() {
+            schemas.check(key, value)?;
+        }
+        Ok(schemas)
+    }
+
+    /// Check a value against the schemas matching its key
+    ///
+    /// # Return Values
+    ///   * Ok: Value conforms to all matching schemas
+    ///   * `ErrorCode::ValidationFailed`: Value doesn't conform
+    pub(crate) fn check(&self, key: &str, value: &KvsValue) -> Result<(), ErrorCode> {
+        for (pattern, schema) in self.schemas.iter() {
+            if !glob_matches(pattern, key) {
+                continue;
+            }
+            validate(schema, value, "").map_err(|(path, message)| {
+                let path = if path.is_empty() { "/" } else { &path };
+                log_error!("value of {key} violates schema '{pattern}' at '{path}': {message}");
+                ErrorCode::ValidationFailed
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> KvsValue {
+        let json: tinyjson::JsonValue = json.parse().unwrap();
+        KvsValue::from(json)
+    }
+
+    #[test]
+    fn test_validate_schema() {
+        let schema = parse(
+            r#"{"type": "object", "required": ["ip"], "additionalProperties": false,
+                "properties": {
+                    "ip": {"type": "string", "minLength": 7},
+                    "mode": {"enum": ["dhcp", "static"]},
+                    "ports": {"type": "array", "maxItems": 2,
+                              "items": {"type": "integer", "minimum": 1, "maximum": 65535}}
+                }}"#,
+        );
+        let check = |json: &str| validate(&schema, &parse(json), "").map_err(|(path, _)| path);
+
+        assert_eq!(check(r#"{"ip": "10.0.0.1", "ports": [80, 443]}"#), Ok(()));
+        assert_eq!(check(r#"{"ip": "10.0.0.1", "mode": "static"}"#), Ok(()));
+        assert_eq!(check(r#"{"mode": "dhcp"}"#), Err("".to_string()));
+        assert_eq!(check(r#"{"ip": 1}"#), Err("/ip".to_string()));
+        assert_eq!(check(r#"{"ip": "1.1"}"#), Err("/ip".to_string()));
+        assert_eq!(
+            check(r#"{"ip": "10.0.0.1", "mode": "x"}"#),
+            Err("/mode".to_string())
+        );
+        assert_eq!(
+            check(r#"{"ip": "10.0.0.1", "ports": [80, 0.5]}"#),
+            Err("/ports/1".to_string())
+        );
+        assert_eq!(
+            check(r#"{"ip": "10.0.0.1", "ports": [1, 2, 3]}"#),
+            Err("/ports".to_string())
+        );
+        assert_eq!(
+            check(r#"{"ip": "10.0.0.1", "other": true}"#),
+            Err("/other".to_string())
+        );
+        assert_eq!(check("[]"), Err("".to_string()));
+
+        // Integers of any representation
+        let port = parse(r#"{"type": "integer", "exclusiveMaximum": 10, "const": 5}"#);
+        assert_eq!(validate(&port, &KvsValue::I64(5), ""), Ok(()));
+        assert!(validate(&port, &KvsValue::U64(10), "").is_err());
+        assert!(validate(&port, &KvsValue::from(4.0), "").is_err());
+    }
+
+    #[test]
+    fn test_key_schemas_take() {
+        let mut defaults = match parse(
+            r#"{"$schemas": {"net/*": {"type": "object"}}, "net/a": {}, "other": 1}"#,
+        ) {
+            KvsValue::Object(map) => map,
+            _ => unreachable!(),
+        };
+        let schemas = KeySchemas::take(&mut defaults).unwrap();
+        assert!(!defaults.contains_key(SCHEMAS_KEY));
+        assert_eq!(schemas.check("other", &KvsValue::from(1.0)), Ok(()));
+        assert_eq!(
+            schemas.check("net/b", &KvsValue::from(1.0)),
+            Err(ErrorCode::ValidationFailed)
+        );
+
+        // Defaults must conform to their schemas
+        let mut defaults = KvsMap::from([
+            (
+                SCHEMAS_KEY.to_string(),
+                parse(r#"{"a": {"type": "string"}}"#),
+            ),
+            ("a".to_string(), KvsValue::from(1.0)),
+        ]);
+        assert!(KeySchemas::take(&mut defaults).is_err());
+
+        let mut defaults = KvsMap::from([(SCHEMAS_KEY.to_string(), parse(r#"{"a": 1}"#))]);
+        assert!(KeySchemas::take(&mut defaults).is_err());
+    }
+}
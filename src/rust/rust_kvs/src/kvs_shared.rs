@@ -0,0 +1,263 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Thread-safe shared KVS handle
+//!
+//! All KVS operations take `&self` and synchronize internally, so a single instance can be used
+//! from multiple threads. [`GenericSharedKvs`] adds shared ownership: every clone refers to the
+//! same instance and the instance is dropped (and flushed, if enabled) with the last clone.
+//!
+//! Handles opened with [`KvsBuilder::build_shared`](crate::kvs_builder::KvsBuilder::build_shared)
+//! are looked up in a process-wide registry by KVS directory and instance ID, so every component
+//! opening the same instance gets a handle to the same data. This guarantees read-your-writes
+//! across handles: a value set through one handle is returned by all other handles of the
+//! instance right away, also before it's flushed. The registry entry lives as long as any of
+//! its handles, the settings of the first open apply to all of them. Isolated handles opt out
+//! of the registry with [`KvsBuilder::isolated`](crate::kvs_builder::KvsBuilder::isolated) and
+//! only see changes of other handles after these are flushed and the isolated handle reopened.
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_api::KvsApi;
+use crate::kvs_auto_flush;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_log::log_error;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, Weak};
+use std::time::Duration;
+
+/// Registered instances by backend type and filename prefix
+type Registry = HashMap<(TypeId, PathBuf), Weak<dyn Any + Send + Sync>>;
+
+/// Lock the process-wide registry of shared instances
+fn registry() -> MutexGuard<'static, Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    // Entries are only inserted or removed whole, a poisoned registry is still consistent
+    REGISTRY
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Return a handle of a registered instance or open and register it
+///
+/// The registry stays locked while the instance is opened, so concurrent opens of the same
+/// instance can't create two instances.
+///
+/// # Parameters
+///   * `prefix`: Filename prefix identifying the instance
+///   * `open`: Opens the instance if it isn't registered
+pub(crate) fn open_registered<J>(
+    prefix: PathBuf,
+    open: impl FnOnce() -> Result<GenericKvs<J>, ErrorCode>,
+) -> Result<GenericSharedKvs<J>, ErrorCode>
+where
+    J: KvsBackend + 'static,
+    GenericKvs<J>: Send + Sync,
+{
+    let mut registry = registry();
+    let key = (TypeId::of::<J>(), prefix);
+    if let Some(kvs) = registry.get(&key).and_then(Weak::upgrade) {
+        if let Ok(kvs) = kvs.downcast::<GenericKvs<J>>() {
+            return Ok(GenericSharedKvs {
+                kvs,
+                registered: true,
+            });
+        }
+    }
+
+    let kvs = Arc::new(open()?);
+    let entry: Arc<dyn Any + Send + Sync> = kvs.clone();
+    registry.insert(key, Arc::downgrade(&entry));
+    Ok(GenericSharedKvs {
+        kvs,
+        registered: true,
+    })
+}
+
+/// Shared handle of a KVS instance
+///
+/// Feature: `FEAT_REQ__KVS__thread_safety`
+pub struct GenericSharedKvs<J: KvsBackend> {
+    /// Shared KVS instance
+    kvs: Arc<GenericKvs<J>>,
+
+    /// Instance is listed in the registry
+    registered: bool,
+}
+
+impl<J: KvsBackend> GenericSharedKvs<J> {
+    /// Take ownership of a KVS instance to share it between threads
+    ///
+    /// # Parameters
+    ///   * `kvs`: KVS instance
+    pub fn new(kvs: GenericKvs<J>) -> Self {
+        Self {
+            kvs: Arc::new(kvs),
+            registered: false,
+        }
+    }
+
+    /// Return if a registry entry refers to this instance
+    fn is_entry(&self, entry: &Weak<dyn Any + Send + Sync>) -> bool {
+        std::ptr::addr_eq(entry.as_ptr(), Arc::as_ptr(&self.kvs))
+    }
+
+    /// Return the number of handles referring to the instance
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.kvs)
+    }
+
+    /// Enable the periodic flush of changed data by a worker thread
+    ///
+    /// Like [`GenericKvs::enable_auto_flush`], additionally a worker thread flushes the instance,
+    /// see [`kvs_auto_flush`](crate::kvs_auto_flush).
+    ///
+    /// # Parameters
+    ///   * `interval`: Maximum time between a flush and the next flush of changed data
+    ///
+    /// # Return Values
+    ///   * Ok: Auto-flush enabled and worker started
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: KVS is read-only
+    ///   * `ErrorCode::ValidationFailed`: Interval is 0
+    ///   * `ErrorCode::UnmappedError`: Worker thread couldn't be spawned
+    pub fn enable_auto_flush(&self, interval: Duration) -> Result<(), ErrorCode>
+    where
+        J: 'static,
+        GenericKvs<J>: Send + Sync,
+    {
+        let schedule = self.kvs.schedule_auto_flush(interval)?;
+        kvs_auto_flush::spawn_worker(&self.kvs, schedule)
+    }
+}
+
+impl<J: KvsBackend> Clone for GenericSharedKvs<J> {
+    fn clone(&self) -> Self {
+        Self {
+            kvs: self.kvs.clone(),
+            registered: self.registered,
+        }
+    }
+}
+
+impl<J: KvsBackend> Drop for GenericSharedKvs<J> {
+    fn drop(&mut self) {
+        if !self.registered {
+            return;
+        }
+
+        // Unregister and flush the last handle with the registry locked, so a concurrent open
+        // either gets this instance or reads the flushed file
+        let mut registry = registry();
+        if Arc::strong_count(&self.kvs) > 1 {
+            return;
+        }
+        registry.retain(|_, entry| entry.strong_count() > 0 && !self.is_entry(entry));
+        if self.kvs.take_flush_on_exit() {
+            if let Err(e) = self.kvs.flush() {
+                log_error!("GenericKvs::flush() failed in Drop: {e:?}");
+            }
+        }
+    }
+}
+
+impl<J: KvsBackend> Deref for GenericSharedKvs<J> {
+    type Target = GenericKvs<J>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.kvs
+    }
+}
+
+impl<J: KvsBackend> From<GenericKvs<J>> for GenericSharedKvs<J> {
+    fn from(kvs: GenericKvs<J>) -> Self {
+        Self::new(kvs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_shared_kvs_is_send_sync() {
+        assert_send_sync::<SharedKvs>();
+    }
+
+    #[test]
+    fn test_shared_kvs_auto_flush() {
+        let dir = tempdir().unwrap();
+        let kvs = SharedKvs::new(
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .flush_on_exit(false)
+                .build()
+                .unwrap(),
+        );
+        let flushed = Arc::new(AtomicUsize::new(0));
+        let count = flushed.clone();
+        kvs.set_flush_callback(move |_| {
+            count.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+
+        kvs.enable_auto_flush(Duration::from_millis(10)).unwrap();
+        // The worker doesn't hold a handle
+        assert_eq!(kvs.handle_count(), 1);
+        kvs.set_value("key", 1.0).unwrap();
+        let start = Instant::now();
+        while flushed.load(Ordering::SeqCst) == 0 {
+            assert!(start.elapsed() < Duration::from_secs(5), "not flushed");
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(dir.path().join("kvs_0_0.json").exists());
+    }
+
+    #[test]
+    fn test_shared_kvs_concurrent_access() {
+        let dir = tempdir().unwrap();
+        let kvs = SharedKvs::new(
+            KvsBuilder::<Kvs>::new(InstanceId::new(0))
+                .dir(dir.path().to_string_lossy().to_string())
+                .build()
+                .unwrap(),
+        );
+
+        let handles: Vec<_> = (0..4)
+            .map(|idx| {
+                let kvs = kvs.clone();
+                thread::spawn(move || {
+                    for n in 0..50 {
+                        kvs.set_value(format!("thread{idx}_{n}"), n as f64).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(kvs.handle_count(), 1);
+        assert_eq!(kvs.get_all_keys().unwrap().len(), 200);
+        assert_eq!(kvs.get_value_as::<f64>("thread3_49").unwrap(), 49.0);
+    }
+}
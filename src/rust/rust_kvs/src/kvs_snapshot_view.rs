@@ -0,0 +1,330 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Read-only snapshot views
+//!
+//! Analysis tools can query the values of an old snapshot while the live store keeps running.
+//! [`open_snapshot_view`](crate::kvs::GenericKvs::open_snapshot_view) loads and verifies the
+//! snapshot file into a [`KvsReadOnlyView`]. The view owns its copy of the data, so neither live
+//! changes nor later flushes rotating the snapshot affect it, and it never changes the live
+//! state.
+//!
+//! A view only holds the values stored in the snapshot, defaults aren't applied.
+//! [`snapshot_diff`](crate::kvs::GenericKvs::snapshot_diff) compares a snapshot with the stored
+//! values of the live store, e.g. to show what changed since the last known-good state.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::SnapshotId;
+use crate::kvs_log::log_error;
+use crate::kvs_value::{KvsMap, KvsValue};
+
+/// Kind of change of a key between a snapshot and the live store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeType {
+    /// Key isn't stored in the snapshot
+    Added,
+
+    /// Key is only stored in the snapshot
+    Removed,
+
+    /// Key has a different value in the live store
+    Changed,
+}
+
+/// Changed key between a snapshot and the live store
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyDiff {
+    /// Changed key
+    pub key: String,
+
+    /// Kind of change
+    pub change: ChangeType,
+
+    /// Value in the snapshot, `None` if added
+    pub old: Option<KvsValue>,
+
+    /// Value in the live store, `None` if removed
+    pub new: Option<KvsValue>,
+}
+
+/// Read-only copy of the data of a snapshot
+#[derive(Clone, Debug)]
+pub struct KvsReadOnlyView {
+    /// Snapshot the data was loaded from
+    id: SnapshotId,
+
+    /// Stored values of the snapshot
+    data: KvsMap,
+}
+
+impl KvsReadOnlyView {
+    /// Create a view of loaded snapshot data
+    pub(crate) fn new(id: SnapshotId, data: KvsMap) -> Self {
+        Self { id, data }
+    }
+
+    /// Return the ID of the snapshot at the time the view was opened
+    pub fn snapshot_id(&self) -> &SnapshotId {
+        &self.id
+    }
+
+    /// Return the count of stored keys
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Return if the snapshot has no stored keys
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Get the stored keys
+    ///
+    /// # Return Values
+    ///   * Keys in arbitrary order
+    pub fn get_all_keys(&self) -> Vec<String> {
+        self.data.keys().cloned().collect()
+    }
+
+    /// Get the stored keys starting with a prefix
+    ///
+    /// # Parameters
+    ///   * `prefix`: Key prefix, e.g. `diagnostics/`
+    ///
+    /// # Return Values
+    ///   * Matching keys in arbitrary order
+    pub fn get_keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        self.data
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Check if a key is stored in the snapshot
+    pub fn key_exists(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    /// Get the value of a key
+    ///
+    /// # Return Values
+    ///   * Ok: Stored value
+    ///   * `ErrorCode::KeyNotFound`: Key isn't stored in the snapshot
+    pub fn get_value(&self, key: &str) -> Result<&KvsValue, ErrorCode> {
+        self.data.get(key).ok_or(ErrorCode::KeyNotFound)
+    }
+
+    /// Get the value of a key as a specific type
+    ///
+    /// # Return Values
+    ///   * Ok: Type specific value
+    ///   * `ErrorCode::ConversionFailed`: Type conversion failed
+    ///   * `ErrorCode::KeyNotFound`: Key isn't stored in the snapshot
+    pub fn get_value_as<T>(&self, key: &str) -> Result<T, ErrorCode>
+    where
+        for<'a> T: TryFrom<&'a KvsValue>,
+        for<'a> <T as TryFrom<&'a KvsValue>>::Error: std::fmt::Debug,
+    {
+        T::try_from(self.get_value(key)?).map_err(|err| {
+            log_error!("get_value could not convert KvsValue from snapshot: {err:#?}");
+            ErrorCode::ConversionFailed
+        })
+    }
+
+    /// Compare the snapshot with newer data
+    ///
+    /// # Parameters
+    ///   * `current`: Stored values to compare with
+    ///
+    /// # Return Values
+    ///   * Changed keys sorted by key, unchanged keys aren't included
+    pub(crate) fn diff(&self, current: &KvsMap) -> Vec<KeyDiff> {
+        let removed = self
+            .data
+            .iter()
+            .filter(|(key, _)| !current.contains_key(*key))
+            .map(|(key, old)| KeyDiff {
+                key: key.clone(),
+                change: ChangeType::Removed,
+                old: Some(old.clone()),
+                new: None,
+            });
+        let set = current.iter().filter_map(|(key, new)| {
+            let old = self.data.get(key);
+            if old == Some(new) {
+                return None;
+            }
+            Some(KeyDiff {
+                key: key.clone(),
+                change: match old {
+                    Some(_) => ChangeType::Changed,
+                    None => ChangeType::Added,
+                },
+                old: old.cloned(),
+                new: Some(new.clone()),
+            })
+        });
+        let mut diff: Vec<KeyDiff> = removed.chain(set).collect();
+        diff.sort_by(|a, b| a.key.cmp(&b.key));
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use std::fs;
+    use tempfile::{tempdir, TempDir};
+
+    /// Open an instance with snapshot 1 holding `net/ip` and `mode`, and the flushed state
+    /// holding a changed `net/ip` only
+    fn open(dir: &TempDir) -> Kvs {
+        let kvs = Kvs::open_with_dir(
+            InstanceId::new(0),
+            dir.path(),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+        )
+        .unwrap();
+        kvs.flush_on_exit(false);
+        kvs.set_value("net/ip", "10.0.0.1".to_string()).unwrap();
+        kvs.set_value("mode", 1.0).unwrap();
+        kvs.flush().unwrap();
+        kvs.set_value("net/ip", "10.0.0.2".to_string()).unwrap();
+        kvs.remove_key("mode").unwrap();
+        kvs.flush().unwrap();
+        kvs
+    }
+
+    #[test]
+    fn test_view_keeps_snapshot_data() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let view = kvs.open_snapshot_view(SnapshotId::new(1)).unwrap();
+        kvs.set_value("net/ip", "10.0.0.3".to_string()).unwrap();
+        kvs.flush().unwrap();
+
+        assert_eq!(*view.snapshot_id(), SnapshotId::new(1));
+        assert_eq!(view.len(), 2);
+        assert_eq!(view.get_value_as::<String>("net/ip").unwrap(), "10.0.0.1");
+        assert_eq!(view.get_value_as::<f64>("mode").unwrap(), 1.0);
+        assert_eq!(view.get_keys_with_prefix("net/"), vec!["net/ip"]);
+        assert_eq!(kvs.get_value_as::<String>("net/ip").unwrap(), "10.0.0.3");
+        assert!(!kvs.key_exists("mode").unwrap());
+    }
+
+    #[test]
+    fn test_view_missing_key() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let view = kvs.open_snapshot_view(SnapshotId::new(1)).unwrap();
+        assert!(!view.key_exists("other"));
+        assert_eq!(view.get_value("other"), Err(ErrorCode::KeyNotFound));
+        assert_eq!(
+            view.get_value_as::<f64>("other"),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert!(view.get_keys_with_prefix("other/").is_empty());
+    }
+
+    #[test]
+    fn test_view_type_mismatch() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let view = kvs.open_snapshot_view(SnapshotId::new(1)).unwrap();
+        assert_eq!(
+            view.get_value_as::<bool>("mode"),
+            Err(ErrorCode::ConversionFailed)
+        );
+    }
+
+    #[test]
+    fn test_view_of_flushed_state() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("unflushed", true).unwrap();
+        let current = kvs.open_snapshot_view(SnapshotId::new(0)).unwrap();
+        assert_eq!(current.get_all_keys(), vec!["net/ip"]);
+        assert!(!current.key_exists("mode"));
+        assert!(!current.key_exists("unflushed"));
+    }
+
+    #[test]
+    fn test_view_invalid_snapshot_id() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        assert_eq!(
+            kvs.open_snapshot_view(SnapshotId::new(9)).err(),
+            Some(ErrorCode::InvalidSnapshotId)
+        );
+        assert_eq!(
+            kvs.snapshot_diff(SnapshotId::new(9)).err(),
+            Some(ErrorCode::InvalidSnapshotId)
+        );
+    }
+
+    #[test]
+    fn test_view_of_corrupted_snapshot() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        let path = dir.path().join("kvs_0_1.json");
+        let data = fs::read_to_string(&path)
+            .unwrap()
+            .replace("10.0.0.1", "10.6.6.6");
+        fs::write(&path, data).unwrap();
+        assert_eq!(
+            kvs.open_snapshot_view(SnapshotId::new(1)).err(),
+            Some(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let dir = tempdir().unwrap();
+        let kvs = open(&dir);
+        kvs.set_value("net/ip", "10.0.0.3".to_string()).unwrap();
+        kvs.set_value("added", true).unwrap();
+
+        let diff = kvs.snapshot_diff(SnapshotId::new(1)).unwrap();
+        let changes: Vec<(&str, ChangeType)> = diff
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.change))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("added", ChangeType::Added),
+                ("mode", ChangeType::Removed),
+                ("net/ip", ChangeType::Changed),
+            ]
+        );
+        assert_eq!(diff[0].old, None);
+        assert_eq!(diff[1].old, Some(KvsValue::from(1.0)));
+        assert_eq!(diff[1].new, None);
+        assert_eq!(diff[2].new, Some(KvsValue::from("10.0.0.3".to_string())));
+    }
+
+    #[test]
+    fn test_diff_without_changes() {
+        let view = KvsReadOnlyView::new(
+            SnapshotId::new(1),
+            KvsMap::from([("a".to_string(), KvsValue::from(1.0))]),
+        );
+        assert!(view
+            .diff(&KvsMap::from([("a".to_string(), KvsValue::from(1.0))]))
+            .is_empty());
+        assert!(KvsReadOnlyView::new(SnapshotId::new(1), KvsMap::new())
+            .diff(&KvsMap::new())
+            .is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-key access statistics
+//!
+//! Counts reads and writes of every key to decide what to cache or prune. Reads are
+//! [`get_value`](crate::kvs_api::KvsApi::get_value) and
+//! [`get_value_as`](crate::kvs_api::KvsApi::get_value_as) calls, writes are sets and removals,
+//! also within transactions. With [`KeyStatsMode::Persistent`](crate::kvs_api::KeyStatsMode) the
+//! counters are written to `kvs_<instance_id>_stats.json` on flush and continued on the next
+//! open.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Durability;
+use crate::kvs_log::log_error;
+use crate::kvs_platform::atomic_replace;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tinyjson::JsonValue;
+
+/// Access counters of a key
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyStats {
+    /// Count of reads
+    pub reads: u64,
+
+    /// Count of writes (sets and removals)
+    pub writes: u64,
+}
+
+impl KeyStats {
+    /// Return the count of all accesses
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes
+    }
+}
+
+/// Access counters of a KVS instance
+pub(crate) struct KeyCounters {
+    /// Statistics file, `None` if the counters aren't persisted
+    path: Option<PathBuf>,
+
+    /// Synchronization of the written file
+    durability: Durability,
+
+    /// Counters by key
+    stats: Mutex<HashMap<String, KeyStats>>,
+}
+
+/// Parse a statistics file
+///
+/// Malformed entries are skipped, the statistics are only informational.
+fn parse_stats(data: &str) -> Result<HashMap<String, KeyStats>, ErrorCode> {
+    let json: JsonValue = data.parse()?;
+    let obj: &HashMap<String, JsonValue> = json.get().ok_or(ErrorCode::JsonParserError)?;
+
+    let counter = |entry: &HashMap<String, JsonValue>, name: &str| {
+        entry
+            .get(name)
+            .and_then(|n| n.get::<f64>())
+            .map(|n| *n as u64)
+            .unwrap_or(0)
+    };
+    Ok(obj
+        .iter()
+        .filter_map(|(key, entry)| {
+            let entry: &HashMap<String, JsonValue> = entry.get()?;
+            let stats = KeyStats {
+                reads: counter(entry, "reads"),
+                writes: counter(entry, "writes"),
+            };
+            Some((key.clone(), stats))
+        })
+        .collect())
+}
+
+impl KeyCounters {
+    /// Create the counters
+    ///
+    /// # Parameters
+    ///   * `path`: Statistics file to load and persist to, `None` to keep the counters in memory
+    ///   * `durability`: Synchronization of the written file
+    pub(crate) fn new(path: Option<PathBuf>, durability: Durability) -> Self {
+        let stats = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| match parse_stats(&data) {
+                Ok(stats) => Some(stats),
+                Err(e) => {
+                    log_error!("key statistics could not be parsed, starting empty: {e:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            durability,
+            stats: Mutex::new(stats),
+        }
+    }
+
+    /// Count a read of a key
+    pub(crate) fn read(&self, key: &str) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.entry(key.to_string()).or_default().reads += 1;
+        }
+    }
+
+    /// Count a write of a key
+    pub(crate) fn write(&self, key: &str) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.entry(key.to_string()).or_default().writes += 1;
+        }
+    }
+
+    /// Return the counters of all accessed keys, most active first
+    pub(crate) fn sorted(&self) -> Result<Vec<(String, KeyStats)>, ErrorCode> {
+        let stats = self.stats.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        let mut sorted: Vec<(String, KeyStats)> =
+            stats.iter().map(|(k, v)| (k.clone(), *v)).collect();
+        sorted.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(&b.0)));
+        Ok(sorted)
+    }
+
+    /// Write the counters to the statistics file, no-op if they aren't persisted
+    pub(crate) fn save(&self) -> Result<(), ErrorCode> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = {
+            let stats = self.stats.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+            JsonValue::Object(
+                stats
+                    .iter()
+                    .map(|(key, stats)| {
+                        let entry = HashMap::from([
+                            ("reads".to_string(), JsonValue::Number(stats.reads as f64)),
+                            ("writes".to_string(), JsonValue::Number(stats.writes as f64)),
+                        ]);
+                        (key.clone(), JsonValue::Object(entry))
+                    })
+                    .collect(),
+            )
+        };
+        atomic_replace(path, json.stringify()?.as_bytes(), self.durability)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_key_counters_sorted() {
+        let counters = KeyCounters::new(None, Durability::default());
+        counters.write("b");
+        counters.read("a");
+        counters.read("b");
+        counters.read("c");
+
+        let sorted = counters.sorted().unwrap();
+        assert_eq!(
+            sorted,
+            vec![
+                (
+                    "b".to_string(),
+                    KeyStats {
+                        reads: 1,
+                        writes: 1
+                    }
+                ),
+                (
+                    "a".to_string(),
+                    KeyStats {
+                        reads: 1,
+                        writes: 0
+                    }
+                ),
+                (
+                    "c".to_string(),
+                    KeyStats {
+                        reads: 1,
+                        writes: 0
+                    }
+                ),
+            ]
+        );
+        assert!(counters.save().is_ok());
+    }
+
+    #[test]
+    fn test_key_counters_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_stats.json");
+
+        let counters = KeyCounters::new(Some(path.clone()), Durability::default());
+        counters.read("a");
+        counters.write("a");
+        counters.save().unwrap();
+
+        let counters = KeyCounters::new(Some(path.clone()), Durability::default());
+        counters.read("a");
+        assert_eq!(
+            counters.sorted().unwrap(),
+            vec![(
+                "a".to_string(),
+                KeyStats {
+                    reads: 2,
+                    writes: 1
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn test_corrupt_counters_ignored() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_stats.json");
+        for data in ["{", "[]", r#"{"a":1}"#] {
+            fs::write(&path, data).unwrap();
+            let counters = KeyCounters::new(Some(path.clone()), Durability::default());
+            assert!(counters.sorted().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_counters_unwritable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("kvs_0_stats.json");
+        let counters = KeyCounters::new(Some(path), Durability::default());
+        counters.write("a");
+        assert!(counters.save().is_err());
+        assert_eq!(counters.sorted().unwrap().len(), 1);
+    }
+}
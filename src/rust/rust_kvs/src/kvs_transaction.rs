@@ -0,0 +1,213 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::error_code::ErrorCode;
+use crate::kvs::GenericKvs;
+use crate::kvs_backend::KvsBackend;
+use crate::kvs_rules::RuleViolation;
+use crate::kvs_value::KvsValue;
+
+/// Staged mutation of a transaction
+pub(crate) enum KvsOperation {
+    /// Assign a value to a key
+    Set(String, KvsValue),
+
+    /// Remove a key
+    Remove(String),
+}
+
+/// Group of `set`/`remove` operations applied atomically
+///
+/// Operations are staged and only become visible when [`commit`](Self::commit) is called. The
+/// commit applies all operations under a single lock, so a concurrent flush either persists all
+/// of them or none. Dropping the transaction without committing discards the staged operations.
+pub struct KvsTransaction<'a, J: KvsBackend> {
+    /// KVS the transaction is applied to
+    kvs: &'a GenericKvs<J>,
+
+    /// Staged operations in call order
+    ops: Vec<KvsOperation>,
+}
+
+impl<'a, J: KvsBackend> KvsTransaction<'a, J> {
+    /// Create an empty transaction for a KVS
+    pub(crate) fn new(kvs: &'a GenericKvs<J>) -> Self {
+        Self {
+            kvs,
+            ops: Vec::new(),
+        }
+    }
+
+    /// Stage assigning a value to a given key
+    ///
+    /// # Parameters
+    ///   * `key`: Key to set value
+    ///   * `value`: Value to be set
+    pub fn set_value<S: Into<String>, V: Into<KvsValue>>(&mut self, key: S, value: V) {
+        self.ops.push(KvsOperation::Set(key.into(), value.into()));
+    }
+
+    /// Stage removing a key
+    ///
+    /// # Parameters
+    ///   * `key`: Key to remove
+    pub fn remove_key(&mut self, key: &str) {
+        self.ops.push(KvsOperation::Remove(key.to_string()));
+    }
+
+    /// Return the number of staged operations
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Return if no operation was staged
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Apply all staged operations atomically
+    ///
+    /// Either all operations are applied or, on error, none of them.
+    ///
+    /// # Return Values
+    ///   * Ok: All operations applied
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::KeyNotFound`: A staged remove refers to a key that doesn't exist
+    ///   * `ErrorCode::ValidationFailed`: Strict mode is enabled and a staged key has no default, a
+    ///     staged value is rejected by a validator or a consistency rule is violated after the
+    ///     commit
+    pub fn commit(self) -> Result<(), ErrorCode> {
+        self.kvs.apply_operations(self.ops)
+    }
+
+    /// Evaluate the consistency rules against the state the commit would result in
+    ///
+    /// # Return Values
+    ///   * Ok: Violations that would reject the commit, empty if it's consistent
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub fn check_rules(&self) -> Result<Vec<RuleViolation>, ErrorCode> {
+        self.kvs.rule_violations(&self.ops)
+    }
+
+    /// Discard all staged operations
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use tempfile::tempdir;
+
+    fn new_kvs() -> (Kvs, tempfile::TempDir) {
+        let dir = tempdir().unwrap();
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Optional,
+            OpenNeedKvs::Optional,
+            Some(dir.path().to_string_lossy().to_string()),
+        )
+        .unwrap();
+        (kvs, dir)
+    }
+
+    #[test]
+    fn test_transaction_commit() {
+        let (kvs, _dir) = new_kvs();
+        kvs.set_value("old", 1.0).unwrap();
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("a", 1.0);
+        tx.set_value("b", true);
+        tx.remove_key("old");
+        assert_eq!(tx.len(), 3);
+
+        // Staged operations are not visible before commit
+        assert!(!kvs.key_exists("a").unwrap());
+        assert!(kvs.key_exists("old").unwrap());
+
+        tx.commit().unwrap();
+        assert_eq!(kvs.get_value_as::<f64>("a").unwrap(), 1.0);
+        assert!(kvs.get_value_as::<bool>("b").unwrap());
+        assert!(!kvs.key_exists("old").unwrap());
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let (kvs, _dir) = new_kvs();
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("a", 1.0);
+        tx.rollback();
+
+        assert!(!kvs.key_exists("a").unwrap());
+    }
+
+    #[test]
+    fn test_transaction_commit_is_all_or_nothing() {
+        let (kvs, _dir) = new_kvs();
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("a", 1.0);
+        tx.remove_key("missing");
+        assert_eq!(tx.commit(), Err(ErrorCode::KeyNotFound));
+
+        assert!(!kvs.key_exists("a").unwrap());
+    }
+
+    #[test]
+    fn test_transaction_consistency_rules() {
+        let (kvs, _dir) = new_kvs();
+        kvs.add_rule("static_ip", |ctx: &RuleContext| match ctx.get("net.mode") {
+            Some(KvsValue::String(mode)) if mode == "static" && !ctx.is_set("net.ip") => {
+                Err("static mode requires net.ip".to_string())
+            }
+            _ => Ok(()),
+        })
+        .unwrap();
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("net.mode", "static".to_string());
+        assert_eq!(
+            tx.check_rules().unwrap(),
+            vec![RuleViolation {
+                rule: "static_ip".to_string(),
+                message: "static mode requires net.ip".to_string(),
+            }]
+        );
+        assert_eq!(tx.commit(), Err(ErrorCode::ValidationFailed));
+        assert!(!kvs.key_exists("net.mode").unwrap());
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("net.mode", "static".to_string());
+        tx.set_value("net.ip", "10.0.0.2".to_string());
+        assert!(tx.check_rules().unwrap().is_empty());
+        tx.commit().unwrap();
+
+        // Single sets aren't checked, but the inconsistent state isn't flushed
+        kvs.remove_key("net.ip").unwrap();
+        assert_eq!(kvs.check_rules().unwrap().len(), 1);
+        assert_eq!(kvs.flush(), Err(ErrorCode::ValidationFailed));
+        assert!(kvs.remove_rule("static_ip").unwrap());
+        kvs.flush().unwrap();
+    }
+
+    #[test]
+    fn test_transaction_remove_staged_key() {
+        let (kvs, _dir) = new_kvs();
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("a", 1.0);
+        tx.remove_key("a");
+        tx.commit().unwrap();
+
+        assert!(!kvs.key_exists("a").unwrap());
+    }
+}
@@ -0,0 +1,294 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Store size history
+//!
+//! Flash planning needs to know how fast an instance grows. With
+//! [`KvsOptions::size_history`](crate::kvs_api::KvsOptions::size_history) enabled the size of the
+//! KVS file and its hash file is sampled after a flush, at most once per [`SAMPLE_INTERVAL`], and
+//! the last [`MAX_SAMPLES`] samples are kept in `kvs_<instance_id>_size_history.json`. The growth
+//! rate is the least-squares slope of the samples, see
+//! [`growth_trend`](crate::kvs::GenericKvs::growth_trend).
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Durability;
+use crate::kvs_api::GrowthTrend;
+use crate::kvs_log::log_error;
+use crate::kvs_platform::atomic_replace;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tinyjson::JsonValue;
+
+/// Maximum count of kept samples
+pub(crate) const MAX_SAMPLES: usize = 64;
+
+/// Minimum time between two samples
+pub(crate) const SAMPLE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Seconds per day
+const DAY: f64 = 24.0 * 60.0 * 60.0;
+
+/// Size of the store at a point in time
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SizeSample {
+    /// Seconds since the Unix epoch
+    time: u64,
+
+    /// Size in bytes
+    size: u64,
+}
+
+/// Size samples of a KVS instance, oldest first
+pub(crate) struct SizeHistory {
+    /// History file, `None` if the samples aren't persisted
+    path: Option<PathBuf>,
+
+    /// Synchronization of the written file
+    durability: Durability,
+
+    /// Kept samples
+    samples: Mutex<VecDeque<SizeSample>>,
+}
+
+/// Parse a history file of `[time, size]` pairs
+///
+/// Malformed entries are skipped.
+fn parse_samples(data: &str) -> Result<VecDeque<SizeSample>, ErrorCode> {
+    let json: JsonValue = data.parse()?;
+    let samples: &Vec<JsonValue> = json.get().ok_or(ErrorCode::JsonParserError)?;
+    Ok(samples
+        .iter()
+        .filter_map(|sample| {
+            let pair: &Vec<JsonValue> = sample.get()?;
+            Some(SizeSample {
+                time: *pair.first()?.get::<f64>()? as u64,
+                size: *pair.get(1)?.get::<f64>()? as u64,
+            })
+        })
+        .collect())
+}
+
+/// Return the seconds since the Unix epoch
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+impl SizeHistory {
+    /// Create the history
+    ///
+    /// # Parameters
+    ///   * `path`: History file to load and persist to, `None` to keep the samples in memory
+    ///   * `durability`: Synchronization of the written file
+    pub(crate) fn new(path: Option<PathBuf>, durability: Durability) -> Self {
+        let samples = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| match parse_samples(&data) {
+                Ok(samples) => Some(samples),
+                Err(e) => {
+                    log_error!("size history could not be parsed, starting empty: {e:?}");
+                    None
+                }
+            })
+            .unwrap_or_default();
+        Self {
+            path,
+            durability,
+            samples: Mutex::new(samples),
+        }
+    }
+
+    /// Record the store size if the last sample is older than the sample interval
+    ///
+    /// # Parameters
+    ///   * `size`: Store size in bytes
+    ///   * `now`: Sample time
+    ///
+    /// # Return Values
+    ///   * Ok: Sample recorded or skipped
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: History file couldn't be written
+    pub(crate) fn record(&self, size: u64, now: SystemTime) -> Result<(), ErrorCode> {
+        let time = unix_secs(now);
+        let mut samples = self
+            .samples
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        if samples
+            .back()
+            .is_some_and(|last| time < last.time + SAMPLE_INTERVAL.as_secs())
+        {
+            return Ok(());
+        }
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(SizeSample { time, size });
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = JsonValue::Array(
+            samples
+                .iter()
+                .map(|sample| {
+                    JsonValue::Array(vec![
+                        JsonValue::Number(sample.time as f64),
+                        JsonValue::Number(sample.size as f64),
+                    ])
+                })
+                .collect(),
+        );
+        atomic_replace(path, json.stringify()?.as_bytes(), self.durability)
+    }
+
+    /// Estimate the growth of the store from the samples
+    ///
+    /// # Return Values
+    ///   * Ok: Growth trend
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn trend(&self) -> Result<GrowthTrend, ErrorCode> {
+        let samples = self
+            .samples
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let count = samples.len() as f64;
+        let mean_time = samples.iter().map(|s| s.time as f64).sum::<f64>() / count;
+        let mean_size = samples.iter().map(|s| s.size as f64).sum::<f64>() / count;
+        let (covariance, variance) = samples.iter().fold((0.0, 0.0), |(cov, var), s| {
+            let dt = s.time as f64 - mean_time;
+            (cov + dt * (s.size as f64 - mean_size), var + dt * dt)
+        });
+        Ok(GrowthTrend {
+            sample_count: samples.len(),
+            current_size: samples.back().map(|s| s.size).unwrap_or_default(),
+            bytes_per_day: (variance > 0.0).then(|| covariance / variance * DAY),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn start() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000)
+    }
+
+    const ONE_DAY: Duration = Duration::from_secs(DAY as u64);
+
+    #[test]
+    fn test_empty_history() {
+        let trend = SizeHistory::new(None, Durability::default())
+            .trend()
+            .unwrap();
+        assert_eq!(trend.sample_count, 0);
+        assert_eq!(trend.current_size, 0);
+        assert_eq!(trend.bytes_per_day, None);
+    }
+
+    #[test]
+    fn test_single_sample_has_no_rate() {
+        let history = SizeHistory::new(None, Durability::default());
+        history.record(1000, start()).unwrap();
+        let trend = history.trend().unwrap();
+        assert_eq!(trend.sample_count, 1);
+        assert_eq!(trend.current_size, 1000);
+        assert_eq!(trend.bytes_per_day, None);
+    }
+
+    #[test]
+    fn test_samples_within_interval_skipped() {
+        let history = SizeHistory::new(None, Durability::default());
+        history.record(1000, start()).unwrap();
+        history
+            .record(5000, start() + SAMPLE_INTERVAL - Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(history.trend().unwrap().current_size, 1000);
+        history.record(5000, start() + SAMPLE_INTERVAL).unwrap();
+        assert_eq!(history.trend().unwrap().current_size, 5000);
+    }
+
+    #[test]
+    fn test_trend_persisted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_size_history.json");
+        let history = SizeHistory::new(Some(path.clone()), Durability::default());
+        history.record(1000, start()).unwrap();
+        history.record(1100, start() + ONE_DAY).unwrap();
+        history.record(1200, start() + ONE_DAY * 2).unwrap();
+
+        let trend = SizeHistory::new(Some(path), Durability::default())
+            .trend()
+            .unwrap();
+        assert_eq!(trend.sample_count, 3);
+        assert_eq!(trend.current_size, 1200);
+        assert_eq!(trend.bytes_per_day, Some(100.0));
+        assert_eq!(trend.time_to_size(1500), Some(ONE_DAY * 3));
+        assert_eq!(trend.time_to_size(1000), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_ring_keeps_newest_samples() {
+        let history = SizeHistory::new(None, Durability::default());
+        for idx in 0..MAX_SAMPLES as u32 + 2 {
+            history
+                .record(u64::from(idx), start() + ONE_DAY * idx)
+                .unwrap();
+        }
+        let trend = history.trend().unwrap();
+        assert_eq!(trend.sample_count, MAX_SAMPLES);
+        assert_eq!(trend.current_size, MAX_SAMPLES as u64 + 1);
+    }
+
+    #[test]
+    fn test_corrupt_history_starts_empty() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0_size_history.json");
+        fs::write(&path, "not json").unwrap();
+        let history = SizeHistory::new(Some(path.clone()), Durability::default());
+        assert_eq!(history.trend().unwrap().sample_count, 0);
+
+        // The next sample replaces the corrupt file
+        history.record(1000, start()).unwrap();
+        let trend = SizeHistory::new(Some(path), Durability::default())
+            .trend()
+            .unwrap();
+        assert_eq!(trend.sample_count, 1);
+    }
+
+    #[test]
+    fn test_malformed_samples_skipped() {
+        let samples = parse_samples("[[1, 2], [3], \"x\", [4, 5]]").unwrap();
+        assert_eq!(
+            samples,
+            VecDeque::from([
+                SizeSample { time: 1, size: 2 },
+                SizeSample { time: 4, size: 5 },
+            ])
+        );
+        assert_eq!(parse_samples("{}"), Err(ErrorCode::JsonParserError));
+    }
+
+    #[test]
+    fn test_unwritable_history() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing").join("kvs_0_size_history.json");
+        let history = SizeHistory::new(Some(path), Durability::default());
+        assert!(history.record(1000, start()).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-key value validation
+//!
+//! Integrators enforce ranges, enumerations or formats centrally instead of in every writer. A
+//! validator is a closure registered for a glob key pattern, see
+//! [`register_validator`](crate::kvs::GenericKvs::register_validator). It's called with the new
+//! value of every matching key on `set_value`, in transactions and on snapshot restores, and
+//! returns a message if the value is rejected. The write then fails with
+//! `ErrorCode::ValidationFailed` and nothing is stored.
+//!
+//! The size of single values can be limited with
+//! [`KvsBuilder::max_value_size`](crate::kvs_builder::KvsBuilder::max_value_size), checked the
+//! same way.
+//!
+//! Validators may be called with the KVS data locked and must not call back into the KVS.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_glob::glob_matches;
+use crate::kvs_log::log_error;
+use crate::kvs_value::KvsValue;
+use std::sync::{Arc, Mutex};
+
+/// Value validator
+///
+/// Returns `Err` with a description if the value must not be stored.
+pub type KvsValidator = Arc<dyn Fn(&KvsValue) -> Result<(), String> + Send + Sync>;
+
+/// Registered validators of a KVS instance
+#[derive(Default)]
+pub(crate) struct Validators {
+    /// Validators by key pattern in registration order
+    validators: Mutex<Vec<(String, KvsValidator)>>,
+}
+
+impl Validators {
+    /// Register a validator, an existing validator with the same pattern is replaced
+    pub(crate) fn add(&self, pattern: String, validator: KvsValidator) -> Result<(), ErrorCode> {
+        let mut validators = self
+            .validators
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        match validators.iter_mut().find(|(p, _)| *p == pattern) {
+            Some(entry) => entry.1 = validator,
+            None => validators.push((pattern, validator)),
+        }
+        Ok(())
+    }
+
+    /// Remove a validator, returns if it was registered
+    pub(crate) fn remove(&self, pattern: &str) -> Result<bool, ErrorCode> {
+        let mut validators = self
+            .validators
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        let count = validators.len();
+        validators.retain(|(p, _)| p != pattern);
+        Ok(validators.len() != count)
+    }
+
+    /// Check a value with all validators matching its key
+    ///
+    /// # Return Values
+    ///   * Ok: Value accepted by all matching validators
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::ValidationFailed`: Value rejected
+    pub(crate) fn check(&self, key: &str, value: &KvsValue) -> Result<(), ErrorCode> {
+        let matching: Vec<(String, KvsValidator)> = self
+            .validators
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?
+            .iter()
+            .filter(|(pattern, _)| glob_matches(pattern, key))
+            .cloned()
+            .collect();
+        for (pattern, validator) in matching {
+            validator(value).map_err(|message| {
+                log_error!("validator '{pattern}' rejected value of {key}: {message}");
+                ErrorCode::ValidationFailed
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range_validator(min: f64, max: f64) -> KvsValidator {
+        Arc::new(move |value: &KvsValue| match value {
+            KvsValue::Number(n) if (min..=max).contains(n) => Ok(()),
+            _ => Err(format!("expected a number in {min}..={max}")),
+        })
+    }
+
+    #[test]
+    fn test_no_validators() {
+        let validators = Validators::default();
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(99.0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validator_rejects_value() {
+        let validators = Validators::default();
+        validators
+            .add("audio/*".to_string(), range_validator(0.0, 10.0))
+            .unwrap();
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(5.0)),
+            Ok(())
+        );
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(11.0)),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            validators.check("audio/muted", &KvsValue::from(true)),
+            Err(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_validator_pattern_not_matching() {
+        let validators = Validators::default();
+        validators
+            .add("audio/*".to_string(), range_validator(0.0, 10.0))
+            .unwrap();
+        assert_eq!(
+            validators.check("video/volume", &KvsValue::from(11.0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_validator_replaced() {
+        let validators = Validators::default();
+        validators
+            .add("audio/*".to_string(), range_validator(0.0, 10.0))
+            .unwrap();
+        validators
+            .add("audio/*".to_string(), range_validator(0.0, 20.0))
+            .unwrap();
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(11.0)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_all_matching_validators_checked() {
+        let validators = Validators::default();
+        validators
+            .add("audio/*".to_string(), range_validator(0.0, 20.0))
+            .unwrap();
+        validators
+            .add("*/volume".to_string(), range_validator(5.0, 30.0))
+            .unwrap();
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(11.0)),
+            Ok(())
+        );
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(1.0)),
+            Err(ErrorCode::ValidationFailed)
+        );
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(25.0)),
+            Err(ErrorCode::ValidationFailed)
+        );
+    }
+
+    #[test]
+    fn test_validator_removed() {
+        let validators = Validators::default();
+        validators
+            .add("*/volume".to_string(), range_validator(5.0, 30.0))
+            .unwrap();
+        assert!(validators.remove("*/volume").unwrap());
+        assert!(!validators.remove("*/volume").unwrap());
+        assert!(!validators.remove("audio/*").unwrap());
+        assert_eq!(
+            validators.check("audio/volume", &KvsValue::from(1.0)),
+            Ok(())
+        );
+    }
+}
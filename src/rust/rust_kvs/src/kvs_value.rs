@@ -11,6 +11,7 @@
 
 use std::collections::HashMap;
 use std::ops::Index;
+use std::str::FromStr;
 
 /// Key-value storage map type
 pub type KvsMap = std::collections::HashMap<String, KvsValue>;
@@ -21,6 +22,12 @@ pub enum KvsValue {
     /// Number
     Number(f64),
 
+    /// 64-bit signed integer, stored without loss of precision
+    I64(i64),
+
+    /// 64-bit unsigned integer, stored without loss of precision
+    U64(u64),
+
     /// Boolean
     Boolean(bool),
 
@@ -35,6 +42,9 @@ pub enum KvsValue {
 
     /// Object
     Object(HashMap<String, KvsValue>),
+
+    /// Byte array, e.g. firmware blobs or serialized messages
+    Bytes(Vec<u8>),
 }
 
 macro_rules! impl_from_t_for_kvs_value {
@@ -48,6 +58,8 @@ macro_rules! impl_from_t_for_kvs_value {
 }
 
 impl_from_t_for_kvs_value!(f64, Number);
+impl_from_t_for_kvs_value!(i64, I64);
+impl_from_t_for_kvs_value!(u64, U64);
 impl_from_t_for_kvs_value!(bool, Boolean);
 impl_from_t_for_kvs_value!(String, String);
 impl_from_t_for_kvs_value!(Vec<KvsValue>, Array);
@@ -76,11 +88,11 @@ macro_rules! impl_tryfrom_kvs_value_to_t {
     };
 }
 
-impl_tryfrom_kvs_value_to_t!(f64, Number);
 impl_tryfrom_kvs_value_to_t!(bool, Boolean);
 impl_tryfrom_kvs_value_to_t!(String, String);
 impl_tryfrom_kvs_value_to_t!(Vec<KvsValue>, Array);
 impl_tryfrom_kvs_value_to_t!(HashMap<String, KvsValue>, Object);
+impl_tryfrom_kvs_value_to_t!(Vec<u8>, Bytes);
 
 impl<'a> TryFrom<&'a KvsValue> for () {
     type Error = ();
@@ -93,6 +105,86 @@ impl<'a> TryFrom<&'a KvsValue> for () {
     }
 }
 
+// Integers are converted from any numeric value that has no fraction and is in range
+macro_rules! impl_tryfrom_kvs_value_to_int {
+    ($to:ty) => {
+        impl<'a> TryFrom<&'a KvsValue> for $to {
+            type Error = ();
+            fn try_from(val: &'a KvsValue) -> Result<$to, Self::Error> {
+                match *val {
+                    // `MAX + 1` is a power of two and exact, `MAX` itself isn't for 64 bit
+                    KvsValue::Number(v)
+                        if v.fract() == 0.0
+                            && v >= <$to>::MIN as f64
+                            && v < <$to>::MAX as f64 + 1.0 =>
+                    {
+                        Ok(v as $to)
+                    }
+                    KvsValue::I64(v) => <$to>::try_from(v).map_err(|_| ()),
+                    KvsValue::U64(v) => <$to>::try_from(v).map_err(|_| ()),
+                    _ => Err(()),
+                }
+            }
+        }
+    };
+}
+
+impl_tryfrom_kvs_value_to_int!(i64);
+impl_tryfrom_kvs_value_to_int!(u64);
+impl_tryfrom_kvs_value_to_int!(i32);
+impl_tryfrom_kvs_value_to_int!(u32);
+impl_tryfrom_kvs_value_to_int!(i16);
+impl_tryfrom_kvs_value_to_int!(u16);
+impl_tryfrom_kvs_value_to_int!(i8);
+impl_tryfrom_kvs_value_to_int!(u8);
+
+// `f64` is converted from integers it represents exactly
+impl<'a> TryFrom<&'a KvsValue> for f64 {
+    type Error = ();
+    fn try_from(val: &'a KvsValue) -> Result<f64, Self::Error> {
+        match *val {
+            KvsValue::Number(v) => Ok(v),
+            KvsValue::I64(v) if v as f64 as i128 == v as i128 => Ok(v as f64),
+            KvsValue::U64(v) if v as f64 as i128 == v as i128 => Ok(v as f64),
+            _ => Err(()),
+        }
+    }
+}
+
+// `f32` is converted from numeric values it represents without overflow, integers exactly
+impl<'a> TryFrom<&'a KvsValue> for f32 {
+    type Error = ();
+    fn try_from(val: &'a KvsValue) -> Result<f32, Self::Error> {
+        match *val {
+            KvsValue::Number(v) if !v.is_finite() || v.abs() <= f32::MAX as f64 => Ok(v as f32),
+            KvsValue::I64(v) if v as f32 as i128 == v as i128 => Ok(v as f32),
+            KvsValue::U64(v) if v as f32 as i128 == v as i128 => Ok(v as f32),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Value that's parsed from a string if it doesn't have the requested type
+///
+/// Opts into parsing for [`get_value_as`](crate::kvs_api::KvsApi::get_value_as), e.g.
+/// `kvs.get_value_as::<Parsed<i32>>("port")` accepts both `8080` and `"8080"`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parsed<T>(pub T);
+
+impl<'a, T> TryFrom<&'a KvsValue> for Parsed<T>
+where
+    T: TryFrom<&'a KvsValue> + FromStr,
+{
+    type Error = ();
+    fn try_from(val: &'a KvsValue) -> Result<Parsed<T>, Self::Error> {
+        match (T::try_from(val), val) {
+            (Ok(v), _) => Ok(Parsed(v)),
+            (Err(_), KvsValue::String(s)) => s.trim().parse().map(Parsed).map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
 // Note: The following logic was copied and adapted from TinyJSON.
 
 pub trait KvsValueGet {
@@ -103,6 +195,60 @@ impl KvsValue {
     pub fn get<T: KvsValueGet>(&self) -> Option<&T> {
         T::get_inner_value(self)
     }
+
+    /// Create a byte array value
+    ///
+    /// There is no `From<Vec<u8>>` conversion, it would make `KvsValue::from(vec![])` ambiguous.
+    pub fn bytes(data: impl Into<Vec<u8>>) -> KvsValue {
+        KvsValue::Bytes(data.into())
+    }
+
+    /// Apply a JSON merge patch (RFC 7386)
+    ///
+    /// Members of an object patch replace the members of the value recursively, `Null` members
+    /// remove them. Any other patch replaces the value.
+    pub(crate) fn merge_patch(self, patch: &KvsValue) -> KvsValue {
+        let KvsValue::Object(patch) = patch else {
+            return patch.clone();
+        };
+        let mut target = match self {
+            KvsValue::Object(target) => target,
+            _ => HashMap::new(),
+        };
+        for (key, value) in patch {
+            if let KvsValue::Null = value {
+                target.remove(key);
+            } else {
+                let member = target.remove(key).unwrap_or(KvsValue::Null);
+                target.insert(key.clone(), member.merge_patch(value));
+            }
+        }
+        KvsValue::Object(target)
+    }
+
+    /// Add a numeric delta, keeping the type of the value
+    ///
+    /// # Return Values
+    ///   * Sum, `None` if a value isn't numeric or the sum overflows the integer type
+    pub(crate) fn checked_add(&self, delta: &KvsValue) -> Option<KvsValue> {
+        use KvsValue::*;
+        match (self, delta) {
+            (Number(value), Number(delta)) => Some(Number(value + delta)),
+            (Number(value), I64(delta)) => Some(Number(value + *delta as f64)),
+            (Number(value), U64(delta)) => Some(Number(value + *delta as f64)),
+            (I64(value), I64(delta)) => value.checked_add(*delta).map(I64),
+            (I64(value), U64(delta)) => i64::try_from(*delta)
+                .ok()
+                .and_then(|delta| value.checked_add(delta))
+                .map(I64),
+            (U64(value), U64(delta)) => value.checked_add(*delta).map(U64),
+            (U64(value), I64(delta)) => value.checked_add_signed(*delta).map(U64),
+            (I64(_) | U64(_), Number(delta)) if delta.fract() == 0.0 => {
+                self.checked_add(&I64(i64::try_from(*delta as i128).ok()?))
+            }
+            _ => None,
+        }
+    }
 }
 
 macro_rules! impl_kvs_get_inner_value {
@@ -120,11 +266,14 @@ macro_rules! impl_kvs_get_inner_value {
 }
 
 impl_kvs_get_inner_value!(f64, Number(n) => n);
+impl_kvs_get_inner_value!(i64, I64(n) => n);
+impl_kvs_get_inner_value!(u64, U64(n) => n);
 impl_kvs_get_inner_value!(bool, Boolean(b) => b);
 impl_kvs_get_inner_value!(String, String(s) => s);
 impl_kvs_get_inner_value!((), Null => &());
 impl_kvs_get_inner_value!(Vec<KvsValue>, Array(a) => a);
 impl_kvs_get_inner_value!(HashMap<String, KvsValue>, Object(h) => h);
+impl_kvs_get_inner_value!(Vec<u8>, Bytes(b) => b);
 
 impl Index<usize> for KvsValue {
     type Output = KvsValue;
@@ -145,6 +294,119 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_merge_patch() {
+        use KvsValue::*;
+        let object = |members: &[(&str, KvsValue)]| {
+            Object(
+                members
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone()))
+                    .collect(),
+            )
+        };
+        let target = object(&[
+            ("a", String("b".to_string())),
+            (
+                "c",
+                object(&[("d", String("e".to_string())), ("f", Number(1.0))]),
+            ),
+        ]);
+        let patch = object(&[
+            ("a", String("z".to_string())),
+            ("c", object(&[("f", Null), ("g", object(&[("h", Null)]))])),
+        ]);
+        assert_eq!(
+            target.merge_patch(&patch),
+            object(&[
+                ("a", String("z".to_string())),
+                (
+                    "c",
+                    object(&[("d", String("e".to_string())), ("g", object(&[]))])
+                ),
+            ])
+        );
+        assert_eq!(
+            Number(1.0).merge_patch(&object(&[("a", Null)])),
+            object(&[])
+        );
+        assert_eq!(object(&[]).merge_patch(&Array(vec![])), Array(vec![]));
+    }
+
+    #[test]
+    fn test_checked_add() {
+        use KvsValue::*;
+        assert_eq!(Number(1.5).checked_add(&I64(-2)), Some(Number(-0.5)));
+        assert_eq!(I64(i64::MAX).checked_add(&I64(1)), None);
+        assert_eq!(I64(-1).checked_add(&U64(u64::MAX)), None);
+        assert_eq!(U64(1).checked_add(&I64(-2)), None);
+        assert_eq!(U64(1).checked_add(&Number(2.0)), Some(U64(3)));
+        assert_eq!(I64(1).checked_add(&Number(1e30)), None);
+        assert_eq!(Boolean(true).checked_add(&I64(1)), None);
+    }
+
+    #[test]
+    fn test_checked_conversion() {
+        use KvsValue::*;
+        assert_eq!(i32::try_from(&Number(-7.0)), Ok(-7));
+        assert_eq!(i32::try_from(&Number(7.5)), Err(()));
+        assert_eq!(i32::try_from(&Number(3e9)), Err(()));
+        assert_eq!(i32::try_from(&Number(f64::NAN)), Err(()));
+        assert_eq!(u8::try_from(&U64(255)), Ok(255));
+        assert_eq!(u8::try_from(&I64(-1)), Err(()));
+        assert_eq!(u16::try_from(&Boolean(true)), Err(()));
+        assert_eq!(f32::try_from(&Number(0.5)), Ok(0.5));
+        assert_eq!(f32::try_from(&Number(1e39)), Err(()));
+        assert_eq!(f32::try_from(&I64(1 << 24)), Ok(16777216.0));
+        assert_eq!(f32::try_from(&I64((1 << 24) + 1)), Err(()));
+
+        assert_eq!(
+            Parsed::<i32>::try_from(&String(" 8080 ".to_string())),
+            Ok(Parsed(8080))
+        );
+        assert_eq!(Parsed::<i32>::try_from(&Number(8080.0)), Ok(Parsed(8080)));
+        assert_eq!(Parsed::<u8>::try_from(&String("256".to_string())), Err(()));
+        assert_eq!(i32::try_from(&String("8080".to_string())), Err(()));
+    }
+
+    #[test]
+    fn test_checked_conversion_i64() {
+        use KvsValue::*;
+        assert_eq!(i64::try_from(&I64(i64::MIN)), Ok(i64::MIN));
+        assert_eq!(i64::try_from(&U64(42)), Ok(42));
+        assert_eq!(i64::try_from(&U64(u64::MAX)), Err(()));
+        assert_eq!(i64::try_from(&Number(-42.0)), Ok(-42));
+        assert_eq!(i64::try_from(&Number(-9.223372036854776e18)), Ok(i64::MIN));
+        assert_eq!(i64::try_from(&Number(9.223372036854776e18)), Err(()));
+        assert_eq!(i64::try_from(&Number(0.5)), Err(()));
+        assert_eq!(i64::try_from(&Number(f64::INFINITY)), Err(()));
+        assert_eq!(i64::try_from(&String("1".to_string())), Err(()));
+    }
+
+    #[test]
+    fn test_checked_conversion_u64() {
+        use KvsValue::*;
+        assert_eq!(u64::try_from(&U64(u64::MAX)), Ok(u64::MAX));
+        assert_eq!(u64::try_from(&I64(42)), Ok(42));
+        assert_eq!(u64::try_from(&I64(-1)), Err(()));
+        assert_eq!(u64::try_from(&Number(42.0)), Ok(42));
+        assert_eq!(u64::try_from(&Number(1.8446744073709552e19)), Err(()));
+        assert_eq!(u64::try_from(&Number(-1.0)), Err(()));
+        assert_eq!(u64::try_from(&Number(1.5)), Err(()));
+        assert_eq!(u64::try_from(&Number(f64::NAN)), Err(()));
+    }
+
+    #[test]
+    fn test_checked_conversion_f64() {
+        use KvsValue::*;
+        assert_eq!(f64::try_from(&Number(0.25)), Ok(0.25));
+        assert_eq!(f64::try_from(&I64(-(1 << 53))), Ok(-9007199254740992.0));
+        assert_eq!(f64::try_from(&I64((1 << 53) + 1)), Err(()));
+        assert_eq!(f64::try_from(&U64(1 << 63)), Ok(9.223372036854776e18));
+        assert_eq!(f64::try_from(&U64(u64::MAX)), Err(()));
+        assert_eq!(f64::try_from(&Boolean(false)), Err(()));
+    }
+
     #[test]
     fn test_get_inner_value() {
         let value = KvsValue::Number(42.0);
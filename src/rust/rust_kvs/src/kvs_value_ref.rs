@@ -0,0 +1,143 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Borrowed value access
+//!
+//! [`get_value`](crate::kvs_api::KvsApi::get_value) returns a copy of the value, which is costly
+//! for strings or byte arrays of hundreds of kilobytes read over and over.
+//! [`get_value_ref`](crate::kvs::GenericKvs::get_value_ref) returns a [`KvsValueRef`] instead,
+//! which dereferences to the value without copying it.
+//!
+//! A reference to a stored value keeps the KVS data locked: all other accesses to the instance
+//! wait until it's dropped, and setting a value from the same thread while holding it deadlocks.
+//! References should only be held while the value is processed. Static defaults are borrowed
+//! without a lock, override and provider values are returned as a copy.
+
+use crate::kvs_fairness::GateGuard;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::fmt;
+use std::ops::Deref;
+use std::sync::MutexGuard;
+
+/// Returned if a stored value vanished, which the held lock prevents
+static NULL: KvsValue = KvsValue::Null;
+
+/// Borrowed or copied value
+enum Source<'a> {
+    /// Stored value, borrowed from the locked KVS data
+    Stored {
+        kvs: MutexGuard<'a, KvsMap>,
+        _gate: Option<GateGuard<'a>>,
+        key: String,
+    },
+
+    /// Static default value
+    Borrowed(&'a KvsValue),
+
+    /// Value without a stable location, e.g. an override
+    Owned(KvsValue),
+}
+
+/// Reference to the value of a key
+///
+/// Dereferences to the [`KvsValue`]. See the [module documentation](self) for the locking.
+pub struct KvsValueRef<'a>(Source<'a>);
+
+impl<'a> KvsValueRef<'a> {
+    /// Borrow a stored value, the key must be stored in the locked data
+    pub(crate) fn stored(
+        kvs: MutexGuard<'a, KvsMap>,
+        gate: Option<GateGuard<'a>>,
+        key: &str,
+    ) -> Self {
+        Self(Source::Stored {
+            kvs,
+            _gate: gate,
+            key: key.to_string(),
+        })
+    }
+
+    /// Borrow a value that isn't changed while the instance is open
+    pub(crate) fn borrowed(value: &'a KvsValue) -> Self {
+        Self(Source::Borrowed(value))
+    }
+
+    /// Wrap a copied value
+    pub(crate) fn owned(value: KvsValue) -> Self {
+        Self(Source::Owned(value))
+    }
+
+    /// Return if the value is borrowed from the stored data and keeps it locked
+    pub fn is_locked(&self) -> bool {
+        matches!(self.0, Source::Stored { .. })
+    }
+}
+
+impl Deref for KvsValueRef<'_> {
+    type Target = KvsValue;
+
+    fn deref(&self) -> &KvsValue {
+        match &self.0 {
+            Source::Stored { kvs, key, .. } => kvs.get(key).unwrap_or(&NULL),
+            Source::Borrowed(value) => value,
+            Source::Owned(value) => value,
+        }
+    }
+}
+
+impl fmt::Debug for KvsValueRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_stored_value_locks_data() {
+        let kvs = Mutex::new(KvsMap::from([(
+            "blob".to_string(),
+            KvsValue::bytes([1, 2, 3]),
+        )]));
+        let stored = KvsValueRef::stored(kvs.lock().unwrap(), None, "blob");
+        assert!(stored.is_locked());
+        assert_eq!(*stored, KvsValue::bytes([1, 2, 3]));
+        assert!(kvs.try_lock().is_err());
+        drop(stored);
+        assert!(kvs.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_vanished_stored_value_is_null() {
+        let kvs = Mutex::new(KvsMap::new());
+        let stored = KvsValueRef::stored(kvs.lock().unwrap(), None, "blob");
+        assert_eq!(*stored, KvsValue::Null);
+    }
+
+    #[test]
+    fn test_borrowed_value() {
+        let default = KvsValue::from("default".to_string());
+        let borrowed = KvsValueRef::borrowed(&default);
+        assert!(!borrowed.is_locked());
+        assert!(std::ptr::eq(&*borrowed, &default));
+    }
+
+    #[test]
+    fn test_owned_value() {
+        let owned = KvsValueRef::owned(KvsValue::from(1.0));
+        assert!(!owned.is_locked());
+        assert_eq!(*owned, KvsValue::from(1.0));
+        assert_eq!(format!("{owned:?}"), format!("{:?}", KvsValue::from(1.0)));
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-key version counters
+//!
+//! With [`KvsOptions::key_versions`](crate::kvs_api::KvsOptions::key_versions) enabled every
+//! change of a key, by a set, removal, transaction or restore, increments its version. A key that
+//! was never changed has version 0. Versions enable optimistic concurrency: a writer reads a
+//! value with [`get_value_with_version`](crate::kvs::GenericKvs::get_value_with_version) and
+//! updates it with [`set_value_if_version`](crate::kvs::GenericKvs::set_value_if_version), which
+//! fails with `ErrorCode::VersionConflict` if the key was changed in between.
+//!
+//! The versions are kept in memory next to the data and written to the KVS file in the reserved
+//! member `"$versions"`, with the `U64` encoding of the storage format. The write-ahead log and
+//! the delta journal of incremental flushes are replayed with the versions of their changes, so
+//! no version issued before a restart is issued again for another value. Keys changed by merge
+//! journal records get their version incremented once on open.
+//! Processes sharing a merge journal see the changes of each other: a conditional write merges
+//! the journal and compares the version while it holds the journal lock. Without a merge journal
+//! changes of other processes aren't detected, use
+//! [`KvsOptions::file_lock`](crate::kvs_api::KvsOptions::file_lock) to exclude them. The member
+//! `"$versions"` can't be used as key.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_warning;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Member of the KVS file holding the key versions
+pub(crate) const VERSIONS_KEY: &str = "$versions";
+
+/// Version counters of a KVS instance
+pub(crate) struct KeyVersions {
+    /// Versions by key
+    versions: Mutex<HashMap<String, u64>>,
+}
+
+/// Remove the key versions from loaded data
+///
+/// Malformed entries are skipped.
+///
+/// # Return Values
+///   * Versions by key, empty if the data has none
+pub(crate) fn take_versions(data: &mut KvsMap) -> HashMap<String, u64> {
+    match data.remove(VERSIONS_KEY) {
+        None => HashMap::new(),
+        Some(KvsValue::Object(versions)) => versions
+            .into_iter()
+            .filter_map(|(key, version)| Some((key, u64::try_from(&version).ok()?)))
+            .collect(),
+        Some(versions) => {
+            log_warning!("ignoring invalid key versions: {versions:?}");
+            HashMap::new()
+        }
+    }
+}
+
+impl KeyVersions {
+    /// Create the counters
+    ///
+    /// # Parameters
+    ///   * `versions`: Versions loaded from the KVS file
+    pub(crate) fn new(versions: HashMap<String, u64>) -> Self {
+        Self {
+            versions: Mutex::new(versions),
+        }
+    }
+
+    /// Increment the version of a changed key
+    pub(crate) fn increment(&self, key: &str) {
+        if let Ok(mut versions) = self.versions.lock() {
+            match versions.get_mut(key) {
+                Some(version) => *version = version.saturating_add(1),
+                None => {
+                    versions.insert(key.to_string(), 1);
+                }
+            }
+        }
+    }
+
+    /// Return the version of a key
+    ///
+    /// # Return Values
+    ///   * Ok: Version, 0 if the key was never changed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn get(&self, key: &str) -> Result<u64, ErrorCode> {
+        let versions = self
+            .versions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        Ok(versions.get(key).copied().unwrap_or_default())
+    }
+
+    /// Add the versions to data that's written to the KVS file
+    ///
+    /// # Return Values
+    ///   * Ok: Versions added, no member is added if no key was changed
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    pub(crate) fn store(&self, data: &mut KvsMap) -> Result<(), ErrorCode> {
+        let versions = self
+            .versions
+            .lock()
+            .map_err(|_| ErrorCode::MutexLockFailed)?;
+        if !versions.is_empty() {
+            let versions = versions
+                .iter()
+                .map(|(key, version)| (key.clone(), KvsValue::U64(*version)))
+                .collect();
+            data.insert(VERSIONS_KEY.to_string(), KvsValue::Object(versions));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_stored_and_taken() {
+        let versions = KeyVersions::new(HashMap::from([("a".to_string(), u64::MAX - 1)]));
+        versions.increment("a");
+        versions.increment("a");
+        versions.increment("b");
+        assert_eq!(versions.get("a").unwrap(), u64::MAX);
+        assert_eq!(versions.get("b").unwrap(), 1);
+        assert_eq!(versions.get("c").unwrap(), 0);
+
+        let mut data = KvsMap::from([("a".to_string(), KvsValue::from(1.0))]);
+        versions.store(&mut data).unwrap();
+        assert_eq!(
+            take_versions(&mut data),
+            HashMap::from([("a".to_string(), u64::MAX), ("b".to_string(), 1)])
+        );
+        assert_eq!(data, KvsMap::from([("a".to_string(), KvsValue::from(1.0))]));
+    }
+
+    #[test]
+    fn test_versions_not_stored_without_changes() {
+        let mut data = KvsMap::new();
+        KeyVersions::new(HashMap::new()).store(&mut data).unwrap();
+        assert!(data.is_empty());
+        assert!(take_versions(&mut data).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_versions_skipped() {
+        let mut data = KvsMap::from([(
+            VERSIONS_KEY.to_string(),
+            KvsValue::Object(HashMap::from([
+                ("a".to_string(), KvsValue::Number(3.0)),
+                ("b".to_string(), KvsValue::from("3".to_string())),
+                ("c".to_string(), KvsValue::I64(-1)),
+            ])),
+        )]);
+        assert_eq!(
+            take_versions(&mut data),
+            HashMap::from([("a".to_string(), 3)])
+        );
+        assert!(data.is_empty());
+
+        let mut data = KvsMap::from([(VERSIONS_KEY.to_string(), KvsValue::from(true))]);
+        assert!(take_versions(&mut data).is_empty());
+        assert!(data.is_empty());
+    }
+}
@@ -0,0 +1,552 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Write-ahead log
+//!
+//! Every mutation is appended as one line to `kvs_<instance_id>.wal` before it's applied to the
+//! in-memory map. On open the log is replayed on top of the loaded KVS file. After the KVS file
+//! was written the log is truncated.
+//!
+//! The delta journal `kvs_<instance_id>.delta` of incremental flushes uses the same format. It's
+//! replayed before the write-ahead log and removed when the KVS file is written.
+//!
+//! Line format: `<adler32 as 8 hex digits> <JSON record>\n`. A line with a wrong checksum or
+//! without line break is a torn write of a crashed process; it and everything behind it is
+//! dropped on replay.
+//!
+//! All records describe the resulting state of a key, replaying a log twice leads to the same
+//! state. So a crash between writing the KVS file and truncating the log is harmless.
+//!
+//! Records of the delta journal carry the key versions after the change in the member
+//! `"versions"`, since an incremental flush writes one record for any count of changes. On
+//! replay every other change counts as one version, which is at least the count of versions
+//! issued for it, so no version issued before a crash is issued again.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_api::Durability;
+use crate::kvs_log::log_warning;
+use crate::kvs_platform::sync_file;
+use crate::kvs_transaction::KvsOperation;
+use crate::kvs_value::{KvsMap, KvsValue};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tinyjson::JsonValue;
+
+/// Logged mutation
+pub(crate) enum WalRecord<'a> {
+    /// Assign a value to a key
+    Set(&'a str, &'a KvsValue),
+
+    /// Remove a key
+    Remove(&'a str),
+
+    /// Apply a group of operations
+    Batch(&'a [KvsOperation]),
+
+    /// Replace the whole map (reset, snapshot restore)
+    Replace(&'a KvsMap),
+}
+
+/// Open log file, count of records since the last truncation and bytes appended since the last
+/// [`take_written`](WriteAheadLog::take_written)
+struct WalState {
+    file: Option<fs::File>,
+    records: usize,
+    written: u64,
+}
+
+/// Write-ahead log of a KVS instance
+pub(crate) struct WriteAheadLog {
+    /// Log filename
+    path: PathBuf,
+
+    /// Record count that triggers a compaction
+    compact_threshold: usize,
+
+    /// Synchronization of appended records
+    durability: Durability,
+
+    /// Log state
+    state: Mutex<WalState>,
+}
+
+fn json_object(entries: Vec<(&str, JsonValue)>) -> JsonValue {
+    JsonValue::Object(
+        entries
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v))
+            .collect(),
+    )
+}
+
+fn json_map(map: &KvsMap) -> JsonValue {
+    JsonValue::from(KvsValue::Object(map.clone()))
+}
+
+fn encode_operation(op: &KvsOperation) -> JsonValue {
+    match op {
+        KvsOperation::Set(key, value) => json_object(vec![
+            ("op", JsonValue::String("set".to_string())),
+            ("key", JsonValue::String(key.clone())),
+            ("value", JsonValue::from(value.clone())),
+        ]),
+        KvsOperation::Remove(key) => json_object(vec![
+            ("op", JsonValue::String("remove".to_string())),
+            ("key", JsonValue::String(key.clone())),
+        ]),
+    }
+}
+
+pub(crate) fn encode_record(record: &WalRecord) -> JsonValue {
+    match record {
+        WalRecord::Set(key, value) => {
+            encode_operation(&KvsOperation::Set(key.to_string(), (*value).clone()))
+        }
+        WalRecord::Remove(key) => encode_operation(&KvsOperation::Remove(key.to_string())),
+        WalRecord::Batch(ops) => json_object(vec![
+            ("op", JsonValue::String("batch".to_string())),
+            (
+                "ops",
+                JsonValue::Array(ops.iter().map(encode_operation).collect()),
+            ),
+        ]),
+        WalRecord::Replace(map) => json_object(vec![
+            ("op", JsonValue::String("replace".to_string())),
+            ("data", json_map(map)),
+        ]),
+    }
+}
+
+/// Apply a decoded record to a map
+///
+/// Returns `None` if the record is malformed.
+pub(crate) fn apply_record(record: &HashMap<String, JsonValue>, map: &mut KvsMap) -> Option<()> {
+    let op: &String = record.get("op")?.get()?;
+    match op.as_str() {
+        "set" => {
+            let key: &String = record.get("key")?.get()?;
+            let value = KvsValue::from(record.get("value")?.clone());
+            map.insert(key.clone(), value);
+        }
+        "remove" => {
+            let key: &String = record.get("key")?.get()?;
+            map.remove(key);
+        }
+        "batch" => {
+            let ops: &Vec<JsonValue> = record.get("ops")?.get()?;
+            for op in ops {
+                apply_record(op.get()?, map)?;
+            }
+        }
+        "replace" => {
+            if let KvsValue::Object(data) = KvsValue::from(record.get("data")?.clone()) {
+                *map = data;
+            } else {
+                return None;
+            }
+        }
+        _ => return None,
+    }
+    Some(())
+}
+
+/// Raise the key versions past the versions issued for the changes of a record
+///
+/// Must be called before the record is applied to the map.
+fn raise_versions(
+    record: &HashMap<String, JsonValue>,
+    map: &KvsMap,
+    versions: &mut HashMap<String, u64>,
+) {
+    if let Some(JsonValue::Object(logged)) = record.get("versions") {
+        for (key, version) in logged {
+            if let Ok(version) = u64::try_from(&KvsValue::from(version.clone())) {
+                let current = versions.entry(key.clone()).or_default();
+                *current = (*current).max(version);
+            }
+        }
+        return;
+    }
+    let mut bump = |key: &str| {
+        let version = versions.entry(key.to_string()).or_default();
+        *version = version.saturating_add(1);
+    };
+    let Some(JsonValue::String(op)) = record.get("op") else {
+        return;
+    };
+    match op.as_str() {
+        "set" | "remove" => {
+            if let Some(JsonValue::String(key)) = record.get("key") {
+                bump(key);
+            }
+        }
+        "batch" => {
+            if let Some(JsonValue::Array(ops)) = record.get("ops") {
+                for op in ops {
+                    if let Some(JsonValue::String(key)) = op
+                        .get::<HashMap<String, JsonValue>>()
+                        .and_then(|op| op.get("key"))
+                    {
+                        bump(key);
+                    }
+                }
+            }
+        }
+        "replace" => {
+            if let Some(KvsValue::Object(data)) = record.get("data").cloned().map(KvsValue::from) {
+                let changed: HashSet<&String> = map
+                    .keys()
+                    .chain(data.keys())
+                    .filter(|key| map.get(*key) != data.get(*key))
+                    .collect();
+                for key in changed {
+                    bump(key);
+                }
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Encode a record as log line including the line break
+pub(crate) fn encode_line(record: &JsonValue) -> Result<String, ErrorCode> {
+    let json = record.stringify()?;
+    let hash = adler32::RollingAdler32::from_buffer(json.as_bytes()).hash();
+    Ok(format!("{hash:08x} {json}\n"))
+}
+
+/// Decode and verify a single log line without line break
+pub(crate) fn decode_line(line: &str) -> Option<HashMap<String, JsonValue>> {
+    let (hash, json) = line.split_once(' ')?;
+    let hash = u32::from_str_radix(hash, 16).ok()?;
+    if adler32::RollingAdler32::from_buffer(json.as_bytes()).hash() != hash {
+        return None;
+    }
+    match json.parse::<JsonValue>().ok()? {
+        JsonValue::Object(record) => Some(record),
+        _ => None,
+    }
+}
+
+impl WriteAheadLog {
+    /// Create the log handle, the file is opened on first append
+    ///
+    /// # Parameters
+    ///   * `path`: Log filename
+    ///   * `compact_threshold`: Record count after which a compaction is requested
+    ///   * `durability`: Synchronization of appended records
+    pub(crate) fn new(path: PathBuf, compact_threshold: usize, durability: Durability) -> Self {
+        Self {
+            path,
+            compact_threshold,
+            durability,
+            state: Mutex::new(WalState {
+                file: None,
+                records: 0,
+                written: 0,
+            }),
+        }
+    }
+
+    /// Replay the log on top of a map
+    ///
+    /// A torn or corrupted tail is cut off the file.
+    ///
+    /// # Parameters
+    ///   * `map`: Map loaded from the KVS file
+    ///   * `versions`: Key versions loaded from the KVS file, raised past the versions issued for
+    ///     the replayed changes
+    ///
+    /// # Return Values
+    ///   * Ok: Count of replayed records
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Log couldn't be read or repaired
+    pub(crate) fn replay(
+        &self,
+        map: &mut KvsMap,
+        versions: &mut HashMap<String, u64>,
+    ) -> Result<usize, ErrorCode> {
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(_) => return Err(ErrorCode::PhysicalStorageFailure),
+        };
+
+        let mut valid_len = 0;
+        let mut records = 0;
+        for line in data.split_inclusive(|b| *b == b'\n') {
+            let Some(line) = line.strip_suffix(b"\n") else {
+                break;
+            };
+            let Some(record) = std::str::from_utf8(line).ok().and_then(decode_line) else {
+                break;
+            };
+            raise_versions(&record, map, versions);
+            if apply_record(&record, map).is_none() {
+                break;
+            }
+            valid_len += line.len() + 1;
+            records += 1;
+        }
+
+        if valid_len != data.len() {
+            log_warning!(
+                "dropping {} bytes of torn write-ahead log {}",
+                data.len() - valid_len,
+                self.path.display()
+            );
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&self.path)
+                .and_then(|file| file.set_len(valid_len as u64))
+                .map_err(|_| ErrorCode::PhysicalStorageFailure)?;
+        }
+
+        state.records = records;
+        Ok(records)
+    }
+
+    /// Append a record and persist it
+    ///
+    /// # Parameters
+    ///   * `record`: Record to append
+    ///
+    /// # Return Values
+    ///   * Ok(`true`): Record appended, the log should be compacted
+    ///   * Ok(`false`): Record appended
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::JsonGeneratorError`: Record couldn't be serialized
+    ///   * `ErrorCode::PhysicalStorageFailure`: Record couldn't be written
+    pub(crate) fn append(&self, record: WalRecord) -> Result<bool, ErrorCode> {
+        self.append_json(encode_record(&record))
+    }
+
+    /// Append a record with the versions of its keys after the change
+    ///
+    /// # Parameters
+    ///   * `record`: Record to append
+    ///   * `versions`: Versions of the changed keys
+    ///
+    /// # Return Values
+    ///   * Like [`append`](Self::append)
+    pub(crate) fn append_versioned(
+        &self,
+        record: WalRecord,
+        versions: HashMap<String, u64>,
+    ) -> Result<bool, ErrorCode> {
+        let mut json = encode_record(&record);
+        if let JsonValue::Object(members) = &mut json {
+            let versions = versions
+                .into_iter()
+                .map(|(key, version)| (key, JsonValue::from(KvsValue::U64(version))))
+                .collect();
+            members.insert("versions".to_string(), JsonValue::Object(versions));
+        }
+        self.append_json(json)
+    }
+
+    /// Append an encoded record and persist it
+    fn append_json(&self, record: JsonValue) -> Result<bool, ErrorCode> {
+        let line = encode_line(&record)?;
+
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        if state.file.is_none() {
+            let file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .map_err(|_| ErrorCode::PhysicalStorageFailure)?;
+            state.file = Some(file);
+        }
+        if let Some(file) = state.file.as_mut() {
+            file.write_all(line.as_bytes())
+                .and_then(|_| sync_file(file, self.durability))
+                .map_err(|_| ErrorCode::PhysicalStorageFailure)?;
+        }
+        state.records += 1;
+        state.written += line.len() as u64;
+
+        Ok(state.records >= self.compact_threshold)
+    }
+
+    /// Return if the log has no records
+    pub(crate) fn is_empty(&self) -> bool {
+        self.state
+            .lock()
+            .map(|state| state.records == 0)
+            .unwrap_or(true)
+    }
+
+    /// Return the count of bytes appended since the last call
+    pub(crate) fn take_written(&self) -> u64 {
+        self.state
+            .lock()
+            .map(|mut state| std::mem::take(&mut state.written))
+            .unwrap_or(0)
+    }
+
+    /// Drop all records after they were written to the KVS file
+    ///
+    /// # Return Values
+    ///   * Ok: Log truncated
+    ///   * `ErrorCode::MutexLockFailed`: Mutex locking failed
+    ///   * `ErrorCode::PhysicalStorageFailure`: Log couldn't be truncated
+    pub(crate) fn truncate(&self) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        state.file = None;
+        state.records = 0;
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(_) => Err(ErrorCode::PhysicalStorageFailure),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_wal_append_and_replay() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.wal");
+        let wal = WriteAheadLog::new(path.clone(), 100, Durability::default());
+
+        let empty = KvsMap::new();
+        wal.append(WalRecord::Set("a", &KvsValue::from(1.0)))
+            .unwrap();
+        wal.append(WalRecord::Set("b", &KvsValue::from(true)))
+            .unwrap();
+        wal.append(WalRecord::Remove("a")).unwrap();
+        wal.append(WalRecord::Batch(&[KvsOperation::Set(
+            "c".to_string(),
+            KvsValue::from("x".to_string()),
+        )]))
+        .unwrap();
+
+        let mut map = KvsMap::new();
+        let wal = WriteAheadLog::new(path.clone(), 100, Durability::default());
+        assert_eq!(wal.replay(&mut map, &mut HashMap::new()).unwrap(), 4);
+        assert!(!map.contains_key("a"));
+        assert_eq!(map["b"], KvsValue::from(true));
+        assert_eq!(map["c"], KvsValue::from("x".to_string()));
+
+        wal.append(WalRecord::Replace(&empty)).unwrap();
+        let mut map = KvsMap::new();
+        WriteAheadLog::new(path, 100, Durability::default())
+            .replay(&mut map, &mut HashMap::new())
+            .unwrap();
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_wal_replay_drops_torn_tail() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.wal");
+        let wal = WriteAheadLog::new(path.clone(), 100, Durability::default());
+        wal.append(WalRecord::Set("a", &KvsValue::from(1.0)))
+            .unwrap();
+        let valid_len = fs::metadata(&path).unwrap().len();
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"0000abcd {\"op\":\"se").unwrap();
+
+        let mut map = KvsMap::new();
+        assert_eq!(
+            WriteAheadLog::new(path.clone(), 100, Durability::default())
+                .replay(&mut map, &mut HashMap::new())
+                .unwrap(),
+            1
+        );
+        assert_eq!(map["a"], KvsValue::from(1.0));
+        assert_eq!(fs::metadata(&path).unwrap().len(), valid_len);
+    }
+
+    #[test]
+    fn test_wal_replay_raises_versions() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.wal");
+        let wal = WriteAheadLog::new(path.clone(), 100, Durability::default());
+        let data = KvsMap::from([("c".to_string(), KvsValue::from(1.0))]);
+        wal.append(WalRecord::Set("a", &KvsValue::from(1.0)))
+            .unwrap();
+        wal.append(WalRecord::Set("a", &KvsValue::from(2.0)))
+            .unwrap();
+        wal.append(WalRecord::Batch(&[
+            KvsOperation::Set("b".to_string(), KvsValue::from(1.0)),
+            KvsOperation::Remove("a".to_string()),
+        ]))
+        .unwrap();
+        wal.append(WalRecord::Replace(&data)).unwrap();
+
+        let mut map = KvsMap::from([("d".to_string(), KvsValue::from(1.0))]);
+        let mut versions = HashMap::from([("a".to_string(), 5), ("d".to_string(), 2)]);
+        wal.replay(&mut map, &mut versions).unwrap();
+        assert_eq!(map, data);
+        assert_eq!(
+            versions,
+            HashMap::from([
+                ("a".to_string(), 8),
+                ("b".to_string(), 2),
+                ("c".to_string(), 1),
+                ("d".to_string(), 3),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_versioned_records() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.delta");
+        let delta = WriteAheadLog::new(path.clone(), usize::MAX, Durability::default());
+        let ops = [KvsOperation::Set("a".to_string(), KvsValue::from(1.0))];
+        delta
+            .append_versioned(
+                WalRecord::Batch(&ops),
+                HashMap::from([("a".to_string(), u64::MAX)]),
+            )
+            .unwrap();
+        delta
+            .append_versioned(
+                WalRecord::Batch(&ops),
+                HashMap::from([("a".to_string(), 3)]),
+            )
+            .unwrap();
+
+        // Logged versions replace the counting, an older version isn't lowering it
+        let mut map = KvsMap::new();
+        let mut versions = HashMap::from([("a".to_string(), 7)]);
+        delta.replay(&mut map, &mut versions).unwrap();
+        assert_eq!(map["a"], KvsValue::from(1.0));
+        assert_eq!(versions, HashMap::from([("a".to_string(), u64::MAX)]));
+    }
+
+    #[test]
+    fn test_wal_compact_threshold_and_truncate() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("kvs_0.wal");
+        let wal = WriteAheadLog::new(path.clone(), 2, Durability::default());
+
+        assert!(!wal.append(WalRecord::Remove("a")).unwrap());
+        assert!(wal.append(WalRecord::Remove("b")).unwrap());
+
+        wal.truncate().unwrap();
+        assert!(!path.exists());
+        assert!(!wal.append(WalRecord::Remove("c")).unwrap());
+    }
+}
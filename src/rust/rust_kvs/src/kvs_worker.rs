@@ -0,0 +1,113 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Worker thread configuration
+//!
+//! Background work of a KVS instance, e.g. flushing or compaction, must not compete with
+//! control loops. All worker threads of an instance are spawned with the instance's
+//! [`WorkerConfig`], which sets their name and stack size.
+//!
+//! Scheduling priority and CPU affinity need platform calls the crate doesn't make (it has no
+//! `libc` dependency and forbids `unsafe`). Configured values are reported as a warning when a
+//! worker starts and the thread keeps the scheduling settings inherited from its creator, so
+//! the creating thread's settings apply.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_warning;
+use std::thread::{self, JoinHandle};
+
+/// Default name prefix of worker threads
+const DEFAULT_NAME: &str = "kvs";
+
+/// Settings of the worker threads of a KVS instance
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WorkerConfig {
+    /// Thread name prefix, the worker role is appended, e.g. `kvs-flush`
+    pub name: Option<String>,
+
+    /// Stack size in bytes, `None` for the platform default
+    pub stack_size: Option<usize>,
+
+    /// Scheduling priority
+    pub priority: Option<i32>,
+
+    /// CPUs the thread may run on
+    pub affinity: Option<Vec<usize>>,
+}
+
+impl WorkerConfig {
+    /// Return the thread name of a worker role
+    pub fn thread_name(&self, role: &str) -> String {
+        format!("{}-{role}", self.name.as_deref().unwrap_or(DEFAULT_NAME))
+    }
+
+    /// Spawn a worker thread with the configured settings
+    ///
+    /// # Parameters
+    ///   * `role`: Worker role appended to the thread name, e.g. `flush`
+    ///   * `f`: Thread body
+    ///
+    /// # Return Values
+    ///   * Ok: Handle of the started thread
+    ///   * `ErrorCode::UnmappedError`: Thread couldn't be spawned
+    pub fn spawn<F, T>(&self, role: &str, f: F) -> Result<JoinHandle<T>, ErrorCode>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let name = self.thread_name(role);
+        let mut builder = thread::Builder::new().name(name.clone());
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+
+        let scheduling = self.priority.is_some() || self.affinity.is_some();
+        Ok(builder.spawn(move || {
+            if scheduling {
+                log_warning!(
+                    "worker thread '{name}': priority and affinity can't be set on this platform, inherited settings are kept"
+                );
+            }
+            f()
+        })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spawn_named_worker() {
+        let config = WorkerConfig {
+            name: Some("diag".to_string()),
+            stack_size: Some(256 * 1024),
+            ..Default::default()
+        };
+        let name = config
+            .spawn("flush", || thread::current().name().map(str::to_string))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(name.as_deref(), Some("diag-flush"));
+        assert_eq!(WorkerConfig::default().thread_name("flush"), "kvs-flush");
+    }
+
+    #[test]
+    fn test_spawn_with_priority_warns_and_runs() {
+        let config = WorkerConfig {
+            priority: Some(10),
+            affinity: Some(vec![0]),
+            ..Default::default()
+        };
+        assert_eq!(config.spawn("compact", || 42).unwrap().join().unwrap(), 42);
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Write amplification statistics
+//!
+//! Flash lifetime analyses compare the bytes written to storage with the bytes logically changed
+//! by the application. Logical bytes are the key and payload sizes of every mutation: numbers
+//! count 8 bytes, booleans 1, `null` 0, strings and byte arrays their length and arrays and
+//! objects the sum of their members. Written bytes are the KVS and hash files written by flushes,
+//! compactions and tagged snapshots plus the records appended to the write-ahead log.
+
+use crate::error_code::ErrorCode;
+use crate::kvs_log::log_warning;
+use crate::kvs_transaction::KvsOperation;
+use crate::kvs_value::{KvsMap, KvsValue};
+use crate::kvs_wal::WalRecord;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Count of flushes the rolling write amplification is calculated over
+const ROLLING_WINDOW: usize = 16;
+
+/// Logically changed and physically written bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WriteStats {
+    /// Bytes changed by the application
+    pub logical_bytes: u64,
+
+    /// Bytes written to storage
+    pub written_bytes: u64,
+}
+
+impl WriteStats {
+    /// Return the write amplification factor, `None` if nothing was changed
+    pub fn amplification(&self) -> Option<f64> {
+        (self.logical_bytes > 0).then(|| self.written_bytes as f64 / self.logical_bytes as f64)
+    }
+
+    fn add(&mut self, other: &WriteStats) {
+        self.logical_bytes += other.logical_bytes;
+        self.written_bytes += other.written_bytes;
+    }
+}
+
+/// Write statistics of a KVS instance since it was opened
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WriteReport {
+    /// Count of flushes
+    pub flushes: u64,
+
+    /// Bytes of the last flush period, from the previous flush up to the last flush
+    pub last_flush: WriteStats,
+
+    /// Bytes of all completed flush periods
+    pub total: WriteStats,
+
+    /// Bytes since the last flush
+    pub pending: WriteStats,
+
+    /// Write amplification over the last flushes, `None` if nothing was changed
+    pub rolling_amplification: Option<f64>,
+}
+
+/// Return the logical size of a value
+pub(crate) fn value_size(value: &KvsValue) -> u64 {
+    match value {
+        KvsValue::Number(_) | KvsValue::I64(_) | KvsValue::U64(_) => 8,
+        KvsValue::Boolean(_) => 1,
+        KvsValue::Null => 0,
+        KvsValue::String(s) => s.len() as u64,
+        KvsValue::Bytes(b) => b.len() as u64,
+        KvsValue::Array(arr) => arr.iter().map(value_size).sum(),
+        KvsValue::Object(obj) => map_size(obj),
+    }
+}
+
+fn map_size(map: &KvsMap) -> u64 {
+    map.iter()
+        .map(|(key, value)| key.len() as u64 + value_size(value))
+        .sum()
+}
+
+fn operation_size(op: &KvsOperation) -> u64 {
+    match op {
+        KvsOperation::Set(key, value) => key.len() as u64 + value_size(value),
+        KvsOperation::Remove(key) => key.len() as u64,
+    }
+}
+
+/// Return the logical size of a mutation
+pub(crate) fn record_size(record: &WalRecord) -> u64 {
+    match record {
+        WalRecord::Set(key, value) => key.len() as u64 + value_size(value),
+        WalRecord::Remove(key) => key.len() as u64,
+        WalRecord::Batch(ops) => ops.iter().map(operation_size).sum(),
+        WalRecord::Replace(map) => map_size(map),
+    }
+}
+
+#[derive(Default)]
+struct WriteState {
+    flushes: u64,
+    last_flush: WriteStats,
+    total: WriteStats,
+    pending: WriteStats,
+    window: VecDeque<WriteStats>,
+}
+
+/// Write counters of a KVS instance
+pub(crate) struct WriteCounters {
+    /// Rolling write amplification above which a warning is logged
+    threshold: Option<f64>,
+
+    /// Counters
+    state: Mutex<WriteState>,
+}
+
+impl WriteCounters {
+    /// Create the counters
+    ///
+    /// # Parameters
+    ///   * `threshold`: Rolling write amplification to warn about, `None` to never warn
+    pub(crate) fn new(threshold: Option<f64>) -> Self {
+        Self {
+            threshold,
+            state: Mutex::new(WriteState::default()),
+        }
+    }
+
+    /// Count logically changed bytes
+    pub(crate) fn changed(&self, bytes: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending.logical_bytes += bytes;
+        }
+    }
+
+    /// Count bytes written to storage
+    pub(crate) fn written(&self, bytes: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.pending.written_bytes += bytes;
+        }
+    }
+
+    /// Complete a flush period and warn if the rolling write amplification exceeds the threshold
+    pub(crate) fn flushed(&self) -> Result<(), ErrorCode> {
+        let mut state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        let period = std::mem::take(&mut state.pending);
+        state.flushes += 1;
+        state.last_flush = period;
+        state.total.add(&period);
+        if state.window.len() == ROLLING_WINDOW {
+            state.window.pop_front();
+        }
+        state.window.push_back(period);
+
+        let rolling = Self::rolling(&state);
+        if let (Some(threshold), Some(rolling)) = (self.threshold, rolling) {
+            if rolling > threshold {
+                log_warning!(
+                    "write amplification {rolling:.1} over the last {} flushes exceeds {threshold:.1}",
+                    state.window.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn rolling(state: &WriteState) -> Option<f64> {
+        let mut sum = WriteStats::default();
+        for period in &state.window {
+            sum.add(period);
+        }
+        sum.amplification()
+    }
+
+    /// Return the current statistics
+    pub(crate) fn report(&self) -> Result<WriteReport, ErrorCode> {
+        let state = self.state.lock().map_err(|_| ErrorCode::MutexLockFailed)?;
+        Ok(WriteReport {
+            flushes: state.flushes,
+            last_flush: state.last_flush,
+            total: state.total,
+            pending: state.pending,
+            rolling_amplification: Self::rolling(&state),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_size() {
+        let value = KvsValue::from(vec![
+            KvsValue::from(1.0),
+            KvsValue::from(true),
+            KvsValue::from("abc".to_string()),
+            KvsValue::bytes([0; 4]),
+            KvsValue::Null,
+        ]);
+        assert_eq!(record_size(&WalRecord::Set("key", &value)), 3 + 16);
+        assert_eq!(record_size(&WalRecord::Remove("key")), 3);
+
+        let ops = [
+            KvsOperation::Set("a".to_string(), KvsValue::from(1u64)),
+            KvsOperation::Remove("bc".to_string()),
+        ];
+        assert_eq!(record_size(&WalRecord::Batch(&ops)), 9 + 2);
+
+        let map = KvsMap::from([("obj".to_string(), KvsValue::from(KvsMap::new()))]);
+        assert_eq!(record_size(&WalRecord::Replace(&map)), 3);
+    }
+
+    #[test]
+    fn test_write_counters() {
+        let counters = WriteCounters::new(Some(2.0));
+        assert_eq!(counters.report().unwrap(), WriteReport::default());
+
+        counters.changed(10);
+        counters.written(100);
+        counters.flushed().unwrap();
+        counters.changed(30);
+        counters.written(20);
+
+        let report = counters.report().unwrap();
+        assert_eq!(report.flushes, 1);
+        assert_eq!(report.last_flush.amplification(), Some(10.0));
+        assert_eq!(
+            report.pending,
+            WriteStats {
+                logical_bytes: 30,
+                written_bytes: 20
+            }
+        );
+
+        counters.flushed().unwrap();
+        let report = counters.report().unwrap();
+        assert_eq!(report.total.written_bytes, 120);
+        assert_eq!(report.rolling_amplification, Some(3.0));
+        assert_eq!(report.pending, WriteStats::default());
+    }
+
+    #[test]
+    fn test_write_counters_rolling_window() {
+        let counters = WriteCounters::new(None);
+        counters.written(1000);
+        counters.flushed().unwrap();
+        for _ in 0..ROLLING_WINDOW {
+            counters.changed(10);
+            counters.written(10);
+            counters.flushed().unwrap();
+        }
+        let report = counters.report().unwrap();
+        assert_eq!(report.rolling_amplification, Some(1.0));
+        assert_eq!(report.total.amplification(), Some(1160.0 / 160.0));
+        // Flushes without changes have no amplification
+        assert_eq!(WriteStats::default().amplification(), None);
+    }
+}
@@ -24,24 +24,38 @@
 //! Without configuration the KVS is flushed on exit by default. This can be controlled by
 //! [`Kvs::flush_on_exit`]. It is possible to manually flush the KVS by calling [`Kvs::flush`].
 //!
-//! All `TinyJSON` provided datatypes can be used:
+//! All `TinyJSON` provided datatypes can be used, extended by lossless 64-bit integers:
 //!   * `Number`: `f64`
+//!   * `I64`: `i64`
+//!   * `U64`: `u64`
 //!   * `Boolean`: `bool`
 //!   * `String`: `String`
 //!   * `Null`: `()`
 //!   * `Array`: `Vec<KvsValue>`
 //!   * `Object`: `HashMap<String, KvsValue>`
+//!   * `Bytes`: `Vec<u8>`
 //!
 //! Note: JSON arrays are not restricted to only contain values of the same type.
 //!
+//! In JSON files `I64`, `U64` and `Bytes` values are written as tagged objects like
+//! `{"$u64": "42"}`, and members of object values starting with `$` get an additional `$`.
+//! Readers of plain JSON, like the C++ implementation, see the tagged objects and the escaped
+//! member names as they're written. Files shared with them should only hold the other types
+//! and no object members starting with `$`.
+//!
+//! Objects don't keep the order of their members. Object members are written sorted by key and
+//! key listings like [`Kvs::get_all_keys`] are sorted, so equal data always gives byte-identical
+//! files and hashes.
+//!
 //! Writing a value to the KVS can be done by calling [`Kvs::set_value`] with the `key` as first
 //! and a `KvsValue` as second parameter. Either `KvsValue::Number(123.0)` or `123.0` can be
 //! used as there will be an auto-Into performed when calling the function.
 //!
 //! To read a value call [`Kvs::get_value`](Kvs::get_value) or [`Kvs::get_value_as::<T>`](Kvs::get_value_as)
-//! with the `key` as first parameter. `T` represents the type to read and can be `f64`, `bool`, `String`, `()`,
+//! with the `key` as first parameter. `T` represents the type to read and can be `f64`, `i64`, `u64`, `bool`, `String`, `()`,
 //! `Vec<KvsValue>`, `HashMap<String, KvsValue>` or `KvsValue`.
-//! Also `let value: f64 = kvs.get_value_as()` can be used.
+//! Also `let value: f64 = kvs.get_value_as()` can be used. Byte arrays are written with
+//! `KvsValue::bytes(data)` and read with `kvs.get_value_as::<Vec<u8>>()`.
 //!
 //! If a `key` isn't available in the KVS a lookup into the defaults storage will be performed and
 //! if the `value` is found the default will be returned. The default value isn't stored when
@@ -50,7 +64,13 @@
 //! it's better to remove the default value and write the value permanently when the KVS is
 //! initialized. To check whether a value has a default call [`Kvs::get_default_value`] and to
 //! see if the value wasn't written yet and will return the default call
-//! [`Kvs::is_value_default`].
+//! [`Kvs::is_value_default`]. A single key is reverted to its default with
+//! [`Kvs::reset_key_to_default`].
+//!
+//! Defaults files can also be written as typed documents with `"$schema": "kvs-defaults/1"`, where
+//! every value is annotated as `{"t": <type>, "v": <value>}` and checked when the KVS is opened.
+//! The member `"$schemas"` of a defaults file attaches JSON Schemas to keys, which new values
+//! have to conform to.
 //!
 //!
 //! ## Example Usage
@@ -60,7 +80,9 @@
 //! use std::collections::HashMap;
 //!
 //! fn main() -> Result<(), ErrorCode> {
-//!     let kvs: Kvs = KvsBuilder::new(InstanceId::new(0)).dir("").build()?;
+//! #   let dir = tempfile::tempdir().unwrap();
+//! #   let dir = dir.path().to_str().unwrap();
+//!     let kvs: Kvs = KvsBuilder::new(InstanceId::new(0)).dir(dir).build()?;
 //!
 //!     kvs.set_value("number", 123.0)?;
 //!     kvs.set_value("bool", true)?;
@@ -136,25 +158,112 @@
 pub mod error_code;
 mod json_backend;
 pub mod kvs;
+pub mod kvs_access;
 pub mod kvs_api;
+pub mod kvs_async;
+pub mod kvs_auto_flush;
 mod kvs_backend;
+mod kvs_base64;
 pub mod kvs_builder;
+mod kvs_cbor;
+pub mod kvs_cipher;
+mod kvs_compress;
+mod kvs_csv;
+pub mod kvs_cursor;
+pub mod kvs_default_provider;
+mod kvs_defaults;
+mod kvs_defaults_hash;
+pub mod kvs_fairness;
+pub mod kvs_flags;
+mod kvs_glob;
+pub mod kvs_image;
+#[cfg(all(unix, feature = "ipc"))]
+pub mod kvs_ipc;
+mod kvs_journal;
+mod kvs_lock;
+pub mod kvs_log;
+pub mod kvs_metadata;
+pub mod kvs_migration;
+pub mod kvs_namespace;
+pub mod kvs_observer;
+pub mod kvs_override;
+mod kvs_platform;
+mod kvs_pointer;
+pub mod kvs_rules;
+mod kvs_schema;
+pub mod kvs_shared;
+pub mod kvs_snapshot_view;
+pub mod kvs_stats;
+pub mod kvs_transaction;
+mod kvs_trend;
+pub mod kvs_validator;
 pub mod kvs_value;
+pub mod kvs_value_ref;
+mod kvs_version;
+mod kvs_wal;
+pub mod kvs_worker;
+pub mod kvs_write_stats;
 
 pub mod kvs_mock;
 
 pub type Kvs = kvs::GenericKvs<json_backend::JsonBackend>;
 
+pub type SharedKvs = kvs_shared::GenericSharedKvs<json_backend::JsonBackend>;
+
+pub type AsyncKvs = kvs_async::GenericAsyncKvs<json_backend::JsonBackend>;
+
 /// Prelude module for convenient imports
 pub mod prelude {
     pub use crate::error_code::ErrorCode;
     pub use crate::kvs::GenericKvs;
+    pub use crate::kvs_access::{AccessPolicy, NamespaceProtection};
+    pub use crate::kvs_api::CompactionReport;
+    pub use crate::kvs_api::Compression;
+    pub use crate::kvs_api::DefaultsPrecedence;
+    pub use crate::kvs_api::Durability;
+    pub use crate::kvs_api::FlushInfo;
+    pub use crate::kvs_api::GrowthTrend;
+    pub use crate::kvs_api::ImportMode;
     pub use crate::kvs_api::InstanceId;
+    pub use crate::kvs_api::JournalRole;
+    pub use crate::kvs_api::KeyStatsMode;
     pub use crate::kvs_api::KvsApi;
+    pub use crate::kvs_api::KvsOptions;
+    pub use crate::kvs_api::KvsStats;
+    pub use crate::kvs_api::OpenMode;
     pub use crate::kvs_api::OpenNeedDefaults;
     pub use crate::kvs_api::OpenNeedKvs;
+    pub use crate::kvs_api::SnapshotGeneration;
     pub use crate::kvs_api::SnapshotId;
+    pub use crate::kvs_api::SnapshotInfo;
+    pub use crate::kvs_api::SnapshotRetention;
+    pub use crate::kvs_api::StorageFormat;
+    pub use crate::kvs_api::{IntegrityIssue, IntegrityReport};
+    pub use crate::kvs_async::GenericAsyncKvs;
     pub use crate::kvs_builder::KvsBuilder;
-    pub use crate::kvs_value::KvsValue;
+    pub use crate::kvs_cipher::KvsCipher;
+    pub use crate::kvs_cursor::KvsCursor;
+    pub use crate::kvs_default_provider::KvsDefaultProvider;
+    pub use crate::kvs_fairness::FairnessPolicy;
+    pub use crate::kvs_image::KvsImageBuilder;
+    #[cfg(all(unix, feature = "ipc"))]
+    pub use crate::kvs_ipc::{IpcAccess, IpcConfig, IpcGrant, KvsIpcClient, KvsIpcServer};
+    pub use crate::kvs_log::{LogCallback, LogLevel};
+    pub use crate::kvs_metadata::KeyMetadata;
+    pub use crate::kvs_migration::KvsMigration;
+    pub use crate::kvs_namespace::KvsNamespace;
+    pub use crate::kvs_observer::{KvsEvent, OverflowPolicy, SubscriptionId};
+    pub use crate::kvs_override::KvsOverrideSession;
+    pub use crate::kvs_rules::{KvsRestoreHook, RuleContext, RuleViolation};
+    pub use crate::kvs_shared::GenericSharedKvs;
+    pub use crate::kvs_snapshot_view::{ChangeType, KeyDiff, KvsReadOnlyView};
+    pub use crate::kvs_stats::KeyStats;
+    pub use crate::kvs_transaction::KvsTransaction;
+    pub use crate::kvs_value::{KvsValue, Parsed};
+    pub use crate::kvs_value_ref::KvsValueRef;
+    pub use crate::kvs_worker::WorkerConfig;
+    pub use crate::kvs_write_stats::{WriteReport, WriteStats};
+    pub use crate::AsyncKvs;
     pub use crate::Kvs;
+    pub use crate::SharedKvs;
 }
@@ -380,9 +380,166 @@ fn cit_persistency_reset_all_default_values() -> Result<(), ErrorCode> {
 }
 
 #[test]
-#[ignore]
 fn cit_persistency_reset_single_default_value() -> Result<(), ErrorCode> {
-    // TODO: This test is not implemented yet.
-    // API supports resetting only all keys.
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    // Values.
+    let keyname1 = "test_number1".to_string();
+    let keyname2 = "test_number2".to_string();
+    let default_value: f64 = 111.1;
+    let non_default_value = 333.3;
+
+    // Create defaults file for instance 0.
+    let default_id = InstanceId::new(0);
+    write_defaults_file(
+        dir.path(),
+        HashMap::from([
+            (keyname1.clone(), JsonValue::from(default_value)),
+            (keyname2.clone(), JsonValue::from(default_value)),
+        ]),
+        &default_id,
+    )?;
+
+    // Assertions.
+    {
+        // KVS instance with defaults.
+        let kvs_with_defaults = Kvs::open(
+            default_id.clone(),
+            OpenNeedDefaults::Required,
+            OpenNeedKvs::Optional,
+            Some(dir_string.clone()),
+        )?;
+
+        // Set non-default values.
+        kvs_with_defaults.set_value(&keyname1, non_default_value)?;
+        kvs_with_defaults.set_value(&keyname2, non_default_value)?;
+        kvs_with_defaults.set_value("no_default", non_default_value)?;
+
+        // Reset only the first key.
+        kvs_with_defaults.reset_key_to_default(&keyname1)?;
+        assert!(
+            kvs_with_defaults.is_value_default(&keyname1)?,
+            "kvs_with_defaults: key '{keyname1}' should be default after reset"
+        );
+        assert_eq!(
+            kvs_with_defaults.get_value_as::<f64>(&keyname1)?,
+            default_value
+        );
+        assert!(
+            !kvs_with_defaults.is_value_default(&keyname2)?,
+            "kvs_with_defaults: key '{keyname2}' should NOT be default"
+        );
+
+        // Resetting a key that is already default is no error.
+        kvs_with_defaults.reset_key_to_default(&keyname1)?;
+
+        // Keys without default can't be reset.
+        assert_eq!(
+            kvs_with_defaults.reset_key_to_default("no_default"),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert_eq!(
+            kvs_with_defaults.get_value_as::<f64>("no_default")?,
+            non_default_value
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cit_persistency_typed_default_values() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+    let filepath = dir.path().join("kvs_0_default.json");
+
+    // Typed defaults file.
+    std::fs::write(
+        &filepath,
+        r#"{
+            "$schema": "kvs-defaults/1",
+            "volume": {"t": "f64", "v": 12},
+            "presets": {"t": "arr", "v": [{"t": "str", "v": "radio"}]}
+        }"#,
+    )?;
+
+    // Assertions.
+    {
+        let kvs = Kvs::open(
+            InstanceId::new(0),
+            OpenNeedDefaults::Required,
+            OpenNeedKvs::Optional,
+            Some(dir_string.clone()),
+        )?;
+        kvs.flush_on_exit(false);
+        assert_eq!(kvs.get_value_as::<f64>("volume")?, 12.0);
+        assert_eq!(
+            kvs.get_default_value("presets")?,
+            KvsValue::from(vec![KvsValue::from("radio".to_string())])
+        );
+        assert_eq!(kvs.get_all_keys()?, Vec::<String>::new());
+    }
+
+    // Value doesn't match its annotation.
+    std::fs::write(
+        &filepath,
+        r#"{"$schema": "kvs-defaults/1", "volume": {"t": "bool", "v": 12}}"#,
+    )?;
+    let kvs = Kvs::open(
+        InstanceId::new(0),
+        OpenNeedDefaults::Required,
+        OpenNeedKvs::Optional,
+        Some(dir_string.clone()),
+    );
+    assert!(matches!(kvs, Err(ErrorCode::ValidationFailed)));
+
+    Ok(())
+}
+
+#[test]
+fn cit_persistency_embedded_default_values() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    // Embedded defaults, as compiled in with `include_bytes!`.
+    const EMBEDDED: &[u8] = br#"{"embedded": 1.0, "shared": 2.0}"#;
+
+    // Assertions.
+    {
+        // No defaults file: embedded defaults satisfy required defaults.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_string.clone())
+            .need_defaults(true)
+            .embedded_defaults(EMBEDDED)
+            .build()?;
+        kvs.flush_on_exit(false);
+        assert_eq!(kvs.get_value_as::<f64>("shared")?, 2.0);
+    }
+
+    write_defaults_file(
+        dir.path(),
+        HashMap::from([("shared".to_string(), JsonValue::from(3.0))]),
+        &InstanceId::new(0),
+    )?;
+
+    for (precedence, embedded, shared) in [
+        (DefaultsPrecedence::OnDisk, None, 3.0),
+        (DefaultsPrecedence::Embedded, Some(1.0), 2.0),
+        (DefaultsPrecedence::MergeOnDisk, Some(1.0), 3.0),
+    ] {
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_string.clone())
+            .embedded_defaults(EMBEDDED)
+            .defaults_precedence(precedence)
+            .build()?;
+        kvs.flush_on_exit(false);
+        assert_eq!(kvs.get_value_as::<f64>("embedded").ok(), embedded);
+        assert_eq!(kvs.get_value_as::<f64>("shared")?, shared);
+    }
+
     Ok(())
 }
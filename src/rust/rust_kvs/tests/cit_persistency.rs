@@ -132,6 +132,48 @@ fn cit_persistency_flush_on_exit_disabled_drop_data() -> Result<(), ErrorCode> {
     Ok(())
 }
 
+/// Flush on exit can be disabled when the KVS is opened.
+#[test]
+fn cit_persistency_flush_on_exit_disabled_on_open() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    {
+        // First KVS run.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .flush_on_exit(false)
+            .build()?;
+        kvs.set_value("number", 123.4)?;
+    }
+
+    {
+        // Second KVS run.
+        // Flush on exit is re-enabled after open.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .flush_on_exit(false)
+            .build()?;
+        assert!(kvs.get_all_keys()?.is_empty());
+        kvs.flush_on_exit(true);
+        kvs.set_value("bool", true)?;
+    }
+
+    // Assertions.
+    {
+        // Third KVS run.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path)
+            .need_kvs(true)
+            .flush_on_exit(false)
+            .build()?;
+        assert_eq!(kvs.get_all_keys()?, vec!["bool".to_string()]);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn cit_persistency_flush_on_exit_disabled_manual_flush() -> Result<(), ErrorCode> {
     // Temp directory.
@@ -195,3 +237,179 @@ fn cit_persistency_flush_on_exit_disabled_manual_flush() -> Result<(), ErrorCode
 
     Ok(())
 }
+
+/// With the write-ahead log enabled, mutations survive without a flush.
+#[test]
+fn cit_persistency_write_ahead_log_replay() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    {
+        // First KVS run.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .write_ahead_log(true)
+            .build()?;
+        kvs.flush_on_exit(false);
+
+        kvs.set_value("number", 123.4)?;
+        kvs.set_value("removed", true)?;
+        kvs.remove_key("removed")?;
+
+        let mut tx = kvs.begin_transaction();
+        tx.set_value("str", "abcd".to_string());
+        tx.commit()?;
+    }
+
+    // Assertions.
+    {
+        // Second KVS run.
+        // KVS file was never written, all values are replayed from the log.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path)
+            .write_ahead_log(true)
+            .build()?;
+        kvs.flush_on_exit(false);
+
+        assert_eq!(kvs.get_value_as::<f64>("number")?, 123.4);
+        assert_eq!(kvs.get_value_as::<String>("str")?, "abcd");
+        assert!(!kvs.key_exists("removed")?);
+    }
+
+    Ok(())
+}
+
+/// Compaction folds the write-ahead log into the KVS file and keeps the old data as snapshot.
+#[test]
+fn cit_persistency_compact() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    {
+        // First KVS run.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .write_ahead_log(true)
+            .build()?;
+        kvs.flush_on_exit(false);
+
+        for idx in 0..20 {
+            kvs.set_value("counter", idx as f64)?;
+        }
+        let report = kvs.compact()?;
+        assert!(report.size_after < report.size_before);
+        assert_eq!(report.snapshot_tag, "pre-compaction");
+        assert!(!dir.path().join("kvs_0.wal").exists());
+
+        kvs.set_value("counter", 100.0)?;
+        kvs.snapshot_restore_tag(&report.snapshot_tag)?;
+        assert_eq!(kvs.get_value_as::<f64>("counter")?, 19.0);
+        assert!(kvs.snapshot_list()?.is_empty());
+    }
+
+    // Assertions.
+    {
+        // Second KVS run.
+        // The restore was journaled on top of the compacted KVS file.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path)
+            .need_kvs(true)
+            .write_ahead_log(true)
+            .build()?;
+        kvs.flush_on_exit(false);
+        assert_eq!(kvs.get_value_as::<f64>("counter")?, 19.0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cit_persistency_cbor_format_detected_on_load() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    {
+        // First KVS run, written as CBOR.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .storage_format(StorageFormat::Cbor)
+            .build()?;
+        kvs.set_value("number", 123.4)?;
+        kvs.set_value("str", "abcd".to_string())?;
+    }
+
+    {
+        // Second KVS run with default format, CBOR file is detected and written back as JSON.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path.clone())
+            .need_kvs(true)
+            .build()?;
+        assert_eq!(kvs.get_value_as::<f64>("number")?, 123.4);
+        assert_eq!(kvs.get_value_as::<String>("str")?, "abcd");
+    }
+
+    // Assertions.
+    {
+        // Third KVS run, current file is JSON again, previous CBOR file is a valid snapshot.
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir_path)
+            .need_kvs(true)
+            .build()?;
+        kvs.flush_on_exit(false);
+        let json = std::fs::read_to_string(kvs.get_kvs_filename(SnapshotId::new(0))?)?;
+        assert!(json.starts_with('{'));
+
+        kvs.snapshot_restore(SnapshotId::new(1))?;
+        assert_eq!(kvs.get_value_as::<f64>("number")?, 123.4);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cit_persistency_integers_lossless() -> Result<(), ErrorCode> {
+    for format in [StorageFormat::Json, StorageFormat::Cbor] {
+        // Temp directory.
+        let dir = tempdir()?;
+        let dir_path = dir.path().to_string_lossy().to_string();
+
+        {
+            // First KVS run.
+            let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+                .dir(dir_path.clone())
+                .storage_format(format)
+                .build()?;
+            kvs.set_value("i64", i64::MIN)?;
+            kvs.set_value("u64", u64::MAX)?;
+            kvs.set_value("u64_small", 7u64)?;
+            kvs.set_value("bytes", KvsValue::bytes([0x00, 0xff, 0x10]))?;
+            kvs.set_value("array", vec![KvsValue::from(9007199254740993u64)])?;
+        }
+
+        // Assertions.
+        {
+            // Second KVS run.
+            let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+                .dir(dir_path)
+                .need_kvs(true)
+                .build()?;
+            kvs.flush_on_exit(false);
+            assert_eq!(kvs.get_value_as::<i64>("i64")?, i64::MIN);
+            assert_eq!(kvs.get_value_as::<u64>("u64")?, u64::MAX);
+            assert_eq!(kvs.get_value("u64_small")?, KvsValue::U64(7));
+            assert_eq!(
+                kvs.get_value_as::<Vec<u8>>("bytes")?,
+                vec![0x00, 0xff, 0x10]
+            );
+            assert_eq!(
+                kvs.get_value("array")?,
+                KvsValue::from(vec![KvsValue::U64(9007199254740993)])
+            );
+        }
+    }
+
+    Ok(())
+}
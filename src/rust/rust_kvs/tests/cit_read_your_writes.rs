@@ -0,0 +1,121 @@
+//! Persistency tests.
+//!
+//! Requirements verified:
+//! - Intra-Process Data Access (feat_req__persistency__intra_process_comm)
+//!   The KVS shall support concurrent intra-process data access.
+//!   Handles of the same instance opened through the shared registry read each other's
+//!   unflushed writes, isolated handles don't.
+//!
+use rust_kvs::prelude::*;
+use std::thread;
+use tempfile::tempdir;
+
+fn shared(dir: &str) -> Result<SharedKvs, ErrorCode> {
+    KvsBuilder::<Kvs>::new(InstanceId::new(0))
+        .dir(dir)
+        .build_shared()
+}
+
+#[test]
+fn cit_read_your_writes_interleaved_handles() -> Result<(), ErrorCode> {
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    let handle1 = shared(&dir_string)?;
+    let handle2 = shared(&dir_string)?;
+    assert_eq!(handle1.handle_count(), 2);
+
+    handle1.set_value("number", 1.0)?;
+    assert_eq!(handle2.get_value_as::<f64>("number")?, 1.0);
+    handle2.set_value("number", 2.0)?;
+    assert_eq!(handle1.get_value_as::<f64>("number")?, 2.0);
+    handle1.remove_key("number")?;
+    assert!(!handle2.key_exists("number")?);
+
+    let mut tx = handle2.begin_transaction();
+    tx.set_value("a", true);
+    tx.set_value("b", false);
+    tx.commit()?;
+    assert!(handle1.get_value_as::<bool>("a")?);
+    assert!(!handle1.get_value_as::<bool>("b")?);
+
+    Ok(())
+}
+
+#[test]
+fn cit_read_your_writes_across_threads() -> Result<(), ErrorCode> {
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    let threads: Vec<_> = (0..4)
+        .map(|idx| {
+            let dir_string = dir_string.clone();
+            thread::spawn(move || -> Result<(), ErrorCode> {
+                let kvs = shared(&dir_string)?;
+                for n in 0..25 {
+                    let key = format!("thread{idx}_{n}");
+                    kvs.set_value(key.as_str(), n as f64)?;
+                    assert_eq!(kvs.get_value_as::<f64>(&key)?, n as f64);
+                }
+                Ok(())
+            })
+        })
+        .collect();
+
+    // Keep one handle open so all threads share one instance
+    let kvs = shared(&dir_string)?;
+    for thread in threads {
+        thread.join().unwrap()?;
+    }
+    assert_eq!(kvs.get_all_keys()?.len(), 100);
+
+    Ok(())
+}
+
+#[test]
+fn cit_read_your_writes_last_handle_flushes() -> Result<(), ErrorCode> {
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    {
+        let handle1 = shared(&dir_string)?;
+        let handle2 = handle1.clone();
+        handle1.set_value("number", 3.0)?;
+        drop(handle1);
+        assert_eq!(handle2.get_value_as::<f64>("number")?, 3.0);
+    }
+
+    // Reopened from the flushed file
+    let kvs = shared(&dir_string)?;
+    assert_eq!(kvs.handle_count(), 1);
+    assert_eq!(kvs.get_value_as::<f64>("number")?, 3.0);
+
+    Ok(())
+}
+
+#[test]
+fn cit_read_your_writes_isolated_handle() -> Result<(), ErrorCode> {
+    let dir = tempdir()?;
+    let dir_string = dir.path().to_string_lossy().to_string();
+
+    let kvs = shared(&dir_string)?;
+    let isolated = KvsBuilder::<Kvs>::new(InstanceId::new(0))
+        .dir(dir_string.clone())
+        .isolated(true)
+        .build_shared()?;
+    isolated.flush_on_exit(false);
+    assert_eq!(kvs.handle_count(), 1);
+
+    kvs.set_value("number", 4.0)?;
+    assert!(!isolated.key_exists("number")?);
+    isolated.set_value("other", 5.0)?;
+    assert!(!kvs.key_exists("other")?);
+
+    // Another instance ID is a separate registry entry
+    let other = KvsBuilder::<Kvs>::new(InstanceId::new(1))
+        .dir(dir_string)
+        .build_shared()?;
+    assert!(!other.key_exists("number")?);
+
+    Ok(())
+}
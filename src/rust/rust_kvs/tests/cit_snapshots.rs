@@ -235,3 +235,80 @@ fn cit_snapshots_get_hash_filename_nonexisting_snapshot() -> Result<(), ErrorCod
     assert!(result.is_err_and(|e| e == ErrorCode::FileNotFound));
     Ok(())
 }
+
+#[test]
+fn cit_snapshots_snapshot_list() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    // Arrange.
+    let instance_id = InstanceId::new(0);
+    let num_snapshots = 3;
+    let kvs = init_kvs(instance_id.clone(), dir_path, num_snapshots)?;
+
+    // Assert.
+    let list = kvs.snapshot_list()?;
+    let ids: Vec<SnapshotId> = list.iter().map(|info| info.id.clone()).collect();
+    assert_eq!(ids, vec![SnapshotId::new(1), SnapshotId::new(2)]);
+    for info in list {
+        let data = std::fs::read(kvs.get_kvs_filename(info.id)?)?;
+        assert_eq!(info.size, data.len() as u64);
+        assert_eq!(
+            info.hash,
+            Some(adler32::RollingAdler32::from_buffer(&data).hash())
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn cit_snapshots_snapshot_create_and_restore_tag() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    // Arrange.
+    let instance_id = InstanceId::new(0);
+    let num_snapshots = 1;
+    let kvs = init_kvs(instance_id.clone(), dir_path, num_snapshots)?;
+    kvs.snapshot_create("before-update")?;
+
+    // Tagged snapshots are not rotated by flushes.
+    for i in 2..=5 {
+        kvs.set_value("counter", i as f64)?;
+        kvs.flush()?;
+        if i == 3 {
+            kvs.snapshot_create("v1.2")?;
+        }
+    }
+
+    // Assert.
+    assert_eq!(
+        kvs.snapshot_tags()?,
+        vec!["before-update".to_string(), "v1.2".to_string()]
+    );
+    kvs.snapshot_restore_tag("v1.2")?;
+    assert_eq!(kvs.get_value_as::<f64>("counter")?, 3.0);
+    kvs.snapshot_restore_tag("before-update")?;
+    assert_eq!(kvs.get_value_as::<f64>("counter")?, 1.0);
+    Ok(())
+}
+
+#[test]
+fn cit_snapshots_snapshot_create_invalid_tag() -> Result<(), ErrorCode> {
+    // Temp directory.
+    let dir = tempdir()?;
+    let dir_path = dir.path().to_string_lossy().to_string();
+
+    // Arrange.
+    let instance_id = InstanceId::new(0);
+    let kvs = init_kvs(instance_id.clone(), dir_path, 0)?;
+
+    // Assert.
+    let result = kvs.snapshot_create("../escape");
+    assert!(result.is_err_and(|e| e == ErrorCode::InvalidSnapshotId));
+    let result = kvs.snapshot_restore_tag("missing");
+    assert!(result.is_err_and(|e| e == ErrorCode::KvsFileReadError));
+    Ok(())
+}
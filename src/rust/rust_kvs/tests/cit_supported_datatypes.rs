@@ -59,6 +59,33 @@ fn cit_supported_datatypes_number() -> Result<(), ErrorCode> {
     supported_datatypes_common_impl(data)
 }
 
+#[test]
+fn cit_supported_datatypes_i64() -> Result<(), ErrorCode> {
+    let data = HashMap::from([
+        ("i64_min", KvsValue::from(i64::MIN)),
+        ("i64_max", KvsValue::from(i64::MAX)),
+    ]);
+    supported_datatypes_common_impl(data)
+}
+
+#[test]
+fn cit_supported_datatypes_u64() -> Result<(), ErrorCode> {
+    let data = HashMap::from([
+        ("u64_max", KvsValue::from(u64::MAX)),
+        ("u64_above_f64", KvsValue::from(9007199254740993u64)),
+    ]);
+    supported_datatypes_common_impl(data)
+}
+
+#[test]
+fn cit_supported_datatypes_bytes() -> Result<(), ErrorCode> {
+    let data = HashMap::from([
+        ("bytes_empty", KvsValue::bytes(Vec::new())),
+        ("bytes", KvsValue::bytes([0x00, 0x7f, 0xff])),
+    ]);
+    supported_datatypes_common_impl(data)
+}
+
 #[test]
 fn cit_supported_datatypes_boolean() -> Result<(), ErrorCode> {
     let data = HashMap::from([("bool", KvsValue::from(true))]);
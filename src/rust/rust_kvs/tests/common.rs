@@ -14,6 +14,9 @@ use std::iter::zip;
 pub fn compare_kvs_values(left: &KvsValue, right: &KvsValue) -> bool {
     match (left, right) {
         (KvsValue::Number(l), KvsValue::Number(r)) => l == r,
+        (KvsValue::I64(l), KvsValue::I64(r)) => l == r,
+        (KvsValue::U64(l), KvsValue::U64(r)) => l == r,
+        (KvsValue::Bytes(l), KvsValue::Bytes(r)) => l == r,
         (KvsValue::Boolean(l), KvsValue::Boolean(r)) => l == r,
         (KvsValue::String(l), KvsValue::String(r)) => l == r,
         (KvsValue::Null, KvsValue::Null) => true,
@@ -0,0 +1,427 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Batch scripts for `kvs_tool -o apply`
+//!
+//! Two script formats are supported:
+//!   * Line based: one `set <key> <json>`, `remove <key>` or `assert <key> <json>` per line,
+//!     empty lines and lines starting with `#` are ignored
+//!   * JSON patch document (RFC 6902): an array of `add`/`replace`, `remove` and `test`
+//!     operations with paths of the form `/<key>`
+//!
+//! All steps of a script are applied in a single transaction. Asserts see the effect of the
+//! steps before them, a failing assert aborts the whole script.
+
+use crate::from_tinyjson;
+use rust_kvs::prelude::*;
+use std::collections::HashMap;
+use tinyjson::JsonValue;
+
+/// Single step of a script
+pub(crate) enum ScriptStep {
+    /// Assign a value to a key
+    Set(String, KvsValue),
+
+    /// Remove a key
+    Remove(String),
+
+    /// Check that a key has the given value
+    Assert(String, KvsValue),
+}
+
+/// Script step with the line (or patch index) it was read from
+pub(crate) struct ScriptLine {
+    /// Line number, starting at 1
+    pub(crate) line: usize,
+
+    /// Parsed step
+    pub(crate) step: ScriptStep,
+}
+
+/// Result of applying a script
+#[derive(Default)]
+pub(crate) struct ScriptSummary {
+    /// Count of set steps
+    pub(crate) set: usize,
+
+    /// Count of remove steps
+    pub(crate) removed: usize,
+
+    /// Count of checked asserts
+    pub(crate) asserts: usize,
+
+    /// Changes were committed
+    pub(crate) committed: bool,
+
+    /// Error and the line it occurred on
+    pub(crate) error: Option<(usize, String)>,
+}
+
+impl ScriptSummary {
+    /// Return the summary as JSON object
+    pub(crate) fn to_json(&self, dry_run: bool) -> JsonValue {
+        let result = match (&self.error, dry_run) {
+            (Some(_), _) => "failed",
+            (None, true) => "dry-run",
+            (None, false) => "ok",
+        };
+        let mut obj = HashMap::from([
+            ("result".to_string(), JsonValue::String(result.to_string())),
+            ("set".to_string(), JsonValue::Number(self.set as f64)),
+            (
+                "removed".to_string(),
+                JsonValue::Number(self.removed as f64),
+            ),
+            (
+                "asserts".to_string(),
+                JsonValue::Number(self.asserts as f64),
+            ),
+            ("committed".to_string(), JsonValue::Boolean(self.committed)),
+        ]);
+        if let Some((line, message)) = &self.error {
+            obj.insert("line".to_string(), JsonValue::Number(*line as f64));
+            obj.insert("error".to_string(), JsonValue::String(message.clone()));
+        }
+        JsonValue::Object(obj)
+    }
+}
+
+/// Parse a JSON value of a script line
+fn parse_value(json: &str, line: usize) -> Result<KvsValue, (usize, String)> {
+    json.trim()
+        .parse::<JsonValue>()
+        .map(|value| from_tinyjson(&value))
+        .map_err(|e| (line, format!("invalid JSON value: {e}")))
+}
+
+/// Parse a line based script
+fn parse_lines(script: &str) -> Result<Vec<ScriptLine>, (usize, String)> {
+    let mut steps = Vec::new();
+    for (idx, text) in script.lines().enumerate() {
+        let line = idx + 1;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let (cmd, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let rest = rest.trim();
+        let (key, json) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if key.is_empty() {
+            return Err((line, format!("missing key for '{cmd}'")));
+        }
+
+        let step = match cmd {
+            "set" => ScriptStep::Set(key.to_string(), parse_value(json, line)?),
+            "remove" if json.trim().is_empty() => ScriptStep::Remove(key.to_string()),
+            "assert" => ScriptStep::Assert(key.to_string(), parse_value(json, line)?),
+            _ => return Err((line, format!("invalid statement '{text}'"))),
+        };
+        steps.push(ScriptLine { line, step });
+    }
+    Ok(steps)
+}
+
+/// Convert a JSON pointer of a top-level member to the key
+fn patch_key(path: &str) -> Option<String> {
+    let key = path.strip_prefix('/')?;
+    if key.contains('/') {
+        return None;
+    }
+    Some(key.replace("~1", "/").replace("~0", "~"))
+}
+
+/// Parse a JSON patch document
+fn parse_patch(patch: &JsonValue) -> Result<Vec<ScriptLine>, (usize, String)> {
+    let ops: &Vec<JsonValue> = patch
+        .get()
+        .ok_or((0, "JSON patch must be an array".to_string()))?;
+
+    let mut steps = Vec::new();
+    for (idx, op) in ops.iter().enumerate() {
+        let line = idx + 1;
+        let op: &HashMap<String, JsonValue> = op
+            .get()
+            .ok_or((line, "patch operation must be an object".to_string()))?;
+        let name = op
+            .get("op")
+            .and_then(|name| name.get::<String>())
+            .ok_or((line, "missing 'op'".to_string()))?;
+        let key = op
+            .get("path")
+            .and_then(|path| path.get::<String>())
+            .and_then(|path| patch_key(path))
+            .ok_or((line, "'path' must refer to a top-level key".to_string()))?;
+        let value = op.get("value").map(from_tinyjson);
+
+        let step = match (name.as_str(), value) {
+            ("add" | "replace", Some(value)) => ScriptStep::Set(key, value),
+            ("remove", _) => ScriptStep::Remove(key),
+            ("test", Some(value)) => ScriptStep::Assert(key, value),
+            _ => return Err((line, format!("unsupported patch operation '{name}'"))),
+        };
+        steps.push(ScriptLine { line, step });
+    }
+    Ok(steps)
+}
+
+/// Parse a script, the format is detected from the content
+///
+/// # Return Values
+///   * Ok: Parsed steps
+///   * Err: Line number and error message
+pub(crate) fn parse(script: &str) -> Result<Vec<ScriptLine>, (usize, String)> {
+    if script.trim_start().starts_with('[') {
+        let patch = script
+            .parse::<JsonValue>()
+            .map_err(|e| (0, format!("invalid JSON patch: {e}")))?;
+        parse_patch(&patch)
+    } else {
+        parse_lines(script)
+    }
+}
+
+/// Apply a script in a single transaction
+///
+/// # Parameters
+///   * `kvs`: KVS instance
+///   * `steps`: Parsed script
+///   * `dry_run`: Only check the script, don't commit any change
+pub(crate) fn apply(kvs: &Kvs, steps: Vec<ScriptLine>, dry_run: bool) -> ScriptSummary {
+    let mut summary = ScriptSummary::default();
+
+    // Values as seen by the script: staged changes overlay the KVS
+    let mut staged: HashMap<String, Option<KvsValue>> = HashMap::new();
+    let mut tx = kvs.begin_transaction();
+
+    for ScriptLine { line, step } in steps {
+        match step {
+            ScriptStep::Set(key, value) => {
+                staged.insert(key.clone(), Some(value.clone()));
+                tx.set_value(key, value);
+                summary.set += 1;
+            }
+            ScriptStep::Remove(key) => {
+                let exists = match staged.get(&key) {
+                    Some(value) => value.is_some(),
+                    None => kvs.key_exists(&key).unwrap_or(false),
+                };
+                if !exists {
+                    summary.error = Some((line, format!("key '{key}' not found")));
+                    return summary;
+                }
+                staged.insert(key.clone(), None);
+                tx.remove_key(&key);
+                summary.removed += 1;
+            }
+            ScriptStep::Assert(key, expected) => {
+                let current = match staged.get(&key) {
+                    Some(value) => value.clone(),
+                    None => kvs.get_value(&key).ok(),
+                };
+                summary.asserts += 1;
+                if current.as_ref() != Some(&expected) {
+                    summary.error = Some((
+                        line,
+                        format!(
+                            "assert failed for '{key}': expected {expected:?}, found {current:?}"
+                        ),
+                    ));
+                    return summary;
+                }
+            }
+        }
+    }
+
+    if dry_run {
+        tx.rollback();
+        return summary;
+    }
+
+    match tx.commit() {
+        Ok(()) => summary.committed = true,
+        Err(e) => summary.error = Some((0, format!("commit failed: {e:?}"))),
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn new_kvs() -> (Kvs, TempDir) {
+        let dir = tempdir().unwrap();
+        let kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        (kvs, dir)
+    }
+
+    fn error(script: &str) -> (usize, String) {
+        parse(script).err().unwrap()
+    }
+
+    #[test]
+    fn test_parse_lines() {
+        let steps = parse("set a 1\n  remove b  \nassert c [1, 2]\n").unwrap();
+        assert_eq!(
+            steps.iter().map(|step| step.line).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(
+            matches!(&steps[0].step, ScriptStep::Set(key, KvsValue::Number(v)) if key == "a" && *v == 1.0)
+        );
+        assert!(matches!(&steps[1].step, ScriptStep::Remove(key) if key == "b"));
+        assert!(
+            matches!(&steps[2].step, ScriptStep::Assert(key, KvsValue::Array(a)) if key == "c" && a.len() == 2)
+        );
+    }
+
+    #[test]
+    fn test_parse_quoted_values() {
+        let steps = parse(r#"set name "two  words # no comment""#).unwrap();
+        let ScriptStep::Set(key, value) = &steps[0].step else {
+            panic!("expected set");
+        };
+        assert_eq!(key, "name");
+        assert_eq!(
+            value,
+            &KvsValue::String("two  words # no comment".to_string())
+        );
+
+        let steps = parse(r#"set obj {"a b": "c\"d"}"#).unwrap();
+        let ScriptStep::Set(_, KvsValue::Object(obj)) = &steps[0].step else {
+            panic!("expected object");
+        };
+        assert_eq!(obj["a b"], KvsValue::String("c\"d".to_string()));
+
+        assert_eq!(error(r#"set name "unterminated"#).0, 1);
+    }
+
+    #[test]
+    fn test_parse_comments_and_empty_lines() {
+        let steps = parse("# header\n\n   # indented\nremove a\n\nset b true\n").unwrap();
+        assert_eq!(
+            steps.iter().map(|step| step.line).collect::<Vec<_>>(),
+            vec![4, 6]
+        );
+        assert!(parse("# only comments\n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors_report_line() {
+        assert_eq!(
+            error("set a 1\n\nfrobnicate a 1"),
+            (3, "invalid statement 'frobnicate a 1'".to_string())
+        );
+        assert_eq!(error("# c\nset"), (2, "missing key for 'set'".to_string()));
+        assert_eq!(
+            error("remove a extra"),
+            (1, "invalid statement 'remove a extra'".to_string())
+        );
+        let (line, message) = error("set a 1\nassert b {");
+        assert_eq!(line, 2);
+        assert!(message.starts_with("invalid JSON value"));
+        let (line, message) = error("set a");
+        assert_eq!(line, 1);
+        assert!(message.starts_with("invalid JSON value"));
+    }
+
+    #[test]
+    fn test_parse_patch() {
+        let steps = parse(
+            r#"[{"op": "add", "path": "/a~1b", "value": 1},
+                {"op": "remove", "path": "/c"},
+                {"op": "test", "path": "/d~0", "value": null}]"#,
+        )
+        .unwrap();
+        assert!(matches!(&steps[0].step, ScriptStep::Set(key, _) if key == "a/b"));
+        assert!(matches!(&steps[1].step, ScriptStep::Remove(key) if key == "c"));
+        assert!(matches!(&steps[2].step, ScriptStep::Assert(key, KvsValue::Null) if key == "d~"));
+        assert_eq!(steps[2].line, 3);
+
+        assert_eq!(error("[1").0, 0);
+        assert_eq!(
+            error(r#"[{"op": "add", "path": "/a", "value": 1}, {"op": "move", "path": "/a"}]"#),
+            (2, "unsupported patch operation 'move'".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"op": "add", "path": "/a/b", "value": 1}]"#),
+            (1, "'path' must refer to a top-level key".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"op": "add", "path": "/a"}]"#),
+            (1, "unsupported patch operation 'add'".to_string())
+        );
+        assert_eq!(
+            error(r#"[{"path": "/a"}]"#),
+            (1, "missing 'op'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_apply_commits() {
+        let (kvs, _dir) = new_kvs();
+        kvs.set_value("old", 1.0).unwrap();
+        let steps = parse("set a 1\nassert a 1\nremove old\nset a 2\nassert a 2").unwrap();
+        let summary = apply(&kvs, steps, false);
+        assert!(summary.error.is_none());
+        assert!(summary.committed);
+        assert_eq!((summary.set, summary.removed, summary.asserts), (2, 1, 2));
+        assert_eq!(kvs.get_value_as::<f64>("a"), Ok(2.0));
+        assert!(!kvs.key_exists("old").unwrap());
+    }
+
+    #[test]
+    fn test_apply_aborts_on_error() {
+        let (kvs, _dir) = new_kvs();
+        kvs.set_value("a", 1.0).unwrap();
+
+        // A failing assert discards the steps before it
+        let steps = parse("set a 5\nset b 1\nassert a 1\nset c 1").unwrap();
+        let summary = apply(&kvs, steps, false);
+        assert!(!summary.committed);
+        let (line, message) = summary.error.clone().unwrap();
+        assert_eq!(line, 3);
+        assert!(message.starts_with("assert failed for 'a'"));
+        assert_eq!(summary.set, 2);
+        assert_eq!(kvs.get_value_as::<f64>("a"), Ok(1.0));
+        assert!(!kvs.key_exists("b").unwrap());
+        assert!(!kvs.key_exists("c").unwrap());
+
+        // Removing a key removed before by the script fails
+        let steps = parse("remove a\nremove a").unwrap();
+        let summary = apply(&kvs, steps, false);
+        assert_eq!(summary.error, Some((2, "key 'a' not found".to_string())));
+        assert!(kvs.key_exists("a").unwrap());
+
+        let json = summary.to_json(false);
+        let obj: &HashMap<String, JsonValue> = json.get().unwrap();
+        assert_eq!(obj["result"], JsonValue::String("failed".to_string()));
+        assert_eq!(obj["line"], JsonValue::Number(2.0));
+    }
+
+    #[test]
+    fn test_apply_dry_run() {
+        let (kvs, _dir) = new_kvs();
+        let summary = apply(&kvs, parse("set a 1\nassert a 1").unwrap(), true);
+        assert!(summary.error.is_none());
+        assert!(!summary.committed);
+        assert!(!kvs.key_exists("a").unwrap());
+        let json = summary.to_json(true);
+        let obj: &HashMap<String, JsonValue> = json.get().unwrap();
+        assert_eq!(obj["result"], JsonValue::String("dry-run".to_string()));
+        assert!(!obj.contains_key("line"));
+    }
+}
@@ -24,11 +24,31 @@
 //!    
 //!    Options:
 //!    -h, --help          Show this help message and exit
-//!    -o, --operation     Specify the operation to perform (setkey, getkey, removekey, listkeys, reset, snapshotcount, snapshotmaxcount, snapshotrestore, getkvsfilename, gethashfilename, createtestdata)
+//!    -o, --operation     Specify the operation to perform (setkey, getkey, removekey, listkeys, reset, snapshotcount, snapshotmaxcount, snapshotlist, snapshotrestore, snapshotcreate, getkvsfilename, gethashfilename, createtestdata, watch, browse, serve, apply, exportcsv, importcsv, dump, import, verify, keystats, stats)
+//!    -i, --instance      Specify the KVS instance ID (default: 0)
+//!    -d, --dir           Specify the directory of the instance files (default: current directory)
 //!    -k, --key           Specify the key to operate on (for key operations)
 //!    -p, --payload       Specify the value to write (for set operations)
-//!    -t, --type          Specify the value type for get operations (number, bool, string, null, array, object or first letter as a short form: n = number (except NULL))
+//!    -t, --type          Specify the value type for get operations (number, i64, u64, bytes (printed as hex), bool, string, null, array, object or first letter as a short form: n = number (except NULL))
 //!    -s, --snapshotid    Specify the snapshot ID for Snapshot operations
+//!    --tag               Specify the snapshot tag (for snapshotcreate, snapshotrestore)
+//!    --prefix            Only watch keys starting with the given prefix (for watch)
+//!    --interval          Poll interval in milliseconds (for watch, default: 500) or auto-flush interval (for serve, default: 5000)
+//!    --jsonl             Print changes as JSON lines (for watch)
+//!    --protected         Ask for confirmation before every write (for browse)
+//!    --socket            Specify the socket path (for serve)
+//!    --client            Client identity and token as <client>:<token>, repeatable (for serve)
+//!    --grant             Namespace access as <client>:<namespace>:<r|rw>, repeatable (for serve)
+//!    -f, --file          Specify the script file (for apply), CSV file (for exportcsv, importcsv) or JSON file (for dump, import)
+//!    --mode              Import mode: overwrite (default), keep or replace (for import)
+//!    --dry-run           Check the script without committing changes (for apply)
+//!    --quota             Size quota in bytes to project the time until it's reached (for stats)
+//!    
+//!    ---------------------------------------
+//!    
+//!    Subcommands (instead of -o, only -i and -d may precede them):
+//!        get, set, remove, list, dump, import, verify, snapshot list, snapshot restore, snapshot create
+//!        Every operation name can be used as well, e.g. kvs_tool get -k MyKey or kvs_tool stats -i 3
 //!    
 //!    ---------------------------------------
 //!    
@@ -48,6 +68,8 @@
 //!        kvs_tool -o setkey  -k MyKey -p 'true'
 //!        kvs_tool -o setkey  -k MyKey -p 15                   
 //!        kvs_tool -o setkey  -k MyKey -p '[456,false,"Second"]'
+//!        kvs_tool -o setkey  -k MyKey -p '{"$u64":"18446744073709551615"}' (64-bit integer, also "$i64")
+//!        kvs_tool -o setkey  -k MyKey -p '{"$bytes":"AP8Q"}' (byte array as base64)
 //!        kvs_tool -o setkey  -k MyKey -p '{"sub-number":789,"sub-string":"Third","sub-bool":true,"sub-array":[1246,false,"Fourth"],"sub-null":null}'
 //!    
 //!    Delete a key:
@@ -62,8 +84,15 @@
 //!    Snapshot Count:
 //!        kvs_tool -o snapshotcount
 //!    
+//!    Snapshot List (ID, generation, creation time, size, hash and tags):
+//!        kvs_tool snapshot list -d /persistent/app
+//!    
 //!    Snapshot Restore:
 //!        kvs_tool -o snapshotrestore -s 1
+//!        kvs_tool -o snapshotrestore --tag before-update
+//!    
+//!    Snapshot Create:
+//!        kvs_tool -o snapshotcreate --tag before-update
 //!    
 //!    Get KVS Filename:
 //!        kvs_tool -o getkvsfilename -s 1
@@ -71,6 +100,40 @@
 //!    Get Hash Filename:
 //!        kvs_tool -o gethashfilename -s 1
 //!    
+//!    Watch Changes (until interrupted):
+//!        kvs_tool -o watch -i 3 --prefix net.
+//!        kvs_tool -o watch --interval 100 --jsonl
+//!    
+//!    Interactive Browser (requires feature "tui"):
+//!        kvs_tool -o browse -i 3 --protected
+//!    
+//!    Serve the KVS to other processes until interrupted (requires feature "ipc"):
+//!        kvs_tool serve -i 3 --socket /run/kvs_3.sock --client audio:secret --grant audio:audio:rw --grant audio::r
+//!    
+//!    Apply Script (single transaction, prints a JSON summary):
+//!        kvs_tool -o apply -f provisioning.kvs
+//!        kvs_tool -o apply -f patch.json --dry-run
+//!
+//!        Script lines: set <key> <json> | remove <key> | assert <key> <json> | # comment
+//!        Or a JSON patch document with add, replace, remove and test operations.
+//!    
+//!    CSV Export and Import (scalar keys only):
+//!        kvs_tool -o exportcsv -f calibration.csv
+//!        kvs_tool -o importcsv -f calibration.csv
+//!    
+//!    JSON Dump and Import (stored keys, defaults aren't included):
+//!        kvs_tool dump -i 3 -f backup.json
+//!        kvs_tool import -i 3 -f backup.json --mode replace
+//!    
+//!    Verify the KVS file and all snapshots (fails if a problem is found):
+//!        kvs_tool verify -d /persistent/app -i 3
+//!    
+//!    Key Statistics (persisted by applications with KeyStatsMode::Persistent, most active first):
+//!        kvs_tool -o keystats -i 3
+//!    
+//!    Store Statistics and Growth Trend (sampled on flush, at most hourly):
+//!        kvs_tool -o stats -i 3 --quota 65536
+//!    
 //!    ---------------------------------------
 //!    
 //!    Create Test Data:
@@ -79,9 +142,15 @@
 //! ```
 //!
 
+mod kvs_script;
+#[cfg(feature = "tui")]
+mod kvs_tui;
+
 use pico_args::Arguments;
 use rust_kvs::prelude::*;
 use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tinyjson::JsonValue;
 
 /// Defines the available operation modes for key and file management.
@@ -94,15 +163,31 @@ enum OperationMode {
     Reset,
     SnapshotCount,
     SnapshotMaxCount,
+    SnapshotList,
     SnapshotRestore,
+    SnapshotCreate,
     GetKvsFilename,
     GetHashFilename,
     CreateTestData,
+    Watch,
+    Browse,
+    Serve,
+    Apply,
+    ExportCsv,
+    ImportCsv,
+    Dump,
+    Import,
+    Verify,
+    KeyStats,
+    Stats,
 }
 /// Defines the supported types for key-value pairs.
 /// This enum is used to specify the type of value when retrieving it from the KVS.
 enum SupportedTypes {
     Number,
+    I64,
+    U64,
+    Bytes,
     Bool,
     String,
     Null,
@@ -112,25 +197,41 @@ enum SupportedTypes {
 }
 // TODO Disable flush_on_exit: read-only access in some Operation-modes  (no modifications to persist)
 
+/// Creates the builder to open a KVS instance in the given directory (default: current directory).
+fn _builder(instance_id: usize, dir: Option<&str>) -> KvsBuilder<Kvs> {
+    let builder = KvsBuilder::new(InstanceId::new(instance_id))
+        .need_defaults(false)
+        .need_kvs(false);
+    match dir {
+        Some(dir) => builder.dir(dir),
+        None => builder,
+    }
+}
+
+/// Maps a subcommand to the name of its operation.
+/// `snapshot` takes a second subcommand (`list`, `restore`, `create`), other operation names are
+/// used as they are.
+fn _subcommand(args: &mut Arguments) -> Option<String> {
+    let command = args.subcommand().ok().flatten()?;
+    Some(match command.as_str() {
+        "get" => "getkey".to_string(),
+        "set" => "setkey".to_string(),
+        "remove" => "removekey".to_string(),
+        "list" => "listkeys".to_string(),
+        "snapshot" => format!(
+            "snapshot{}",
+            args.subcommand().ok().flatten().unwrap_or_default()
+        ),
+        _ => command,
+    })
+}
+
 /// Converts a TinyJSON value to a KVS value.
+///
+/// Objects of the form `{"$i64": "<n>"}` and `{"$u64": "<n>"}` are converted to 64-bit integers,
+/// `{"$bytes": "<base64>"}` to byte arrays.
 fn from_tinyjson(value: &JsonValue) -> KvsValue {
-    match value {
-        JsonValue::Number(n) => KvsValue::Number(*n),
-        JsonValue::Boolean(b) => KvsValue::Boolean(*b),
-        JsonValue::String(s) => KvsValue::String(s.clone()),
-        JsonValue::Null => KvsValue::Null,
-        JsonValue::Array(arr) => {
-            let v = arr.iter().map(from_tinyjson).collect();
-            KvsValue::Array(v)
-        }
-        JsonValue::Object(obj) => {
-            let map = obj
-                .iter()
-                .map(|(k, v)| (k.clone(), from_tinyjson(v)))
-                .collect();
-            KvsValue::Object(map)
-        }
-    }
+    KvsValue::from(value.clone())
 }
 
 /// Gets the key-value pair from the KVS and prints it to the console.
@@ -191,6 +292,9 @@ fn _getkey(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
     let get_mode = match datatype {
         Some(op) => match op.as_str() {
             "number" => SupportedTypes::Number,
+            "i64" => SupportedTypes::I64,
+            "u64" => SupportedTypes::U64,
+            "bytes" => SupportedTypes::Bytes,
             "bool" => SupportedTypes::Bool,
             "string" => SupportedTypes::String,
             "null" => SupportedTypes::Null,
@@ -214,6 +318,28 @@ fn _getkey(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
             })?;
             println!("Key:'{key}' \nValue: {value}");
         }
+        SupportedTypes::I64 => {
+            let value = kvs.get_value_as::<i64>(&key).map_err(|e| {
+                eprintln!("KVS get failed: {e:?}");
+                e
+            })?;
+            println!("Key:'{key}' \nValue: {value}");
+        }
+        SupportedTypes::U64 => {
+            let value = kvs.get_value_as::<u64>(&key).map_err(|e| {
+                eprintln!("KVS get failed: {e:?}");
+                e
+            })?;
+            println!("Key:'{key}' \nValue: {value}");
+        }
+        SupportedTypes::Bytes => {
+            let value = kvs.get_value_as::<Vec<u8>>(&key).map_err(|e| {
+                eprintln!("KVS get failed: {e:?}");
+                e
+            })?;
+            let hex: String = value.iter().map(|b| format!("{b:02x}")).collect();
+            println!("Key:'{key}' \nValue: {hex}");
+        }
         SupportedTypes::Bool => {
             let value = kvs.get_value_as::<bool>(&key).map_err(|e| {
                 eprintln!("KVS get failed: {e:?}");
@@ -395,12 +521,59 @@ fn _snapshotmaxcount() -> Result<(), ErrorCode> {
     Ok(())
 }
 
+/// Lists the snapshots that can be restored, newest first, and the snapshot tags.
+fn _snapshotlist(kvs: Kvs) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    println!("----------------------");
+    println!("Snapshot List");
+    let snapshots = kvs.snapshot_list().map_err(|e| {
+        eprintln!("KVS snapshot_list failed: {e:?}");
+        e
+    })?;
+    println!(
+        "{:>4} {:>12} {:>12} {:>10} {:>10}",
+        "id", "generation", "created", "size", "hash"
+    );
+    for info in snapshots {
+        let generation = info
+            .generation
+            .map_or("-".to_string(), |generation| generation.0.to_string());
+        let created = info
+            .created
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let hash = info
+            .hash
+            .map_or("-".to_string(), |hash| format!("{hash:08x}"));
+        println!(
+            "{:>4} {generation:>12} {created:>12} {:>10} {hash:>10}",
+            info.id.0, info.size
+        );
+    }
+    for tag in kvs.snapshot_tags()? {
+        println!("tag: {tag}");
+    }
+    println!("----------------------");
+    Ok(())
+}
+
 /// Restores a snapshot in the KVS.
 /// It takes a snapshot ID as an argument and restores the KVS to that snapshot.
 fn _snapshotrestore(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
     println!("----------------------");
     println!("Snapshot Restore");
 
+    if let Ok(Some(tag)) = args.opt_value_from_str::<_, String>("--tag") {
+        println!("Restore Snapshot '{tag}'");
+        kvs.snapshot_restore_tag(&tag).map_err(|e| {
+            eprintln!("KVS restore failed: {e:?}");
+            e
+        })?;
+        println!("----------------------");
+        return Ok(());
+    }
+
     let snapshot_id: u32 = match args.opt_value_from_str("--snapshotid") {
         Ok(Some(val)) => val,
         Ok(None) | Err(_) => match args.opt_value_from_str("-s") {
@@ -421,6 +594,28 @@ fn _snapshotrestore(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
     Ok(())
 }
 
+/// Creates a tagged snapshot of the current KVS data.
+fn _snapshotcreate(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    println!("----------------------");
+    println!("Snapshot Create");
+    kvs.flush_on_exit(false);
+
+    let tag: String = match args.opt_value_from_str("--tag") {
+        Ok(Some(val)) => val,
+        _ => {
+            eprintln!("Error: Snapshot tag (--tag) needs to be specified!");
+            return Err(ErrorCode::UnmappedError);
+        }
+    };
+    kvs.snapshot_create(&tag).map_err(|e| {
+        eprintln!("KVS snapshot create failed: {e:?}");
+        e
+    })?;
+    println!("Created Snapshot '{tag}'");
+    println!("----------------------");
+    Ok(())
+}
+
 /// Retrieves the KVS filename for a given snapshot ID.
 fn _getkvsfilename(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
     println!("----------------------");
@@ -523,15 +718,481 @@ fn _createtestdata(kvs: Kvs) -> Result<(), ErrorCode> {
     Ok(())
 }
 
+/// Reads all keys of a KVS instance that match the prefix.
+/// The KVS is opened read-only, flush-on-exit is disabled.
+fn _watch_read(
+    instance_id: usize,
+    dir: Option<&str>,
+    prefix: &str,
+) -> Result<HashMap<String, KvsValue>, ErrorCode> {
+    let kvs: Kvs = _builder(instance_id, dir).build()?;
+    kvs.flush_on_exit(false);
+
+    let mut values = HashMap::new();
+    for key in kvs.get_all_keys()? {
+        if key.starts_with(prefix) {
+            let value = kvs.get_value(&key)?;
+            values.insert(key, value);
+        }
+    }
+    Ok(values)
+}
+
+/// Prints a single change either human-readable or as JSON line.
+fn _watch_print(key: &str, old: Option<&KvsValue>, new: Option<&KvsValue>, jsonl: bool) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let change = match (old, new) {
+        (None, Some(_)) => "added",
+        (Some(_), None) => "removed",
+        _ => "changed",
+    };
+
+    if jsonl {
+        let to_json = |value: Option<&KvsValue>| match value {
+            Some(value) => JsonValue::from(value.clone()),
+            None => JsonValue::Null,
+        };
+        let line = JsonValue::Object(HashMap::from([
+            ("timestamp".to_string(), JsonValue::Number(timestamp)),
+            ("key".to_string(), JsonValue::String(key.to_string())),
+            ("change".to_string(), JsonValue::String(change.to_string())),
+            ("old".to_string(), to_json(old)),
+            ("new".to_string(), to_json(new)),
+        ]));
+        match line.stringify() {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("Error: JSON generation failed: {e:?}"),
+        }
+    } else {
+        println!("[{timestamp:.3}] {change} '{key}': {old:?} -> {new:?}");
+    }
+}
+
+/// Watches a KVS instance and prints all changes of persisted keys.
+/// The instance files are polled, so changes become visible as soon as another process
+/// flushed them (or journaled them with the write-ahead log).
+fn _watch(
+    kvs: Kvs,
+    instance_id: usize,
+    dir: Option<&str>,
+    mut args: Arguments,
+) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    drop(kvs);
+
+    let prefix: String = args
+        .opt_value_from_str("--prefix")
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    let interval: u64 = args
+        .opt_value_from_str("--interval")
+        .ok()
+        .flatten()
+        .unwrap_or(500);
+    let jsonl = args.contains("--jsonl");
+
+    if !jsonl {
+        println!("----------------------");
+        println!("Watch KVS instance {instance_id} (prefix: '{prefix}')");
+    }
+
+    let mut current = _watch_read(instance_id, dir, &prefix)?;
+    loop {
+        thread::sleep(Duration::from_millis(interval));
+
+        let next = match _watch_read(instance_id, dir, &prefix) {
+            Ok(next) => next,
+            Err(e) => {
+                // The file may be read while another process replaces it, retry next interval
+                eprintln!("KVS watch read failed: {e:?}");
+                continue;
+            }
+        };
+
+        let mut keys: Vec<&String> = current.keys().chain(next.keys()).collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let old = current.get(key);
+            let new = next.get(key);
+            if old != new {
+                _watch_print(key, old, new, jsonl);
+            }
+        }
+
+        current = next;
+    }
+}
+
+/// Applies a script file as a single transaction and prints a JSON summary.
+/// With `--dry-run` the script is only checked, nothing is persisted.
+fn _apply(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    let file: String = match args.opt_value_from_str("--file") {
+        Ok(Some(val)) => val,
+        Ok(None) | Err(_) => match args.opt_value_from_str("-f") {
+            Ok(Some(val)) => val,
+            _ => {
+                kvs.flush_on_exit(false);
+                eprintln!("Error: Script file (-f or --file) needs to be specified!");
+                return Err(ErrorCode::UnmappedError);
+            }
+        },
+    };
+    let dry_run = args.contains("--dry-run");
+
+    let script = std::fs::read_to_string(&file).map_err(|e| {
+        kvs.flush_on_exit(false);
+        eprintln!("Error: Script file '{file}' can't be read: {e}");
+        ErrorCode::from(e)
+    })?;
+
+    let summary = match kvs_script::parse(&script) {
+        Ok(steps) => kvs_script::apply(&kvs, steps, dry_run),
+        Err(error) => kvs_script::ScriptSummary {
+            error: Some(error),
+            ..Default::default()
+        },
+    };
+    if !summary.committed {
+        kvs.flush_on_exit(false);
+    }
+
+    match summary.to_json(dry_run).stringify() {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("Error: JSON generation failed: {e:?}"),
+    }
+
+    if summary.error.is_some() {
+        Err(ErrorCode::UnmappedError)
+    } else {
+        Ok(())
+    }
+}
+
+/// Exports all scalar keys as CSV to a file or, without `--file`, to stdout.
+fn _exportcsv(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    let file: Option<String> = match args.opt_value_from_str("--file") {
+        Ok(Some(val)) => Some(val),
+        Ok(None) | Err(_) => args.opt_value_from_str("-f").ok().flatten(),
+    };
+
+    let csv = kvs.export_csv().map_err(|e| {
+        eprintln!("KVS CSV export failed: {e:?}");
+        e
+    })?;
+    match file {
+        Some(file) => std::fs::write(&file, csv).map_err(|e| {
+            eprintln!("Error: CSV file '{file}' can't be written: {e}");
+            ErrorCode::from(e)
+        })?,
+        None => print!("{csv}"),
+    }
+    Ok(())
+}
+
+/// Imports scalar keys from a CSV file, only changed rows are applied.
+fn _importcsv(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    println!("----------------------");
+    println!("Import CSV");
+    let file: String = match args.opt_value_from_str("--file") {
+        Ok(Some(val)) => val,
+        Ok(None) | Err(_) => match args.opt_value_from_str("-f") {
+            Ok(Some(val)) => val,
+            _ => {
+                kvs.flush_on_exit(false);
+                eprintln!("Error: CSV file (-f or --file) needs to be specified!");
+                return Err(ErrorCode::UnmappedError);
+            }
+        },
+    };
+
+    let csv = std::fs::read_to_string(&file).map_err(|e| {
+        kvs.flush_on_exit(false);
+        eprintln!("Error: CSV file '{file}' can't be read: {e}");
+        ErrorCode::from(e)
+    })?;
+    let count = kvs.import_csv(&csv).map_err(|e| {
+        kvs.flush_on_exit(false);
+        eprintln!("KVS CSV import failed: {e:?}");
+        e
+    })?;
+    println!("Changed keys: {count}");
+    println!("----------------------");
+    Ok(())
+}
+
+/// Dumps the stored keys as JSON document to a file or, without `--file`, to stdout.
+fn _dump(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    let file: Option<String> = match args.opt_value_from_str("--file") {
+        Ok(Some(val)) => Some(val),
+        Ok(None) | Err(_) => args.opt_value_from_str("-f").ok().flatten(),
+    };
+
+    let json = kvs.export_json().map_err(|e| {
+        eprintln!("KVS JSON export failed: {e:?}");
+        e
+    })?;
+    match file {
+        Some(file) => std::fs::write(&file, json).map_err(|e| {
+            eprintln!("Error: JSON file '{file}' can't be written: {e}");
+            ErrorCode::from(e)
+        })?,
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Imports keys from a JSON document created by `dump` or a JSON KVS file.
+/// The import mode selects how stored keys are handled, all changes are applied atomically.
+fn _import(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    println!("----------------------");
+    println!("Import JSON");
+    let file: String = match args.opt_value_from_str("--file") {
+        Ok(Some(val)) => val,
+        Ok(None) | Err(_) => match args.opt_value_from_str("-f") {
+            Ok(Some(val)) => val,
+            _ => {
+                kvs.flush_on_exit(false);
+                eprintln!("Error: JSON file (-f or --file) needs to be specified!");
+                return Err(ErrorCode::UnmappedError);
+            }
+        },
+    };
+    let mode: Option<String> = args.opt_value_from_str("--mode").ok().flatten();
+    let mode = match mode.as_deref() {
+        None | Some("overwrite") => ImportMode::Overwrite,
+        Some("keep") => ImportMode::KeepExisting,
+        Some("replace") => ImportMode::Replace,
+        Some(mode) => {
+            kvs.flush_on_exit(false);
+            eprintln!("Error: Invalid import mode '{mode}' (overwrite, keep or replace)");
+            return Err(ErrorCode::UnmappedError);
+        }
+    };
+
+    let json = std::fs::read_to_string(&file).map_err(|e| {
+        kvs.flush_on_exit(false);
+        eprintln!("Error: JSON file '{file}' can't be read: {e}");
+        ErrorCode::from(e)
+    })?;
+    let count = kvs.import_json(&json, mode).map_err(|e| {
+        kvs.flush_on_exit(false);
+        eprintln!("KVS JSON import failed: {e:?}");
+        e
+    })?;
+    println!("Changed keys: {count}");
+    println!("----------------------");
+    Ok(())
+}
+
+/// Verifies the KVS file and all snapshots and prints the found problems.
+/// Fails with `ErrorCode::ValidationFailed` if a problem was found.
+fn _verify(kvs: Kvs) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    let report = kvs.verify_integrity().map_err(|e| {
+        eprintln!("KVS verify_integrity failed: {e:?}");
+        e
+    })?;
+    println!("----------------------");
+    println!("Verify");
+    println!("Checked files: {}", report.checked_files);
+    for issue in &report.issues {
+        println!("Issue: {issue:?}");
+    }
+    println!("----------------------");
+    if report.is_ok() {
+        Ok(())
+    } else {
+        Err(ErrorCode::ValidationFailed)
+    }
+}
+
+/// Prints the persisted per-key read and write counters, most active first.
+fn _keystats(kvs: Kvs, instance_id: usize, dir: Option<&str>) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    drop(kvs);
+
+    // Reopen with statistics enabled, the instance is only read
+    let kvs: Kvs = _builder(instance_id, dir)
+        .key_stats(KeyStatsMode::Persistent)
+        .build()?;
+    kvs.flush_on_exit(false);
+
+    let stats = kvs.key_stats().map_err(|e| {
+        eprintln!("KVS key_stats failed: {e:?}");
+        e
+    })?;
+    println!("----------------------");
+    println!("Key Statistics");
+    println!("{:>10} {:>10}  key", "reads", "writes");
+    for (key, stats) in stats {
+        println!("{:>10} {:>10}  {key}", stats.reads, stats.writes);
+    }
+    println!("----------------------");
+    Ok(())
+}
+
+/// Prints the usage statistics and the growth trend of the store.
+fn _stats(
+    kvs: Kvs,
+    instance_id: usize,
+    dir: Option<&str>,
+    mut args: Arguments,
+) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    drop(kvs);
+
+    // Reopen with the size history enabled, the instance is only read
+    let kvs: Kvs = _builder(instance_id, dir).size_history(true).build()?;
+    kvs.flush_on_exit(false);
+    let quota: Option<u64> = args.opt_value_from_str("--quota").ok().flatten();
+
+    let stats = kvs.stats().map_err(|e| {
+        eprintln!("KVS stats failed: {e:?}");
+        e
+    })?;
+    let trend = kvs.growth_trend().map_err(|e| {
+        eprintln!("KVS growth_trend failed: {e:?}");
+        e
+    })?;
+    println!("----------------------");
+    println!("Store Statistics");
+    println!("Keys: {}", stats.key_count);
+    println!("Serialized size: {} bytes", stats.serialized_size);
+    println!("Snapshot disk usage: {} bytes", stats.snapshot_disk_usage);
+    println!("Size samples: {}", trend.sample_count);
+    match trend.bytes_per_day {
+        Some(rate) => println!("Growth: {rate:.1} bytes/day"),
+        None => println!("Growth: unknown (fewer than two samples)"),
+    }
+    if let Some(quota) = quota {
+        match trend.time_to_size(quota) {
+            Some(time) => println!(
+                "Quota of {quota} bytes reached in: {:.1} days",
+                time.as_secs_f64() / (24.0 * 60.0 * 60.0)
+            ),
+            None => println!("Quota of {quota} bytes: not reached at the current trend"),
+        }
+    }
+    println!("----------------------");
+    Ok(())
+}
+
+/// Starts the interactive KVS browser.
+#[cfg(feature = "tui")]
+fn _browse(kvs: Kvs, instance_id: usize, mut args: Arguments) -> Result<(), ErrorCode> {
+    let protected = args.contains("--protected");
+    kvs_tui::run(kvs, instance_id, protected)
+}
+
+/// The interactive KVS browser isn't available without feature "tui".
+#[cfg(not(feature = "tui"))]
+fn _browse(kvs: Kvs, _instance_id: usize, _args: Arguments) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    eprintln!("Error: kvs_tool was built without the interactive browser (feature \"tui\")");
+    Err(ErrorCode::UnmappedError)
+}
+
+/// Serves the KVS on a Unix domain socket until the process is terminated.
+/// Changes are flushed by auto-flush, clients need a `--client` token and `--grant`ed namespaces.
+#[cfg(all(unix, feature = "ipc"))]
+fn _serve(kvs: Kvs, mut args: Arguments) -> Result<(), ErrorCode> {
+    let socket: String = match args.opt_value_from_str("--socket") {
+        Ok(Some(val)) => val,
+        _ => {
+            kvs.flush_on_exit(false);
+            eprintln!("Error: Socket path (--socket) needs to be specified!");
+            return Err(ErrorCode::UnmappedError);
+        }
+    };
+    let interval: u64 = args
+        .opt_value_from_str("--interval")
+        .ok()
+        .flatten()
+        .unwrap_or(5000);
+
+    let mut config = IpcConfig::default();
+    for client in args
+        .values_from_str::<_, String>("--client")
+        .unwrap_or_default()
+    {
+        let Some((client, token)) = client.split_once(':') else {
+            eprintln!("Error: Invalid client '{client}' (<client>:<token>)");
+            return Err(ErrorCode::UnmappedError);
+        };
+        config.clients.insert(client.to_string(), token.to_string());
+    }
+    for grant in args
+        .values_from_str::<_, String>("--grant")
+        .unwrap_or_default()
+    {
+        let parsed = grant.split_once(':').and_then(|(client, rest)| {
+            let (namespace, access) = rest.rsplit_once(':')?;
+            let access = match access {
+                "r" => IpcAccess::Read,
+                "rw" => IpcAccess::ReadWrite,
+                _ => return None,
+            };
+            Some(IpcGrant {
+                client: client.to_string(),
+                namespace: namespace.to_string(),
+                access,
+            })
+        });
+        match parsed {
+            Some(grant) => config.grants.push(grant),
+            None => {
+                eprintln!("Error: Invalid grant '{grant}' (<client>:<namespace>:<r|rw>)");
+                return Err(ErrorCode::UnmappedError);
+            }
+        }
+    }
+
+    let kvs = SharedKvs::new(kvs);
+    kvs.enable_auto_flush(Duration::from_millis(interval))?;
+    let _server = KvsIpcServer::start(kvs, &socket, config).map_err(|e| {
+        eprintln!("KVS serve failed: {e:?}");
+        e
+    })?;
+    println!("----------------------");
+    println!("Serving KVS on {socket}");
+    loop {
+        thread::park();
+    }
+}
+
+/// The KVS service isn't available without feature "ipc".
+#[cfg(not(all(unix, feature = "ipc")))]
+fn _serve(kvs: Kvs, _args: Arguments) -> Result<(), ErrorCode> {
+    kvs.flush_on_exit(false);
+    eprintln!("Error: kvs_tool was built without the KVS service (feature \"ipc\")");
+    Err(ErrorCode::UnmappedError)
+}
+
 /// Main function to run the KVS tool command line interface.
 fn main() -> Result<(), ErrorCode> {
     let mut args = Arguments::from_env();
 
-    let builder = KvsBuilder::new(InstanceId::new(0))
-        .need_defaults(false)
-        .need_kvs(false);
+    let instance_id: usize = match args.opt_value_from_str("--instance") {
+        Ok(Some(val)) => val,
+        Ok(None) | Err(_) => match args.opt_value_from_str("-i") {
+            Ok(Some(val)) => val,
+            _ => 0,
+        },
+    };
+    let dir: Option<String> = match args.opt_value_from_str("--dir") {
+        Ok(Some(val)) => Some(val),
+        Ok(None) | Err(_) => args.opt_value_from_str("-d").ok().flatten(),
+    };
+    let dir = dir.as_deref();
 
-    let kvs = match builder.build() {
+    let kvs = match _builder(instance_id, dir).build() {
         Ok(kvs) => kvs,
         Err(e) => {
             eprintln!("Error opening KVS: {e:?}");
@@ -553,14 +1214,34 @@ fn main() -> Result<(), ErrorCode> {
 
         Options:
         -h, --help          Show this help message and exit
-        -o, --operation     Specify the operation to perform (setkey, getkey, removekey, listkeys, reset, snapshotcount, snapshotmaxcount, snapshotrestore, getkvsfilename, gethashfilename, createtestdata)
+        -o, --operation     Specify the operation to perform (setkey, getkey, removekey, listkeys, reset, snapshotcount, snapshotmaxcount, snapshotlist, snapshotrestore, snapshotcreate, getkvsfilename, gethashfilename, createtestdata, watch, browse, serve, apply, exportcsv, importcsv, dump, import, verify, keystats, stats)
+        -i, --instance      Specify the KVS instance ID (default: 0)
+        -d, --dir           Specify the directory of the instance files (default: current directory)
         -k, --key           Specify the key to operate on (for key operations)
         -p, --payload       Specify the value to write (for set operations)
-        -t, --type          Specify the value type for get operations (number, bool, string, null, array, object or first letter as a short form: n = number (except NULL))
+        -t, --type          Specify the value type for get operations (number, i64, u64, bytes (printed as hex), bool, string, null, array, object or first letter as a short form: n = number (except NULL))
         -s, --snapshotid    Specify the snapshot ID for Snapshot operations
+        --tag               Specify the snapshot tag (for snapshotcreate, snapshotrestore)
+        --prefix            Only watch keys starting with the given prefix (for watch)
+        --interval          Poll interval in milliseconds (for watch, default: 500) or auto-flush interval (for serve, default: 5000)
+        --jsonl             Print changes as JSON lines (for watch)
+        --protected         Ask for confirmation before every write (for browse)
+        --socket            Specify the socket path (for serve)
+        --client            Client identity and token as <client>:<token>, repeatable (for serve)
+        --grant             Namespace access as <client>:<namespace>:<r|rw>, repeatable (for serve)
+        -f, --file          Specify the script file (for apply), CSV file (for exportcsv, importcsv) or JSON file (for dump, import)
+        --mode              Import mode: overwrite (default), keep or replace (for import)
+        --dry-run           Check the script without committing changes (for apply)
+        --quota             Size quota in bytes to project the time until it's reached (for stats)
         
         ---------------------------------------
     
+        Subcommands (instead of -o, only -i and -d may precede them):
+            get, set, remove, list, dump, import, verify, snapshot list, snapshot restore, snapshot create
+            Every operation name can be used as well, e.g. kvs_tool get -k MyKey or kvs_tool stats -i 3
+        
+        ---------------------------------------
+        
         Usage Examples:
 
         Read a Key and show value:
@@ -577,6 +1258,8 @@ fn main() -> Result<(), ErrorCode> {
             kvs_tool -o setkey  -k MyKey -p 'true'
             kvs_tool -o setkey  -k MyKey -p 15                   
             kvs_tool -o setkey  -k MyKey -p '[456,false,"Second"]'
+            kvs_tool -o setkey  -k MyKey -p '{"$u64":"18446744073709551615"}' (64-bit integer, also "$i64")
+            kvs_tool -o setkey  -k MyKey -p '{"$bytes":"AP8Q"}' (byte array as base64)
             kvs_tool -o setkey  -k MyKey -p '{"sub-number":789,"sub-string":"Third","sub-bool":true,"sub-array":[1246,false,"Fourth"],"sub-null":null}'
 
         Delete a key:
@@ -591,8 +1274,15 @@ fn main() -> Result<(), ErrorCode> {
         Snapshot Count:
             kvs_tool -o snapshotcount
         
+        Snapshot List (ID, generation, creation time, size, hash and tags):
+            kvs_tool snapshot list -d /persistent/app
+        
         Snapshot Restore:
             kvs_tool -o snapshotrestore -s 1
+            kvs_tool -o snapshotrestore --tag before-update
+
+        Snapshot Create:
+            kvs_tool -o snapshotcreate --tag before-update
 
         Get KVS Filename:
             kvs_tool -o getkvsfilename -s 1
@@ -600,6 +1290,40 @@ fn main() -> Result<(), ErrorCode> {
         Get Hash Filename:
             kvs_tool -o gethashfilename -s 1
 
+        Watch Changes (until interrupted):
+            kvs_tool -o watch -i 3 --prefix net.
+            kvs_tool -o watch --interval 100 --jsonl
+
+        Interactive Browser (requires feature "tui"):
+            kvs_tool -o browse -i 3 --protected
+
+        Serve the KVS to other processes until interrupted (requires feature "ipc"):
+            kvs_tool serve -i 3 --socket /run/kvs_3.sock --client audio:secret --grant audio:audio:rw --grant audio::r
+        
+        Apply Script (single transaction, prints a JSON summary):
+            kvs_tool -o apply -f provisioning.kvs
+            kvs_tool -o apply -f patch.json --dry-run
+
+            Script lines: set <key> <json> | remove <key> | assert <key> <json> | # comment
+            Or a JSON patch document with add, replace, remove and test operations.
+
+        CSV Export and Import (scalar keys only):
+            kvs_tool -o exportcsv -f calibration.csv
+            kvs_tool -o importcsv -f calibration.csv
+
+        JSON Dump and Import (stored keys, defaults aren't included):
+            kvs_tool dump -i 3 -f backup.json
+            kvs_tool import -i 3 -f backup.json --mode replace
+        
+        Verify the KVS file and all snapshots (fails if a problem is found):
+            kvs_tool verify -d /persistent/app -i 3
+        
+        Key Statistics (persisted by applications with KeyStatsMode::Persistent, most active first):
+            kvs_tool -o keystats -i 3
+
+        Store Statistics and Growth Trend (sampled on flush, at most hourly):
+            kvs_tool -o stats -i 3 --quota 65536
+
         ---------------------------------------
 
         Create Test Data:
@@ -615,12 +1339,15 @@ fn main() -> Result<(), ErrorCode> {
         Ok(Some(val)) => Some(val),
         Ok(None) | Err(_) => match args.opt_value_from_str("-o") {
             Ok(Some(val)) => Some(val),
-            _ => {
-                eprintln!(
-                    "Error: No operation specified. Use -o or --operation followed by a value."
-                );
-                return Err(ErrorCode::UnmappedError);
-            }
+            _ => match _subcommand(&mut args) {
+                Some(val) => Some(val),
+                None => {
+                    eprintln!(
+                        "Error: No operation specified. Use a subcommand or -o or --operation followed by a value."
+                    );
+                    return Err(ErrorCode::UnmappedError);
+                }
+            },
         },
     };
     let op_mode = match operation {
@@ -633,9 +1360,22 @@ fn main() -> Result<(), ErrorCode> {
             "createtestdata" => OperationMode::CreateTestData,
             "snapshotcount" => OperationMode::SnapshotCount,
             "snapshotmaxcount" => OperationMode::SnapshotMaxCount,
+            "snapshotlist" => OperationMode::SnapshotList,
             "snapshotrestore" => OperationMode::SnapshotRestore,
+            "snapshotcreate" => OperationMode::SnapshotCreate,
             "getkvsfilename" => OperationMode::GetKvsFilename,
             "gethashfilename" => OperationMode::GetHashFilename,
+            "watch" => OperationMode::Watch,
+            "browse" => OperationMode::Browse,
+            "serve" => OperationMode::Serve,
+            "apply" => OperationMode::Apply,
+            "exportcsv" => OperationMode::ExportCsv,
+            "importcsv" => OperationMode::ImportCsv,
+            "dump" => OperationMode::Dump,
+            "import" => OperationMode::Import,
+            "verify" => OperationMode::Verify,
+            "keystats" => OperationMode::KeyStats,
+            "stats" => OperationMode::Stats,
             _ => OperationMode::Invalid,
         },
         None => OperationMode::Invalid,
@@ -670,10 +1410,18 @@ fn main() -> Result<(), ErrorCode> {
             _snapshotmaxcount()?;
             Ok(())
         }
+        OperationMode::SnapshotList => {
+            _snapshotlist(kvs)?;
+            Ok(())
+        }
         OperationMode::SnapshotRestore => {
             _snapshotrestore(kvs, args)?;
             Ok(())
         }
+        OperationMode::SnapshotCreate => {
+            _snapshotcreate(kvs, args)?;
+            Ok(())
+        }
         OperationMode::GetKvsFilename => {
             _getkvsfilename(kvs, args)?;
             Ok(())
@@ -686,6 +1434,50 @@ fn main() -> Result<(), ErrorCode> {
             _createtestdata(kvs)?;
             Ok(())
         }
+        OperationMode::Watch => {
+            _watch(kvs, instance_id, dir, args)?;
+            Ok(())
+        }
+        OperationMode::Browse => {
+            _browse(kvs, instance_id, args)?;
+            Ok(())
+        }
+        OperationMode::Serve => {
+            _serve(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::Apply => {
+            _apply(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::ExportCsv => {
+            _exportcsv(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::KeyStats => {
+            _keystats(kvs, instance_id, dir)?;
+            Ok(())
+        }
+        OperationMode::Stats => {
+            _stats(kvs, instance_id, dir, args)?;
+            Ok(())
+        }
+        OperationMode::ImportCsv => {
+            _importcsv(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::Dump => {
+            _dump(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::Import => {
+            _import(kvs, args)?;
+            Ok(())
+        }
+        OperationMode::Verify => {
+            _verify(kvs)?;
+            Ok(())
+        }
         OperationMode::Invalid => {
             println!("----------------------");
             eprintln!("Invalid operation specified. Use -o or --operation to specify a valid operation. (See -h or --help for more information)");
@@ -0,0 +1,462 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Interactive KVS browser (feature `tui`)
+//!
+//! The screen is redrawn with ANSI escape sequences after every command, so it works on any
+//! target terminal (serial console, SSH) without a curses library. Keys are shown as a tree,
+//! split at `.`, next to a snapshot panel. Commands are read line by line from stdin.
+
+use crate::from_tinyjson;
+use rust_kvs::prelude::*;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use tinyjson::JsonValue;
+
+/// Key separator used for the tree view
+const TREE_SEPARATOR: char = '.';
+
+/// Maximum length of a value preview in the tree view
+const PREVIEW_LEN: usize = 40;
+
+/// Command help shown below the panels
+const COMMANDS: &str = "\
+Commands: <n> open entry | .. up | set <key> <json> | rm <key> | restore <id>
+          flush | refresh | q quit";
+
+/// Entry of the tree view
+enum TreeEntry {
+    /// Group of keys sharing the next path segment
+    Group { path: String, count: usize },
+
+    /// Key without further children
+    Key { key: String },
+}
+
+/// Browser state
+struct Browser {
+    /// Browsed KVS
+    kvs: Kvs,
+
+    /// Instance ID shown in the header
+    instance_id: usize,
+
+    /// Ask before every write
+    protected: bool,
+
+    /// Current tree path, empty for the root
+    path: String,
+
+    /// Entries of the current path
+    entries: Vec<TreeEntry>,
+
+    /// Key shown in the value preview
+    selected: Option<String>,
+
+    /// Result of the last command
+    status: String,
+}
+
+/// Build the tree entries below `path`
+fn tree_entries(keys: &[String], path: &str) -> Vec<TreeEntry> {
+    let mut groups: BTreeMap<String, usize> = BTreeMap::new();
+    let mut leaves = Vec::new();
+
+    for key in keys {
+        let rest = if path.is_empty() {
+            key.as_str()
+        } else {
+            match key
+                .strip_prefix(path)
+                .and_then(|rest| rest.strip_prefix(TREE_SEPARATOR))
+            {
+                Some(rest) => rest,
+                None => continue,
+            }
+        };
+
+        match rest.split_once(TREE_SEPARATOR) {
+            Some((segment, _)) => {
+                let group = if path.is_empty() {
+                    segment.to_string()
+                } else {
+                    format!("{path}{TREE_SEPARATOR}{segment}")
+                };
+                *groups.entry(group).or_default() += 1;
+            }
+            None => leaves.push(key.clone()),
+        }
+    }
+    leaves.sort();
+
+    groups
+        .into_iter()
+        .map(|(path, count)| TreeEntry::Group { path, count })
+        .chain(leaves.into_iter().map(|key| TreeEntry::Key { key }))
+        .collect()
+}
+
+/// Return a single-line, length limited preview of a value
+fn preview(value: &KvsValue) -> String {
+    let text = match JsonValue::from(value.clone()).stringify() {
+        Ok(text) => text,
+        Err(_) => format!("{value:?}"),
+    };
+    if text.chars().count() > PREVIEW_LEN {
+        let short: String = text.chars().take(PREVIEW_LEN).collect();
+        format!("{short}...")
+    } else {
+        text
+    }
+}
+
+impl Browser {
+    /// Reload the entries of the current path
+    fn refresh(&mut self) -> Result<(), ErrorCode> {
+        let keys = self.kvs.get_all_keys()?;
+        self.entries = tree_entries(&keys, &self.path);
+        if let Some(key) = &self.selected {
+            if !self.kvs.key_exists(key)? {
+                self.selected = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draw the tree, value preview and snapshot panel
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "\x1b[2J\x1b[H")?;
+        writeln!(
+            out,
+            "KVS instance {}{} - /{}",
+            self.instance_id,
+            if self.protected { " (protected)" } else { "" },
+            self.path.replace(TREE_SEPARATOR, "/")
+        )?;
+        writeln!(out, "{}", "=".repeat(60))?;
+
+        if self.entries.is_empty() {
+            writeln!(out, "  (no keys)")?;
+        }
+        for (idx, entry) in self.entries.iter().enumerate() {
+            match entry {
+                TreeEntry::Group { path, count } => {
+                    let name = path.rsplit(TREE_SEPARATOR).next().unwrap_or(path);
+                    writeln!(out, "{:>4}  [+] {name}/ ({count} keys)", idx + 1)?;
+                }
+                TreeEntry::Key { key } => {
+                    let name = key.rsplit(TREE_SEPARATOR).next().unwrap_or(key);
+                    let value = match self.kvs.get_value(key) {
+                        Ok(value) => preview(&value),
+                        Err(e) => format!("<{e:?}>"),
+                    };
+                    writeln!(out, "{:>4}      {name} = {value}", idx + 1)?;
+                }
+            }
+        }
+
+        writeln!(out, "{}", "-".repeat(60))?;
+        if let Some(key) = &self.selected {
+            writeln!(out, "Key:     {key}")?;
+            match self.kvs.get_value(key) {
+                Ok(value) => writeln!(out, "Value:   {value:?}")?,
+                Err(e) => writeln!(out, "Value:   <{e:?}>")?,
+            }
+            if let Ok(default) = self.kvs.get_default_value(key) {
+                let is_default = self.kvs.is_value_default(key).unwrap_or(false);
+                writeln!(out, "Default: {default:?} (is default: {is_default})")?;
+            }
+            writeln!(out, "{}", "-".repeat(60))?;
+        }
+
+        let count = self.kvs.snapshot_count();
+        let max_count = self.kvs.snapshot_retention().max_count;
+        writeln!(out, "Snapshots: {count} of {max_count}")?;
+        for idx in 1..=count {
+            let id = SnapshotId::new(idx);
+            if let Ok(filename) = self.kvs.get_kvs_filename(id) {
+                writeln!(out, "  {idx}: {}", filename.display())?;
+            }
+        }
+        writeln!(out, "{}", "-".repeat(60))?;
+
+        writeln!(out, "{COMMANDS}")?;
+        if !self.status.is_empty() {
+            writeln!(out, "{}", self.status)?;
+        }
+        write!(out, "> ")?;
+        out.flush()
+    }
+
+    /// Ask for confirmation before a write to a protected instance
+    fn confirm(&self, input: &mut impl BufRead, action: &str) -> bool {
+        if !self.protected {
+            return true;
+        }
+        print!(
+            "Instance {} is protected, {action}? [y/N] ",
+            self.instance_id
+        );
+        let _ = io::stdout().flush();
+        let mut answer = String::new();
+        if input.read_line(&mut answer).is_err() {
+            return false;
+        }
+        matches!(answer.trim(), "y" | "Y" | "yes")
+    }
+
+    /// Execute a single command line
+    ///
+    /// Returns `false` if the browser should quit.
+    fn execute(&mut self, line: &str, input: &mut impl BufRead) -> bool {
+        let line = line.trim();
+        let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        let result = match cmd {
+            "" | "refresh" => Ok(String::new()),
+            "q" | "quit" => return false,
+            ".." => {
+                self.path = match self.path.rsplit_once(TREE_SEPARATOR) {
+                    Some((parent, _)) => parent.to_string(),
+                    None => String::new(),
+                };
+                Ok(String::new())
+            }
+            "set" => match rest.split_once(' ') {
+                Some((key, json)) => match json.trim().parse::<JsonValue>() {
+                    Ok(json) if self.confirm(input, &format!("set '{key}'")) => {
+                        self.kvs.set_value(key, from_tinyjson(&json)).map(|_| {
+                            self.selected = Some(key.to_string());
+                            format!("Set '{key}'")
+                        })
+                    }
+                    Ok(_) => Ok("Aborted".to_string()),
+                    Err(e) => Ok(format!("Invalid JSON value: {e}")),
+                },
+                None => Ok("Usage: set <key> <json>".to_string()),
+            },
+            "rm" if !rest.is_empty() => {
+                if self.confirm(input, &format!("remove '{rest}'")) {
+                    self.kvs
+                        .remove_key(rest)
+                        .map(|_| format!("Removed '{rest}'"))
+                } else {
+                    Ok("Aborted".to_string())
+                }
+            }
+            "restore" => match rest.parse::<usize>() {
+                Ok(id) => {
+                    if self.confirm(input, &format!("restore snapshot {id}")) {
+                        self.kvs
+                            .snapshot_restore(SnapshotId::new(id))
+                            .map(|_| format!("Restored snapshot {id}"))
+                    } else {
+                        Ok("Aborted".to_string())
+                    }
+                }
+                Err(_) => Ok("Usage: restore <id>".to_string()),
+            },
+            "flush" => {
+                if self.confirm(input, "flush") {
+                    self.kvs.flush().map(|_| "Flushed".to_string())
+                } else {
+                    Ok("Aborted".to_string())
+                }
+            }
+            _ => match line.parse::<usize>() {
+                Ok(idx) if idx >= 1 && idx <= self.entries.len() => {
+                    match &self.entries[idx - 1] {
+                        TreeEntry::Group { path, .. } => self.path = path.clone(),
+                        TreeEntry::Key { key } => self.selected = Some(key.clone()),
+                    }
+                    Ok(String::new())
+                }
+                _ => Ok(format!("Unknown command: {line}")),
+            },
+        };
+
+        self.status = match result.and_then(|status| self.refresh().map(|_| status)) {
+            Ok(status) => status,
+            Err(e) => format!("Error: {e:?}"),
+        };
+        true
+    }
+}
+
+/// Run the interactive browser until the user quits
+///
+/// Modifications are persisted on `flush` or on exit.
+///
+/// # Parameters
+///   * `kvs`: KVS instance to browse
+///   * `instance_id`: Instance ID shown in the header
+///   * `protected`: Ask for confirmation before every write
+pub(crate) fn run(kvs: Kvs, instance_id: usize, protected: bool) -> Result<(), ErrorCode> {
+    let mut browser = Browser {
+        kvs,
+        instance_id,
+        protected,
+        path: String::new(),
+        entries: Vec::new(),
+        selected: None,
+        status: String::new(),
+    };
+    browser.refresh()?;
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut stdout = io::stdout();
+    loop {
+        browser
+            .draw(&mut stdout)
+            .map_err(|_| ErrorCode::UnmappedError)?;
+
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                if !browser.execute(&line, &mut input) {
+                    break;
+                }
+            }
+            Err(_) => return Err(ErrorCode::UnmappedError),
+        }
+    }
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::{tempdir, TempDir};
+
+    fn new_browser(protected: bool) -> (Browser, TempDir) {
+        let dir = tempdir().unwrap();
+        let kvs: Kvs = KvsBuilder::new(InstanceId::new(0))
+            .dir(dir.path().to_string_lossy().to_string())
+            .flush_on_exit(false)
+            .build()
+            .unwrap();
+        for key in ["audio.volume", "audio.eq.bass", "audiox", "net.mtu"] {
+            kvs.set_value(key, 1.0).unwrap();
+        }
+        let mut browser = Browser {
+            kvs,
+            instance_id: 0,
+            protected,
+            path: String::new(),
+            entries: Vec::new(),
+            selected: None,
+            status: String::new(),
+        };
+        browser.refresh().unwrap();
+        (browser, dir)
+    }
+
+    fn names(entries: &[TreeEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                TreeEntry::Group { path, count } => format!("{path}/{count}"),
+                TreeEntry::Key { key } => key.clone(),
+            })
+            .collect()
+    }
+
+    fn execute(browser: &mut Browser, line: &str, input: &str) -> bool {
+        browser.execute(line, &mut input.as_bytes())
+    }
+
+    #[test]
+    fn test_tree_entries_filter_by_path() {
+        let keys: Vec<String> = ["b", "a.x", "a.y.z", "ab", "a"]
+            .iter()
+            .map(|key| key.to_string())
+            .collect();
+        assert_eq!(names(&tree_entries(&keys, "")), ["a/2", "a", "ab", "b"]);
+        assert_eq!(names(&tree_entries(&keys, "a")), ["a.y/1", "a.x"]);
+        assert_eq!(names(&tree_entries(&keys, "a.y")), ["a.y.z"]);
+        assert!(tree_entries(&keys, "c").is_empty());
+    }
+
+    #[test]
+    fn test_preview_is_truncated() {
+        assert_eq!(preview(&KvsValue::from(1.5)), "1.5");
+        let long = preview(&KvsValue::String("x".repeat(100)));
+        assert_eq!(long.chars().count(), PREVIEW_LEN + 3);
+        assert!(long.ends_with("..."));
+    }
+
+    #[test]
+    fn test_selection_and_navigation() {
+        let (mut browser, _dir) = new_browser(false);
+        assert_eq!(names(&browser.entries), ["audio/2", "net/1", "audiox"]);
+
+        assert!(execute(&mut browser, "1", ""));
+        assert_eq!(browser.path, "audio");
+        assert_eq!(names(&browser.entries), ["audio.eq/1", "audio.volume"]);
+
+        execute(&mut browser, "2", "");
+        assert_eq!(browser.selected.as_deref(), Some("audio.volume"));
+        assert_eq!(browser.path, "audio");
+
+        execute(&mut browser, "3", "");
+        assert_eq!(browser.status, "Unknown command: 3");
+        assert_eq!(browser.selected.as_deref(), Some("audio.volume"));
+
+        execute(&mut browser, "..", "");
+        assert_eq!(browser.path, "");
+        execute(&mut browser, "..", "");
+        assert_eq!(browser.path, "");
+        assert!(!execute(&mut browser, "q", ""));
+    }
+
+    #[test]
+    fn test_edit_commit() {
+        let (mut browser, _dir) = new_browser(false);
+        execute(&mut browser, r#"set net.ip "10.0.0.1""#, "");
+        assert_eq!(browser.status, "Set 'net.ip'");
+        assert_eq!(browser.selected.as_deref(), Some("net.ip"));
+        assert_eq!(
+            browser.kvs.get_value_as::<String>("net.ip"),
+            Ok("10.0.0.1".to_string())
+        );
+
+        execute(&mut browser, "set net.ip {", "");
+        assert!(browser.status.starts_with("Invalid JSON value"));
+        execute(&mut browser, "set net.ip", "");
+        assert_eq!(browser.status, "Usage: set <key> <json>");
+
+        // Removing the selected key clears the selection
+        execute(&mut browser, "rm net.ip", "");
+        assert_eq!(browser.status, "Removed 'net.ip'");
+        assert_eq!(browser.selected, None);
+        execute(&mut browser, "rm net.ip", "");
+        assert_eq!(browser.status, "Error: KeyNotFound");
+    }
+
+    #[test]
+    fn test_protected_edit_needs_confirmation() {
+        let (mut browser, _dir) = new_browser(true);
+        execute(&mut browser, "set net.mtu 9000", "n\n");
+        assert_eq!(browser.status, "Aborted");
+        assert_eq!(browser.kvs.get_value_as::<f64>("net.mtu"), Ok(1.0));
+
+        execute(&mut browser, "set net.mtu 9000", "y\n");
+        assert_eq!(browser.kvs.get_value_as::<f64>("net.mtu"), Ok(9000.0));
+
+        execute(&mut browser, "rm net.mtu", "");
+        assert_eq!(browser.status, "Aborted");
+        assert!(browser.kvs.key_exists("net.mtu").unwrap());
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (c) 2025 Contributors to the Eclipse Foundation
+//
+// See the NOTICE file(s) distributed with this work for additional
+// information regarding copyright ownership.
+//
+// This program and the accompanying materials are made available under the
+// terms of the Apache License Version 2.0 which is available at
+// <https://www.apache.org/licenses/LICENSE-2.0>
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! # Verify the `release-abort` Profile has no Unwinding Runtime
+//!
+//! The precompiled standard library keeps its landing pads (`_Unwind_Resume`,
+//! `rust_eh_personality`) with `panic = "abort"`, so these are no indicator. The unwinding
+//! panic runtime and the functions raising an exception must not be linked.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Symbols only linked with the unwinding panic runtime
+const UNWIND_SYMBOLS: &[&str] = &["_Unwind_RaiseException", "panic_unwind"];
+
+/// Build `kvs_tool` with the `release-abort` profile and scan the binary for unwind symbols.
+#[test]
+fn panic_abort_profile_has_no_unwind_symbols() {
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("release-abort");
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let status = Command::new(cargo)
+        .args([
+            "build",
+            "--offline",
+            "--quiet",
+            "--profile",
+            "release-abort",
+        ])
+        .args(["--bin", "kvs_tool", "--target-dir"])
+        .arg(&target_dir)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let binary = target_dir
+        .join("release-abort")
+        .join(format!("kvs_tool{}", std::env::consts::EXE_SUFFIX));
+    let content = std::fs::read(binary).unwrap();
+    assert!(contains(&content, "panic_abort"));
+    for symbol in UNWIND_SYMBOLS {
+        assert!(!contains(&content, symbol), "unwind symbol {symbol} linked");
+    }
+}
+
+/// Check if the binary contains a symbol name
+fn contains(content: &[u8], symbol: &str) -> bool {
+    content
+        .windows(symbol.len())
+        .any(|window| window == symbol.as_bytes())
+}